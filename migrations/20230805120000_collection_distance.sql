@@ -0,0 +1,2 @@
+ALTER TABLE collection ADD COLUMN distance TEXT NOT NULL DEFAULT 'cosine';
+ALTER TABLE collection ADD COLUMN dimension INTEGER NOT NULL DEFAULT 384;
@@ -0,0 +1 @@
+ALTER TABLE collection ADD COLUMN model TEXT NOT NULL DEFAULT 'model';
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS chunk_vector (
+    chunk_id INTEGER NOT NULL,
+    collection_id INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    vector BLOB NOT NULL,
+    PRIMARY KEY (chunk_id, model),
+    FOREIGN KEY (chunk_id) REFERENCES chunk(id),
+    FOREIGN KEY (collection_id) REFERENCES collection(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_chunk_vector_collection ON chunk_vector(collection_id, model);
+
+CREATE TABLE IF NOT EXISTS query_log (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    collection TEXT NOT NULL,
+    model TEXT NOT NULL,
+    query TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_query_log_collection ON query_log(collection);
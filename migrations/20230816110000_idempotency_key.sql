@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS idempotency_key (
+    key TEXT NOT NULL PRIMARY KEY,
+    request_hash INTEGER NOT NULL,
+    status INTEGER NOT NULL,
+    body TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE document ADD COLUMN summary TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS collection_alias (
+    collection_id INTEGER NOT NULL,
+    alias TEXT NOT NULL,
+    expansion TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (collection_id, alias),
+    FOREIGN KEY (collection_id) REFERENCES collection(id) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS heading (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    level INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    anchor TEXT NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES document(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_heading_document ON heading(document_id);
+CREATE INDEX IF NOT EXISTS idx_heading_text ON heading(text);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS link (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL,
+    source_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    -- Repo path the link points at, NULL for external links.
+    target_path TEXT,
+    FOREIGN KEY (document_id) REFERENCES document(id),
+    FOREIGN KEY (source_id) REFERENCES source(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_document ON link(document_id);
+CREATE INDEX IF NOT EXISTS idx_link_target ON link(source_id, target_path);
@@ -0,0 +1,8 @@
+-- Cached results of checking external links, shared by all sources.
+CREATE TABLE IF NOT EXISTS link_check (
+    url TEXT NOT NULL PRIMARY KEY,
+    -- HTTP status of the response, NULL if the request failed.
+    status INTEGER,
+    error TEXT,
+    checked_at TEXT NOT NULL
+);
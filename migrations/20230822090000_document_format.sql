@@ -0,0 +1 @@
+ALTER TABLE document ADD COLUMN format TEXT NOT NULL DEFAULT 'markdown';
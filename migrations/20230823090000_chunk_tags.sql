@@ -0,0 +1 @@
+ALTER TABLE chunk ADD COLUMN tags TEXT NOT NULL DEFAULT '';
@@ -0,0 +1 @@
+ALTER TABLE source ADD COLUMN skip_front_matter TEXT NOT NULL DEFAULT 'draft=true;search=false;noindex';
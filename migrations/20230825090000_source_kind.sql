@@ -0,0 +1,2 @@
+ALTER TABLE source ADD COLUMN kind TEXT NOT NULL DEFAULT 'github';
+ALTER TABLE source ADD COLUMN url TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,6 @@
+ALTER TABLE source ADD COLUMN crawl_max_depth INTEGER NOT NULL DEFAULT 10;
+ALTER TABLE source ADD COLUMN crawl_max_pages INTEGER NOT NULL DEFAULT 500;
+ALTER TABLE source ADD COLUMN crawl_include TEXT NOT NULL DEFAULT '';
+ALTER TABLE source ADD COLUMN crawl_exclude TEXT NOT NULL DEFAULT '';
+ALTER TABLE source ADD COLUMN crawl_strip_query INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE source ADD COLUMN crawl_dedup INTEGER NOT NULL DEFAULT 1;
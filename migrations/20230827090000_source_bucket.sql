@@ -0,0 +1,13 @@
+ALTER TABLE source ADD COLUMN bucket_region TEXT NOT NULL DEFAULT 'us-east-1';
+ALTER TABLE source ADD COLUMN bucket_prefix TEXT NOT NULL DEFAULT '';
+ALTER TABLE source ADD COLUMN bucket_access_key_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE source ADD COLUMN bucket_secret_access_key TEXT NOT NULL DEFAULT '';
+
+-- ETags of the objects a bucket source's documents were parsed from.
+CREATE TABLE IF NOT EXISTS object_etag (
+    source_id INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    etag TEXT NOT NULL,
+    PRIMARY KEY (source_id, path),
+    FOREIGN KEY (source_id) REFERENCES source(id)
+);
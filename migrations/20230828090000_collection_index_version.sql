@@ -0,0 +1 @@
+ALTER TABLE collection ADD COLUMN index_version INTEGER NOT NULL DEFAULT 0;
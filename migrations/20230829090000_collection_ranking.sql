@@ -0,0 +1 @@
+ALTER TABLE collection ADD COLUMN ranking TEXT NOT NULL DEFAULT '';
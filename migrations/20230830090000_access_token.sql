@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS access_token (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    collection_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    requests_per_minute INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (collection_id) REFERENCES collection(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+ALTER TABLE access_token ADD COLUMN allowed_origins TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS session (
+    id_hash TEXT PRIMARY KEY NOT NULL,
+    subject TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL
+);
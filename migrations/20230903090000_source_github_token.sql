@@ -0,0 +1,3 @@
+-- Token GitHub sources are fetched with instead of the server's, encrypted
+-- when a secrets key is configured.
+ALTER TABLE source ADD COLUMN github_token TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+-- When the source was last parsed, NULL if it never was.
+ALTER TABLE source ADD COLUMN parsed_at TEXT;
@@ -0,0 +1,2 @@
+-- JSON summary of the files fetched by the last parse, NULL if it never was.
+ALTER TABLE source ADD COLUMN parse_summary TEXT;
@@ -0,0 +1,2 @@
+-- Other paths of the same content in the repo, one per line.
+ALTER TABLE document ADD COLUMN alternate_paths TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,2 @@
+-- Whether files of the repo's submodules are indexed along with its own.
+ALTER TABLE source ADD COLUMN github_submodules INTEGER NOT NULL DEFAULT 0;
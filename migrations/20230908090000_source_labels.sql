@@ -0,0 +1,2 @@
+-- Tags added to every chunk of the source, separated by semicolons.
+ALTER TABLE source ADD COLUMN labels TEXT NOT NULL DEFAULT '';
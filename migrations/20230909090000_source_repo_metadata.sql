@@ -0,0 +1,2 @@
+-- Description, topics, language and homepage of a GitHub source's repo, as JSON.
+ALTER TABLE source ADD COLUMN repo_metadata TEXT NOT NULL DEFAULT '';
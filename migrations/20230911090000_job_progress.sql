@@ -0,0 +1,3 @@
+-- Documents the job processed and why it failed, empty unless it did.
+ALTER TABLE job ADD COLUMN documents INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE job ADD COLUMN error TEXT NOT NULL DEFAULT '';
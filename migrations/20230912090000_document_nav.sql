@@ -0,0 +1,4 @@
+-- Sections of the docs site's nav the document is in, and its position in
+-- the nav, NULL if it isn't in one.
+ALTER TABLE document ADD COLUMN nav_path TEXT NOT NULL DEFAULT '';
+ALTER TABLE document ADD COLUMN nav_index INTEGER;
@@ -0,0 +1,2 @@
+-- Anchor of the heading the chunk starts at, empty if it doesn't start at one.
+ALTER TABLE chunk ADD COLUMN anchor TEXT NOT NULL DEFAULT '';
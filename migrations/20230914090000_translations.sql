@@ -0,0 +1,7 @@
+-- Whether translated copies of docs are skipped or indexed, see `Translations`.
+ALTER TABLE source ADD COLUMN translations TEXT NOT NULL DEFAULT 'skip';
+-- Language of translated documents and the path of their canonical document,
+-- both empty for canonical documents.
+ALTER TABLE document ADD COLUMN language TEXT NOT NULL DEFAULT '';
+ALTER TABLE document ADD COLUMN canonical_path TEXT NOT NULL DEFAULT '';
+ALTER TABLE chunk ADD COLUMN language TEXT NOT NULL DEFAULT '';
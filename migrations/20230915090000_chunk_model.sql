@@ -0,0 +1,11 @@
+-- Model, its version and the dimension each chunk's vector was created with,
+-- so vectors of different models don't end up in one collection.
+ALTER TABLE chunk ADD COLUMN model TEXT NOT NULL DEFAULT '';
+ALTER TABLE chunk ADD COLUMN model_version TEXT NOT NULL DEFAULT '';
+ALTER TABLE chunk ADD COLUMN dimension INTEGER NOT NULL DEFAULT 0;
+-- Existing chunks were encoded with their collection's model, chunks left
+-- without a collection don't say. Vectors are bincode encoded, a u64 length
+-- followed by the f32s.
+UPDATE chunk SET
+    model = COALESCE((SELECT model FROM collection WHERE collection.id = chunk.collection_id), ''),
+    dimension = (LENGTH(vector) - 8) / 4;
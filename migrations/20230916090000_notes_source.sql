@@ -0,0 +1,4 @@
+-- A collection has a single notes source, which is created by the first
+-- ad-hoc document. Concurrent ones would otherwise both create it.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_source_notes ON source(collection_id)
+    WHERE kind = 'upload' AND repo = 'notes';
@@ -0,0 +1,3 @@
+-- Summaries of parses are kept with the reports of their jobs, so an
+-- incremental, partial or unchanged parse doesn't replace the last one's.
+ALTER TABLE source DROP COLUMN parse_summary;
@@ -0,0 +1,8 @@
+-- Link to the document, resolved when it's parsed so files of submodules
+-- link to the submodule's repo. Empty for documents without one.
+ALTER TABLE document ADD COLUMN url TEXT NOT NULL DEFAULT '';
+-- Existing documents of repos link to the repo's branch.
+UPDATE document SET url = COALESCE((
+    SELECT 'https://github.com/' || source.owner || '/' || source.repo || '/blob/' || source.branch || '/' || document.path
+    FROM source WHERE source.id = document.source_id AND source.kind = 'github'
+), '');
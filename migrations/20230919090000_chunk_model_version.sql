@@ -0,0 +1,4 @@
+-- Chunks recorded the embeddings provider as their model version, which
+-- doesn't tell weights of a model apart. They don't say, like chunks stored
+-- before versions were recorded.
+UPDATE chunk SET model_version = '' WHERE model_version IN ('bert', 'hash');
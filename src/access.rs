@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Prefix of access tokens, so leaked ones are easy to recognize.
+const TOKEN_PREFIX: &str = "rtfm_pub_";
+
+/// Window requests of a token are counted in.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Returns a new random access token.
+pub fn generate_token() -> String {
+    format!(
+        "{}{}{}",
+        TOKEN_PREFIX,
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// Tokens are looked up by their hash, the token itself is never stored.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Returns the origin as browsers send it in the `Origin` header, e.g.
+/// `https://docs.example.com` for `https://docs.example.com/`.
+pub fn normalize_origin(origin: &str) -> Result<String> {
+    let url = Url::parse(origin.trim()).map_err(|_| anyhow!("Invalid origin '{}'", origin))?;
+    if !matches!(url.scheme(), "http" | "https") || url.path() != "/" {
+        return Err(anyhow!(
+            "Origin '{}' has to be a http(s) scheme and host without a path",
+            origin
+        ));
+    }
+    Ok(url.origin().ascii_serialization())
+}
+
+/// Counts requests per access token in fixed one minute windows.
+#[derive(Clone, Default)]
+pub struct RateLimits {
+    windows: Arc<Mutex<Windows>>,
+}
+
+#[derive(Default)]
+struct Windows {
+    /// Start and request count of each token's window.
+    by_token: HashMap<i64, (Instant, u32)>,
+    /// When expired windows, e.g. of deleted or idle tokens, were last dropped.
+    swept: Option<Instant>,
+}
+
+impl RateLimits {
+    /// Counts a request of the token. Returns how long until the next window
+    /// if it's over the limit.
+    pub fn check(&self, token_id: i64, per_minute: u32) -> Result<(), Duration> {
+        self.check_at(token_id, per_minute, Instant::now())
+    }
+
+    fn check_at(&self, token_id: i64, per_minute: u32, now: Instant) -> Result<(), Duration> {
+        let mut windows = self.windows.lock().expect("Rate limits lock is poisoned");
+        // An expired window counts like a missing one, so they're dropped once a window.
+        if windows
+            .swept
+            .map_or(true, |swept| now.duration_since(swept) >= RATE_WINDOW)
+        {
+            windows
+                .by_token
+                .retain(|_, (started, _)| now.duration_since(*started) < RATE_WINDOW);
+            windows.swept = Some(now);
+        }
+        let (started, count) = windows.by_token.entry(token_id).or_insert((now, 0));
+        if now.duration_since(*started) >= RATE_WINDOW {
+            *started = now;
+            *count = 0;
+        }
+        if *count >= per_minute {
+            return Err(RATE_WINDOW - now.duration_since(*started));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_origin() {
+        assert_eq!(
+            normalize_origin("https://Docs.example.com/").unwrap(),
+            "https://docs.example.com"
+        );
+        assert_eq!(
+            normalize_origin("http://localhost:8080").unwrap(),
+            "http://localhost:8080"
+        );
+        assert!(normalize_origin("https://example.com/docs").is_err());
+        assert!(normalize_origin("example.com").is_err());
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let limits = RateLimits::default();
+        let now = Instant::now();
+        assert!(limits.check_at(1, 2, now).is_ok());
+        assert!(limits.check_at(1, 2, now).is_ok());
+        assert!(limits.check_at(1, 2, now).is_err());
+        // Tokens are limited separately.
+        assert!(limits.check_at(2, 2, now).is_ok());
+        assert!(limits.check_at(1, 2, now + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limits_drop_expired_windows() {
+        let limits = RateLimits::default();
+        let now = Instant::now();
+        for token_id in 0..100 {
+            assert!(limits.check_at(token_id, 2, now).is_ok());
+        }
+        assert_eq!(limits.windows.lock().unwrap().by_token.len(), 100);
+        assert!(limits.check_at(100, 2, now + RATE_WINDOW).is_ok());
+        assert_eq!(limits.windows.lock().unwrap().by_token.len(), 1);
+    }
+}
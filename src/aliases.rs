@@ -0,0 +1,55 @@
+use anyhow::Context;
+
+use crate::{types::Alias, AppState};
+
+/// Expands org-specific jargon in the query with the collection's aliases,
+/// e.g. "k8s pods" becomes "k8s kubernetes pods". Embedding models don't
+/// know such terms, so the original word is kept next to its expansion.
+pub async fn expand(state: &AppState, collection: &str, query: &str) -> anyhow::Result<String> {
+    let aliases = state
+        .db
+        .query_aliases_by_collection_name(collection)
+        .await
+        .context("Failed to query aliases")?;
+    Ok(expand_with(query, &aliases))
+}
+
+pub fn expand_with(query: &str, aliases: &[Alias]) -> String {
+    if aliases.is_empty() {
+        return query.to_string();
+    }
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        words.push(word.to_string());
+        let term = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if let Some(alias) = aliases.iter().find(|a| a.alias.eq_ignore_ascii_case(term)) {
+            words.push(alias.expansion.clone());
+        }
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn alias(alias: &str, expansion: &str) -> Alias {
+        Alias {
+            collection_id: 1,
+            alias: alias.to_string(),
+            expansion: expansion.to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_expand_with_aliases() {
+        let aliases = vec![alias("k8s", "kubernetes"), alias("tf", "terraform")];
+        assert_eq!(
+            expand_with("Deploy K8s pods with tf?", &aliases),
+            "Deploy K8s kubernetes pods with tf? terraform"
+        );
+        assert_eq!(expand_with("no jargon", &aliases), "no jargon");
+    }
+}
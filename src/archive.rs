@@ -0,0 +1,183 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+    path::Component,
+};
+
+use crate::pdf;
+
+/// Archives unpacking to more are rejected, so a zip bomb can't exhaust memory.
+pub const MAX_UNPACKED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Unpacks a zip or (gzipped) tar archive into its text files keyed by path.
+/// A directory wrapping all files, like the one of GitHub's snapshots, is
+/// stripped. PDFs are converted to text, other binary files are left out.
+pub fn unpack(data: &[u8]) -> Result<BTreeMap<String, String>> {
+    let entries = if data.starts_with(b"PK\x03\x04") {
+        unzip(data)?
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        untar(GzDecoder::new(data))?
+    } else if data.get(257..262) == Some(b"ustar") {
+        untar(data)?
+    } else {
+        bail!("Unsupported archive, expected a zip or tar.gz");
+    };
+
+    let prefix = common_dir(entries.keys());
+    let mut files = BTreeMap::new();
+    for (path, data) in entries {
+        let path = path[prefix.len()..].to_string();
+        let text = if pdf::is_pdf(&path) {
+            match pdf::extract_text(&data) {
+                Ok(text) => text,
+                Err(err) => {
+                    tracing::warn!("Skipping '{}': {:?}", path, err);
+                    continue;
+                }
+            }
+        } else {
+            match String::from_utf8(data) {
+                Ok(text) => text,
+                Err(_) => {
+                    tracing::info!("Skipping '{}', not text", path);
+                    continue;
+                }
+            }
+        };
+        files.insert(path, text);
+    }
+    tracing::info!("Unpacked {} files", files.len());
+    Ok(files)
+}
+
+// Raw files keyed by path, directories and macOS resource forks are left out.
+fn unzip(data: &[u8]) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).context("Failed to read zip")?;
+    let mut entries = BTreeMap::new();
+    let mut total = 0;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).context("Failed to read zip entry")?;
+        if file.is_dir() {
+            continue;
+        }
+        // Names escaping the archive's tree have no enclosed name.
+        let path = match file.enclosed_name() {
+            Some(path) => path.to_string_lossy().replace('\\', "/"),
+            None => continue,
+        };
+        if path.starts_with("__MACOSX/") {
+            continue;
+        }
+        let data = read_limited(&mut file, &mut total)?;
+        entries.insert(path, data);
+    }
+    Ok(entries)
+}
+
+// Raw regular files keyed by path.
+fn untar<R: Read>(reader: R) -> Result<BTreeMap<String, Vec<u8>>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = BTreeMap::new();
+    let mut total = 0;
+    for entry in archive.entries().context("Failed to read tar")? {
+        let mut entry = entry.context("Failed to read tar entry")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().context("Invalid tar entry path")?;
+        let mut parts = Vec::new();
+        let mut enclosed = true;
+        for component in path.components() {
+            match component {
+                Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+                Component::CurDir => {}
+                // Absolute paths and `..` could point outside the archive's tree.
+                _ => enclosed = false,
+            }
+        }
+        if !enclosed || parts.is_empty() {
+            continue;
+        }
+        let data = read_limited(&mut entry, &mut total)?;
+        entries.insert(parts.join("/"), data);
+    }
+    Ok(entries)
+}
+
+// Reads the entry while keeping the archive's total below `MAX_UNPACKED_BYTES`,
+// the sizes archives declare can't be trusted.
+fn read_limited(reader: &mut impl Read, total: &mut u64) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader
+        .take(MAX_UNPACKED_BYTES - *total + 1)
+        .read_to_end(&mut data)
+        .context("Failed to read archive entry")?;
+    *total += data.len() as u64;
+    if *total > MAX_UNPACKED_BYTES {
+        bail!(
+            "Archive unpacks to more than the limit of {} bytes",
+            MAX_UNPACKED_BYTES
+        );
+    }
+    Ok(data)
+}
+
+// The top level directory shared by all paths, with a trailing slash.
+fn common_dir<'a>(mut paths: impl Iterator<Item = &'a String>) -> String {
+    let prefix = match paths.next().and_then(|path| path.split_once('/')) {
+        Some((dir, _)) => format!("{}/", dir),
+        None => return String::new(),
+    };
+    match paths.all(|path| path.starts_with(&prefix)) {
+        true => prefix,
+        false => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn test_unpack() {
+        let files: [(&str, &[u8]); 3] = [
+            ("widgets-main/README.md", b"# Widgets"),
+            ("widgets-main/docs/spin.md", b"Widgets spin."),
+            (
+                "widgets-main/logo.png",
+                &[0x89, 0x50, 0x4e, 0x47, 0xff, 0xfe],
+            ),
+        ];
+        let expected = BTreeMap::from([
+            ("README.md".to_string(), "# Widgets".to_string()),
+            ("docs/spin.md".to_string(), "Widgets spin.".to_string()),
+        ]);
+
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, path, data).unwrap();
+        }
+        let tar_gz = tar.into_inner().unwrap().finish().unwrap();
+        assert_eq!(unpack(&tar_gz).unwrap(), expected);
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, data) in files {
+            zip.start_file(path, Default::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.start_file("../escape.md", Default::default()).unwrap();
+        zip.write_all(b"outside").unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        assert_eq!(unpack(&zip).unwrap(), expected);
+
+        assert!(unpack(b"# Not an archive").is_err());
+    }
+}
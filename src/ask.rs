@@ -0,0 +1,251 @@
+use anyhow::Context;
+use futures::{Stream, StreamExt};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+use crate::{aliases, index, tinyvector::SimilarityResult, AppState};
+
+/// Number of chunks passed to the chat model as context.
+pub const CONTEXT_CHUNKS: usize = 5;
+
+const SYSTEM_PROMPT: &str = "You answer questions about documentation. \
+Use only the numbered documentation excerpts provided by the user. \
+If they don't contain the answer, say that you don't know. \
+Cite the excerpts you use by their number in brackets, e.g. [1]. \
+When quoting an excerpt, put its exact text in double quotes followed by the number, e.g. \"text\" [1].";
+
+const HYDE_PROMPT: &str =
+    "Write a short passage of technical documentation that answers the question. \
+It doesn't have to be correct, it's only used to find similar documentation.";
+
+/// How the vector used for retrieval is built from the query.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Embeds the query as is.
+    #[default]
+    Direct,
+    /// Hypothetical document embeddings: embeds an answer drafted by the LLM,
+    /// which is closer to the docs than a vague question is.
+    Hyde,
+}
+
+/// Returns the text to embed for the query. HyDE drafts differ between calls,
+/// so results of the same query may differ too.
+pub async fn query_text(
+    state: &AppState,
+    query: &str,
+    strategy: Strategy,
+) -> anyhow::Result<String> {
+    match strategy {
+        Strategy::Direct => Ok(query.to_string()),
+        Strategy::Hyde => {
+            let draft = state
+                .openai
+                .create_chat(HYDE_PROMPT, query)
+                .await
+                .context("Failed to draft hypothetical document")?;
+            tracing::debug!("Drafted hypothetical document: {}", draft);
+            // An empty draft can't be searched for, e.g. in offline mode.
+            if draft.trim().is_empty() {
+                return Ok(query.to_string());
+            }
+            Ok(draft)
+        }
+    }
+}
+
+/// Source of a claim in the answer.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Citation {
+    /// Number of the excerpt as cited in the answer, starting from 1.
+    pub index: usize,
+    pub document_id: i64,
+    pub path: String,
+    pub chunk_id: String,
+    /// Quoted text, if the claim quotes the excerpt.
+    pub quote: Option<String>,
+    /// Character offsets of the quote within the chunk.
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// Unset when the quote doesn't appear in the chunk or the excerpt doesn't exist.
+    pub verified: bool,
+}
+
+/// Finds chunks relevant to the question to ground the answer in.
+pub async fn retrieve(
+    state: &AppState,
+    collection: &str,
+    question: &str,
+    k: usize,
+    strategy: Strategy,
+) -> anyhow::Result<Vec<SimilarityResult>> {
+    let question = aliases::expand(state, collection, question).await?;
+    let collection = index::resolve_collection(&state.tinyvector, collection, None)
+        .await
+        .context("Failed to get Tinyvector collection")?;
+    let text = query_text(state, &question, strategy).await?;
+    let deadline = Instant::now() + state.cfg.search_deadline;
+    let query = state
+        .embeddings(&collection.model)?
+        .encode(&[text])
+        .await
+        .context("Failed to create embedding")?;
+    let boost_tag = index::boost_tag(&question);
+    let (results, _) = collection.get_similarity_within(&query[0], k, Some(deadline), boost_tag);
+    Ok(results)
+}
+
+/// Streams pieces of the answer as the chat model generates them.
+pub async fn answer_stream(
+    state: &AppState,
+    question: &str,
+    context: &[SimilarityResult],
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<String>>> {
+    let mut prompt = String::from("Documentation:\n\n");
+    for (i, result) in context.iter().enumerate() {
+        prompt.push_str(&format!(
+            "[{}] {}\n{}\n\n",
+            i + 1,
+            result.embedding.metadata.path,
+            result.embedding.blob
+        ));
+    }
+    prompt.push_str(&format!("Question: {}", question));
+
+    let stream = state
+        .openai
+        .create_chat_stream(SYSTEM_PROMPT, &prompt)
+        .await
+        .context("Failed to start answer")?;
+    Ok(stream.map(|delta| delta.context("Failed to receive answer")))
+}
+
+/// Extracts citations from the answer and verifies quoted text against the cited chunks.
+pub fn cite(answer: &str, context: &[SimilarityResult]) -> Vec<Citation> {
+    let quote_re = Regex::new(r#"["“]([^"”]+)["”]\s*\[(\d+)\]"#).unwrap();
+    let marker_re = Regex::new(r"\[(\d+)\]").unwrap();
+
+    let mut citations = Vec::new();
+    let mut quoted = Vec::new();
+    for cap in quote_re.captures_iter(answer) {
+        let index = cap[2].parse().unwrap_or_default();
+        citations.push(citation(index, Some(cap[1].trim()), context));
+        // The marker is reported with the quote, not on its own.
+        quoted.push(cap.get(2).map(|m| m.start()).unwrap_or_default());
+    }
+    for cap in marker_re.captures_iter(answer) {
+        let marker = cap.get(1).unwrap();
+        if quoted.contains(&marker.start()) {
+            continue;
+        }
+        let index = marker.as_str().parse().unwrap_or_default();
+        if citations
+            .iter()
+            .any(|c| c.index == index && c.quote.is_none())
+        {
+            continue;
+        }
+        citations.push(citation(index, None, context));
+    }
+    citations
+}
+
+fn citation(index: usize, quote: Option<&str>, context: &[SimilarityResult]) -> Citation {
+    let Some(result) = index.checked_sub(1).and_then(|i| context.get(i)) else {
+        return Citation {
+            index,
+            document_id: 0,
+            path: String::new(),
+            chunk_id: String::new(),
+            quote: quote.map(str::to_string),
+            start: None,
+            end: None,
+            verified: false,
+        };
+    };
+    let blob = &result.embedding.blob;
+    let span = quote.and_then(|quote| {
+        blob.find(quote).map(|offset| {
+            let start = blob[..offset].chars().count();
+            (start, start + quote.chars().count())
+        })
+    });
+    Citation {
+        index,
+        document_id: result.embedding.metadata.document_id,
+        path: result.embedding.metadata.path.clone(),
+        chunk_id: result.embedding.id.clone(),
+        quote: quote.map(str::to_string),
+        start: span.map(|(start, _)| start),
+        end: span.map(|(_, end)| end),
+        verified: quote.is_none() || span.is_some(),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct Answer {
+    pub answer: String,
+    pub citations: Vec<Citation>,
+}
+
+/// Answers the question in one go, for clients that don't need streaming.
+pub async fn answer(
+    state: &AppState,
+    collection: &str,
+    question: &str,
+    strategy: Strategy,
+) -> anyhow::Result<Answer> {
+    let context = retrieve(state, collection, question, CONTEXT_CHUNKS, strategy).await?;
+    let mut stream = Box::pin(answer_stream(state, question, &context).await?);
+    let mut answer = String::new();
+    while let Some(text) = stream.next().await {
+        answer.push_str(&text?);
+    }
+    let citations = cite(&answer, &context);
+    Ok(Answer { answer, citations })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedding, Metadata};
+
+    fn result(id: &str, blob: &str) -> SimilarityResult {
+        SimilarityResult {
+            score: 1.0,
+            embedding: Embedding::new(
+                id.to_string(),
+                Vec::new(),
+                blob.to_string(),
+                Metadata {
+                    document_id: 7,
+                    path: "docs/index.md".to_string(),
+                    ..Default::default()
+                },
+            ),
+        }
+    }
+
+    #[test]
+    fn test_cite_verifies_quotes() {
+        let context = vec![result(
+            "42",
+            "Certificates are renewed automatically by ACM.",
+        )];
+        let answer = "They are \"renewed automatically\" [1], see \"never expire\" [1] and [2].";
+
+        let citations = cite(answer, &context);
+
+        assert_eq!(citations.len(), 3);
+        assert_eq!(citations[0].chunk_id, "42");
+        assert_eq!(citations[0].start, Some(17));
+        assert_eq!(citations[0].end, Some(38));
+        assert!(citations[0].verified);
+        assert!(!citations[1].verified);
+        assert_eq!(citations[2].index, 2);
+        assert!(!citations[2].verified);
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{header, HeaderName, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use serde_json::Value;
+
+use crate::{middleware::read_body, types::AuditEntry, AppState};
+
+/// Set to the user of the request's session, clients' own values are dropped.
+static ACTOR: HeaderName = HeaderName::from_static("x-actor");
+
+static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Characters of the payload kept in the summary.
+const MAX_SUMMARY_CHARS: usize = 500;
+
+/// Records requests changing anything into the audit log. Reads pass through.
+///
+/// JSON payloads are summarized with secrets redacted, other bodies, like
+/// archives, only by their type and size.
+pub async fn audit(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return next.run(req).await;
+    }
+    let header = |name: &HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let actor = match header(&ACTOR) {
+        actor if actor.is_empty() => "anonymous".to_string(),
+        actor => actor,
+    };
+    let request_id = header(&REQUEST_ID);
+    let content_type = header(&header::CONTENT_TYPE);
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let action = format!("{} {}", req.method(), route);
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str().to_string())
+        .unwrap_or_default();
+
+    let (req, summary) = if content_type.starts_with("application/json") {
+        let (parts, req_body) = req.into_parts();
+        let bytes = match read_body(req_body, state.cfg.max_body_bytes).await {
+            Ok(bytes) => bytes,
+            Err(status) => {
+                tracing::warn!("Failed to read body of '{}': {}", path, status);
+                return status.into_response();
+            }
+        };
+        let summary = summarize(&bytes);
+        (Request::from_parts(parts, Body::from(bytes)), summary)
+    } else {
+        let summary = match header(&header::CONTENT_LENGTH).as_str() {
+            "" | "0" => String::new(),
+            len => format!("<{} bytes of {}>", len, content_type),
+        };
+        (req, summary)
+    };
+
+    let resp = next.run(req).await;
+    let entry = AuditEntry {
+        id: 0,
+        actor,
+        request_id,
+        action,
+        path,
+        summary,
+        status: resp.status().as_u16(),
+        created_at: Utc::now(),
+    };
+    if let Err(err) = state.db.insert_audit_entry(&entry).await {
+        tracing::error!("Failed to record audit entry: {}", err);
+    }
+    resp
+}
+
+// The payload as compact JSON with secrets redacted, truncated. Payloads that
+// don't parse can't be redacted, only their size and checksum are kept.
+fn summarize(bytes: &[u8]) -> String {
+    let summary = match serde_json::from_slice::<Value>(bytes) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => format!(
+            "<{} bytes of invalid JSON, crc32 {:08x}>",
+            bytes.len(),
+            crc32fast::hash(bytes)
+        ),
+    };
+    match summary.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", &summary[..end]),
+        None => summary,
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                // Compared without separators, so `apiKey` and `api-key` match too.
+                let key: String = key
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .collect::<String>()
+                    .to_lowercase();
+                if [
+                    "secret",
+                    "password",
+                    "token",
+                    "privatekey",
+                    "apikey",
+                    "credential",
+                    "authorization",
+                ]
+                .iter()
+                .any(|secret| key.contains(secret))
+                {
+                    *value = Value::String("<redacted>".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let payload = br#"{"url":"https://docs.example.com","bucket":{"access_key_id":"AKIA","secret_access_key":"hunter2"}}"#;
+        assert_eq!(
+            summarize(payload),
+            r#"{"bucket":{"access_key_id":"AKIA","secret_access_key":"<redacted>"},"url":"https://docs.example.com"}"#
+        );
+        let invalid = br#"{"token":"hunter2""#;
+        assert_eq!(
+            summarize(invalid),
+            format!(
+                "<18 bytes of invalid JSON, crc32 {:08x}>",
+                crc32fast::hash(invalid)
+            )
+        );
+        let long = format!("\"{}\"", "a".repeat(600));
+        assert_eq!(
+            summarize(long.as_bytes()).chars().count(),
+            MAX_SUMMARY_CHARS + 1
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut value = serde_json::json!({
+            "name": "docs",
+            "api_key": "sk-1",
+            "openAiApiKey": "sk-2",
+            "models": [{ "name": "bert", "Api-Key": "sk-3" }],
+            "github": { "private_key": "-----BEGIN", "app_id": 1 },
+            "credentials": { "user": "bot" },
+        });
+        redact(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "name": "docs",
+                "api_key": "<redacted>",
+                "openAiApiKey": "<redacted>",
+                "models": [{ "name": "bert", "Api-Key": "<redacted>" }],
+                "github": { "private_key": "<redacted>", "app_id": 1 },
+                "credentials": "<redacted>",
+            })
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Consecutive failures after which a breaker opens.
+const FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open breaker rejects calls before letting a probe through.
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0} is unavailable, retry later")]
+pub struct BreakerOpen(&'static str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail fast until the cooldown passes.
+    Open,
+    /// A single probe call decides whether the breaker closes or opens again.
+    HalfOpen,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakerStatus {
+    pub name: &'static str,
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+}
+
+/// Stops calling an external dependency after repeated failures, so an outage
+/// fails jobs fast instead of having each of them wait for its own timeout.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    failures: u32,
+    opened_at: Option<Instant>,
+    probing: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            inner: Mutex::new(Inner {
+                failures: 0,
+                opened_at: None,
+                probing: false,
+            }),
+        }
+    }
+
+    /// Runs the call unless the breaker is open, recording whether it failed.
+    pub async fn call<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: From<BreakerOpen>,
+    {
+        let mut permit = self.acquire()?;
+        let result = f.await;
+        permit.probe = false;
+        self.record(result.is_ok());
+        result
+    }
+
+    fn acquire(&self) -> Result<Permit<'_>, BreakerOpen> {
+        let mut inner = self.inner.lock().unwrap();
+        match self.state(&inner) {
+            BreakerState::Closed => Ok(Permit {
+                breaker: self,
+                probe: false,
+            }),
+            BreakerState::HalfOpen if !inner.probing => {
+                inner.probing = true;
+                Ok(Permit {
+                    breaker: self,
+                    probe: true,
+                })
+            }
+            BreakerState::HalfOpen | BreakerState::Open => Err(BreakerOpen(self.name)),
+        }
+    }
+
+    fn record(&self, ok: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.probing = false;
+        if ok {
+            if inner.opened_at.is_some() {
+                tracing::info!("Circuit breaker '{}' closed", self.name);
+            }
+            inner.failures = 0;
+            inner.opened_at = None;
+            return;
+        }
+        inner.failures += 1;
+        // A failed probe restarts the cooldown.
+        if inner.failures >= FAILURE_THRESHOLD {
+            if inner.opened_at.is_none() {
+                tracing::warn!(
+                    "Circuit breaker '{}' opened after {} failures",
+                    self.name,
+                    inner.failures
+                );
+            }
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    fn state(&self, inner: &Inner) -> BreakerState {
+        match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(opened_at) if opened_at.elapsed() < COOLDOWN => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        BreakerStatus {
+            name: self.name,
+            state: self.state(&inner),
+            consecutive_failures: inner.failures,
+        }
+    }
+}
+
+// Held while a call runs. A probe dropped before it finishes, e.g. by a
+// timeout, is given up, so the next call probes instead of the breaker
+// staying half open for good.
+struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.inner.lock().unwrap().probing = false;
+        }
+    }
+}
+
+/// Breakers of the external dependencies, shared by all requests and jobs.
+#[derive(Debug, Clone)]
+pub struct Breakers {
+    pub github: Arc<CircuitBreaker>,
+    pub openai: Arc<CircuitBreaker>,
+}
+
+impl Default for Breakers {
+    fn default() -> Self {
+        Self {
+            github: Arc::new(CircuitBreaker::new("github")),
+            openai: Arc::new(CircuitBreaker::new("openai")),
+        }
+    }
+}
+
+impl Breakers {
+    pub fn statuses(&self) -> Vec<BreakerStatus> {
+        vec![self.github.status(), self.openai.status()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_breaker_opens_after_failures() {
+        let breaker = CircuitBreaker::new("test");
+        for _ in 0..FAILURE_THRESHOLD {
+            let res: anyhow::Result<()> =
+                breaker.call(async { Err(anyhow::anyhow!("down")) }).await;
+            assert!(res.is_err());
+        }
+        assert_eq!(breaker.status().state, BreakerState::Open);
+
+        let mut called = false;
+        let res: anyhow::Result<()> = breaker
+            .call(async {
+                called = true;
+                Ok(())
+            })
+            .await;
+        assert!(res.is_err());
+        assert!(!called);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_probe_is_given_up() {
+        let breaker = CircuitBreaker::new("test");
+        {
+            let mut inner = breaker.inner.lock().unwrap();
+            inner.failures = FAILURE_THRESHOLD;
+            inner.opened_at = Instant::now().checked_sub(COOLDOWN);
+        }
+        assert_eq!(breaker.status().state, BreakerState::HalfOpen);
+
+        let probe = breaker.call(std::future::pending::<anyhow::Result<()>>());
+        let res = tokio::time::timeout(Duration::from_millis(10), probe).await;
+        assert!(res.is_err());
+
+        let res: anyhow::Result<()> = breaker.call(async { Ok(()) }).await;
+        assert!(res.is_ok());
+        assert_eq!(breaker.status().state, BreakerState::Closed);
+    }
+}
@@ -2,8 +2,11 @@ use std::{
     env::var,
     net::{Ipv6Addr, SocketAddr},
     sync::Arc,
+    time::Duration,
 };
 
+use crate::EmbeddingsProvider;
+
 pub type Config = Arc<Configuration>;
 
 #[derive(serde::Deserialize)]
@@ -14,8 +17,96 @@ pub struct Configuration {
     pub app_port: u16,
 
     pub db_dsn: String,
+    /// Empty when a GitHub App is configured and no token is given.
     pub github_token: String,
     pub open_ai_key: String,
+
+    /// How long a single search may scan before returning partial results.
+    pub search_deadline: Duration,
+    /// Device to run embeddings models on: `auto`, `cpu`, `cuda` or `cuda:N`.
+    pub embeddings_device: String,
+    /// Where embeddings come from: `bert` (local models) or `hash`.
+    pub embeddings_provider: EmbeddingsProvider,
+    /// Dimension of vectors of the `hash` provider.
+    pub hash_dimension: usize,
+    /// Models collections may be re-embedded with or get as candidates, on
+    /// top of the already loaded ones. Names are directories on the server.
+    pub embeddings_models: Vec<String>,
+    /// Maximum size of request bodies in bytes.
+    pub max_body_bytes: usize,
+    /// Maximum size of uploaded archives in bytes.
+    pub max_upload_bytes: usize,
+    /// Base url the server is reachable at, used in feeds.
+    pub public_url: String,
+    /// Timeout of requests to endpoints without a more specific one.
+    pub request_timeout: Duration,
+    /// Timeout of search and lookup requests, should exceed `search_deadline`
+    /// so partial results make it back.
+    pub search_timeout: Duration,
+    /// Timeout of long running requests, e.g. parsing, encoding and admin jobs.
+    pub long_timeout: Duration,
+    /// Number of search and lookup requests handled at once, others wait for a slot.
+    pub search_concurrency: usize,
+    /// Number of ask requests handled at once, others wait for a slot.
+    pub ask_concurrency: usize,
+    /// Command run with an image url as its argument, prints text found in the
+    /// image. Images are described by alt text and captions only when unset.
+    pub ocr_command: Option<String>,
+    /// Disables all outbound network, only already indexed content is served
+    /// and OpenAI is replaced with a deterministic mock.
+    pub offline: bool,
+    /// Gates the dashboard behind an OpenID Connect login when set.
+    pub oidc: Option<OidcConfig>,
+    /// Hex encoded 32 byte key stored credentials are encrypted with.
+    pub secrets_key: Option<String>,
+    /// Authenticates to GitHub as an app instead of with `github_token`.
+    pub github_app: Option<GitHubAppConfig>,
+    /// Proxy of `HTTPS_PROXY` for the GitHub client, other clients pick the
+    /// variable up themselves.
+    pub proxy: Option<String>,
+    /// Limits of a parse unless the request sets its own.
+    pub parse_budget: ParseBudget,
+    /// HTTP-date the unversioned `/api` routes are removed at, announced in
+    /// their `Sunset` header.
+    pub api_sunset: Option<String>,
+}
+
+/// Limits of a single parse, so a source pointed at a far larger repo or
+/// site than intended stops instead of fetching gigabytes.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct ParseBudget {
+    pub max_files: usize,
+    /// Total size of fetched contents.
+    pub max_bytes: u64,
+    pub max_duration: Duration,
+}
+
+/// GitHub App sources are fetched as, for higher rate limits and access
+/// managed by the orgs installing it.
+#[derive(serde::Deserialize, Clone)]
+pub struct GitHubAppConfig {
+    pub app_id: u64,
+    /// PEM encoded RSA key generated in the app's settings.
+    pub private_key: String,
+    /// Installation used for every repo, looked up per repo owner when unset.
+    pub installation_id: Option<u64>,
+}
+
+/// OpenID Connect provider users sign in to the dashboard with.
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcConfig {
+    /// E.g. `https://accounts.google.com`, its discovery document is fetched
+    /// from `/.well-known/openid-configuration` below it.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Userinfo claim listing the user's groups.
+    pub groups_claim: String,
+    /// Members of any of these groups are admins.
+    pub admin_groups: Vec<String>,
+    /// Members of any of these groups may view the dashboard, anyone signed
+    /// in may if empty.
+    pub viewer_groups: Vec<String>,
 }
 
 impl Configuration {
@@ -26,9 +117,157 @@ impl Configuration {
             .expect("Unable to parse the value of the PORT environment variable. Please make sure it is a valid unsigned 16-bit integer");
 
         let db_dsn = var("DATABASE_URL").expect("Missing DATABASE_URL environment variable");
-        let github_token = var("GITHUB_TOKEN").expect("Missing GITHUB_TOKEN environment variablw");
-        let open_ai_key =
-            var("OPENAI_API_KEY").expect("Missing OPENAI_API_KEY environment variablw");
+        let offline = var("OFFLINE")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+        // Tokens are never used offline, so they may be left out.
+        let token_var = |name: &str| match var(name) {
+            Ok(token) => token,
+            Err(_) if offline => String::new(),
+            Err(_) => panic!("Missing {} environment variable", name),
+        };
+        let github_app = var("GITHUB_APP_ID")
+            .ok()
+            .filter(|id| !id.is_empty())
+            .map(|id| {
+                let private_key = match var("GITHUB_APP_PRIVATE_KEY_FILE") {
+                    Ok(path) if !path.is_empty() => std::fs::read_to_string(&path)
+                        .unwrap_or_else(|_| panic!("Unable to read GitHub App private key file '{}'", path)),
+                    // Keys in the environment usually have their newlines escaped.
+                    _ => var("GITHUB_APP_PRIVATE_KEY")
+                        .expect("Missing GITHUB_APP_PRIVATE_KEY environment variable")
+                        .replace("\\n", "\n"),
+                };
+                GitHubAppConfig {
+                    app_id: id.parse().expect("Unable to parse the value of the GITHUB_APP_ID environment variable. Please make sure it is a valid number"),
+                    private_key,
+                    installation_id: var("GITHUB_APP_INSTALLATION_ID")
+                        .ok()
+                        .filter(|id| !id.is_empty())
+                        .map(|id| id.parse().expect("Unable to parse the value of the GITHUB_APP_INSTALLATION_ID environment variable. Please make sure it is a valid number")),
+                }
+            });
+        let github_token = match github_app {
+            Some(_) => var("GITHUB_TOKEN").unwrap_or_default(),
+            None => token_var("GITHUB_TOKEN"),
+        };
+        let open_ai_key = token_var("OPENAI_API_KEY");
+
+        let search_deadline = var("SEARCH_DEADLINE_MS")
+            .unwrap_or_else(|_| "5000".to_string())
+            .parse::<u64>()
+            .expect("Unable to parse the value of the SEARCH_DEADLINE_MS environment variable. Please make sure it is a valid number of milliseconds");
+        let search_deadline = Duration::from_millis(search_deadline);
+
+        let request_timeout = timeout_var("REQUEST_TIMEOUT_MS", 15_000);
+        let search_timeout = timeout_var("SEARCH_TIMEOUT_MS", 8_000);
+        let long_timeout = timeout_var("LONG_TIMEOUT_MS", 300_000);
+
+        let search_concurrency = var("SEARCH_CONCURRENCY")
+            .unwrap_or_else(|_| "8".to_string())
+            .parse::<usize>()
+            .expect("Unable to parse the value of the SEARCH_CONCURRENCY environment variable. Please make sure it is a valid number");
+        let ask_concurrency = var("ASK_CONCURRENCY")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .expect("Unable to parse the value of the ASK_CONCURRENCY environment variable. Please make sure it is a valid number");
+
+        let embeddings_device = var("EMBEDDINGS_DEVICE").unwrap_or_else(|_| "auto".to_string());
+        let embeddings_provider = var("EMBEDDINGS_PROVIDER")
+            .map(|value| value.parse::<EmbeddingsProvider>().expect("Unable to parse the value of the EMBEDDINGS_PROVIDER environment variable. Please make sure it is either 'bert' or 'hash'"))
+            .unwrap_or_default();
+        let hash_dimension = var("HASH_EMBEDDINGS_DIMENSION")
+            .unwrap_or_else(|_| "384".to_string())
+            .parse::<usize>()
+            .expect("Unable to parse the value of the HASH_EMBEDDINGS_DIMENSION environment variable. Please make sure it is a valid number");
+        let embeddings_models = var("EMBEDDINGS_MODELS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|model| model.trim().to_string())
+            .filter(|model| !model.is_empty())
+            .collect();
+
+        let max_body_bytes = var("MAX_BODY_BYTES")
+            .unwrap_or_else(|_| "2097152".to_string())
+            .parse::<usize>()
+            .expect("Unable to parse the value of the MAX_BODY_BYTES environment variable. Please make sure it is a valid number of bytes");
+
+        let max_upload_bytes = var("MAX_UPLOAD_BYTES")
+            .unwrap_or_else(|_| "52428800".to_string())
+            .parse::<usize>()
+            .expect("Unable to parse the value of the MAX_UPLOAD_BYTES environment variable. Please make sure it is a valid number of bytes");
+
+        let public_url = var("PUBLIC_URL")
+            .unwrap_or_else(|_| format!("http://localhost:{}", app_port))
+            .trim_end_matches('/')
+            .to_string();
+
+        let ocr_command = var("OCR_COMMAND")
+            .ok()
+            .filter(|command| !command.is_empty());
+
+        let oidc = var("OIDC_ISSUER")
+            .ok()
+            .filter(|issuer| !issuer.is_empty())
+            .map(|issuer| {
+                let groups_var = |name: &str| {
+                    var(name)
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|group| group.trim().to_string())
+                        .filter(|group| !group.is_empty())
+                        .collect()
+                };
+                OidcConfig {
+                    issuer: issuer.trim_end_matches('/').to_string(),
+                    client_id: var("OIDC_CLIENT_ID")
+                        .expect("Missing OIDC_CLIENT_ID environment variable"),
+                    client_secret: var("OIDC_CLIENT_SECRET")
+                        .expect("Missing OIDC_CLIENT_SECRET environment variable"),
+                    groups_claim: var("OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".to_string()),
+                    admin_groups: groups_var("OIDC_ADMIN_GROUPS"),
+                    viewer_groups: groups_var("OIDC_VIEWER_GROUPS"),
+                }
+            });
+
+        // A file lets the key be mounted by a KMS or secret manager instead of
+        // living in the environment.
+        let secrets_key = match var("SECRETS_KEY_FILE") {
+            Ok(path) if !path.is_empty() => Some(
+                std::fs::read_to_string(&path)
+                    .unwrap_or_else(|_| panic!("Unable to read secrets key file '{}'", path))
+                    .trim()
+                    .to_string(),
+            ),
+            _ => var("SECRETS_KEY").ok().filter(|key| !key.is_empty()),
+        };
+
+        let proxy = var("HTTPS_PROXY")
+            .or_else(|_| var("https_proxy"))
+            .ok()
+            .filter(|proxy| !proxy.is_empty());
+
+        let parse_budget = ParseBudget {
+            max_files: var("PARSE_MAX_FILES")
+                .unwrap_or_else(|_| "10000".to_string())
+                .parse::<usize>()
+                .expect("Unable to parse the value of the PARSE_MAX_FILES environment variable. Please make sure it is a valid number"),
+            max_bytes: var("PARSE_MAX_BYTES")
+                .unwrap_or_else(|_| "1073741824".to_string())
+                .parse::<u64>()
+                .expect("Unable to parse the value of the PARSE_MAX_BYTES environment variable. Please make sure it is a valid number of bytes"),
+            max_duration: timeout_var("PARSE_MAX_DURATION_MS", 3_600_000),
+        };
+
+        let api_sunset = var("API_SUNSET")
+            .ok()
+            .filter(|date| !date.is_empty())
+            .map(|date| {
+                chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .expect("Unable to parse the value of the API_SUNSET environment variable. Please make sure it is a date like 2024-06-30")
+                    .format("%a, %d %b %Y 00:00:00 GMT")
+                    .to_string()
+            });
 
         let listen_address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, app_port));
 
@@ -38,6 +277,27 @@ impl Configuration {
             db_dsn,
             github_token,
             open_ai_key,
+            search_deadline,
+            embeddings_device,
+            embeddings_provider,
+            hash_dimension,
+            embeddings_models,
+            max_body_bytes,
+            max_upload_bytes,
+            public_url,
+            request_timeout,
+            search_timeout,
+            long_timeout,
+            search_concurrency,
+            ask_concurrency,
+            ocr_command,
+            offline,
+            oidc,
+            secrets_key,
+            github_app,
+            proxy,
+            parse_budget,
+            api_sunset,
         })
     }
 
@@ -45,3 +305,16 @@ impl Configuration {
         self.db_dsn = db_dsn
     }
 }
+
+fn timeout_var(name: &str, default_ms: u64) -> Duration {
+    let Ok(value) = var(name) else {
+        return Duration::from_millis(default_ms);
+    };
+    let ms = value.parse::<u64>().unwrap_or_else(|_| {
+        panic!(
+            "Unable to parse the value of the {} environment variable. Please make sure it is a valid number of milliseconds",
+            name
+        )
+    });
+    Duration::from_millis(ms)
+}
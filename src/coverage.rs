@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tree_sitter::{Node, Parser};
+
+use crate::{types::Document, Db};
+
+/// Public item of the indexed code.
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub kind: String,
+    pub name: String,
+    pub path: String,
+    /// 1-based line the item starts at.
+    pub line: usize,
+}
+
+/// Public items of a source and which of them no document mentions.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct CoverageReport {
+    pub source_id: i64,
+    pub symbols: usize,
+    pub documented: usize,
+    pub undocumented: Vec<Symbol>,
+}
+
+/// Reports by source with the fingerprint of the collection's documents they
+/// were made from, reused until any of the documents changes.
+#[derive(Clone, Default)]
+pub struct CoverageCache(Arc<Mutex<HashMap<i64, (String, CoverageReport)>>>);
+
+/// Item kinds reported when they are `pub`, keyed by tree-sitter node kind.
+const ITEM_KINDS: &[(&str, &str)] = &[
+    ("function_item", "fn"),
+    ("struct_item", "struct"),
+    ("enum_item", "enum"),
+    ("trait_item", "trait"),
+    ("type_item", "type"),
+    ("const_item", "const"),
+    ("static_item", "static"),
+    ("macro_definition", "macro"),
+];
+
+/// Returns public items of a Rust file, including methods of impl blocks.
+pub fn public_symbols(path: &str, code: &str) -> Result<Vec<Symbol>> {
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_rust::language())
+        .context("Failed to load Rust grammar")?;
+    let tree = parser
+        .parse(code, None)
+        .context("Failed to parse Rust code")?;
+    let mut symbols = Vec::new();
+    collect_symbols(tree.root_node(), code.as_bytes(), path, &mut symbols);
+    Ok(symbols)
+}
+
+fn collect_symbols(node: Node, code: &[u8], path: &str, symbols: &mut Vec<Symbol>) {
+    let kind = ITEM_KINDS
+        .iter()
+        .find(|(node_kind, _)| *node_kind == node.kind());
+    if let Some((_, kind)) = kind {
+        let exported = match *kind {
+            "macro" => is_macro_exported(node, code),
+            _ => is_public(node, code),
+        };
+        let name = node
+            .child_by_field_name("name")
+            .and_then(|name| name.utf8_text(code).ok());
+        if let (true, Some(name)) = (exported, name) {
+            symbols.push(Symbol {
+                kind: kind.to_string(),
+                name: name.to_string(),
+                path: path.to_string(),
+                line: node.start_position().row + 1,
+            });
+        }
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        // Private modules and function bodies can't contain public API.
+        if child.kind() == "mod_item" && !is_public(child, code) || child.kind() == "block" {
+            continue;
+        }
+        collect_symbols(child, code, path, symbols);
+    }
+}
+
+// Only plain `pub` counts, `pub(crate)` items aren't part of the public API.
+fn is_public(node: Node, code: &[u8]) -> bool {
+    let mut cursor = node.walk();
+    let public = node
+        .children(&mut cursor)
+        .any(|child| child.kind() == "visibility_modifier" && child.utf8_text(code) == Ok("pub"));
+    public
+}
+
+// Macros are exported with `#[macro_export]` rather than `pub`, the attribute
+// is a sibling preceding the definition.
+fn is_macro_exported(node: Node, code: &[u8]) -> bool {
+    let mut sibling = node.prev_sibling();
+    while let Some(node) = sibling {
+        match node.kind() {
+            "attribute_item" => {
+                if node
+                    .utf8_text(code)
+                    .map_or(false, |text| text.contains("macro_export"))
+                {
+                    return true;
+                }
+            }
+            "line_comment" | "block_comment" => {}
+            _ => return false,
+        }
+        sibling = node.prev_sibling();
+    }
+    false
+}
+
+/// Correlates public items of the source's Rust files with mentions in the
+/// collection's other documents, reporting items no document mentions.
+/// Reports are cached until the collection's documents change.
+pub async fn coverage(db: &Db, cache: &CoverageCache, source_id: i64) -> Result<CoverageReport> {
+    let source = db.select_source(source_id).await?;
+    let version = db
+        .select_collection_documents_version(source.collection_id)
+        .await?;
+    if let Some((cached, report)) = cache.0.lock().unwrap().get(&source_id) {
+        if *cached == version {
+            return Ok(report.clone());
+        }
+    }
+
+    let code = db.query_documents_by_source(source_id).await?;
+    let docs = db
+        .query_documents_by_collection(source.collection_id)
+        .await?;
+    let report = tokio::task::spawn_blocking(move || analyze(source_id, &code, &docs))
+        .await
+        .context("Coverage analysis panicked")??;
+    cache
+        .0
+        .lock()
+        .unwrap()
+        .insert(source_id, (version, report.clone()));
+    Ok(report)
+}
+
+fn analyze(source_id: i64, code: &[Document], docs: &[Document]) -> Result<CoverageReport> {
+    let mentions: HashSet<&str> = docs
+        .iter()
+        .filter(|doc| !is_rust(&doc.path))
+        .flat_map(|doc| identifiers(&doc.data))
+        .collect();
+
+    let mut report = CoverageReport {
+        source_id,
+        ..Default::default()
+    };
+    for doc in code.iter().filter(|doc| is_rust(&doc.path)) {
+        let symbols = match public_symbols(&doc.path, &doc.data) {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                tracing::warn!("Failed to parse '{}': {:?}", doc.path, err);
+                continue;
+            }
+        };
+        for symbol in symbols {
+            report.symbols += 1;
+            if mentions.contains(symbol.name.as_str()) {
+                report.documented += 1;
+            } else {
+                report.undocumented.push(symbol);
+            }
+        }
+    }
+    Ok(report)
+}
+
+fn is_rust(path: &str) -> bool {
+    path.ends_with(".rs")
+}
+
+// Identifier-like words of the text, `foo::Bar` yields both `foo` and `Bar`.
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_symbols() {
+        let code = r#"
+pub struct Client;
+struct Private;
+
+impl Client {
+    pub fn connect() {
+        pub fn nested() {}
+    }
+    fn helper() {}
+    pub(crate) fn internal() {}
+}
+
+mod internal {
+    pub fn hidden() {}
+}
+
+pub mod api {
+    pub enum Method { Get }
+}
+
+#[macro_export]
+/// Builds a client.
+macro_rules! client { () => {} }
+
+macro_rules! local { () => {} }
+"#;
+        let names: Vec<(String, String)> = public_symbols("src/lib.rs", code)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.kind, s.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("struct".to_string(), "Client".to_string()),
+                ("fn".to_string(), "connect".to_string()),
+                ("enum".to_string(), "Method".to_string()),
+                ("macro".to_string(), "client".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+/// Opaque pagination token pointing at the last item of the previous page.
+///
+/// Pages continue after the item's position instead of skipping an offset,
+/// so items added or removed concurrently don't shift the following pages.
+/// Listings ordered by id only leave the score at zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub score: f32,
+    pub id: String,
+}
+
+impl Cursor {
+    pub fn new(score: f32, id: impl Into<String>) -> Self {
+        Self {
+            score,
+            id: id.into(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{:08x}:{}", self.score.to_bits(), self.id)
+            .bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        if token.len() % 2 != 0 {
+            return None;
+        }
+        let bytes = (0..token.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(token.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let raw = String::from_utf8(bytes).ok()?;
+        let (score, id) = raw.split_once(':')?;
+        Some(Self {
+            score: f32::from_bits(u32::from_str_radix(score, 16).ok()?),
+            id: id.to_string(),
+        })
+    }
+
+    /// Id of the last item in listings ordered by numeric id.
+    pub fn last_id(&self) -> Option<i64> {
+        self.id.parse().ok()
+    }
+}
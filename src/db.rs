@@ -1,11 +1,24 @@
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
-use std::{collections::HashSet, str::FromStr};
+use futures::{stream::BoxStream, StreamExt};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+};
 
-use crate::types::{Chunk, Document, Source};
+use crate::parser::{is_target_file, TREE_ETAG};
+use crate::secrets::{self, Cipher};
+use crate::types::{
+    AccessToken, Alias, AuditEntry, BrokenLink, BucketOptions, Chunk, Collection, CrawlOptions,
+    Document, DocumentEntry, DocumentRef, GitHubOptions, Heading, IdempotencyRecord, Job, JobKind,
+    JobStatus, Link, LinkCheck, Role, Session, Source, SourceKind, SourceStats,
+};
 
 #[derive(Clone)]
 pub struct Db {
     pub pool: SqlitePool,
+    /// Encrypts stored credentials when a secrets key is configured.
+    cipher: Option<Arc<Cipher>>,
 }
 
 impl Db {
@@ -13,7 +26,80 @@ impl Db {
     pub async fn new(url: &str) -> Result<Self, sqlx::Error> {
         let options = SqliteConnectOptions::from_str(url)?;
         let pool = SqlitePoolOptions::new().connect_with(options).await?;
-        Ok(Self { pool })
+        Ok(Self { pool, cipher: None })
+    }
+
+    /// Encrypts credentials written from now on and decrypts them when read.
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(Arc::new(cipher));
+        self
+    }
+
+    /// Encrypts credentials stored before a secrets key was configured, and
+    /// checks the encrypted ones decrypt with the configured key, so a wrong
+    /// or missing key fails at startup. Returns the number of credentials updated.
+    pub async fn encrypt_secrets(&self) -> Result<u64, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT id as "id!", bucket_secret_access_key, github_token FROM source"#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let unsealed = |value: &str| !value.is_empty() && !secrets::is_encrypted(value);
+        let mut updated = 0;
+        for row in rows {
+            self.open(row.bucket_secret_access_key.clone())?;
+            self.open(row.github_token.clone())?;
+            if self.cipher.is_none() {
+                continue;
+            }
+            if unsealed(&row.bucket_secret_access_key) {
+                let sealed = self.seal(&row.bucket_secret_access_key)?;
+                sqlx::query!(
+                    r#"UPDATE source SET bucket_secret_access_key = ? WHERE id = ?"#,
+                    sealed,
+                    row.id
+                )
+                .execute(&self.pool)
+                .await?;
+                updated += 1;
+            }
+            if unsealed(&row.github_token) {
+                let sealed = self.seal(&row.github_token)?;
+                sqlx::query!(
+                    r#"UPDATE source SET github_token = ? WHERE id = ?"#,
+                    sealed,
+                    row.id
+                )
+                .execute(&self.pool)
+                .await?;
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
+    // Encrypts the credential for storing, if a key is configured.
+    fn seal(&self, value: &str) -> Result<String, sqlx::Error> {
+        match &self.cipher {
+            Some(cipher) => cipher
+                .encrypt(value)
+                .map_err(|err| sqlx::Error::Protocol(err.to_string())),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    // Decrypts a stored credential. Ones that can't be decrypted fail the
+    // read, rather than a fetch going out without its credentials.
+    fn open(&self, value: String) -> Result<String, sqlx::Error> {
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(&value).map_err(|err| {
+                sqlx::Error::Protocol(format!("Failed to decrypt stored credential: {:#}", err))
+            }),
+            None if secrets::is_encrypted(&value) => Err(sqlx::Error::Protocol(
+                "Stored credential is encrypted, but no secrets key is configured".to_string(),
+            )),
+            None => Ok(value),
+        }
     }
 
     /// Runs database migrations from the "./migrations" directory.
@@ -23,67 +109,70 @@ impl Db {
     }
 
     /// Creates a new in-memory database connection for tests.
+    ///
+    /// Every connection gets its own in-memory database, so the pool holds a
+    /// single connection that is never recycled.
     pub async fn new_in_memory() -> Result<Self, sqlx::Error> {
-        Db::new("sqlite::memory:").await
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        Ok(Self { pool, cipher: None })
     }
 
-    pub async fn insert_source(&self, data: &Source) -> Result<(), sqlx::Error> {
-        let allowed_ext = stringify_vec(data.allowed_ext.clone());
-        let allowed_dirs = stringify_vec(data.allowed_dirs.clone());
-        let ignored_dirs = stringify_vec(data.ignored_dirs.clone());
-        sqlx::query!(
+    pub async fn insert_collection(&self, data: &Collection) -> Result<i64, sqlx::Error> {
+        let distance = data.distance.as_str();
+        let dimension = data.dimension as u32;
+        let id = sqlx::query!(
             r#"
-        INSERT INTO source (collection_id, owner, repo, branch, allowed_ext, allowed_dirs, ignored_dirs, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO collection (name, model, distance, dimension, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
         "#,
-            data.collection_id,
-            data.owner,
-            data.repo,
-            data.branch,
-            allowed_ext,
-            allowed_dirs,
-            ignored_dirs,
+            data.name,
+            data.model,
+            distance,
+            dimension,
             data.created_at,
             data.updated_at,
         )
         .execute(&self.pool)
-        .await?;
-        Ok(())
+        .await?
+        .last_insert_rowid();
+        Ok(id)
     }
 
-    pub async fn select_source(&self, id: i64) -> Result<Source, sqlx::Error> {
-        let row = sqlx::query!(r#"SELECT * FROM source WHERE id = ?"#, id)
+    pub async fn select_collection(&self, id: i64) -> Result<Collection, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM collection WHERE id = ?"#, id)
             .fetch_one(&self.pool)
             .await?;
-        Ok(Source {
+        Ok(Collection {
             id: row.id,
-            collection_id: row.collection_id,
-            owner: row.owner,
-            repo: row.repo,
-            branch: row.branch,
-            allowed_ext: row.allowed_ext.split(';').map(|x| x.to_string()).collect(),
-            allowed_dirs: row.allowed_dirs.split(';').map(|x| x.to_string()).collect(),
-            ignored_dirs: row.ignored_dirs.split(';').map(|x| x.to_string()).collect(),
+            name: row.name,
+            model: row.model,
+            distance: row.distance.parse().unwrap_or_default(),
+            dimension: row.dimension as usize,
+            index_version: row.index_version,
             created_at: row.created_at.parse().unwrap_or_default(),
             updated_at: row.updated_at.parse().unwrap_or_default(),
         })
     }
 
-    pub async fn query_sources(&self) -> Result<Vec<Source>, sqlx::Error> {
-        let rows = sqlx::query!(r#" SELECT * FROM source"#)
+    pub async fn query_collections(&self) -> Result<Vec<Collection>, sqlx::Error> {
+        let rows = sqlx::query!(r#"SELECT * FROM collection"#)
             .fetch_all(&self.pool)
             .await?;
         let data = rows
             .into_iter()
-            .map(|row| Source {
+            .map(|row| Collection {
                 id: row.id,
-                collection_id: row.collection_id,
-                owner: row.owner,
-                repo: row.repo,
-                branch: row.branch,
-                allowed_ext: row.allowed_ext.split(';').map(|x| x.to_string()).collect(),
-                allowed_dirs: row.allowed_dirs.split(';').map(|x| x.to_string()).collect(),
-                ignored_dirs: row.ignored_dirs.split(';').map(|x| x.to_string()).collect(),
+                name: row.name,
+                model: row.model,
+                distance: row.distance.parse().unwrap_or_default(),
+                dimension: row.dimension as usize,
+                index_version: row.index_version,
                 created_at: row.created_at.parse().unwrap_or_default(),
                 updated_at: row.updated_at.parse().unwrap_or_default(),
             })
@@ -91,188 +180,2583 @@ impl Db {
         Ok(data)
     }
 
-    pub async fn insert_document(&self, data: &Document) -> Result<(), sqlx::Error> {
-        let tokens_len = data.tokens_len as u32;
+    pub async fn update_collection_name(&self, id: i64, name: &str) -> Result<(), sqlx::Error> {
+        let updated_at = chrono::Utc::now();
         sqlx::query!(
-            r#"
-        INSERT INTO document (source_id, collection_id, path, checksum, tokens_len, data, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-        "#,
-            data.source_id,
-            data.collection_id,
-            data.path,
-            data.checksum,
-            tokens_len,
-            data.data,
-            data.created_at,
-            data.updated_at,
+            r#"UPDATE collection SET name = ?, updated_at = ? WHERE id = ?"#,
+            name,
+            updated_at,
+            id,
         )
         .execute(&self.pool)
         .await?;
         Ok(())
     }
 
-    pub async fn select_document(
+    /// Renames collections at once, e.g. a collection along with its shadows.
+    pub async fn update_collection_names(
         &self,
-        source_id: i64,
-        path: &str,
-    ) -> Result<Document, sqlx::Error> {
-        let row = sqlx::query!(
-            r#"
-            SELECT * FROM document WHERE source_id = ? AND path = ?"#,
-            source_id,
-            path
-        )
-        .fetch_one(&self.pool)
-        .await?;
-        Ok(Document {
-            id: row.id,
-            source_id: row.source_id,
-            collection_id: row.collection_id,
-            path: row.path,
-            checksum: row.checksum as u32,
-            tokens_len: row.tokens_len as usize,
-            data: row.data,
-            created_at: row.created_at.parse().unwrap_or_default(),
-            updated_at: row.updated_at.parse().unwrap_or_default(),
-        })
-    }
-
-    pub async fn insert_documents(&self, docs: &[Document]) -> Result<(), sqlx::Error> {
+        names: &[(i64, String)],
+    ) -> Result<(), sqlx::Error> {
+        let updated_at = chrono::Utc::now();
         let mut tx = self.pool.begin().await?;
-        for data in docs {
-            let tokens = data.tokens_len as u32;
-            sqlx::query!(r#"
-                INSERT INTO document (source_id, collection_id, path, checksum, tokens_len, data, created_at, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-                "#,
-                data.source_id,
-                data.collection_id,
-                data.path,
-                data.checksum,
-                tokens,
-                data.data,
-                data.created_at,
-                data.updated_at,
+        for (id, name) in names {
+            sqlx::query!(
+                r#"UPDATE collection SET name = ?, updated_at = ? WHERE id = ?"#,
+                name,
+                updated_at,
+                id,
             )
             .execute(&mut *tx)
             .await?;
         }
+        tx.commit().await
+    }
+
+    /// Deletes the collection along with its sources, documents, headings,
+    /// links, chunks, candidate vectors, object ETags, aliases and access tokens.
+    pub async fn delete_collection(&self, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        self.delete_collection_with(&mut *tx, id).await?;
         tx.commit().await?;
         Ok(())
     }
 
-    pub async fn query_documents_by_source(
+    async fn delete_collection_with(
         &self,
-        source_id: i64,
-    ) -> Result<Vec<Document>, sqlx::Error> {
-        let mut docs = Vec::new();
-        let rows = sqlx::query!(r#"SELECT * FROM document WHERE source_id = ?"#, source_id)
-            .fetch_all(&self.pool)
+        conn: &mut SqliteConnection,
+        id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM chunk_vector WHERE collection_id = ?"#, id)
+            .execute(&mut *conn)
             .await?;
-        for row in rows {
-            let doc = Document {
-                id: row.id,
-                source_id: row.source_id,
-                collection_id: row.collection_id,
-                path: row.path,
-                checksum: row.checksum as u32,
-                tokens_len: row.tokens_len as usize,
-                data: row.data,
-                created_at: row.created_at.parse().unwrap_or_default(),
-                updated_at: row.updated_at.parse().unwrap_or_default(),
-            };
-            docs.push(doc);
-        }
-        Ok(docs)
-    }
-
-    pub async fn delete_documents_by_source(&self, source_id: i64) -> Result<(), sqlx::Error> {
-        let _ = sqlx::query!(r#"DELETE FROM document WHERE source_id = ?"#, source_id)
-            .execute(&self.pool)
+        sqlx::query!(r#"DELETE FROM chunk WHERE collection_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            r#"DELETE FROM heading WHERE document_id IN (SELECT id FROM document WHERE collection_id = ?)"#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM link WHERE source_id IN (SELECT id FROM source WHERE collection_id = ?)"#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(r#"DELETE FROM document WHERE collection_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            r#"DELETE FROM object_etag WHERE source_id IN (SELECT id FROM source WHERE collection_id = ?)"#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(r#"DELETE FROM source WHERE collection_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(
+            r#"DELETE FROM collection_alias WHERE collection_id = ?"#,
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(r#"DELETE FROM access_token WHERE collection_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(r#"DELETE FROM collection WHERE id = ?"#, id)
+            .execute(&mut *conn)
             .await?;
         Ok(())
     }
 
-    pub async fn insert_chunk(&self, data: &Chunk) -> Result<(), sqlx::Error> {
-        let vector = bincode::serialize(&data.vector).expect("Failed to serialize vector");
-        let chunk_index = data.chunk_index as u32;
+    /// Returns the JSON of the collection's search pipeline, empty if it has
+    /// none, or none if the collection doesn't exist.
+    pub async fn select_ranking(&self, collection: &str) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"SELECT ranking FROM collection WHERE name = ? ORDER BY id LIMIT 1"#,
+            collection
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| row.ranking))
+    }
+
+    /// Stores the JSON of the collection's search pipeline, empty to reset it.
+    pub async fn update_ranking(&self, id: i64, ranking: &str) -> Result<(), sqlx::Error> {
+        let updated_at = chrono::Utc::now();
         sqlx::query!(
-            r#"
-        INSERT INTO chunk (document_id, source_id, collection_id, chunk_index, context, data, vector)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
-        "#,
-            data.document_id,
-            data.source_id,
-            data.collection_id,
-            chunk_index,
-            data.context,
-            data.data,
-            vector,
+            r#"UPDATE collection SET ranking = ?, updated_at = ? WHERE id = ?"#,
+            ranking,
+            updated_at,
+            id,
         )
         .execute(&self.pool)
         .await?;
         Ok(())
     }
 
-    pub async fn query_chunks_by_source(&self, source_id: i64) -> Result<Vec<Chunk>, sqlx::Error> {
-        let mut chunks = Vec::new();
-        let rows = sqlx::query!(r#" SELECT * FROM chunk WHERE source_id = ?"#, source_id)
-            .fetch_all(&self.pool)
-            .await?;
-        for row in rows {
-            let vector: Vec<f32> =
-                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
-            chunks.push(Chunk {
-                id: row.id,
-                document_id: row.document_id,
-                source_id: row.source_id,
-                collection_id: row.collection_id,
-                chunk_index: row.chunk_index as usize,
-                context: row.context,
-                data: row.data,
-                vector,
-            });
-        }
-        Ok(chunks)
+    /// Increments the collection's index version, returns the new one.
+    pub async fn bump_index_version(&self, id: i64) -> Result<i64, sqlx::Error> {
+        let updated_at = chrono::Utc::now();
+        let row = sqlx::query!(
+            r#"UPDATE collection SET index_version = index_version + 1, updated_at = ? WHERE id = ? RETURNING index_version"#,
+            updated_at,
+            id,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.index_version)
     }
 
-    pub async fn query_chunks_by_collection(
-        &self,
-        collection_id: i64,
-    ) -> Result<Vec<Chunk>, sqlx::Error> {
-        let mut chunks = Vec::new();
-        let rows = sqlx::query!(
-            r#" SELECT * FROM chunk WHERE collection_id = ?"#,
-            collection_id
+    /// Copies the collection with its sources, documents, headings, links,
+    /// chunks and candidate vectors into a new collection named `name`.
+    /// Returns the new collection's id.
+    pub async fn clone_collection(&self, id: i64, name: &str) -> Result<i64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let now = chrono::Utc::now();
+        let clone_id = sqlx::query!(
+            r#"
+        INSERT INTO collection (name, model, distance, dimension, ranking, created_at, updated_at)
+        SELECT ?, model, distance, dimension, ranking, ?, ? FROM collection WHERE id = ?
+        "#,
+            name,
+            now,
+            now,
+            id,
         )
-        .fetch_all(&self.pool)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
+        // Copies get ids past the ones ever handed out, in the order of the
+        // originals, so ids of copied rows are derived from their original's
+        // rank instead of being looked up row by row.
+        let bases = sqlx::query!(
+            r#"
+        SELECT
+            MAX(COALESCE((SELECT MAX(id) FROM source), 0), COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'source'), 0)) as "source!: i64",
+            MAX(COALESCE((SELECT MAX(id) FROM document), 0), COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'document'), 0)) as "document!: i64",
+            MAX(COALESCE((SELECT MAX(id) FROM chunk), 0), COALESCE((SELECT seq FROM sqlite_sequence WHERE name = 'chunk'), 0)) as "chunk!: i64"
+        "#
+        )
+        .fetch_one(&mut *tx)
         .await?;
-        for row in rows {
-            let vector: Vec<f32> =
-                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
-            chunks.push(Chunk {
-                id: row.id,
-                document_id: row.document_id,
-                source_id: row.source_id,
-                collection_id: row.collection_id,
-                chunk_index: row.chunk_index as usize,
-                context: row.context,
-                data: row.data,
-                vector,
-            });
-        }
-        Ok(chunks)
-    }
 
-    pub async fn delete_chunks_by_source(&self, source_id: i64) -> Result<(), sqlx::Error> {
-        let _ = sqlx::query!(r#"DELETE FROM chunk WHERE source_id = ?"#, source_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+        sqlx::query!(
+            r#"
+        WITH s AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM source WHERE collection_id = ?)
+        INSERT INTO source (id, collection_id, kind, url, owner, repo, branch, allowed_ext, allowed_dirs, ignored_dirs, skip_front_matter, labels, translations, repo_metadata,
+            crawl_max_depth, crawl_max_pages, crawl_include, crawl_exclude, crawl_strip_query, crawl_dedup,
+            bucket_region, bucket_prefix, bucket_access_key_id, bucket_secret_access_key, github_token, github_submodules,
+            created_at, updated_at)
+        SELECT s.new_id, ?, kind, url, owner, repo, branch, allowed_ext, allowed_dirs, ignored_dirs, skip_front_matter, labels, translations, repo_metadata,
+            crawl_max_depth, crawl_max_pages, crawl_include, crawl_exclude, crawl_strip_query, crawl_dedup,
+            bucket_region, bucket_prefix, bucket_access_key_id, bucket_secret_access_key, github_token, github_submodules, ?, ?
+        FROM source JOIN s ON s.id = source.id
+        "#,
+            bases.source,
+            id,
+            clone_id,
+            now,
+            now,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        WITH s AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM source WHERE collection_id = ?)
+        INSERT INTO object_etag (source_id, path, etag)
+        SELECT s.new_id, path, etag FROM object_etag JOIN s ON s.id = object_etag.source_id
+        "#,
+            bases.source,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+        WITH s AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM source WHERE collection_id = ?),
+            d AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM document WHERE collection_id = ?)
+        INSERT INTO document (id, source_id, collection_id, path, checksum, tokens_len, data, summary, format, alternate_paths, nav_path, nav_index, language, canonical_path, url, created_at, updated_at)
+        SELECT d.new_id, s.new_id, ?, path, checksum, tokens_len, data, summary, format, alternate_paths, nav_path, nav_index, language, canonical_path, url, created_at, updated_at
+        FROM document JOIN d ON d.id = document.id JOIN s ON s.id = document.source_id
+        "#,
+            bases.source,
+            id,
+            bases.document,
+            id,
+            clone_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        WITH d AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM document WHERE collection_id = ?)
+        INSERT INTO heading (document_id, position, level, text, anchor)
+        SELECT d.new_id, position, level, text, anchor FROM heading JOIN d ON d.id = heading.document_id
+        "#,
+            bases.document,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        WITH s AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM source WHERE collection_id = ?),
+            d AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM document WHERE collection_id = ?)
+        INSERT INTO link (document_id, source_id, url, target_path)
+        SELECT d.new_id, s.new_id, url, target_path
+        FROM link JOIN d ON d.id = link.document_id JOIN document ON document.id = link.document_id JOIN s ON s.id = document.source_id
+        "#,
+            bases.source,
+            id,
+            bases.document,
+            id,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+        WITH s AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM source WHERE collection_id = ?),
+            d AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM document WHERE collection_id = ?),
+            c AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM chunk WHERE collection_id = ?)
+        INSERT INTO chunk (id, document_id, source_id, collection_id, chunk_index, context, data, vector, tags, anchor, language, model, model_version, dimension)
+        SELECT c.new_id, d.new_id, s.new_id, ?, chunk_index, context, data, vector, tags, anchor, language, model, model_version, dimension
+        FROM chunk JOIN c ON c.id = chunk.id JOIN d ON d.id = chunk.document_id JOIN s ON s.id = chunk.source_id
+        "#,
+            bases.source,
+            id,
+            bases.document,
+            id,
+            bases.chunk,
+            id,
+            clone_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        WITH c AS (SELECT id, ? + ROW_NUMBER() OVER (ORDER BY id) AS new_id FROM chunk WHERE collection_id = ?)
+        INSERT INTO chunk_vector (chunk_id, collection_id, model, vector)
+        SELECT c.new_id, ?, model, vector FROM chunk_vector JOIN c ON c.id = chunk_vector.chunk_id
+        "#,
+            bases.chunk,
+            id,
+            clone_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(clone_id)
     }
-}
 
-fn stringify_vec(vec: HashSet<String>) -> String {
+    /// Moves the sources of collection `from` into `into`, along with their
+    /// documents, chunks and candidate vectors. Returns the number of moved sources.
+    pub async fn merge_collection(&self, from: i64, into: i64) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let moved = sqlx::query!(
+            r#"UPDATE source SET collection_id = ? WHERE collection_id = ?"#,
+            into,
+            from
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        sqlx::query!(
+            r#"UPDATE document SET collection_id = ? WHERE collection_id = ?"#,
+            into,
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"UPDATE chunk SET collection_id = ? WHERE collection_id = ?"#,
+            into,
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"UPDATE chunk_vector SET collection_id = ? WHERE collection_id = ?"#,
+            into,
+            from
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    /// Replaces vectors of the collection's chunks and switches the collection to the new model.
+    pub async fn update_collection_vectors(
+        &self,
+        collection_id: i64,
+        model: &str,
+        model_version: &str,
+        dimension: usize,
+        vectors: &[(i64, Vec<f32>)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let dimension = dimension as u32;
+        for (chunk_id, vector) in vectors {
+            let vector = bincode::serialize(vector).expect("Failed to serialize vector");
+            sqlx::query!(
+                r#"UPDATE chunk SET vector = ?, model = ?, model_version = ?, dimension = ? WHERE id = ? AND collection_id = ?"#,
+                vector,
+                model,
+                model_version,
+                dimension,
+                chunk_id,
+                collection_id,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        let updated_at = chrono::Utc::now();
+        sqlx::query!(
+            r#"UPDATE collection SET model = ?, dimension = ?, updated_at = ? WHERE id = ?"#,
+            model,
+            dimension,
+            updated_at,
+            collection_id,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn insert_source(&self, data: &Source) -> Result<i64, sqlx::Error> {
+        let mut conn = self.pool.acquire().await?;
+        self.insert_source_with(&mut *conn, data).await
+    }
+
+    /// Inserts all sources or, if any fails, none of them. Returns their ids
+    /// in the order of the sources, or the ids of their collections that
+    /// don't exist, checked in the same transaction.
+    pub async fn insert_sources(
+        &self,
+        sources: &[Source],
+    ) -> Result<Result<Vec<i64>, HashSet<i64>>, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut missing = HashSet::new();
+        let collection_ids: HashSet<i64> = sources.iter().map(|s| s.collection_id).collect();
+        for collection_id in collection_ids {
+            let row = sqlx::query!(
+                r#"SELECT id as "id!" FROM collection WHERE id = ?"#,
+                collection_id
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+            if row.is_none() {
+                missing.insert(collection_id);
+            }
+        }
+        if !missing.is_empty() {
+            return Ok(Err(missing));
+        }
+        let mut ids = Vec::with_capacity(sources.len());
+        for source in sources {
+            ids.push(self.insert_source_with(&mut *tx, source).await?);
+        }
+        tx.commit().await?;
+        Ok(Ok(ids))
+    }
+
+    /// Inserts sources of the repo's packages and adds their directories to
+    /// the ignored dirs of the repo's own source, so their files are indexed
+    /// once. Returns the ids of the inserted sources and the number of the repo
+    /// source's documents deleted by its new filters.
+    pub async fn insert_package_sources(
+        &self,
+        parent: &Source,
+        packages: &[Source],
+    ) -> Result<(Vec<i64>, usize), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut ids = Vec::with_capacity(packages.len());
+        let mut parent = parent.clone();
+        for package in packages {
+            ids.push(self.insert_source_with(&mut *tx, package).await?);
+            parent
+                .ignored_dirs
+                .extend(package.allowed_dirs.iter().cloned());
+        }
+        let deleted = self.update_source_with(&mut *tx, &parent).await?;
+        tx.commit().await?;
+        Ok((ids, deleted))
+    }
+
+    async fn insert_source_with(
+        &self,
+        conn: &mut SqliteConnection,
+        data: &Source,
+    ) -> Result<i64, sqlx::Error> {
+        let allowed_ext = stringify_vec(data.allowed_ext.clone());
+        let allowed_dirs = stringify_vec(data.allowed_dirs.clone());
+        let ignored_dirs = stringify_vec(data.ignored_dirs.clone());
+        let skip_front_matter = stringify_vec(data.skip_front_matter.clone());
+        let labels = stringify_vec(data.labels.clone());
+        let kind = data.kind.as_str();
+        let translations = data.translations.as_str();
+        let crawl_max_depth = data.crawl.max_depth as i64;
+        let crawl_max_pages = data.crawl.max_pages as i64;
+        // Regexes may contain `;`, so patterns are kept one per line.
+        let crawl_include = data.crawl.include.join("\n");
+        let crawl_exclude = data.crawl.exclude.join("\n");
+        let crawl_strip_query = data.crawl.strip_query as i64;
+        let crawl_dedup = data.crawl.dedup as i64;
+        let secret_access_key = self.seal(&data.bucket.secret_access_key)?;
+        let github_token = self.seal(&data.github.token)?;
+        let github_submodules = data.github.submodules as i64;
+        let repo_metadata = serde_json::to_string(&data.metadata)
+            .map_err(|err| sqlx::Error::Protocol(err.to_string()))?;
+        let id = sqlx::query!(
+            r#"
+        INSERT INTO source (collection_id, kind, url, owner, repo, branch, allowed_ext, allowed_dirs, ignored_dirs, skip_front_matter, labels, translations, repo_metadata,
+            crawl_max_depth, crawl_max_pages, crawl_include, crawl_exclude, crawl_strip_query, crawl_dedup,
+            bucket_region, bucket_prefix, bucket_access_key_id, bucket_secret_access_key, github_token, github_submodules,
+            created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+            data.collection_id,
+            kind,
+            data.url,
+            data.owner,
+            data.repo,
+            data.branch,
+            allowed_ext,
+            allowed_dirs,
+            ignored_dirs,
+            skip_front_matter,
+            labels,
+            translations,
+            repo_metadata,
+            crawl_max_depth,
+            crawl_max_pages,
+            crawl_include,
+            crawl_exclude,
+            crawl_strip_query,
+            crawl_dedup,
+            data.bucket.region,
+            data.bucket.prefix,
+            data.bucket.access_key_id,
+            secret_access_key,
+            github_token,
+            github_submodules,
+            data.created_at,
+            data.updated_at,
+        )
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Updates the branch and filters of the source and deletes documents of
+    /// files the filters no longer match. The tree's ETag is dropped, so the
+    /// next parse lists the tree again instead of getting a 304 for it, the
+    /// files' ETags are kept, so it still finds the removed ones. Returns the
+    /// number of deleted documents.
+    pub async fn update_source(&self, data: &Source) -> Result<usize, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = self.update_source_with(&mut *tx, data).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn update_source_with(
+        &self,
+        conn: &mut SqliteConnection,
+        data: &Source,
+    ) -> Result<usize, sqlx::Error> {
+        let allowed_ext = stringify_vec(data.allowed_ext.clone());
+        let allowed_dirs = stringify_vec(data.allowed_dirs.clone());
+        let ignored_dirs = stringify_vec(data.ignored_dirs.clone());
+        let updated_at = chrono::Utc::now();
+        sqlx::query!(
+            r#"
+        UPDATE source SET branch = ?, allowed_ext = ?, allowed_dirs = ?, ignored_dirs = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+            data.branch,
+            allowed_ext,
+            allowed_dirs,
+            ignored_dirs,
+            updated_at,
+            data.id,
+        )
+        .execute(&mut *conn)
+        .await?;
+        sqlx::query!(
+            r#"DELETE FROM object_etag WHERE source_id = ? AND path = ?"#,
+            data.id,
+            TREE_ETAG
+        )
+        .execute(&mut *conn)
+        .await?;
+        // Filters only apply to the paths of repos.
+        let mut deleted = 0;
+        if data.kind == SourceKind::GitHub {
+            let documents = sqlx::query!(
+                r#"SELECT id as "id!", path FROM document WHERE source_id = ?"#,
+                data.id
+            )
+            .fetch_all(&mut *conn)
+            .await?;
+            for document in documents {
+                if is_target_file(data, &document.path) {
+                    continue;
+                }
+                self.delete_document_with(&mut *conn, document.id).await?;
+                // Otherwise the file is skipped as unchanged once it matches again.
+                sqlx::query!(
+                    r#"DELETE FROM object_etag WHERE source_id = ? AND path = ?"#,
+                    data.id,
+                    document.path
+                )
+                .execute(&mut *conn)
+                .await?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
+    pub async fn select_source(&self, id: i64) -> Result<Source, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM source WHERE id = ?"#, id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Source {
+            id: row.id,
+            collection_id: row.collection_id,
+            kind: row.kind.parse().unwrap_or_default(),
+            url: row.url,
+            owner: row.owner,
+            repo: row.repo,
+            branch: row.branch,
+            allowed_ext: parse_tags(&row.allowed_ext).into_iter().collect(),
+            allowed_dirs: parse_tags(&row.allowed_dirs).into_iter().collect(),
+            ignored_dirs: parse_tags(&row.ignored_dirs).into_iter().collect(),
+            skip_front_matter: parse_tags(&row.skip_front_matter).into_iter().collect(),
+            labels: parse_tags(&row.labels).into_iter().collect(),
+            translations: row.translations.parse().unwrap_or_default(),
+            crawl: CrawlOptions {
+                max_depth: row.crawl_max_depth as usize,
+                max_pages: row.crawl_max_pages as usize,
+                include: parse_patterns(&row.crawl_include),
+                exclude: parse_patterns(&row.crawl_exclude),
+                strip_query: row.crawl_strip_query != 0,
+                dedup: row.crawl_dedup != 0,
+            },
+            bucket: BucketOptions {
+                region: row.bucket_region,
+                prefix: row.bucket_prefix,
+                access_key_id: row.bucket_access_key_id,
+                secret_access_key: self.open(row.bucket_secret_access_key)?,
+            },
+            github: GitHubOptions {
+                token: self.open(row.github_token)?,
+                submodules: row.github_submodules != 0,
+            },
+            metadata: serde_json::from_str(&row.repo_metadata).unwrap_or_default(),
+            created_at: row.created_at.parse().unwrap_or_default(),
+            updated_at: row.updated_at.parse().unwrap_or_default(),
+        })
+    }
+
+    pub async fn query_sources(&self) -> Result<Vec<Source>, sqlx::Error> {
+        let rows = sqlx::query!(r#" SELECT * FROM source"#)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|row| {
+                Ok(Source {
+                    id: row.id,
+                    collection_id: row.collection_id,
+                    kind: row.kind.parse().unwrap_or_default(),
+                    url: row.url,
+                    owner: row.owner,
+                    repo: row.repo,
+                    branch: row.branch,
+                    allowed_ext: parse_tags(&row.allowed_ext).into_iter().collect(),
+                    allowed_dirs: parse_tags(&row.allowed_dirs).into_iter().collect(),
+                    ignored_dirs: parse_tags(&row.ignored_dirs).into_iter().collect(),
+                    skip_front_matter: parse_tags(&row.skip_front_matter).into_iter().collect(),
+                    labels: parse_tags(&row.labels).into_iter().collect(),
+                    translations: row.translations.parse().unwrap_or_default(),
+                    crawl: CrawlOptions {
+                        max_depth: row.crawl_max_depth as usize,
+                        max_pages: row.crawl_max_pages as usize,
+                        include: parse_patterns(&row.crawl_include),
+                        exclude: parse_patterns(&row.crawl_exclude),
+                        strip_query: row.crawl_strip_query != 0,
+                        dedup: row.crawl_dedup != 0,
+                    },
+                    bucket: BucketOptions {
+                        region: row.bucket_region,
+                        prefix: row.bucket_prefix,
+                        access_key_id: row.bucket_access_key_id,
+                        secret_access_key: self.open(row.bucket_secret_access_key)?,
+                    },
+                    github: GitHubOptions {
+                        token: self.open(row.github_token)?,
+                        submodules: row.github_submodules != 0,
+                    },
+                    metadata: serde_json::from_str(&row.repo_metadata).unwrap_or_default(),
+                    created_at: row.created_at.parse().unwrap_or_default(),
+                    updated_at: row.updated_at.parse().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    pub async fn select_source_stats(&self, source_id: i64) -> Result<SourceStats, sqlx::Error> {
+        let docs = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "documents: i64", COALESCE(SUM(tokens_len), 0) as "tokens: i64"
+            FROM document WHERE source_id = ?
+            "#,
+            source_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let chunks = sqlx::query!(
+            r#"SELECT COUNT(*) as "chunks: i64" FROM chunk WHERE source_id = ?"#,
+            source_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let parse = JobKind::Parse.as_str();
+        let sync = JobKind::Sync.as_str();
+        let succeeded = JobStatus::Succeeded.as_str();
+        let parsed = sqlx::query!(
+            r#"
+            SELECT parsed_at, (
+                SELECT result FROM job
+                WHERE job.source_id = source.id AND kind IN (?, ?) AND status = ?
+                ORDER BY job.id DESC LIMIT 1
+            ) as "report?: String"
+            FROM source WHERE id = ?
+            "#,
+            parse,
+            sync,
+            succeeded,
+            source_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        let (parsed_at, report) = parsed
+            .map(|row| (row.parsed_at, row.report))
+            .unwrap_or_default();
+        Ok(source_stats(
+            docs.documents,
+            chunks.chunks,
+            docs.tokens,
+            parsed_at,
+            report,
+        ))
+    }
+
+    /// Totals of every source by its id, aggregated in one query.
+    pub async fn query_source_stats(&self) -> Result<HashMap<i64, SourceStats>, sqlx::Error> {
+        let parse = JobKind::Parse.as_str();
+        let sync = JobKind::Sync.as_str();
+        let succeeded = JobStatus::Succeeded.as_str();
+        let rows = sqlx::query!(
+            r#"
+            SELECT source.id as "id!", source.parsed_at, (
+                    SELECT result FROM job
+                    WHERE job.source_id = source.id AND kind IN (?, ?) AND status = ?
+                    ORDER BY job.id DESC LIMIT 1
+                ) as "report?: String",
+                COALESCE(docs.documents, 0) as "documents!: i64",
+                COALESCE(docs.tokens, 0) as "tokens!: i64",
+                COALESCE(chunks.chunks, 0) as "chunks!: i64"
+            FROM source
+            LEFT JOIN (
+                SELECT source_id, COUNT(*) as documents, SUM(tokens_len) as tokens
+                FROM document GROUP BY source_id
+            ) docs ON docs.source_id = source.id
+            LEFT JOIN (
+                SELECT source_id, COUNT(*) as chunks FROM chunk GROUP BY source_id
+            ) chunks ON chunks.source_id = source.id
+            "#,
+            parse,
+            sync,
+            succeeded
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let stats = source_stats(
+                    row.documents,
+                    row.chunks,
+                    row.tokens,
+                    row.parsed_at,
+                    row.report,
+                );
+                (row.id, stats)
+            })
+            .collect())
+    }
+
+    /// Records when the source was parsed.
+    pub async fn update_source_parsed_at(
+        &self,
+        id: i64,
+        parsed_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"UPDATE source SET parsed_at = ? WHERE id = ?"#,
+            parsed_at,
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the source along with its documents, headings, links, chunks,
+    /// candidate vectors and object ETags, and the shadow collection holding
+    /// its rollback if given, in one transaction.
+    pub async fn delete_source(&self, id: i64, shadow_id: Option<i64>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(shadow_id) = shadow_id {
+            self.delete_collection_with(&mut *tx, shadow_id).await?;
+        }
+        sqlx::query!(
+            r#"DELETE FROM chunk_vector WHERE chunk_id IN (SELECT id FROM chunk WHERE source_id = ?)"#,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(r#"DELETE FROM chunk WHERE source_id = ?"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            r#"DELETE FROM heading WHERE document_id IN (SELECT id FROM document WHERE source_id = ?)"#,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(r#"DELETE FROM link WHERE source_id = ?"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM document WHERE source_id = ?"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM object_etag WHERE source_id = ?"#, id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(r#"DELETE FROM source WHERE id = ?"#, id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Swaps the documents, links, chunks, candidate vectors and object ETags
+    /// of two sources, which may belong to different collections. Headings
+    /// follow their documents.
+    pub async fn swap_source_contents(&self, a: i64, b: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let a_collection = sqlx::query!(r#"SELECT collection_id FROM source WHERE id = ?"#, a)
+            .fetch_one(&mut *tx)
+            .await?
+            .collection_id;
+        let b_collection = sqlx::query!(r#"SELECT collection_id FROM source WHERE id = ?"#, b)
+            .fetch_one(&mut *tx)
+            .await?
+            .collection_id;
+        sqlx::query!(
+            r#"
+        UPDATE chunk_vector SET collection_id = CASE
+            WHEN chunk_id IN (SELECT id FROM chunk WHERE source_id = ?) THEN ? ELSE ? END
+        WHERE chunk_id IN (SELECT id FROM chunk WHERE source_id IN (?, ?))
+        "#,
+            a,
+            b_collection,
+            a_collection,
+            a,
+            b,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        UPDATE chunk SET
+            collection_id = CASE source_id WHEN ? THEN ? ELSE ? END,
+            source_id = CASE source_id WHEN ? THEN ? ELSE ? END
+        WHERE source_id IN (?, ?)
+        "#,
+            a,
+            b_collection,
+            a_collection,
+            a,
+            b,
+            a,
+            a,
+            b,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+        UPDATE document SET
+            collection_id = CASE source_id WHEN ? THEN ? ELSE ? END,
+            source_id = CASE source_id WHEN ? THEN ? ELSE ? END
+        WHERE source_id IN (?, ?)
+        "#,
+            a,
+            b_collection,
+            a_collection,
+            a,
+            b,
+            a,
+            a,
+            b,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"UPDATE link SET source_id = CASE source_id WHEN ? THEN ? ELSE ? END WHERE source_id IN (?, ?)"#,
+            a,
+            b,
+            a,
+            a,
+            b,
+        )
+        .execute(&mut *tx)
+        .await?;
+        // Paths are unique per source, so ETags are moved through a placeholder id.
+        let placeholder = -a;
+        for (from, to) in [(a, placeholder), (b, a), (placeholder, b)] {
+            sqlx::query!(
+                r#"UPDATE object_etag SET source_id = ? WHERE source_id = ?"#,
+                to,
+                from
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn insert_document(&self, data: &Document) -> Result<i64, sqlx::Error> {
+        let tokens_len = data.tokens_len as u32;
+        let format = data.format.as_str();
+        let alternate_paths = data.alternate_paths.join("\n");
+        let nav_index = data.nav_index.map(|index| index as i64);
+        let id = sqlx::query!(
+            r#"
+        INSERT INTO document (source_id, collection_id, path, checksum, tokens_len, data, format, alternate_paths, nav_path, nav_index, language, canonical_path, url, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+            data.source_id,
+            data.collection_id,
+            data.path,
+            data.checksum,
+            tokens_len,
+            data.data,
+            format,
+            alternate_paths,
+            data.nav_path,
+            nav_index,
+            data.language,
+            data.canonical_path,
+            data.url,
+            data.created_at,
+            data.updated_at,
+        )
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    pub async fn select_document_by_id(&self, id: i64) -> Result<Document, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM document WHERE id = ?"#, id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Document {
+            id: row.id,
+            source_id: row.source_id,
+            collection_id: row.collection_id,
+            path: row.path,
+            checksum: row.checksum as u32,
+            tokens_len: row.tokens_len as usize,
+            data: row.data,
+            format: row.format.parse().unwrap_or_default(),
+            summary: row.summary,
+            alternate_paths: parse_patterns(&row.alternate_paths),
+            nav_path: row.nav_path,
+            nav_index: row.nav_index.map(|index| index as usize),
+            language: row.language,
+            canonical_path: row.canonical_path,
+            url: row.url,
+            created_at: row.created_at.parse().unwrap_or_default(),
+            updated_at: row.updated_at.parse().unwrap_or_default(),
+        })
+    }
+
+    pub async fn select_document(
+        &self,
+        source_id: i64,
+        path: &str,
+    ) -> Result<Document, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT * FROM document WHERE source_id = ? AND path = ?"#,
+            source_id,
+            path
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Document {
+            id: row.id,
+            source_id: row.source_id,
+            collection_id: row.collection_id,
+            path: row.path,
+            checksum: row.checksum as u32,
+            tokens_len: row.tokens_len as usize,
+            data: row.data,
+            format: row.format.parse().unwrap_or_default(),
+            summary: row.summary,
+            alternate_paths: parse_patterns(&row.alternate_paths),
+            nav_path: row.nav_path,
+            nav_index: row.nav_index.map(|index| index as usize),
+            language: row.language,
+            canonical_path: row.canonical_path,
+            url: row.url,
+            created_at: row.created_at.parse().unwrap_or_default(),
+            updated_at: row.updated_at.parse().unwrap_or_default(),
+        })
+    }
+
+    pub async fn insert_documents(&self, docs: &[Document]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for data in docs {
+            let tokens = data.tokens_len as u32;
+            let format = data.format.as_str();
+            let alternate_paths = data.alternate_paths.join("\n");
+            let nav_index = data.nav_index.map(|index| index as i64);
+            sqlx::query!(r#"
+                INSERT INTO document (source_id, collection_id, path, checksum, tokens_len, data, format, alternate_paths, nav_path, nav_index, language, canonical_path, url, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                data.source_id,
+                data.collection_id,
+                data.path,
+                data.checksum,
+                tokens,
+                data.data,
+                format,
+                alternate_paths,
+                data.nav_path,
+                nav_index,
+                data.language,
+                data.canonical_path,
+                data.url,
+                data.created_at,
+                data.updated_at,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn query_documents_by_source(
+        &self,
+        source_id: i64,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let mut docs = Vec::new();
+        let rows = sqlx::query!(r#"SELECT * FROM document WHERE source_id = ?"#, source_id)
+            .fetch_all(&self.pool)
+            .await?;
+        for row in rows {
+            let doc = Document {
+                id: row.id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                path: row.path,
+                checksum: row.checksum as u32,
+                tokens_len: row.tokens_len as usize,
+                data: row.data,
+                format: row.format.parse().unwrap_or_default(),
+                summary: row.summary,
+                alternate_paths: parse_patterns(&row.alternate_paths),
+                nav_path: row.nav_path,
+                nav_index: row.nav_index.map(|index| index as usize),
+                language: row.language,
+                canonical_path: row.canonical_path,
+                url: row.url,
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            };
+            docs.push(doc);
+        }
+        Ok(docs)
+    }
+
+    pub async fn query_documents_by_collection(
+        &self,
+        collection_id: i64,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM document WHERE collection_id = ?"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Document {
+                id: row.id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                path: row.path,
+                checksum: row.checksum as u32,
+                tokens_len: row.tokens_len as usize,
+                data: row.data,
+                format: row.format.parse().unwrap_or_default(),
+                summary: row.summary,
+                alternate_paths: parse_patterns(&row.alternate_paths),
+                nav_path: row.nav_path,
+                nav_index: row.nav_index.map(|index| index as usize),
+                language: row.language,
+                canonical_path: row.canonical_path,
+                url: row.url,
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Returns entries of all documents with their repos, most recently updated first.
+    pub async fn query_document_entries(&self) -> Result<Vec<DocumentEntry>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.path, d.updated_at, s.owner, s.repo, s.branch
+            FROM document d JOIN source s ON s.id = d.source_id
+            ORDER BY d.updated_at DESC, d.id DESC
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| DocumentEntry {
+                id: row.id,
+                path: row.path,
+                owner: row.owner,
+                repo: row.repo,
+                branch: row.branch,
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Returns paths of all documents in the collection keyed by document id.
+    /// Returns paths, nav entries and links of the collection's documents keyed
+    /// by id.
+    pub async fn query_document_refs_by_collection(
+        &self,
+        collection_id: i64,
+    ) -> Result<HashMap<i64, DocumentRef>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT id, path, nav_path, nav_index, url FROM document WHERE collection_id = ?"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let document = DocumentRef {
+                    path: row.path,
+                    nav_path: row.nav_path,
+                    nav_index: row.nav_index.map(|index| index as usize),
+                    url: row.url,
+                };
+                (row.id, document)
+            })
+            .collect())
+    }
+
+    /// Returns checksums of the source's documents keyed by path.
+    pub async fn query_document_checksums(
+        &self,
+        source_id: i64,
+    ) -> Result<HashMap<String, u32>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT path, checksum FROM document WHERE source_id = ?"#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.path, row.checksum as u32))
+            .collect())
+    }
+
+    /// Returns ids of the source's documents that have chunks.
+    pub async fn query_encoded_document_ids(
+        &self,
+        source_id: i64,
+    ) -> Result<HashSet<i64>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT document_id FROM chunk WHERE source_id = ?"#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.document_id).collect())
+    }
+
+    /// Returns the collection's documents that contain the text anywhere.
+    pub async fn query_documents_containing(
+        &self,
+        collection_id: i64,
+        text: &str,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM document WHERE collection_id = ? AND instr(data, ?) > 0"#,
+            collection_id,
+            text
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let docs = rows
+            .into_iter()
+            .map(|row| Document {
+                id: row.id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                path: row.path,
+                checksum: row.checksum as u32,
+                tokens_len: row.tokens_len as usize,
+                data: row.data,
+                format: row.format.parse().unwrap_or_default(),
+                summary: row.summary,
+                alternate_paths: parse_patterns(&row.alternate_paths),
+                nav_path: row.nav_path,
+                nav_index: row.nav_index.map(|index| index as usize),
+                language: row.language,
+                canonical_path: row.canonical_path,
+                url: row.url,
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+            .collect();
+        Ok(docs)
+    }
+
+    /// Returns up to `limit` documents of the source with ids greater than
+    /// `after_id`, skipping `offset` of them. Their data is left empty.
+    pub async fn query_documents_page(
+        &self,
+        source_id: i64,
+        after_id: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Document>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+        SELECT id, source_id, collection_id, path, checksum, tokens_len, format, summary, alternate_paths,
+            nav_path, nav_index, language, canonical_path, url, created_at, updated_at
+        FROM document WHERE source_id = ? AND id > ? ORDER BY id LIMIT ? OFFSET ?
+        "#,
+            source_id,
+            after_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let docs = rows
+            .into_iter()
+            .map(|row| Document {
+                id: row.id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                path: row.path,
+                checksum: row.checksum as u32,
+                tokens_len: row.tokens_len as usize,
+                data: String::new(),
+                format: row.format.parse().unwrap_or_default(),
+                summary: row.summary,
+                alternate_paths: parse_patterns(&row.alternate_paths),
+                nav_path: row.nav_path,
+                nav_index: row.nav_index.map(|index| index as usize),
+                language: row.language,
+                canonical_path: row.canonical_path,
+                url: row.url,
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+            .collect();
+        Ok(docs)
+    }
+
+    /// Streams all of the source's documents without their data, ordered by
+    /// id, for exports too large to hold in memory.
+    pub fn stream_documents(&self, source_id: i64) -> BoxStream<'_, Result<Document, sqlx::Error>> {
+        sqlx::query!(
+            r#"
+        SELECT id, source_id, collection_id, path, checksum, tokens_len, format, summary, alternate_paths,
+            nav_path, nav_index, language, canonical_path, url, created_at, updated_at
+        FROM document WHERE source_id = ? ORDER BY id
+        "#,
+            source_id
+        )
+        .fetch(&self.pool)
+        .map(|row| {
+            row.map(|row| Document {
+                id: row.id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                path: row.path,
+                checksum: row.checksum as u32,
+                tokens_len: row.tokens_len as usize,
+                data: String::new(),
+                format: row.format.parse().unwrap_or_default(),
+                summary: row.summary,
+                alternate_paths: parse_patterns(&row.alternate_paths),
+                nav_path: row.nav_path,
+                nav_index: row.nav_index.map(|index| index as usize),
+                language: row.language,
+                canonical_path: row.canonical_path,
+                url: row.url,
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+        })
+        .boxed()
+    }
+
+    pub async fn update_document_summary(
+        &self,
+        document_id: i64,
+        summary: &str,
+    ) -> Result<(), sqlx::Error> {
+        let updated_at = chrono::Utc::now();
+        sqlx::query!(
+            r#"UPDATE document SET summary = ?, updated_at = ? WHERE id = ?"#,
+            summary,
+            updated_at,
+            document_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Fingerprint of the source's documents, changes whenever any of them does.
+    pub async fn select_documents_version(&self, source_id: i64) -> Result<String, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count: i64", COALESCE(SUM(checksum), 0) as "checksums: i64",
+                MAX(updated_at) as "updated_at: String"
+            FROM document WHERE source_id = ?
+            "#,
+            source_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(format!(
+            "{}:{}:{}",
+            row.count,
+            row.checksums,
+            row.updated_at.unwrap_or_default()
+        ))
+    }
+
+    /// Fingerprint of the documents of all of the collection's sources.
+    pub async fn select_collection_documents_version(
+        &self,
+        collection_id: i64,
+    ) -> Result<String, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT COUNT(*) as "count: i64", COALESCE(SUM(checksum), 0) as "checksums: i64",
+                MAX(updated_at) as "updated_at: String"
+            FROM document WHERE collection_id = ?
+            "#,
+            collection_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(format!(
+            "{}:{}:{}",
+            row.count,
+            row.checksums,
+            row.updated_at.unwrap_or_default()
+        ))
+    }
+
+    pub async fn delete_documents_by_source(&self, source_id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(
+            r#"DELETE FROM heading WHERE document_id IN (SELECT id FROM document WHERE source_id = ?)"#,
+            source_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(r#"DELETE FROM link WHERE source_id = ?"#, source_id)
+            .execute(&mut *tx)
+            .await?;
+        let _ = sqlx::query!(r#"DELETE FROM document WHERE source_id = ?"#, source_id)
+            .execute(&mut *tx)
+            .await?;
+        // Without documents every file has to be fetched again.
+        sqlx::query!(r#"DELETE FROM object_etag WHERE source_id = ?"#, source_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes the source's document at the path along with its chunks,
+    /// headings and links, e.g. before storing a changed version of it.
+    pub async fn delete_document_by_path(
+        &self,
+        source_id: i64,
+        path: &str,
+    ) -> Result<(), sqlx::Error> {
+        self.delete_document_by_path_except(source_id, path, 0)
+            .await
+    }
+
+    /// Replaces what the listing of the source tells about the document, the
+    /// other paths of its content, its place in the nav and its link.
+    pub async fn update_document_listing(
+        &self,
+        source_id: i64,
+        path: &str,
+        alternate_paths: &[String],
+        nav_path: &str,
+        nav_index: Option<usize>,
+        url: &str,
+    ) -> Result<(), sqlx::Error> {
+        let alternate_paths = alternate_paths.join("\n");
+        let nav_index = nav_index.map(|index| index as i64);
+        sqlx::query!(
+            r#"
+        UPDATE document SET alternate_paths = ?, nav_path = ?, nav_index = ?, url = ?
+        WHERE source_id = ? AND path = ?
+        "#,
+            alternate_paths,
+            nav_path,
+            nav_index,
+            url,
+            source_id,
+            path
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the documents at the path but the one with the `keep` id, e.g.
+    /// the ones replaced by a document stored next to them.
+    pub async fn delete_document_by_path_except(
+        &self,
+        source_id: i64,
+        path: &str,
+        keep: i64,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let ids = sqlx::query!(
+            r#"SELECT id as "id!" FROM document WHERE source_id = ? AND path = ? AND id != ?"#,
+            source_id,
+            path,
+            keep
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in ids {
+            self.delete_document_with(&mut *tx, row.id).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes the document with its chunks, headings and links.
+    pub async fn delete_document(&self, id: i64) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        self.delete_document_with(&mut *tx, id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_document_with(
+        &self,
+        conn: &mut SqliteConnection,
+        id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM chunk WHERE document_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(r#"DELETE FROM heading WHERE document_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(r#"DELETE FROM link WHERE document_id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query!(r#"DELETE FROM document WHERE id = ?"#, id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Returns ETags of the objects or files the bucket or GitHub source was
+    /// last parsed from, keyed by path.
+    pub async fn query_object_etags(
+        &self,
+        source_id: i64,
+    ) -> Result<HashMap<String, String>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT path, etag FROM object_etag WHERE source_id = ?"#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.path, row.etag)).collect())
+    }
+
+    pub async fn upsert_object_etag(
+        &self,
+        source_id: i64,
+        path: &str,
+        etag: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT OR REPLACE INTO object_etag (source_id, path, etag) VALUES (?, ?, ?)"#,
+            source_id,
+            path,
+            etag
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_object_etag(&self, source_id: i64, path: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"DELETE FROM object_etag WHERE source_id = ? AND path = ?"#,
+            source_id,
+            path
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Replaces the document's headings with the given ones.
+    pub async fn replace_headings(
+        &self,
+        document_id: i64,
+        headings: &[Heading],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(r#"DELETE FROM heading WHERE document_id = ?"#, document_id)
+            .execute(&mut *tx)
+            .await?;
+        for heading in headings {
+            let position = heading.position as u32;
+            sqlx::query!(
+                r#"
+            INSERT INTO heading (document_id, position, level, text, anchor)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+                document_id,
+                position,
+                heading.level,
+                heading.text,
+                heading.anchor,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Replaces the document's links with the given ones.
+    pub async fn replace_links(&self, document_id: i64, links: &[Link]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(r#"DELETE FROM link WHERE document_id = ?"#, document_id)
+            .execute(&mut *tx)
+            .await?;
+        for link in links {
+            sqlx::query!(
+                r#"INSERT INTO link (document_id, source_id, url, target_path) VALUES (?, ?, ?, ?)"#,
+                document_id,
+                link.source_id,
+                link.url,
+                link.target_path,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns links of the document, paired with the id of the document they point at.
+    pub async fn query_links_from(
+        &self,
+        document_id: i64,
+    ) -> Result<Vec<(Link, Option<i64>)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT l.document_id, l.source_id, l.url, l.target_path, d.id as "target_id?: i64"
+            FROM link l LEFT JOIN document d ON d.source_id = l.source_id AND d.path = l.target_path
+            WHERE l.document_id = ?
+            ORDER BY l.id
+            "#,
+            document_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let link = Link {
+                    document_id: row.document_id,
+                    source_id: row.source_id,
+                    url: row.url,
+                    target_path: row.target_path,
+                };
+                (link, row.target_id)
+            })
+            .collect())
+    }
+
+    /// Returns links pointing at the document.
+    pub async fn query_links_to(&self, document_id: i64) -> Result<Vec<Link>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT l.document_id, l.source_id, l.url, l.target_path
+            FROM link l JOIN document d ON d.source_id = l.source_id AND d.path = l.target_path
+            WHERE d.id = ?
+            ORDER BY l.id
+            "#,
+            document_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Link {
+                document_id: row.document_id,
+                source_id: row.source_id,
+                url: row.url,
+                target_path: row.target_path,
+            })
+            .collect())
+    }
+
+    /// Returns relative links of the source that point at paths with no document.
+    pub async fn query_dangling_links(
+        &self,
+        source_id: i64,
+    ) -> Result<Vec<BrokenLink>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT l.document_id, d.path, l.url, l.target_path as "target_path!: String"
+            FROM link l
+            JOIN document d ON d.id = l.document_id
+            LEFT JOIN document t ON t.source_id = l.source_id AND t.path = l.target_path
+            WHERE l.source_id = ? AND l.target_path IS NOT NULL AND t.id IS NULL
+            ORDER BY d.path, l.id
+            "#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| BrokenLink {
+                document_id: row.document_id,
+                path: row.path,
+                url: row.url,
+                reason: format!("No document at '{}'", row.target_path),
+            })
+            .collect())
+    }
+
+    /// Returns distinct urls of the source's links that point outside of the repo.
+    pub async fn query_external_urls(&self, source_id: i64) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT url FROM link WHERE source_id = ? AND target_path IS NULL"#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.url).collect())
+    }
+
+    /// Returns external links of the source whose last check failed.
+    /// Rate limited responses aren't counted as broken.
+    pub async fn query_failed_links(&self, source_id: i64) -> Result<Vec<BrokenLink>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT l.document_id, d.path, l.url, c.status, c.error
+            FROM link l
+            JOIN document d ON d.id = l.document_id
+            JOIN link_check c ON c.url = l.url
+            WHERE l.source_id = ? AND l.target_path IS NULL
+                AND (c.status IS NULL OR (c.status >= 400 AND c.status != 429))
+            ORDER BY d.path, l.id
+            "#,
+            source_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| BrokenLink {
+                document_id: row.document_id,
+                path: row.path,
+                url: row.url,
+                reason: match row.status {
+                    Some(status) => format!("HTTP {}", status),
+                    None => row.error.unwrap_or_default(),
+                },
+            })
+            .collect())
+    }
+
+    pub async fn select_link_check(&self, url: &str) -> Result<Option<LinkCheck>, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM link_check WHERE url = ?"#, url)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| LinkCheck {
+            url: row.url,
+            status: row.status.map(|status| status as u16),
+            error: row.error,
+            checked_at: row.checked_at.parse().unwrap_or_default(),
+        }))
+    }
+
+    pub async fn upsert_link_check(&self, data: &LinkCheck) -> Result<(), sqlx::Error> {
+        let status = data.status.map(|status| status as u32);
+        sqlx::query!(
+            r#"
+        INSERT OR REPLACE INTO link_check (url, status, error, checked_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+            data.url,
+            status,
+            data.error,
+            data.checked_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Counts links pointing at each of the collection's documents, keyed by document id.
+    pub async fn query_inbound_link_counts(
+        &self,
+        collection_id: i64,
+    ) -> Result<HashMap<i64, i64>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id as "id!", COUNT(DISTINCT l.document_id) as "count: i64"
+            FROM link l JOIN document d ON d.source_id = l.source_id AND d.path = l.target_path
+            WHERE d.collection_id = ? AND l.document_id != d.id
+            GROUP BY d.id
+            "#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| (row.id, row.count)).collect())
+    }
+
+    pub async fn query_headings_by_document(
+        &self,
+        document_id: i64,
+    ) -> Result<Vec<Heading>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM heading WHERE document_id = ? ORDER BY position"#,
+            document_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Heading {
+                document_id: row.document_id,
+                position: row.position as usize,
+                level: row.level as u8,
+                text: row.text,
+                anchor: row.anchor,
+            })
+            .collect())
+    }
+
+    /// Returns the collection's headings containing the text, case-insensitively.
+    pub async fn query_headings_containing(
+        &self,
+        collection_id: i64,
+        text: &str,
+    ) -> Result<Vec<Heading>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT h.document_id, h.position, h.level, h.text, h.anchor
+            FROM heading h JOIN document d ON d.id = h.document_id
+            WHERE d.collection_id = ? AND h.text LIKE '%' || ? || '%'
+            "#,
+            collection_id,
+            text
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Heading {
+                document_id: row.document_id,
+                position: row.position as usize,
+                level: row.level as u8,
+                text: row.text,
+                anchor: row.anchor,
+            })
+            .collect())
+    }
+
+    pub async fn insert_chunk(&self, data: &Chunk) -> Result<(), sqlx::Error> {
+        let vector = bincode::serialize(&data.vector).expect("Failed to serialize vector");
+        let chunk_index = data.chunk_index as u32;
+        let tags = data.tags.join(";");
+        let dimension = data.dimension as u32;
+        sqlx::query!(
+            r#"
+        INSERT INTO chunk (document_id, source_id, collection_id, chunk_index, context, data, vector, tags, anchor, language, model, model_version, dimension)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+            data.document_id,
+            data.source_id,
+            data.collection_id,
+            chunk_index,
+            data.context,
+            data.data,
+            vector,
+            tags,
+            data.anchor,
+            data.language,
+            data.model,
+            data.model_version,
+            dimension,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn query_chunks_by_source(&self, source_id: i64) -> Result<Vec<Chunk>, sqlx::Error> {
+        let mut chunks = Vec::new();
+        let rows = sqlx::query!(r#" SELECT * FROM chunk WHERE source_id = ?"#, source_id)
+            .fetch_all(&self.pool)
+            .await?;
+        for row in rows {
+            let vector: Vec<f32> =
+                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
+            chunks.push(Chunk {
+                id: row.id,
+                document_id: row.document_id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                chunk_index: row.chunk_index as usize,
+                context: row.context,
+                data: row.data,
+                vector,
+                tags: parse_tags(&row.tags),
+                anchor: row.anchor,
+                language: row.language,
+                model: row.model,
+                model_version: row.model_version,
+                dimension: row.dimension as usize,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// Returns up to `limit` chunks of the source with ids greater than
+    /// `after_id`, skipping `offset` of them.
+    pub async fn query_chunks_page(
+        &self,
+        source_id: i64,
+        after_id: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<Chunk>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM chunk WHERE source_id = ? AND id > ? ORDER BY id LIMIT ? OFFSET ?"#,
+            source_id,
+            after_id,
+            limit,
+            offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut chunks = Vec::with_capacity(rows.len());
+        for row in rows {
+            let vector: Vec<f32> =
+                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
+            chunks.push(Chunk {
+                id: row.id,
+                document_id: row.document_id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                chunk_index: row.chunk_index as usize,
+                context: row.context,
+                data: row.data,
+                vector,
+                tags: parse_tags(&row.tags),
+                anchor: row.anchor,
+                language: row.language,
+                model: row.model,
+                model_version: row.model_version,
+                dimension: row.dimension as usize,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// Streams all of the source's chunks ordered by id, for exports too large
+    /// to hold in memory.
+    pub fn stream_chunks(&self, source_id: i64) -> BoxStream<'_, Result<Chunk, sqlx::Error>> {
+        sqlx::query!(
+            r#"SELECT * FROM chunk WHERE source_id = ? ORDER BY id"#,
+            source_id
+        )
+        .fetch(&self.pool)
+        .map(|row| {
+            let row = row?;
+            let vector: Vec<f32> =
+                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
+            Ok(Chunk {
+                id: row.id,
+                document_id: row.document_id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                chunk_index: row.chunk_index as usize,
+                context: row.context,
+                data: row.data,
+                vector,
+                tags: parse_tags(&row.tags),
+                anchor: row.anchor,
+                language: row.language,
+                model: row.model,
+                model_version: row.model_version,
+                dimension: row.dimension as usize,
+            })
+        })
+        .boxed()
+    }
+
+    /// Fingerprint of the source's chunks, they are only inserted and deleted,
+    /// so the count with the latest id is enough.
+    pub async fn select_chunks_version(&self, source_id: i64) -> Result<String, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"SELECT COUNT(*) as "count: i64", COALESCE(MAX(id), 0) as "max_id: i64" FROM chunk WHERE source_id = ?"#,
+            source_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(format!("{}:{}", row.count, row.max_id))
+    }
+
+    pub async fn query_chunks_by_collection(
+        &self,
+        collection_id: i64,
+    ) -> Result<Vec<Chunk>, sqlx::Error> {
+        let mut chunks = Vec::new();
+        let rows = sqlx::query!(
+            r#" SELECT * FROM chunk WHERE collection_id = ?"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            let vector: Vec<f32> =
+                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
+            chunks.push(Chunk {
+                id: row.id,
+                document_id: row.document_id,
+                source_id: row.source_id,
+                collection_id: row.collection_id,
+                chunk_index: row.chunk_index as usize,
+                context: row.context,
+                data: row.data,
+                vector,
+                tags: parse_tags(&row.tags),
+                anchor: row.anchor,
+                language: row.language,
+                model: row.model,
+                model_version: row.model_version,
+                dimension: row.dimension as usize,
+            });
+        }
+        Ok(chunks)
+    }
+
+    /// Stores vectors of a candidate model alongside the chunks' primary vectors.
+    pub async fn insert_chunk_vectors(
+        &self,
+        collection_id: i64,
+        model: &str,
+        vectors: &[(i64, Vec<f32>)],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (chunk_id, vector) in vectors {
+            let vector = bincode::serialize(vector).expect("Failed to serialize vector");
+            sqlx::query!(
+                r#"
+                INSERT OR REPLACE INTO chunk_vector (chunk_id, collection_id, model, vector)
+                VALUES (?, ?, ?, ?)
+                "#,
+                chunk_id,
+                collection_id,
+                model,
+                vector,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns vectors of a candidate model keyed by chunk id.
+    pub async fn query_chunk_vectors(
+        &self,
+        collection_id: i64,
+        model: &str,
+    ) -> Result<HashMap<i64, Vec<f32>>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT chunk_id, vector FROM chunk_vector WHERE collection_id = ? AND model = ?"#,
+            collection_id,
+            model
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut vectors = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let vector: Vec<f32> =
+                bincode::deserialize(&row.vector).expect("Failed to deserialize vector");
+            vectors.insert(row.chunk_id, vector);
+        }
+        Ok(vectors)
+    }
+
+    /// Returns names of candidate models that have vectors in the collection.
+    pub async fn query_candidate_models(
+        &self,
+        collection_id: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT model FROM chunk_vector WHERE collection_id = ?"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.model).collect())
+    }
+
+    /// Returns the distinct `(model, model_version, dimension)` the collection's
+    /// chunks after `after_id` were encoded with, and the id of the last chunk
+    /// of each.
+    pub async fn query_chunk_models(
+        &self,
+        collection_id: i64,
+        after_id: i64,
+    ) -> Result<Vec<(String, String, usize, i64)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT model as "model!", model_version as "model_version!", dimension as "dimension!", MAX(id) as "last_id!: i64"
+            FROM chunk
+            WHERE collection_id = ? AND id > ?
+            GROUP BY model, model_version, dimension
+            "#,
+            collection_id,
+            after_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                (
+                    row.model,
+                    row.model_version,
+                    row.dimension as usize,
+                    row.last_id,
+                )
+            })
+            .collect())
+    }
+
+    pub async fn insert_query_log(
+        &self,
+        collection: &str,
+        model: &str,
+        query: &str,
+    ) -> Result<(), sqlx::Error> {
+        let created_at = chrono::Utc::now();
+        sqlx::query!(
+            r#"INSERT INTO query_log (collection, model, query, created_at) VALUES (?, ?, ?, ?)"#,
+            collection,
+            model,
+            query,
+            created_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the most recent distinct queries logged for the collection.
+    pub async fn query_logged_queries(
+        &self,
+        collection: &str,
+        limit: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT query as "query!" FROM query_log WHERE collection = ?
+            GROUP BY query ORDER BY MAX(id) DESC LIMIT ?
+            "#,
+            collection,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|row| row.query).collect())
+    }
+
+    pub async fn delete_chunks_by_source(&self, source_id: i64) -> Result<(), sqlx::Error> {
+        let _ = sqlx::query!(r#"DELETE FROM chunk WHERE source_id = ?"#, source_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn select_idempotency_record(
+        &self,
+        key: &str,
+    ) -> Result<Option<IdempotencyRecord>, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM idempotency_key WHERE key = ?"#, key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| IdempotencyRecord {
+            key: row.key,
+            request_hash: row.request_hash as u32,
+            status: row.status as u16,
+            body: row.body,
+            created_at: row.created_at.parse().unwrap_or_default(),
+        }))
+    }
+
+    /// Inserts the record unless its key exists, returns whether it was inserted.
+    pub async fn insert_idempotency_record(
+        &self,
+        data: &IdempotencyRecord,
+    ) -> Result<bool, sqlx::Error> {
+        let status = data.status as u32;
+        let inserted = sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO idempotency_key (key, request_hash, status, body, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+            data.key,
+            data.request_hash,
+            status,
+            data.body,
+            data.created_at,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    /// Stores the response of the request holding the key.
+    pub async fn update_idempotency_record(
+        &self,
+        key: &str,
+        status: u16,
+        body: &str,
+    ) -> Result<(), sqlx::Error> {
+        let status = status as u32;
+        sqlx::query!(
+            r#"UPDATE idempotency_key SET status = ?, body = ? WHERE key = ?"#,
+            status,
+            body,
+            key,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_idempotency_record(&self, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM idempotency_key WHERE key = ?"#, key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Inserts the alias, or replaces the expansion if it already exists.
+    pub async fn upsert_alias(&self, data: &Alias) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+        INSERT OR REPLACE INTO collection_alias (collection_id, alias, expansion, created_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+            data.collection_id,
+            data.alias,
+            data.expansion,
+            data.created_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn query_aliases(&self, collection_id: i64) -> Result<Vec<Alias>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM collection_alias WHERE collection_id = ? ORDER BY alias"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Alias {
+                collection_id: row.collection_id,
+                alias: row.alias,
+                expansion: row.expansion,
+                created_at: row.created_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn query_aliases_by_collection_name(
+        &self,
+        name: &str,
+    ) -> Result<Vec<Alias>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT a.collection_id, a.alias, a.expansion, a.created_at
+            FROM collection_alias a JOIN collection c ON c.id = a.collection_id
+            WHERE c.name = ?
+            "#,
+            name
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Alias {
+                collection_id: row.collection_id,
+                alias: row.alias,
+                expansion: row.expansion,
+                created_at: row.created_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn delete_alias(&self, collection_id: i64, alias: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"DELETE FROM collection_alias WHERE collection_id = ? AND alias = ?"#,
+            collection_id,
+            alias
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn insert_audit_entry(&self, data: &AuditEntry) -> Result<i64, sqlx::Error> {
+        let status = data.status as u32;
+        let id = sqlx::query!(
+            r#"
+        INSERT INTO audit_log (actor, request_id, action, path, summary, status, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+            data.actor,
+            data.request_id,
+            data.action,
+            data.path,
+            data.summary,
+            status,
+            data.created_at,
+        )
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Returns entries newest first, older than `before` if given. `actor` and
+    /// `action` filter if given.
+    pub async fn query_audit_log(
+        &self,
+        actor: Option<&str>,
+        action: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<AuditEntry>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT * FROM audit_log
+            WHERE (? IS NULL OR actor = ?) AND (? IS NULL OR action = ?) AND (? IS NULL OR id < ?)
+            ORDER BY id DESC LIMIT ?
+            "#,
+            actor,
+            actor,
+            action,
+            action,
+            before,
+            before,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| AuditEntry {
+                id: row.id,
+                actor: row.actor,
+                request_id: row.request_id,
+                action: row.action,
+                path: row.path,
+                summary: row.summary,
+                status: row.status as u16,
+                created_at: row.created_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Inserts the session, dropping expired ones along the way.
+    pub async fn insert_session(&self, data: &Session) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query!(r#"DELETE FROM session WHERE expires_at < ?"#, now)
+            .execute(&self.pool)
+            .await?;
+        let role = data.role.as_str();
+        sqlx::query!(
+            r#"
+        INSERT INTO session (id_hash, subject, email, role, created_at, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+            data.id_hash,
+            data.subject,
+            data.email,
+            role,
+            data.created_at,
+            data.expires_at,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the session with the given hash, if it hasn't expired.
+    pub async fn select_session(&self, id_hash: &str) -> Result<Option<Session>, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let row = sqlx::query!(
+            r#"SELECT * FROM session WHERE id_hash = ? AND expires_at > ?"#,
+            id_hash,
+            now
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| Session {
+            id_hash: row.id_hash,
+            subject: row.subject,
+            email: row.email,
+            role: row.role.parse().unwrap_or(Role::Viewer),
+            created_at: row.created_at.parse().unwrap_or_default(),
+            expires_at: row.expires_at.parse().unwrap_or_default(),
+        }))
+    }
+
+    pub async fn delete_session(&self, id_hash: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!(r#"DELETE FROM session WHERE id_hash = ?"#, id_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_access_token(&self, data: &AccessToken) -> Result<i64, sqlx::Error> {
+        let allowed_origins = data.allowed_origins.join("\n");
+        let id = sqlx::query!(
+            r#"
+        INSERT INTO access_token
+            (collection_id, name, token_hash, requests_per_minute, allowed_origins, created_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+            data.collection_id,
+            data.name,
+            data.token_hash,
+            data.requests_per_minute,
+            allowed_origins,
+            data.created_at,
+        )
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    pub async fn query_access_tokens(
+        &self,
+        collection_id: i64,
+    ) -> Result<Vec<AccessToken>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"SELECT * FROM access_token WHERE collection_id = ? ORDER BY id"#,
+            collection_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| AccessToken {
+                id: row.id,
+                collection_id: row.collection_id,
+                name: row.name,
+                token_hash: row.token_hash,
+                requests_per_minute: row.requests_per_minute as u32,
+                allowed_origins: parse_patterns(&row.allowed_origins),
+                created_at: row.created_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Returns the token with the given hash, if it wasn't revoked.
+    pub async fn select_access_token_by_hash(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<AccessToken>, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id as "id!", collection_id, name, token_hash, requests_per_minute, allowed_origins, created_at
+            FROM access_token WHERE token_hash = ?
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(|row| AccessToken {
+            id: row.id,
+            collection_id: row.collection_id,
+            name: row.name,
+            token_hash: row.token_hash,
+            requests_per_minute: row.requests_per_minute as u32,
+            allowed_origins: parse_patterns(&row.allowed_origins),
+            created_at: row.created_at.parse().unwrap_or_default(),
+        }))
+    }
+
+    /// Revokes the token, returns whether it existed.
+    pub async fn delete_access_token(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let deleted = sqlx::query!(r#"DELETE FROM access_token WHERE id = ?"#, id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(deleted > 0)
+    }
+
+    pub async fn insert_job(&self, data: &Job) -> Result<i64, sqlx::Error> {
+        let kind = data.kind.as_str();
+        let status = data.status.as_str();
+        let documents = data.documents as i64;
+        let result = data.result.as_ref().map(|result| result.to_string());
+        let id = sqlx::query!(
+            r#"
+        INSERT INTO job (source_id, kind, status, documents, error, result, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+            data.source_id,
+            kind,
+            status,
+            documents,
+            data.error,
+            result,
+            data.created_at,
+            data.updated_at,
+        )
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Updates the status of the job, and its outcome once it's finished.
+    pub async fn update_job(&self, data: &Job) -> Result<(), sqlx::Error> {
+        let status = data.status.as_str();
+        let documents = data.documents as i64;
+        let result = data.result.as_ref().map(|result| result.to_string());
+        let updated_at = chrono::Utc::now();
+        sqlx::query!(
+            r#"
+        UPDATE job SET status = ?, documents = ?, error = ?, result = ?, updated_at = ?
+        WHERE id = ?
+        "#,
+            status,
+            documents,
+            data.error,
+            result,
+            updated_at,
+            data.id,
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Fails jobs left queued or running by a previous run of the server, their
+    /// tasks are gone. Returns how many there were.
+    pub async fn fail_unfinished_jobs(&self) -> Result<u64, sqlx::Error> {
+        let updated_at = chrono::Utc::now();
+        let status = JobStatus::Failed.as_str();
+        let queued = JobStatus::Queued.as_str();
+        let running = JobStatus::Running.as_str();
+        let failed = sqlx::query!(
+            r#"
+        UPDATE job SET status = ?, error = 'Interrupted by a restart of the server', updated_at = ?
+        WHERE status IN (?, ?)
+        "#,
+            status,
+            updated_at,
+            queued,
+            running,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(failed)
+    }
+
+    /// Returns jobs newest first, older than `before` if given. `source_id`
+    /// and `status` filter if given.
+    pub async fn query_jobs(
+        &self,
+        source_id: Option<i64>,
+        status: Option<JobStatus>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        let status = status.map(|status| status.as_str());
+        let rows = sqlx::query!(
+            r#"
+            SELECT * FROM job
+            WHERE (? IS NULL OR source_id = ?) AND (? IS NULL OR status = ?) AND (? IS NULL OR id < ?)
+            ORDER BY id DESC LIMIT ?
+            "#,
+            source_id,
+            source_id,
+            status,
+            status,
+            before,
+            before,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| Job {
+                id: row.id,
+                source_id: row.source_id,
+                kind: row.kind.parse().unwrap_or(JobKind::Parse),
+                status: row.status.parse().unwrap_or(JobStatus::Failed),
+                documents: row.documents as usize,
+                error: row.error,
+                result: row
+                    .result
+                    .and_then(|result| serde_json::from_str(&result).ok()),
+                created_at: row.created_at.parse().unwrap_or_default(),
+                updated_at: row.updated_at.parse().unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    pub async fn select_job(&self, id: i64) -> Result<Job, sqlx::Error> {
+        let row = sqlx::query!(r#"SELECT * FROM job WHERE id = ?"#, id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Job {
+            id: row.id,
+            source_id: row.source_id,
+            kind: row.kind.parse().unwrap_or(JobKind::Parse),
+            status: row.status.parse().unwrap_or(JobStatus::Failed),
+            documents: row.documents as usize,
+            error: row.error,
+            result: row
+                .result
+                .and_then(|result| serde_json::from_str(&result).ok()),
+            created_at: row.created_at.parse().unwrap_or_default(),
+            updated_at: row.updated_at.parse().unwrap_or_default(),
+        })
+    }
+}
+
+fn stringify_vec(vec: HashSet<String>) -> String {
     vec.into_iter().collect::<Vec<_>>().join(";")
 }
+
+fn parse_patterns(value: &str) -> Vec<String> {
+    value
+        .lines()
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| pattern.to_string())
+        .collect()
+}
+
+fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+// The summary is the one of the last parse job, whose report is the parse's
+// own or, of syncs, nested under `parse`.
+fn source_stats(
+    documents: i64,
+    chunks: i64,
+    tokens: i64,
+    parsed_at: Option<String>,
+    report: Option<String>,
+) -> SourceStats {
+    let report = report.and_then(|report| serde_json::from_str::<serde_json::Value>(&report).ok());
+    let summary = report.and_then(|report| {
+        let parse = report.get("parse").unwrap_or(&report);
+        serde_json::from_value(parse.get("summary")?.clone()).ok()
+    });
+    SourceStats {
+        documents,
+        chunks,
+        tokens,
+        parsed_at: parsed_at.and_then(|parsed_at| parsed_at.parse().ok()),
+        summary,
+    }
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use utoipa::ToSchema;
+
+/// Config files of documentation sites, their directory is a package.
+const SITE_CONFIGS: &[&str] = &[
+    "mkdocs.yml",
+    "mkdocs.yaml",
+    "docusaurus.config.js",
+    "docusaurus.config.ts",
+    "docusaurus.config.mjs",
+];
+
+const DOCS_DIR: &str = "docs";
+
+/// Directories of dependencies, their docs aren't the repo's.
+const THIRD_PARTY_DIRS: &[&str] = &["node_modules", "vendor", "third_party"];
+
+/// Package of a monorepo with docs of its own, indexed as a source of its own.
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Package {
+    /// Directory of the package, e.g. `packages/cli`.
+    pub path: String,
+    /// Name of the package's directory, the label of its chunks.
+    pub name: String,
+    /// Directory the package's source is limited to, its `docs` folder if it
+    /// has one.
+    pub docs_dir: String,
+}
+
+/// Finds packages in the repo's paths, directories below the root with a
+/// `docs` folder or an mkdocs or Docusaurus config. Paths in ignored
+/// directories are skipped, the root is the repo's own source.
+pub fn packages(paths: &[String], ignored_dirs: &HashSet<String>) -> Vec<Package> {
+    let paths: Vec<&String> = paths
+        .iter()
+        .filter(|path| {
+            !ignored_dirs
+                .iter()
+                .any(|dir| path.starts_with(dir.as_str()))
+        })
+        .filter(|path| {
+            !path
+                .split('/')
+                .any(|segment| THIRD_PARTY_DIRS.contains(&segment))
+        })
+        .collect();
+    let mut docs_dirs: HashSet<String> = HashSet::new();
+    let mut dirs: BTreeSet<String> = BTreeSet::new();
+    for path in &paths {
+        let segments: Vec<&str> = path.split('/').collect();
+        // The outermost docs folder, docs of docs aren't packages.
+        if let Some(at) = segments[..segments.len() - 1]
+            .iter()
+            .position(|segment| *segment == DOCS_DIR)
+        {
+            if at > 0 {
+                let dir = segments[..at].join("/");
+                docs_dirs.insert(dir.clone());
+                dirs.insert(dir);
+            }
+            continue;
+        }
+        let file = segments[segments.len() - 1];
+        if SITE_CONFIGS.contains(&file) && segments.len() > 1 {
+            dirs.insert(segments[..segments.len() - 1].join("/"));
+        }
+    }
+
+    // Packages nested in another one are part of it.
+    let mut packages: Vec<Package> = Vec::new();
+    for dir in dirs {
+        let nested = packages
+            .iter()
+            .any(|package| dir.starts_with(&format!("{}/", package.path)));
+        if nested {
+            continue;
+        }
+        let docs_dir = match docs_dirs.contains(&dir) {
+            true => format!("{}/{}/", dir, DOCS_DIR),
+            false => format!("{}/", dir),
+        };
+        packages.push(Package {
+            name: dir.rsplit('/').next().unwrap_or_default().to_string(),
+            path: dir,
+            docs_dir,
+        });
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packages() {
+        let paths: Vec<String> = [
+            "README.md",
+            "docs/intro.md",
+            "packages/cli/docs/usage.md",
+            "packages/cli/docs/guides/docs/nested.md",
+            "packages/cli/src/main.rs",
+            "packages/web/mkdocs.yml",
+            "packages/web/pages/index.md",
+            "packages/web/plugins/docs/readme.md",
+            "apps/site/docusaurus.config.ts",
+            "apps/site/node_modules/lib/docs/api.md",
+            "legacy/docs/old.md",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        let ignored = HashSet::from(["legacy/".to_string()]);
+        let packages = packages(&paths, &ignored);
+        let found: Vec<(&str, &str, &str)> = packages
+            .iter()
+            .map(|package| {
+                (
+                    package.path.as_str(),
+                    package.name.as_str(),
+                    package.docs_dir.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("apps/site", "site", "apps/site/"),
+                ("packages/cli", "cli", "packages/cli/docs/"),
+                ("packages/web", "web", "packages/web/"),
+            ]
+        );
+    }
+}
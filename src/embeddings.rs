@@ -1,27 +1,364 @@
-use rust_bert::{
-    pipelines::sentence_embeddings::{SentenceEmbeddingsBuilder, SentenceEmbeddingsModel},
-    RustBertError,
+use anyhow::{anyhow, Result};
+#[cfg(feature = "bert")]
+use rust_bert::pipelines::sentence_embeddings::{
+    SentenceEmbeddingsBuilder, SentenceEmbeddingsModel,
 };
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+#[cfg(feature = "bert")]
 use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::Configuration;
+
+/// Name of the model loaded at startup, it's also the directory the model is loaded from.
+pub const DEFAULT_MODEL: &str = "model";
+
+#[cfg(feature = "bert")]
+pub use tch::Device;
+
+/// Stand-in for `tch::Device` when built without libtorch, only CPU exists.
+#[cfg(not(feature = "bert"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Device {
+    Cpu,
+}
+
+/// Where embeddings come from, set with `EMBEDDINGS_PROVIDER`.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingsProvider {
+    /// Local sentence embeddings models, needs the `bert` feature.
+    Bert,
+    /// `HashEmbeddings`, no model weights or libtorch needed.
+    Hash,
+}
+
+impl Default for EmbeddingsProvider {
+    fn default() -> Self {
+        if cfg!(feature = "bert") {
+            EmbeddingsProvider::Bert
+        } else {
+            EmbeddingsProvider::Hash
+        }
+    }
+}
+
+impl FromStr for EmbeddingsProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "bert" => Ok(EmbeddingsProvider::Bert),
+            "hash" => Ok(EmbeddingsProvider::Hash),
+            other => Err(anyhow!("Unknown embeddings provider '{}'", other)),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Embeddings {
-    model: Arc<Mutex<SentenceEmbeddingsModel>>,
+    name: String,
+    /// See `version`.
+    version: String,
+    device: Device,
+    backend: Backend,
+    stats: Arc<Stats>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    #[cfg(feature = "bert")]
+    Bert(Arc<Mutex<SentenceEmbeddingsModel>>),
+    Hash(HashEmbeddings),
+}
+
+/// Stable pseudo-embeddings hashed from the words of the text.
+///
+/// Words are lowercased and hashed into buckets of the vector, so texts
+/// sharing words get similar vectors and searches behave sensibly. Useful for
+/// integration tests and for running the pipeline on machines without libtorch.
+#[derive(Clone, Copy, Debug)]
+pub struct HashEmbeddings {
+    dimension: usize,
+}
+
+impl HashEmbeddings {
+    pub fn new(dimension: usize) -> Self {
+        Self { dimension }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn encode(&self, sentences: &[String]) -> Vec<Vec<f32>> {
+        sentences
+            .iter()
+            .map(|sentence| hash_vector(sentence, self.dimension))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    dimension: AtomicUsize,
+    encodes: AtomicU64,
+    latency_us: AtomicU64,
+}
+
+/// Describes a loaded model, useful when diagnosing slow queries.
+#[derive(Serialize, Debug)]
+pub struct ModelInfo {
+    pub name: String,
+    pub dimension: usize,
+    pub device: String,
+    pub encodes: u64,
+    pub average_latency_ms: f64,
 }
 
 impl Embeddings {
-    pub fn new() -> Result<Self, RustBertError> {
-        tracing::info!("Loading local model 'AllMiniLmL12V2' from disk");
-        let model = SentenceEmbeddingsBuilder::local("model")
-            .with_device(tch::Device::cuda_if_available())
+    pub fn new(device: Device) -> Result<Self> {
+        Self::from_dir(DEFAULT_MODEL, device)
+    }
+
+    /// Loads the model with the given name from the configured provider.
+    pub fn from_config(name: &str, cfg: &Configuration) -> Result<Self> {
+        match cfg.embeddings_provider {
+            EmbeddingsProvider::Bert => Self::from_dir(name, select_device(&cfg.embeddings_device)),
+            EmbeddingsProvider::Hash => Ok(Self::hashed(name, cfg.hash_dimension)),
+        }
+    }
+
+    /// Loads a local sentence embeddings model, the directory is used as the model name.
+    #[cfg(feature = "bert")]
+    pub fn from_dir(dir: &str, device: Device) -> Result<Self> {
+        tracing::info!("Loading local model '{}' from disk on {:?}", dir, device);
+        let model = SentenceEmbeddingsBuilder::local(dir)
+            .with_device(device)
             .create_model()?;
         Ok(Self {
-            model: Arc::new(Mutex::new(model)),
+            name: dir.to_string(),
+            version: model_version(dir),
+            device,
+            backend: Backend::Bert(Arc::new(Mutex::new(model))),
+            stats: Arc::new(Stats::default()),
         })
     }
 
-    pub async fn encode(&self, sentences: &[String]) -> Result<Vec<Vec<f32>>, RustBertError> {
-        self.model.lock().await.encode(sentences)
+    #[cfg(not(feature = "bert"))]
+    pub fn from_dir(dir: &str, _device: Device) -> Result<Self> {
+        Err(anyhow!(
+            "Unable to load model '{}', built without the `bert` feature",
+            dir
+        ))
+    }
+
+    /// Creates a model backed by `HashEmbeddings` of the given dimension.
+    pub fn hashed(name: &str, dimension: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            version: format!("hash-{}", dimension),
+            device: Device::Cpu,
+            backend: Backend::Hash(HashEmbeddings::new(dimension)),
+            stats: Arc::new(Stats::default()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Version of the vectors the model produces, a model of the same name
+    /// served by another provider or with other weights produces vectors that
+    /// can't be compared.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub async fn encode(&self, sentences: &[String]) -> Result<Vec<Vec<f32>>> {
+        let instant = Instant::now();
+        let vectors = match &self.backend {
+            #[cfg(feature = "bert")]
+            Backend::Bert(model) => model.lock().await.encode(sentences)?,
+            Backend::Hash(hash) => hash.encode(sentences),
+        };
+        let latency = instant.elapsed().as_micros() as u64;
+
+        self.stats.encodes.fetch_add(1, Ordering::Relaxed);
+        self.stats.latency_us.fetch_add(latency, Ordering::Relaxed);
+        if let Some(vector) = vectors.first() {
+            self.stats.dimension.store(vector.len(), Ordering::Relaxed);
+        }
+        Ok(vectors)
+    }
+
+    /// Returns the dimension of vectors produced by the model.
+    pub async fn dimension(&self) -> Result<usize> {
+        let vectors = self.encode(&["dimension probe".to_string()]).await?;
+        Ok(vectors.first().map(|v| v.len()).unwrap_or_default())
+    }
+
+    /// Runs a dummy encode, so model weights are loaded before the first query.
+    pub async fn warm_up(&self) -> Result<()> {
+        let instant = Instant::now();
+        let _ = self.dimension().await?;
+        tracing::info!(
+            "Warmed up model '{}', elapsed {:?}",
+            self.name,
+            instant.elapsed()
+        );
+        Ok(())
+    }
+
+    pub fn info(&self) -> ModelInfo {
+        let encodes = self.stats.encodes.load(Ordering::Relaxed);
+        let latency_us = self.stats.latency_us.load(Ordering::Relaxed);
+        let average_latency_ms = if encodes == 0 {
+            0.0
+        } else {
+            latency_us as f64 / encodes as f64 / 1000.0
+        };
+        ModelInfo {
+            name: self.name.clone(),
+            dimension: self.stats.dimension.load(Ordering::Relaxed),
+            device: format!("{:?}", self.device),
+            encodes,
+            average_latency_ms,
+        }
+    }
+}
+
+// Feature hashing of lowercased words, each word adds +1 or -1 to one bucket.
+fn hash_vector(text: &str, dimension: usize) -> Vec<f32> {
+    let mut vector = vec![0.0; dimension];
+    if dimension == 0 {
+        return vector;
+    }
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let hash = crc32fast::hash(word.to_lowercase().as_bytes());
+        let sign = if hash & 1 == 0 { 1.0 } else { -1.0 };
+        vector[(hash >> 1) as usize % dimension] += sign;
+    }
+    crate::normalize(&vector)
+}
+
+// Version of a local model, hashed from its configs and the size of its
+// weights, so replacing the files of the model's directory changes it.
+#[cfg(feature = "bert")]
+fn model_version(dir: &str) -> String {
+    let dir = std::path::Path::new(dir);
+    let mut hasher = crc32fast::Hasher::new();
+    for config in ["modules.json", "config.json"] {
+        if let Ok(bytes) = std::fs::read(dir.join(config)) {
+            hasher.update(&bytes);
+        }
+    }
+    if let Ok(metadata) = std::fs::metadata(dir.join("rust_model.ot")) {
+        hasher.update(&metadata.len().to_le_bytes());
+    }
+    format!("bert-{:08x}", hasher.finalize())
+}
+
+/// Resolves the device to run models on from a setting:
+/// `auto` (CUDA if available), `cpu`, `cuda` or `cuda:N`.
+///
+/// Falls back to CPU with a warning when CUDA isn't available, and to the
+/// first CUDA device when the requested one doesn't exist.
+#[cfg(feature = "bert")]
+pub fn select_device(setting: &str) -> Device {
+    let setting = setting.trim().to_lowercase();
+    if setting == "cpu" {
+        return tch::Device::Cpu;
+    }
+
+    let index = match setting.as_str() {
+        "" | "auto" | "cuda" => 0,
+        value => match value
+            .strip_prefix("cuda:")
+            .and_then(|i| i.parse::<usize>().ok())
+        {
+            Some(index) => index,
+            None => {
+                tracing::warn!("Unknown device '{}', falling back to 'auto'", value);
+                0
+            }
+        },
+    };
+
+    if !tch::Cuda::is_available() {
+        tracing::warn!("CUDA is unavailable, running embeddings models on CPU");
+        return tch::Device::Cpu;
+    }
+
+    let count = tch::Cuda::device_count() as usize;
+    if index >= count {
+        tracing::warn!(
+            "CUDA device {} doesn't exist ({} available), falling back to device 0",
+            index,
+            count
+        );
+        return tch::Device::Cuda(0);
+    }
+    tch::Device::Cuda(index)
+}
+
+#[cfg(not(feature = "bert"))]
+pub fn select_device(setting: &str) -> Device {
+    let setting = setting.trim().to_lowercase();
+    if !matches!(setting.as_str(), "" | "auto" | "cpu") {
+        tracing::warn!(
+            "Built without the `bert` feature, running on CPU instead of '{}'",
+            setting
+        );
+    }
+    Device::Cpu
+}
+
+/// Embedding models loaded into memory, keyed by name.
+#[derive(Clone, Default)]
+pub struct Models {
+    models: Arc<RwLock<HashMap<String, Embeddings>>>,
+}
+
+impl Models {
+    pub fn new(default: Embeddings) -> Self {
+        let models = Self::default();
+        models.insert(default);
+        models
+    }
+
+    pub fn insert(&self, model: Embeddings) {
+        self.models
+            .write()
+            .expect("Models lock is poisoned")
+            .insert(model.name.clone(), model);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Embeddings> {
+        self.models
+            .read()
+            .expect("Models lock is poisoned")
+            .get(name)
+            .cloned()
+    }
+
+    pub fn all(&self) -> Vec<Embeddings> {
+        self.models
+            .read()
+            .expect("Models lock is poisoned")
+            .values()
+            .cloned()
+            .collect()
     }
 }
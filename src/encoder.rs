@@ -1,11 +1,80 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use markdown::ParseOptions;
-use regex::Regex;
+use regex::{Captures, Regex};
+use std::collections::{HashMap, HashSet};
+
+use crate::{mail, pdf, DocumentFormat};
+
+/// Chunks this short carry no meaning on their own and are dropped.
+const MIN_CHUNK_LEN: usize = 8;
+
+/// Code without sections is packed into chunks of up to this many characters.
+const CODE_CHUNK_CHARS: usize = 1500;
+
+/// Tag of chunks containing a warning, caution or danger admonition.
+pub const WARNING_TAG: &str = "warning";
+
+// Byte ranges of `:::name` ... `:::` admonition containers, nested ones included.
+fn admonition_containers(value: &str) -> Vec<std::ops::Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut open = Vec::new();
+    let mut offset = 0;
+    for line in value.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed == ":::" {
+            if let Some(start) = open.pop() {
+                ranges.push(start..offset + line.len());
+            }
+        } else if trimmed.starts_with(":::") {
+            open.push(offset);
+        }
+        offset += line.len();
+    }
+    // Unclosed containers run to the end of the document.
+    ranges.extend(open.into_iter().map(|start| start..value.len()));
+    ranges
+}
+
+/// Returns tags of the chunk, `WARNING_TAG` if it contains a warning admonition
+/// in markdown (`> **Warning**`, `> [!CAUTION]`, `:::danger`), rst or AsciiDoc.
+pub fn chunk_tags(chunk: &str) -> Vec<String> {
+    let warning_re = Regex::new(
+        r"(?im)^\s*(?:>\s*)?(?:\*\*(?:warning|caution|danger)\*\*|\[!(?:warning|caution)\]|:::\s*(?:warning|caution|danger)|\.\.\s+(?:warning|caution|danger)::|(?:warning|caution):\s)",
+    )
+    .unwrap();
+    match warning_re.is_match(chunk) {
+        true => vec![WARNING_TAG.to_string()],
+        false => Vec::new(),
+    }
+}
+
+/// Splits the document into chunks with the splitter of its format.
+pub fn split(format: DocumentFormat, value: &str) -> Result<Vec<String>> {
+    match format {
+        DocumentFormat::Markdown => split_by_headings(value),
+        DocumentFormat::Rst => Ok(split_rst(value)),
+        DocumentFormat::AsciiDoc => Ok(split_asciidoc(value)),
+        DocumentFormat::Html => Ok(split_html(value)),
+        DocumentFormat::Code => Ok(split_code(value)),
+        DocumentFormat::OpenApi => split_openapi(value),
+        DocumentFormat::Notebook => split_by_headings(&notebook_to_markdown(value)?),
+        DocumentFormat::Man => Ok(split_lines(&man_to_text(value), |lines, i| {
+            lines[i].starts_with("# ") || lines[i].starts_with("## ")
+        })),
+        DocumentFormat::Help => Ok(split_help(value)),
+        DocumentFormat::Pdf => Ok(split_pdf(value)),
+        DocumentFormat::Email => Ok(mail::parse_messages(value)
+            .into_iter()
+            .map(|message| message.body)
+            .collect()),
+    }
+}
 
 pub fn split_by_headings(value: &str) -> Result<Vec<String>> {
     let mut chunks = Vec::new();
     let tree = markdown::to_mdast(value, &ParseOptions::default())
         .map_err(|err| anyhow::anyhow!("Failed to build markdown tree {}", err))?;
+    let containers = admonition_containers(value);
     let mut prev_offset = 0;
     let root = tree.children().unwrap();
     for node in root {
@@ -15,6 +84,13 @@ pub fn split_by_headings(value: &str) -> Result<Vec<String>> {
                     continue;
                 }
                 if let Some(pos) = &heading.position {
+                    // Headings inside admonitions stay with the parent section.
+                    if containers
+                        .iter()
+                        .any(|range| range.contains(&pos.start.offset))
+                    {
+                        continue;
+                    }
                     let chunk = &value[prev_offset..pos.start.offset];
                     if chunk.len() > 8 {
                         chunks.push(chunk.to_owned());
@@ -28,6 +104,642 @@ pub fn split_by_headings(value: &str) -> Result<Vec<String>> {
     Ok(chunks)
 }
 
+// Cuts the lines into sections starting at lines for which `is_section` is true.
+fn split_lines(value: &str, is_section: impl Fn(&[&str], usize) -> bool) -> Vec<String> {
+    let lines: Vec<&str> = value.lines().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for i in 1..lines.len() {
+        if is_section(&lines, i) {
+            chunks.push(lines[start..i].join("\n"));
+            start = i;
+        }
+    }
+    chunks.push(lines[start..].join("\n"));
+    chunks
+        .into_iter()
+        .filter(|chunk| chunk.trim().len() > MIN_CHUNK_LEN)
+        .collect()
+}
+
+// Sections of reStructuredText start at a title underlined with punctuation,
+// optionally overlined as well.
+fn split_rst(value: &str) -> Vec<String> {
+    let is_adornment = |line: &str| {
+        let line = line.trim_end();
+        line.len() > 2
+            && line
+                .chars()
+                .all(|c| matches!(c, '=' | '-' | '~' | '^' | '*' | '+' | '#' | '"'))
+            && line.chars().all(|c| Some(c) == line.chars().next())
+    };
+    split_lines(value, |lines, i| {
+        let title = lines[i].trim();
+        if title.is_empty() || is_adornment(title) {
+            // An overline starts the section as well.
+            return is_adornment(lines[i])
+                && lines.get(i + 2).map_or(false, |line| is_adornment(line))
+                && lines
+                    .get(i + 1)
+                    .map_or(false, |line| !line.trim().is_empty());
+        }
+        let overlined = i > 0 && is_adornment(lines[i - 1]);
+        !overlined && lines.get(i + 1).map_or(false, |line| is_adornment(line))
+    })
+}
+
+// Sections of AsciiDoc start at `=` to `====` followed by a space.
+fn split_asciidoc(value: &str) -> Vec<String> {
+    split_lines(value, |lines, i| {
+        let level = lines[i].chars().take_while(|c| *c == '=').count();
+        (1..=4).contains(&level) && lines[i][level..].starts_with(' ')
+    })
+}
+
+// Splits at `h1` to `h3` tags and strips markup, scripts and styles are dropped.
+fn split_html(value: &str) -> Vec<String> {
+    let heading_re = Regex::new(r"(?i)<h[1-3][\s>]").unwrap();
+    let hidden_re = Regex::new(r"(?is)<(script|style)\b.*?</(script|style)>").unwrap();
+    let tag_re = Regex::new(r"(?s)<[^>]*>").unwrap();
+    let blank_re = Regex::new(r"\n\s*\n+").unwrap();
+
+    let mut offsets: Vec<usize> = heading_re.find_iter(value).map(|m| m.start()).collect();
+    offsets.insert(0, 0);
+    offsets.push(value.len());
+    offsets
+        .windows(2)
+        .map(|pair| {
+            let html = hidden_re.replace_all(&value[pair[0]..pair[1]], "");
+            let text = tag_re.replace_all(&html, "\n");
+            let text = decode_entities(&text);
+            blank_re.replace_all(text.trim(), "\n\n").to_string()
+        })
+        .filter(|chunk| chunk.len() > MIN_CHUNK_LEN)
+        .collect()
+}
+
+// Renders a roff man(7) page as plain text, `.SH` and `.SS` become `#` and
+// `##` headings so that sections can be split.
+fn man_to_text(value: &str) -> String {
+    let mut text = String::new();
+    for line in value.lines() {
+        let Some(request) = line.strip_prefix('.').or_else(|| line.strip_prefix('\'')) else {
+            text.push_str(&man_escapes(line));
+            text.push('\n');
+            continue;
+        };
+        let (name, args) = request
+            .trim_start()
+            .split_once(' ')
+            .unwrap_or((request, ""));
+        let args = man_escapes(args.trim());
+        match name {
+            "SH" => text.push_str(&format!("\n# {}\n\n", args.trim_matches('"'))),
+            "SS" => text.push_str(&format!("\n## {}\n\n", args.trim_matches('"'))),
+            "PP" | "P" | "LP" | "sp" | "TP" => text.push('\n'),
+            "IP" => text.push_str(&format!("\n{}\n", args.trim_matches('"'))),
+            // Font macros alternate fonts between arguments, which are joined.
+            "BR" | "RB" | "IR" | "RI" | "BI" | "IB" => {
+                let parts: Vec<&str> = man_args(&args);
+                text.push_str(&parts.concat());
+                text.push('\n');
+            }
+            "B" | "I" | "SM" | "SB" => {
+                text.push_str(&man_args(&args).join(" "));
+                text.push('\n');
+            }
+            // Comments, the title and layout requests have no text.
+            _ => {}
+        }
+    }
+    let blank_re = Regex::new(r"\n\s*\n+").unwrap();
+    blank_re.replace_all(text.trim(), "\n\n").to_string()
+}
+
+// Splits macro arguments at spaces, double quoted arguments may contain spaces.
+fn man_args(args: &str) -> Vec<&str> {
+    let arg_re = Regex::new(r#""([^"]*)"|(\S+)"#).unwrap();
+    arg_re
+        .captures_iter(args)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|arg| arg.as_str())
+        .collect()
+}
+
+// Drops font changes and resolves the common escapes and special characters.
+fn man_escapes(line: &str) -> String {
+    let font_re = Regex::new(r"\\f(\[[^\]]*\]|\(..|.)").unwrap();
+    let special_re = Regex::new(r"\\\((..)|\\\[([^\]]*)\]").unwrap();
+    let line = font_re.replace_all(line, "");
+    let line = special_re.replace_all(&line, |caps: &Captures| {
+        let name = caps
+            .get(1)
+            .or_else(|| caps.get(2))
+            .map_or("", |m| m.as_str());
+        match name {
+            "em" => "\u{2014}",
+            "en" => "\u{2013}",
+            "aq" => "'",
+            "dq" => "\"",
+            "bu" => "\u{2022}",
+            "co" => "\u{a9}",
+            "lq" => "\u{201c}",
+            "rq" => "\u{201d}",
+            _ => "",
+        }
+        .to_string()
+    });
+    line.replace("\\-", "-")
+        .replace("\\&", "")
+        .replace("\\ ", " ")
+        .replace("\\e", "\\")
+}
+
+// Sections of `--help` output start at unindented headings like `Options:`
+// or `COMMANDS`, usage lines stay with the description above them.
+fn split_help(value: &str) -> Vec<String> {
+    split_lines(value, |lines, i| {
+        let line = lines[i].trim_end();
+        if line.is_empty() || line.len() > 40 || line.starts_with(char::is_whitespace) {
+            return false;
+        }
+        let title = line.strip_suffix(':').unwrap_or(line);
+        let upper = !title.is_empty()
+            && title.chars().any(|c| c.is_alphabetic())
+            && title
+                .chars()
+                .all(|c| c.is_uppercase() || c == ' ' || c == '-');
+        let lower = title.to_lowercase();
+        (line.ends_with(':') || upper) && !lower.starts_with("usage") && !line.starts_with('-')
+    })
+}
+
+// Splits extracted PDF text per page, and pages further at numbered headings
+// like `2.1 Design`. Chunks are prefixed with their page number.
+fn split_pdf(value: &str) -> Vec<String> {
+    let heading_re = Regex::new(r"^\d+(\.\d+)*\.?\s+\p{Lu}").unwrap();
+    value
+        .split(pdf::PAGE_BREAK)
+        .enumerate()
+        .flat_map(|(index, page)| {
+            split_lines(page, |lines, i| {
+                lines[i].len() < 80 && heading_re.is_match(lines[i].trim_start())
+            })
+            .into_iter()
+            .map(move |chunk| format!("Page {}\n{}", index + 1, chunk.trim()))
+        })
+        .collect()
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// Packs blocks separated by blank lines into chunks of up to `CODE_CHUNK_CHARS`,
+// so functions and items mostly stay whole.
+fn split_code(value: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for block in value.split("\n\n") {
+        if !chunk.is_empty() && chunk.len() + block.len() > CODE_CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut chunk));
+        }
+        if !chunk.is_empty() {
+            chunk.push_str("\n\n");
+        }
+        chunk.push_str(block);
+    }
+    chunks.push(chunk);
+    chunks
+        .into_iter()
+        .filter(|chunk| chunk.trim().len() > MIN_CHUNK_LEN)
+        .collect()
+}
+
+// One chunk per operation: method, path, summary and the operation itself as YAML.
+// JSON specs parse as well, JSON being a subset of YAML.
+fn split_openapi(value: &str) -> Result<Vec<String>> {
+    let spec: serde_yaml::Value =
+        serde_yaml::from_str(value).context("Failed to parse OpenAPI spec")?;
+    let mut chunks = Vec::new();
+    if let Some(info) = spec.get("info") {
+        chunks.push(serde_yaml::to_string(info)?);
+    }
+    let Some(paths) = spec.get("paths").and_then(|paths| paths.as_mapping()) else {
+        return Ok(chunks);
+    };
+    for (path, item) in paths {
+        let path = path.as_str().unwrap_or_default();
+        let Some(operations) = item.as_mapping() else {
+            continue;
+        };
+        for (method, operation) in operations {
+            // Path items also hold shared `parameters` and `servers`.
+            let method = method.as_str().unwrap_or_default();
+            if !matches!(
+                method,
+                "get" | "put" | "post" | "delete" | "options" | "head" | "patch" | "trace"
+            ) {
+                continue;
+            }
+            let method = method.to_uppercase();
+            let summary = operation
+                .get("summary")
+                .and_then(|summary| summary.as_str())
+                .unwrap_or_default();
+            chunks.push(format!(
+                "{} {}\n{}\n\n{}",
+                method,
+                path,
+                summary,
+                serde_yaml::to_string(operation)?
+            ));
+        }
+    }
+    Ok(chunks)
+}
+
+// Markdown cells as is, code cells fenced with the notebook's language.
+fn notebook_to_markdown(value: &str) -> Result<String> {
+    let notebook: serde_json::Value =
+        serde_json::from_str(value).context("Failed to parse notebook")?;
+    let language = notebook["metadata"]["kernelspec"]["language"]
+        .as_str()
+        .unwrap_or_default();
+    let mut markdown = String::new();
+    for cell in notebook["cells"].as_array().into_iter().flatten() {
+        // Sources are either a string or a list of lines.
+        let source = match &cell["source"] {
+            serde_json::Value::Array(lines) => lines
+                .iter()
+                .filter_map(|line| line.as_str())
+                .collect::<String>(),
+            source => source.as_str().unwrap_or_default().to_string(),
+        };
+        match cell["cell_type"].as_str() {
+            Some("markdown") => markdown.push_str(&source),
+            Some("code") => markdown.push_str(&format!("```{}\n{}\n```", language, source)),
+            _ => continue,
+        }
+        markdown.push_str("\n\n");
+    }
+    Ok(markdown)
+}
+
+const MD_IMAGE_RE: &str = r#"!\[([^\]]*)\]\(\s*<?([^)\s>]+)>?(?:\s+["']([^"']*)["'])?\s*\)"#;
+const HTML_IMAGE_RE: &str = r"(?i)<img\b[^>]*>";
+
+/// Returns urls of markdown and HTML images in the document, as written.
+pub fn image_urls(value: &str) -> Vec<String> {
+    let md_re = Regex::new(MD_IMAGE_RE).unwrap();
+    let html_re = Regex::new(HTML_IMAGE_RE).unwrap();
+    let mut urls: Vec<String> = md_re
+        .captures_iter(value)
+        .map(|caps| caps[2].to_string())
+        .chain(
+            html_re
+                .find_iter(value)
+                .filter_map(|tag| html_attr(tag.as_str(), "src")),
+        )
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// Replaces markdown and HTML images with their alt text, title and figure
+/// caption, so they end up in chunk text; screenshots are often described
+/// nowhere else. `image_texts` maps image urls to text found in the image, e.g. by OCR.
+pub fn describe_images(value: &str, image_texts: &HashMap<String, String>) -> String {
+    let figure_re = Regex::new(r"(?is)<figure\b[^>]*>(.*?)</figure>").unwrap();
+    let caption_re = Regex::new(r"(?is)<figcaption\b[^>]*>(.*?)</figcaption>").unwrap();
+    let md_re = Regex::new(MD_IMAGE_RE).unwrap();
+    let html_re = Regex::new(HTML_IMAGE_RE).unwrap();
+    let tag_re = Regex::new(r"<[^>]*>").unwrap();
+
+    let value = figure_re.replace_all(value, |caps: &Captures| {
+        let inner = &caps[1];
+        let caption = caption_re
+            .captures(inner)
+            .map(|caps| tag_re.replace_all(&caps[1], "").trim().to_string())
+            .unwrap_or_default();
+        let (alt, url, title) = if let Some(caps) = md_re.captures(inner) {
+            let title = caps.get(3).map_or("", |title| title.as_str());
+            (caps[1].to_string(), caps[2].to_string(), title.to_string())
+        } else if let Some(tag) = html_re.find(inner) {
+            (
+                html_attr(tag.as_str(), "alt").unwrap_or_default(),
+                html_attr(tag.as_str(), "src").unwrap_or_default(),
+                html_attr(tag.as_str(), "title").unwrap_or_default(),
+            )
+        } else {
+            Default::default()
+        };
+        image_text(&[&alt, &title, &caption], image_texts.get(&url))
+    });
+    let value = html_re.replace_all(&value, |caps: &Captures| {
+        let tag = &caps[0];
+        let alt = html_attr(tag, "alt").unwrap_or_default();
+        let title = html_attr(tag, "title").unwrap_or_default();
+        let url = html_attr(tag, "src").unwrap_or_default();
+        image_text(&[&alt, &title], image_texts.get(&url))
+    });
+    md_re
+        .replace_all(&value, |caps: &Captures| {
+            let title = caps.get(3).map_or("", |title| title.as_str());
+            image_text(&[&caps[1], title], image_texts.get(&caps[2]))
+        })
+        .to_string()
+}
+
+// Joins non-empty descriptions into `Image: alt. title. caption`.
+fn image_text(parts: &[&str], ocr: Option<&String>) -> String {
+    let parts: Vec<&str> = parts
+        .iter()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect();
+    let mut text = match parts.is_empty() {
+        true => String::new(),
+        false => format!("Image: {}.", parts.join(". ")),
+    };
+    if let Some(ocr) = ocr.filter(|ocr| !ocr.trim().is_empty()) {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&format!("Text in image: {}", ocr.trim()));
+    }
+    text
+}
+
+// Attributes follow whitespace, so `alt` doesn't match inside `data-alt`.
+fn html_attr(tag: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(
+        r#"(?i)\s{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#,
+        regex::escape(name)
+    ))
+    .unwrap();
+    let caps = re.captures(tag)?;
+    caps.get(1)
+        .or_else(|| caps.get(2))
+        .or_else(|| caps.get(3))
+        .map(|value| value.as_str().to_string())
+}
+
+/// Languages of fenced code blocks holding diagrams.
+const DIAGRAM_LANGUAGES: &[&str] = &["mermaid", "plantuml", "puml"];
+
+/// Replaces mermaid and PlantUML code blocks with their node and edge labels,
+/// raw diagram syntax only adds noise to embeddings. Blocks are dropped
+/// entirely when `exclude` is set.
+pub fn replace_diagrams(value: &str, exclude: bool) -> String {
+    let mut out = Vec::new();
+    let mut found = false;
+    let mut lines = value.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        let fence_char = trimmed.chars().next().unwrap_or_default();
+        let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+        let is_fence = (fence_char == '`' || fence_char == '~') && fence_len >= 3;
+        // Fence chars are ascii, so the char count is a valid byte offset.
+        let language = match is_fence {
+            true => trimmed[fence_len..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .to_lowercase(),
+            false => String::new(),
+        };
+        if !DIAGRAM_LANGUAGES.contains(&language.as_str()) {
+            out.push(line.to_string());
+            continue;
+        }
+
+        found = true;
+        let mut body = Vec::new();
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line.len() >= fence_len && line.chars().all(|c| c == fence_char) {
+                break;
+            }
+            body.push(line);
+        }
+        let labels = diagram_labels(&body);
+        if !exclude && !labels.is_empty() {
+            out.push(format!("Diagram: {}.", labels.join(", ")));
+        }
+    }
+    match found {
+        true => out.join("\n"),
+        false => value.to_string(),
+    }
+}
+
+// Node texts, edge labels, messages and declared names, in order of appearance.
+fn diagram_labels(lines: &[&str]) -> Vec<String> {
+    let node_re = Regex::new(r#"[\[\(\{>]+"?([^\[\](){}|"]+)"?[\]\)\}]+"#).unwrap();
+    let edge_re = Regex::new(r"\|([^|]+)\|").unwrap();
+    let quoted_re = Regex::new(r#""([^"]+)""#).unwrap();
+    let declared_re = Regex::new(
+        r"^(?:participant|actor|title|class|state|entity|database|component|usecase|section)\s+(.+?)(?:\s+as\s+(.+))?$",
+    )
+    .unwrap();
+    // Styling and directives carry no meaning.
+    let ignored = [
+        "%%",
+        "style",
+        "classDef",
+        "linkStyle",
+        "click",
+        "skinparam",
+        "!",
+        "@",
+    ];
+
+    let mut labels: Vec<String> = Vec::new();
+    for line in lines {
+        if ignored.iter().any(|prefix| line.starts_with(prefix)) {
+            continue;
+        }
+        let mut found: Vec<&str> = Vec::new();
+        if let Some(caps) = declared_re.captures(line) {
+            found.push(caps.get(2).unwrap_or_else(|| caps.get(1).unwrap()).as_str());
+        } else {
+            found.extend(
+                node_re
+                    .captures_iter(line)
+                    .map(|caps| caps.get(1).unwrap().as_str()),
+            );
+            found.extend(
+                edge_re
+                    .captures_iter(line)
+                    .map(|caps| caps.get(1).unwrap().as_str()),
+            );
+            found.extend(
+                quoted_re
+                    .captures_iter(line)
+                    .map(|caps| caps.get(1).unwrap().as_str()),
+            );
+            // Messages of sequence diagrams and descriptions of states.
+            if let Some((_, message)) = line.split_once(':') {
+                found.push(message);
+            }
+        }
+        for label in found {
+            let label = label.trim().trim_matches('"').trim();
+            if !label.is_empty() && !labels.iter().any(|seen| seen == label) {
+                labels.push(label.to_string());
+            }
+        }
+    }
+    labels
+}
+
+/// Returns the level and text of every heading in the document, in order.
+pub fn extract_headings(value: &str) -> Result<Vec<(u8, String)>> {
+    let tree = markdown::to_mdast(value, &ParseOptions::default())
+        .map_err(|err| anyhow::anyhow!("Failed to build markdown tree {}", err))?;
+    let mut headings = Vec::new();
+    for node in tree.children().into_iter().flatten() {
+        if let markdown::mdast::Node::Heading(heading) = node {
+            headings.push((heading.depth, node.to_string().trim().to_string()));
+        }
+    }
+    Ok(headings)
+}
+
+/// Slug of a heading the way GitHub builds it: lowercased, spaces replaced with
+/// dashes, and everything but letters, numbers, combining marks, `-` and `_`
+/// dropped. Non-ASCII letters are kept as they are, emoji are dropped.
+pub fn slug(text: &str) -> String {
+    let is_mark = |c: char| {
+        matches!(c,
+            '\u{0300}'..='\u{036f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe20}'..='\u{fe2f}')
+    };
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || is_mark(c) || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds GitHub style anchors for the headings. Repeated slugs are suffixed
+/// with `-1`, `-2`, ..., skipping suffixes taken by another heading, e.g. a
+/// literal `Example 1` heading.
+pub fn heading_anchors<'a>(headings: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    headings
+        .into_iter()
+        .map(|text| {
+            let slug = slug(text);
+            let mut anchor = slug.clone();
+            while seen.contains_key(&anchor) {
+                let count = seen.entry(slug.clone()).or_default();
+                *count += 1;
+                anchor = format!("{}-{}", slug, count);
+            }
+            seen.insert(anchor.clone(), 0);
+            anchor
+        })
+        .collect()
+}
+
+/// Anchor of the heading each chunk starts with, empty for chunks that don't
+/// start with one, e.g. the intro before the first heading. Headings are
+/// counted across all chunks, so repeated ones get the document's suffixes.
+pub fn chunk_anchors<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>> {
+    let mut texts = Vec::new();
+    let mut firsts = Vec::new();
+    for chunk in chunks {
+        let tree = markdown::to_mdast(chunk, &ParseOptions::default())
+            .map_err(|err| anyhow::anyhow!("Failed to build markdown tree {}", err))?;
+        let nodes = tree
+            .children()
+            .map(|nodes| nodes.as_slice())
+            .unwrap_or_default();
+        firsts.push(match nodes.first() {
+            Some(markdown::mdast::Node::Heading(_)) => Some(texts.len()),
+            _ => None,
+        });
+        for node in nodes {
+            if let markdown::mdast::Node::Heading(_) = node {
+                texts.push(node.to_string().trim().to_string());
+            }
+        }
+    }
+    let anchors = heading_anchors(texts.iter().map(String::as_str));
+    Ok(firsts
+        .into_iter()
+        .map(|first| first.map(|i| anchors[i].clone()).unwrap_or_default())
+        .collect())
+}
+
+/// Returns the YAML front matter of the document, delimited by `---` lines.
+pub fn front_matter(input: &str) -> Option<&str> {
+    let rest = input.trim_start_matches('\u{feff}').strip_prefix("---")?;
+    let rest = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some(&rest[..offset]);
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Front matter rules of sources that don't set their own: drafts, pages
+/// hidden from search and `noindex` pages are skipped.
+pub fn default_skip_front_matter() -> Vec<String> {
+    ["draft=true", "search=false", "noindex"]
+        .map(String::from)
+        .into()
+}
+
+/// Returns the first rule matching the document's front matter, if any.
+/// Rules are `key=value`, matching when the key has that value, or a bare
+/// `key`, matching when the key is set to anything but `false`.
+pub fn excluded_by(input: &str, rules: &HashSet<String>) -> Option<String> {
+    let head: serde_yaml::Mapping = serde_yaml::from_str(front_matter(input)?).ok()?;
+    let mut rules: Vec<&String> = rules.iter().filter(|rule| !rule.is_empty()).collect();
+    rules.sort();
+    rules
+        .into_iter()
+        .find(|rule| {
+            let (key, expected) = match rule.split_once('=') {
+                Some((key, value)) => (key.trim(), Some(value.trim())),
+                None => (rule.trim(), None),
+            };
+            let value = match head.get(key) {
+                Some(serde_yaml::Value::Bool(value)) => value.to_string(),
+                Some(serde_yaml::Value::Number(value)) => value.to_string(),
+                Some(serde_yaml::Value::String(value)) => value.clone(),
+                Some(serde_yaml::Value::Null) | None => return false,
+                Some(_) => String::new(),
+            };
+            match expected {
+                Some(expected) => value.eq_ignore_ascii_case(expected),
+                None => value != "false",
+            }
+        })
+        .cloned()
+}
+
 #[derive(Debug)]
 pub struct Head {
     pub subcategory: String,
@@ -87,6 +799,196 @@ pub fn remove_head(input: String) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_extract_headings() {
+        let input = "# Resource: `aws_acm_certificate`\n\nText\n\n## Example Usage\n\n```\n# not a heading\n```";
+        let headings = extract_headings(input).unwrap();
+        assert_eq!(
+            headings,
+            vec![
+                (1, "Resource: aws_acm_certificate".to_string()),
+                (2, "Example Usage".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_heading_anchors() {
+        let anchors = heading_anchors([
+            "Resource: aws_acm_certificate",
+            "Example Usage",
+            "Example Usage",
+        ]);
+        assert_eq!(
+            anchors,
+            vec![
+                "resource-aws_acm_certificate",
+                "example-usage",
+                "example-usage-1"
+            ]
+        );
+
+        let anchors = heading_anchors(["Foo", "Foo 1", "Foo", "Foo"]);
+        assert_eq!(anchors, vec!["foo", "foo-1", "foo-2", "foo-3"]);
+
+        let anchors =
+            heading_anchors(["Über uns", "🚀 Launch", "Café", "日本語 API", "C++ & Rust?"]);
+        assert_eq!(
+            anchors,
+            vec!["über-uns", "-launch", "café", "日本語-api", "c--rust"]
+        );
+    }
+
+    #[test]
+    fn test_chunk_anchors() {
+        let chunks = split_by_headings(
+            "Intro text here\n\n# Setup\n\nInstall it.\n\n## Setup\n\nAgain.\n\n#### Notes\n\nMore.\n\n## Usage\n\nRun it.\n",
+        )
+        .unwrap();
+        assert_eq!(
+            chunk_anchors(chunks.iter().map(String::as_str)).unwrap(),
+            vec!["", "setup", "setup-1"]
+        );
+    }
+
+    #[test]
+    fn test_split_by_format() {
+        let rst = "Intro text here\n\nInstall\n=======\n\nRun the installer.\n\nUsage\n-----\n\nCall the widget.";
+        assert_eq!(
+            split(DocumentFormat::Rst, rst).unwrap(),
+            vec![
+                "Intro text here\n",
+                "Install\n=======\n\nRun the installer.\n",
+                "Usage\n-----\n\nCall the widget."
+            ]
+        );
+
+        let adoc = "= Guide\n\nIntro.\n\n== Install\n\nRun the installer.";
+        assert_eq!(
+            split(DocumentFormat::AsciiDoc, adoc).unwrap(),
+            vec!["= Guide\n\nIntro.\n", "== Install\n\nRun the installer."]
+        );
+
+        let html = "<h1>Guide</h1><p>Intro &amp; more.</p><script>x()</script><h2>Install</h2><p>Run it.</p>";
+        assert_eq!(
+            split(DocumentFormat::Html, html).unwrap(),
+            vec!["Guide\n\nIntro & more.", "Install\n\nRun it."]
+        );
+
+        let man = ".TH LS 1\n.SH NAME\nls \\- list directory contents\n.SH OPTIONS\n.TP\n.BR \\-a \", \" \\-\\-all\ndo not ignore entries starting with \\fB.\\fR";
+        assert_eq!(
+            split(DocumentFormat::Man, man).unwrap(),
+            vec![
+                "# NAME\n\nls - list directory contents\n",
+                "# OPTIONS\n\n-a, --all\ndo not ignore entries starting with ."
+            ]
+        );
+
+        let help = "Manage widgets.\n\nUsage: widgets [OPTIONS] <COMMAND>\n\nCommands:\n  spin  Spin a widget\n\nOptions:\n  -v, --verbose  Print more\n  -h, --help     Print help";
+        assert_eq!(
+            split(DocumentFormat::Help, help).unwrap(),
+            vec![
+                "Manage widgets.\n\nUsage: widgets [OPTIONS] <COMMAND>\n",
+                "Commands:\n  spin  Spin a widget\n",
+                "Options:\n  -v, --verbose  Print more\n  -h, --help     Print help"
+            ]
+        );
+
+        let pdf = "Widget Design\nAbstract text.\n1. Introduction\nWidgets spin.\x0c2.1 Limits\nAt most ten.";
+        assert_eq!(
+            split(DocumentFormat::Pdf, pdf).unwrap(),
+            vec![
+                "Page 1\nWidget Design\nAbstract text.",
+                "Page 1\n1. Introduction\nWidgets spin.",
+                "Page 2\n2.1 Limits\nAt most ten."
+            ]
+        );
+
+        let spec = "openapi: 3.0.0\npaths:\n  /widgets:\n    get:\n      summary: List widgets\n";
+        let chunks = split(DocumentFormat::OpenApi, spec).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].starts_with("GET /widgets\nList widgets"));
+    }
+
+    #[test]
+    fn test_describe_images() {
+        let input = "Open the settings.\n\n![Settings page](img/settings.png \"Toggles\")\n\n<figure><img src=\"a.png\" alt=\"Dashboard\"><figcaption>The <b>main</b> view</figcaption></figure>\n\n![](badge.svg)";
+        let ocr = HashMap::from([("img/settings.png".to_string(), "Dark mode".to_string())]);
+        assert_eq!(
+            describe_images(input, &ocr),
+            "Open the settings.\n\nImage: Settings page. Toggles. Text in image: Dark mode\n\nImage: Dashboard. The main view.\n\n"
+        );
+        assert_eq!(
+            image_urls(input),
+            vec!["a.png", "badge.svg", "img/settings.png"]
+        );
+
+        let input =
+            "<img data-alt=\"lazy\" src=\"b.png\" alt='Login form'> <img src=c.png alt=Logo>";
+        assert_eq!(
+            describe_images(input, &HashMap::new()),
+            "Image: Login form. Image: Logo."
+        );
+    }
+
+    #[test]
+    fn test_replace_diagrams() {
+        let input = "Flow:\n\n```mermaid\ngraph TD\n  A[Start] -->|valid| B{Save?}\n  style A fill:#f9f\n```\n\n```plantuml\nactor User\nUser -> App : Login\n```\nDone.";
+        assert_eq!(
+            replace_diagrams(input, false),
+            "Flow:\n\nDiagram: Start, Save?, valid.\n\nDiagram: User, Login.\nDone."
+        );
+        assert_eq!(replace_diagrams(input, true), "Flow:\n\n\nDone.");
+        assert_eq!(
+            replace_diagrams("```rust\nfn a() {}\n```\n", false),
+            "```rust\nfn a() {}\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_admonitions() {
+        let input = "# Setup\n\nInstall it.\n\n:::warning\n## Limits\nOnly 10 widgets.\n:::\n\n# Usage\n\nRun it.\n";
+        let chunks = split_by_headings(input).unwrap();
+        assert_eq!(
+            chunks,
+            vec!["# Setup\n\nInstall it.\n\n:::warning\n## Limits\nOnly 10 widgets.\n:::\n\n"]
+        );
+        assert_eq!(chunk_tags(&chunks[0]), vec![WARNING_TAG]);
+        assert_eq!(
+            chunk_tags("> [!NOTE]\n> Just a note."),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            chunk_tags("> **Warning**\n> Data is lost."),
+            vec![WARNING_TAG]
+        );
+    }
+
+    #[test]
+    fn test_excluded_by() {
+        let rules: HashSet<String> = ["draft=true", "search=false", "noindex"]
+            .map(String::from)
+            .into();
+        let doc = |head: &str| format!("---\ntitle: Page\n{}\n---\n# Page", head);
+        assert_eq!(
+            excluded_by(&doc("draft: true"), &rules).as_deref(),
+            Some("draft=true")
+        );
+        assert_eq!(
+            excluded_by(&doc("search: false"), &rules).as_deref(),
+            Some("search=false")
+        );
+        assert_eq!(
+            excluded_by(&doc("noindex: yes"), &rules).as_deref(),
+            Some("noindex")
+        );
+        assert_eq!(excluded_by(&doc("draft: false"), &rules), None);
+        assert_eq!(
+            excluded_by("# No front matter\n\ndraft: true", &rules),
+            None
+        );
+    }
+
     #[test]
     fn test_extract_head() {
         let input = r#"---subcategory: "ACM"---Other content"#;
@@ -1,16 +1,28 @@
 use anyhow::{anyhow, Error};
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde_json::json;
 
+/// Marks responses of `ServerError::NoContent`, which `/api/v1` reports as 404s.
+#[derive(Clone, Copy, Debug)]
+pub struct Missing;
+
 #[allow(unused)]
 pub enum ServerError {
     DbError(Error),
-    // ValidationError(Error),
+    ValidationError(Error),
     NoContent(Error),
+    NotFound(Error),
+    Unauthorized(Error),
+    Forbidden(Error),
+    /// Carries the seconds until requests are accepted again.
+    RateLimited(u64),
+    Conflict(Error),
+    /// A service the request depends on, e.g. the OIDC provider, failed.
+    BadGateway(Error),
     EncodingError(Error),
     GitHubAPIError(Error),
     Embeddings(Error),
@@ -23,18 +35,56 @@ impl IntoResponse for ServerError {
                 tracing::error!("{:?}", err);
                 HTTPError::iternal_error().into_response()
             }
-            // ServerError::ValidationError(err) => {
-            //     tracing::error!("{:?}", err);
-            //     HTTPError::new(err)
-            //         .with_status(StatusCode::BAD_REQUEST)
-            //         .into_response()
-            // }
-            ServerError::NoContent(err) => {
+            ServerError::ValidationError(err) => {
                 tracing::error!("{:?}", err);
                 HTTPError::new(err)
+                    .with_status(StatusCode::BAD_REQUEST)
+                    .into_response()
+            }
+            ServerError::NoContent(err) => {
+                tracing::error!("{:?}", err);
+                let mut resp = HTTPError::new(err)
                     .with_status(StatusCode::NO_CONTENT)
+                    .into_response();
+                resp.extensions_mut().insert(Missing);
+                resp
+            }
+            ServerError::NotFound(err) => {
+                tracing::warn!("{:?}", err);
+                HTTPError::new(err)
+                    .with_status(StatusCode::NOT_FOUND)
+                    .into_response()
+            }
+            ServerError::Conflict(err) => {
+                tracing::warn!("{:?}", err);
+                HTTPError::new(err)
+                    .with_status(StatusCode::CONFLICT)
+                    .into_response()
+            }
+            ServerError::Unauthorized(err) => {
+                tracing::warn!("{:?}", err);
+                HTTPError::new(err)
+                    .with_status(StatusCode::UNAUTHORIZED)
+                    .into_response()
+            }
+            ServerError::Forbidden(err) => {
+                tracing::warn!("{:?}", err);
+                HTTPError::new(err)
+                    .with_status(StatusCode::FORBIDDEN)
+                    .into_response()
+            }
+            ServerError::BadGateway(err) => {
+                tracing::error!("{:?}", err);
+                HTTPError::new(anyhow!("Upstream service failed"))
+                    .with_status(StatusCode::BAD_GATEWAY)
                     .into_response()
             }
+            ServerError::RateLimited(retry_after) => (
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                HTTPError::new(anyhow!("Rate limit exceeded"))
+                    .with_status(StatusCode::TOO_MANY_REQUESTS),
+            )
+                .into_response(),
             ServerError::GitHubAPIError(err) | ServerError::Embeddings(err) => {
                 tracing::error!("{:?}", err);
                 HTTPError::iternal_error().into_response()
@@ -0,0 +1,29 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+/// Builds a strong ETag from a fingerprint that changes whenever the payload does,
+/// e.g. document checksums or a collection version.
+pub fn etag(fingerprint: &str) -> String {
+    format!("\"{:08x}\"", crc32fast::hash(fingerprint.as_bytes()))
+}
+
+/// Checks whether `If-None-Match` contains the ETag, meaning the client's copy is fresh.
+pub fn is_fresh(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+pub fn not_modified(etag: &str) -> Response {
+    (StatusCode::NOT_MODIFIED, [(header::ETAG, etag.to_string())]).into_response()
+}
+
+pub fn with_etag(etag: &str, resp: impl IntoResponse) -> Response {
+    ([(header::ETAG, etag.to_string())], resp).into_response()
+}
@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use tokio::time::Instant;
+
+use crate::{index, AppState};
+
+/// Comparison of two embedding models on the queries logged for a collection.
+#[derive(Serialize, Debug)]
+pub struct EvalReport {
+    pub collection: String,
+    pub baseline: String,
+    pub candidate: String,
+    pub queries: usize,
+    /// Mean Jaccard overlap of the top-k results of both models.
+    pub mean_overlap: f32,
+    pub baseline_mean_top_score: f32,
+    pub candidate_mean_top_score: f32,
+    pub baseline_mean_latency_ms: f32,
+    pub candidate_mean_latency_ms: f32,
+    pub items: Vec<EvalItem>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EvalItem {
+    pub query: String,
+    pub overlap: f32,
+    pub baseline_paths: Vec<String>,
+    pub candidate_paths: Vec<String>,
+}
+
+struct Run {
+    ids: HashSet<String>,
+    paths: Vec<String>,
+    top_score: f32,
+    latency_ms: f32,
+}
+
+/// Replays up to `limit` logged queries against the collection's primary model
+/// and the candidate model, comparing their top-k results.
+pub async fn compare_models(
+    state: &AppState,
+    collection: &str,
+    candidate: &str,
+    limit: i64,
+    k: usize,
+) -> Result<EvalReport> {
+    let baseline = index::resolve_collection(&state.tinyvector, collection, None)
+        .await
+        .ok_or_else(|| anyhow!("Collection '{}' is not loaded", collection))?;
+    let variant = index::resolve_collection(&state.tinyvector, collection, Some(candidate))
+        .await
+        .ok_or_else(|| anyhow!("Model '{}' is not loaded for '{}'", candidate, collection))?;
+    let baseline_model = state.embeddings(&baseline.model)?;
+    let candidate_model = state.embeddings(&variant.model)?;
+
+    let queries = state
+        .db
+        .query_logged_queries(collection, limit)
+        .await
+        .context("Failed to query logged queries")?;
+
+    let mut items = Vec::with_capacity(queries.len());
+    let (mut overlap_sum, mut baseline_score_sum, mut candidate_score_sum) = (0.0, 0.0, 0.0);
+    let (mut baseline_latency_sum, mut candidate_latency_sum) = (0.0, 0.0);
+    for query in queries {
+        let a = run_query(&baseline_model, &baseline, &query, k).await?;
+        let b = run_query(&candidate_model, &variant, &query, k).await?;
+
+        let union = a.ids.union(&b.ids).count();
+        let overlap = if union == 0 {
+            1.0
+        } else {
+            a.ids.intersection(&b.ids).count() as f32 / union as f32
+        };
+
+        overlap_sum += overlap;
+        baseline_score_sum += a.top_score;
+        candidate_score_sum += b.top_score;
+        baseline_latency_sum += a.latency_ms;
+        candidate_latency_sum += b.latency_ms;
+        items.push(EvalItem {
+            query,
+            overlap,
+            baseline_paths: a.paths,
+            candidate_paths: b.paths,
+        });
+    }
+
+    let n = items.len().max(1) as f32;
+    Ok(EvalReport {
+        collection: collection.to_string(),
+        baseline: baseline.model.clone(),
+        candidate: variant.model.clone(),
+        queries: items.len(),
+        mean_overlap: overlap_sum / n,
+        baseline_mean_top_score: baseline_score_sum / n,
+        candidate_mean_top_score: candidate_score_sum / n,
+        baseline_mean_latency_ms: baseline_latency_sum / n,
+        candidate_mean_latency_ms: candidate_latency_sum / n,
+        items,
+    })
+}
+
+async fn run_query(
+    model: &crate::Embeddings,
+    collection: &crate::tinyvector::Collection,
+    query: &str,
+    k: usize,
+) -> Result<Run> {
+    let instant = Instant::now();
+    let vector = model
+        .encode(&[query.to_string()])
+        .await
+        .context("Failed to create embedding")?;
+    let results = collection.get_similarity(&vector[0], k);
+    let latency_ms = instant.elapsed().as_secs_f32() * 1000.0;
+
+    Ok(Run {
+        ids: results.iter().map(|r| r.embedding.id.clone()).collect(),
+        paths: results
+            .iter()
+            .map(|r| r.embedding.metadata.path.clone())
+            .collect(),
+        top_score: results.first().map(|r| r.score).unwrap_or_default(),
+        latency_ms,
+    })
+}
@@ -0,0 +1,119 @@
+use anyhow::{Context, Result};
+use sailfish::TemplateOnce;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+use crate::Db;
+
+/// Words shorter or longer than this aren't added to the keyword index.
+const MIN_WORD_LEN: usize = 2;
+const MAX_WORD_LEN: usize = 40;
+
+#[derive(TemplateOnce)]
+#[template(path = "export/doc.html")]
+struct DocPage<'a> {
+    repo: &'a str,
+    path: &'a str,
+    source_url: &'a str,
+    html: String,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "export/index.html")]
+struct IndexPage {
+    data: Vec<IndexedDoc>,
+}
+
+#[derive(Serialize)]
+struct IndexedDoc {
+    repo: String,
+    path: String,
+    href: String,
+}
+
+/// Keyword index searched by the exported index page.
+#[derive(Serialize, Default)]
+struct SearchIndex {
+    docs: Vec<IndexedDoc>,
+    /// Word to `(position in docs, occurrences)` pairs.
+    terms: BTreeMap<String, Vec<(usize, u32)>>,
+}
+
+/// Renders every indexed document to static HTML under `dir`, along with an
+/// index page that searches a precomputed keyword index in the browser.
+/// Returns the number of exported documents.
+pub async fn export_site(db: &Db, dir: &Path) -> Result<usize> {
+    let docs_dir = dir.join("docs");
+    tokio::fs::create_dir_all(&docs_dir)
+        .await
+        .with_context(|| format!("Failed to create '{}'", docs_dir.display()))?;
+
+    let entries = db
+        .query_document_entries()
+        .await
+        .context("Failed to query documents")?;
+    let mut index = SearchIndex::default();
+    for entry in entries {
+        // Documents are loaded one by one, so large indexes don't have to fit in memory.
+        let document = db
+            .select_document_by_id(entry.id)
+            .await
+            .context("Failed to select document")?;
+        let repo = format!("{}/{}", entry.owner, entry.repo);
+        let source_url = format!(
+            "https://github.com/{}/blob/{}/{}",
+            repo, entry.branch, entry.path
+        );
+        let page = DocPage {
+            repo: &repo,
+            path: &entry.path,
+            source_url: &source_url,
+            html: markdown::to_html(&document.data),
+        }
+        .render_once()
+        .context("Failed to render document")?;
+        let file = format!("{}.html", entry.id);
+        tokio::fs::write(docs_dir.join(&file), page)
+            .await
+            .with_context(|| format!("Failed to write '{}'", file))?;
+
+        let position = index.docs.len();
+        for (word, count) in word_counts(&document.data) {
+            index.terms.entry(word).or_default().push((position, count));
+        }
+        index.docs.push(IndexedDoc {
+            repo,
+            path: entry.path,
+            href: format!("docs/{}", file),
+        });
+    }
+
+    // Loaded as a script rather than fetched, so the site also works from disk.
+    let script = format!(
+        "window.SEARCH_INDEX = {};\n",
+        serde_json::to_string(&index).context("Failed to serialize search index")?
+    );
+    tokio::fs::write(dir.join("search-index.js"), script)
+        .await
+        .context("Failed to write search index")?;
+
+    let exported = index.docs.len();
+    let page = IndexPage { data: index.docs }
+        .render_once()
+        .context("Failed to render index")?;
+    tokio::fs::write(dir.join("index.html"), page)
+        .await
+        .context("Failed to write index")?;
+    Ok(exported)
+}
+
+fn word_counts(text: &str) -> BTreeMap<String, u32> {
+    let mut counts = BTreeMap::new();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let len = word.chars().count();
+        if (MIN_WORD_LEN..=MAX_WORD_LEN).contains(&len) {
+            *counts.entry(word.to_lowercase()).or_default() += 1;
+        }
+    }
+    counts
+}
@@ -0,0 +1,58 @@
+use anyhow::anyhow;
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::FromRequest,
+    http::Request,
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::errors::ServerError;
+
+/// Maximum nesting of arrays and objects accepted in JSON bodies.
+pub const MAX_JSON_DEPTH: usize = 16;
+
+/// Same as `Json`, but rejects bodies nested deeper than `MAX_JSON_DEPTH`.
+pub struct LimitedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for LimitedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<Value>::from_request(req, state)
+            .await
+            .map_err(|err| err.into_response())?;
+
+        if json_depth(&value) > MAX_JSON_DEPTH {
+            return Err(ServerError::ValidationError(anyhow!(
+                "JSON body is nested deeper than {} levels",
+                MAX_JSON_DEPTH
+            ))
+            .into_response());
+        }
+
+        let payload = serde_json::from_value(value).map_err(|err| {
+            ServerError::ValidationError(anyhow!("Invalid JSON body: {}", err)).into_response()
+        })?;
+        Ok(LimitedJson(payload))
+    }
+}
+
+fn json_depth(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => 1 + items.iter().map(json_depth).max().unwrap_or(0),
+        Value::Object(map) => 1 + map.values().map(json_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
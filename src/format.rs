@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Format of a document's content, decides how the document is split into chunks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    #[default]
+    Markdown,
+    Rst,
+    AsciiDoc,
+    Html,
+    Code,
+    OpenApi,
+    Notebook,
+    /// Roff man pages.
+    Man,
+    /// Output of a CLI's `--help`.
+    Help,
+    /// Text extracted from a PDF, pages are separated by form feeds.
+    Pdf,
+    /// An mbox archive or a single EML message.
+    Email,
+}
+
+impl DocumentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentFormat::Markdown => "markdown",
+            DocumentFormat::Rst => "rst",
+            DocumentFormat::AsciiDoc => "asciidoc",
+            DocumentFormat::Html => "html",
+            DocumentFormat::Code => "code",
+            DocumentFormat::OpenApi => "openapi",
+            DocumentFormat::Notebook => "notebook",
+            DocumentFormat::Man => "man",
+            DocumentFormat::Help => "help",
+            DocumentFormat::Pdf => "pdf",
+            DocumentFormat::Email => "email",
+        }
+    }
+
+    /// Detects the format from the path's extension, falling back to sniffing
+    /// the content when the extension is missing or ambiguous, e.g. `.yaml`
+    /// files are only OpenAPI specs if they declare a spec version.
+    pub fn detect(path: &str, data: &str) -> Self {
+        let ext = path
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "md" | "mdx" | "markdown" => DocumentFormat::Markdown,
+            "rst" => DocumentFormat::Rst,
+            "adoc" | "asciidoc" | "asc" => DocumentFormat::AsciiDoc,
+            "html" | "htm" => DocumentFormat::Html,
+            "ipynb" => DocumentFormat::Notebook,
+            "man" | "roff" => DocumentFormat::Man,
+            "help" => DocumentFormat::Help,
+            "pdf" => DocumentFormat::Pdf,
+            "mbox" | "eml" => DocumentFormat::Email,
+            "yaml" | "yml" | "json" if is_openapi(data) => DocumentFormat::OpenApi,
+            "yaml" | "yml" | "json" | "toml" | "rs" | "py" | "go" | "js" | "ts" | "jsx" | "tsx"
+            | "java" | "kt" | "c" | "h" | "cc" | "cpp" | "hpp" | "cs" | "rb" | "php" | "sh"
+            | "swift" | "scala" | "hcl" | "tf" | "sql" => DocumentFormat::Code,
+            _ => Self::sniff(data),
+        }
+    }
+
+    // Guesses the format of content without a known extension, e.g. `.txt` or `README`.
+    fn sniff(data: &str) -> Self {
+        let start = data.trim_start();
+        let lower: String = start.chars().take(64).collect::<String>().to_lowercase();
+        if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+            return DocumentFormat::Html;
+        }
+        if start.starts_with('{') && data.contains("\"nbformat\"") && data.contains("\"cells\"") {
+            return DocumentFormat::Notebook;
+        }
+        if is_openapi(data) {
+            return DocumentFormat::OpenApi;
+        }
+        // Man sections are extensions, e.g. `ls.1` or `printf.3p`, but so are
+        // versions, so only roff content makes a man page.
+        if start.starts_with(".TH ") || start.starts_with(".\\\"") || start.starts_with("'\\\"") {
+            return DocumentFormat::Man;
+        }
+        if lower.starts_with("usage:") {
+            return DocumentFormat::Help;
+        }
+        if start.starts_with("= ") || data.lines().any(|line| line.starts_with(":toc:")) {
+            return DocumentFormat::AsciiDoc;
+        }
+        // Section underlines look like markdown setext headings, directives don't.
+        if data.lines().any(|line| line.starts_with(".. ")) {
+            return DocumentFormat::Rst;
+        }
+        DocumentFormat::Markdown
+    }
+}
+
+impl FromStr for DocumentFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" => Ok(DocumentFormat::Markdown),
+            "rst" => Ok(DocumentFormat::Rst),
+            "asciidoc" => Ok(DocumentFormat::AsciiDoc),
+            "html" => Ok(DocumentFormat::Html),
+            "code" => Ok(DocumentFormat::Code),
+            "openapi" => Ok(DocumentFormat::OpenApi),
+            "notebook" => Ok(DocumentFormat::Notebook),
+            "man" => Ok(DocumentFormat::Man),
+            "help" => Ok(DocumentFormat::Help),
+            "pdf" => Ok(DocumentFormat::Pdf),
+            "email" => Ok(DocumentFormat::Email),
+            _ => Err(format!("Unknown document format '{}'", s)),
+        }
+    }
+}
+
+// OpenAPI and Swagger specs declare their version in a top level key.
+fn is_openapi(data: &str) -> bool {
+    data.lines().take(50).any(|line| {
+        let line = line.trim_start_matches(|c: char| c == '{' || c.is_whitespace());
+        ["openapi", "swagger", "\"openapi\"", "\"swagger\""]
+            .iter()
+            .any(|key| {
+                line.strip_prefix(key)
+                    .map_or(false, |rest| rest.trim_start().starts_with(':'))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let cases = [
+            ("docs/index.md", "# Title", DocumentFormat::Markdown),
+            ("docs/index.rst", "Title\n=====", DocumentFormat::Rst),
+            ("guide.adoc", "= Guide", DocumentFormat::AsciiDoc),
+            (
+                "api/openapi.yaml",
+                "openapi: 3.0.0\ninfo:",
+                DocumentFormat::OpenApi,
+            ),
+            ("config.yaml", "name: widgets", DocumentFormat::Code),
+            ("src/lib.rs", "pub fn main() {}", DocumentFormat::Code),
+            ("notes.ipynb", "{}", DocumentFormat::Notebook),
+            (
+                "README",
+                "Title\n=====\n\n.. note:: Text",
+                DocumentFormat::Rst,
+            ),
+            ("CHANGES", "Title\n=====\n\nText", DocumentFormat::Markdown),
+            ("page.txt", "<!DOCTYPE html><html>", DocumentFormat::Html),
+            ("NOTES", "Some text", DocumentFormat::Markdown),
+            ("man/ls.1", ".TH LS 1", DocumentFormat::Man),
+            (
+                "printf.3p",
+                ".\\\" Copyright\n.TH PRINTF",
+                DocumentFormat::Man,
+            ),
+            (
+                "docs/kubectl.txt",
+                "Usage:\n  kubectl [flags]",
+                DocumentFormat::Help,
+            ),
+            ("v1.2", "Release notes", DocumentFormat::Markdown),
+            ("docs/design.pdf", "Overview", DocumentFormat::Pdf),
+            ("archive/2023-08.mbox", "From jane", DocumentFormat::Email),
+        ];
+        for (path, data, format) in cases {
+            assert_eq!(DocumentFormat::detect(path, data), format, "{}", path);
+        }
+    }
+}
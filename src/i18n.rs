@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet};
+
+/// Language of the canonical docs, translations are copies of them.
+const CANONICAL_LANGUAGE: &str = "en";
+
+/// Directory of Docusaurus translations, `i18n/<language>/...`.
+const I18N_DIR: &str = "i18n";
+
+/// Languages docs are commonly translated to. Directories and file suffixes
+/// are only taken for languages of this list, e.g. `docs/go/` isn't one.
+const LANGUAGES: &[&str] = &[
+    "ar", "bg", "bn", "ca", "cs", "da", "de", "el", "es", "fa", "fi", "fr", "he", "hi", "hu", "id",
+    "it", "ja", "ko", "ms", "nl", "no", "pl", "pt", "ro", "ru", "sk", "sv", "ta", "th", "tr", "uk",
+    "ur", "vi", "zh",
+];
+
+/// Docusaurus plugin directories of translated docs and blog posts, and the
+/// directories their canonical files live in.
+const DOCUSAURUS_PLUGINS: &[(&str, &str)] = &[
+    ("docusaurus-plugin-content-docs", "docs"),
+    ("docusaurus-plugin-content-blog", "blog"),
+    ("docusaurus-plugin-content-pages", "src/pages"),
+];
+
+/// Translated copy of a canonical document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translation {
+    /// Lowercased language code, e.g. `zh` or `pt-br`.
+    pub language: String,
+    pub canonical_path: String,
+}
+
+/// Finds translated copies among the paths, keyed by their path: files in
+/// `i18n/<language>/` trees, language directories like `docs/zh/` and
+/// suffixed files like `README.zh-CN.md`. A path is only a translation when
+/// its canonical document is one of the paths as well.
+pub fn translations(paths: &[String]) -> HashMap<String, Translation> {
+    let known: HashSet<&str> = paths.iter().map(String::as_str).collect();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let translation = candidates(path)
+                .into_iter()
+                .find_map(|(language, canonical)| {
+                    known.contains(canonical.as_str()).then(|| Translation {
+                        language,
+                        canonical_path: canonical,
+                    })
+                })?;
+            Some((path.clone(), translation))
+        })
+        .collect()
+}
+
+// Language and possible canonical paths of the path, closest match first.
+fn candidates(path: &str) -> Vec<(String, String)> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let (dirs, file) = segments.split_at(segments.len() - 1);
+    let mut candidates = Vec::new();
+
+    // Docusaurus keeps translations apart, `i18n/<language>/<plugin>/<version>/...`.
+    if let Some(at) = dirs.iter().position(|dir| *dir == I18N_DIR) {
+        if let Some(language) = dirs.get(at + 1).and_then(|dir| language(dir, true)) {
+            let rest = &segments[at + 2..];
+            let rest = match DOCUSAURUS_PLUGINS
+                .iter()
+                .find(|(plugin, _)| rest.first() == Some(plugin))
+            {
+                // Docs are versioned, `current` is the unreleased version.
+                Some((plugin, dir)) if *plugin == DOCUSAURUS_PLUGINS[0].0 && rest.len() > 2 => {
+                    [&[*dir][..], &rest[2..]].concat()
+                }
+                Some((_, dir)) => [&[*dir][..], &rest[1..]].concat(),
+                None => rest.to_vec(),
+            };
+            let canonical = [&segments[..at], &rest[..]].concat().join("/");
+            candidates.push((language, canonical));
+        }
+    }
+
+    for (at, dir) in dirs.iter().enumerate() {
+        if let Some(language) = language(dir, false) {
+            let mut removed = segments.clone();
+            removed.remove(at);
+            candidates.push((language.clone(), removed.join("/")));
+            let mut replaced = segments.clone();
+            replaced[at] = CANONICAL_LANGUAGE;
+            candidates.push((language, replaced.join("/")));
+        }
+    }
+
+    // `README.zh-CN.md` is a translation of `README.md`.
+    let parts: Vec<&str> = file[0].split('.').collect();
+    if parts.len() > 2 {
+        let suffix = parts[parts.len() - 2];
+        if let Some(language) = language(suffix, false) {
+            let dir = dirs
+                .iter()
+                .map(|dir| format!("{}/", dir))
+                .collect::<String>();
+            let (stem, ext) = (&parts[..parts.len() - 2], parts[parts.len() - 1]);
+            candidates.push((
+                language.clone(),
+                format!("{}{}.{}", dir, stem.join("."), ext),
+            ));
+            candidates.push((
+                language,
+                format!("{}{}.{}.{}", dir, stem.join("."), CANONICAL_LANGUAGE, ext),
+            ));
+        }
+    }
+    candidates
+}
+
+// The lowercased language code of a directory or suffix, like `zh`, `zh-CN`
+// or `pt_BR`. Any code is taken under `i18n`, elsewhere only known ones.
+fn language(value: &str, any: bool) -> Option<String> {
+    let value = value.to_lowercase().replace('_', "-");
+    let (code, region) = match value.split_once('-') {
+        Some((code, region)) => (code, Some(region)),
+        None => (value.as_str(), None),
+    };
+    let is_code = match any {
+        true => (2..=3).contains(&code.len()) && code.chars().all(|c| c.is_ascii_lowercase()),
+        false => LANGUAGES.contains(&code),
+    };
+    let is_region = region.map_or(true, |region| {
+        (2..=4).contains(&region.len()) && region.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+    match is_code && is_region && code != CANONICAL_LANGUAGE {
+        true => Some(value),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translations() {
+        let paths: Vec<String> = [
+            "README.md",
+            "README.zh-CN.md",
+            "docs/intro.md",
+            "docs/zh/intro.md",
+            "docs/ja/intro.md",
+            "docs/ja/only-in-japanese.md",
+            "guides/en/setup.md",
+            "guides/pt_BR/setup.md",
+            "website/docs/install.md",
+            "website/i18n/fr/docusaurus-plugin-content-docs/current/install.md",
+            "website/i18n/fr/code.json",
+            "docs/go/intro.md",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        let translations = translations(&paths);
+        let mut found: Vec<(&str, &str, &str)> = translations
+            .iter()
+            .map(|(path, translation)| {
+                (
+                    path.as_str(),
+                    translation.language.as_str(),
+                    translation.canonical_path.as_str(),
+                )
+            })
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ("README.zh-CN.md", "zh-cn", "README.md"),
+                ("docs/ja/intro.md", "ja", "docs/intro.md"),
+                ("docs/zh/intro.md", "zh", "docs/intro.md"),
+                ("guides/pt_BR/setup.md", "pt-br", "guides/en/setup.md"),
+                (
+                    "website/i18n/fr/docusaurus-plugin-content-docs/current/install.md",
+                    "fr",
+                    "website/docs/install.md"
+                ),
+            ]
+        );
+    }
+}
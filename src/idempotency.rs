@@ -0,0 +1,161 @@
+use axum::{
+    body::{self, Body, Full},
+    extract::State,
+    http::{header, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+
+use crate::{errors::ServerError, middleware::read_body, types::IdempotencyRecord, AppState};
+
+static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Status of a claimed key whose request hasn't finished yet.
+const IN_PROGRESS: u16 = 0;
+
+/// Replays the stored response for requests retried with the same `Idempotency-Key`.
+///
+/// The key is bound to a hash of the method, path with query and body, reusing it with a
+/// different request is rejected. Only successful responses are stored, so
+/// failed requests can be retried with the same key. Retries arriving while
+/// the first request is still running get a 409 Conflict.
+pub async fn idempotency(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let key = match req.headers().get(&IDEMPOTENCY_KEY) {
+        Some(value) => match value.to_str() {
+            Ok(key) => key.to_string(),
+            Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+        },
+        None => return next.run(req).await,
+    };
+
+    let (parts, req_body) = req.into_parts();
+    let bytes = match read_body(req_body, state.cfg.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(status) => return status.into_response(),
+    };
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(parts.method.as_str().as_bytes());
+    let path = parts
+        .uri
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or_default();
+    hasher.update(path.as_bytes());
+    hasher.update(&bytes);
+    let request_hash = hasher.finalize();
+
+    // The key is claimed before the handler runs, so concurrent retries with
+    // it don't both run it.
+    let claim = IdempotencyRecord {
+        key: key.clone(),
+        request_hash,
+        status: IN_PROGRESS,
+        body: String::new(),
+        created_at: Utc::now(),
+    };
+    match state.db.insert_idempotency_record(&claim).await {
+        Ok(true) => {}
+        Ok(false) => return claimed(&state, &key, request_hash).await,
+        Err(err) => {
+            return ServerError::DbError(anyhow::anyhow!(
+                "Failed to store idempotency key: {}",
+                err
+            ))
+            .into_response()
+        }
+    }
+
+    let resp = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    if !resp.status().is_success() {
+        release(&state, &key).await;
+        return resp;
+    }
+
+    let (parts, resp_body) = resp.into_parts();
+    let bytes = match hyper::body::to_bytes(resp_body).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!("Failed to read response body: {}", err);
+            release(&state, &key).await;
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let body = String::from_utf8_lossy(&bytes);
+    if let Err(err) = state
+        .db
+        .update_idempotency_record(&key, parts.status.as_u16(), &body)
+        .await
+    {
+        tracing::error!("Failed to store idempotency key: {}", err);
+    }
+
+    Response::from_parts(parts, body::boxed(Full::from(bytes)))
+}
+
+// Responds to a request whose key is already taken: replays the stored
+// response, or rejects the request while the first one is still running.
+async fn claimed(state: &AppState, key: &str, request_hash: u32) -> Response {
+    match state.db.select_idempotency_record(key).await {
+        Ok(Some(record)) if record.request_hash != request_hash => {
+            ServerError::ValidationError(anyhow::anyhow!(
+                "Idempotency-Key '{}' was already used with a different request",
+                key
+            ))
+            .into_response()
+        }
+        Ok(Some(record)) if record.status != IN_PROGRESS => {
+            tracing::info!("Replaying response for Idempotency-Key '{}'", key);
+            replay(record)
+        }
+        // Claims outlive their request only if the server stopped while
+        // running it, those are released for the next retry.
+        Ok(Some(record))
+            if Utc::now() - record.created_at
+                > chrono::Duration::from_std(state.cfg.long_timeout).unwrap_or_default() =>
+        {
+            release(state, key).await;
+            ServerError::Conflict(anyhow::anyhow!(
+                "The request with Idempotency-Key '{}' was abandoned, retry it",
+                key
+            ))
+            .into_response()
+        }
+        // Still running, or released by a failed request in the meantime.
+        Ok(_) => ServerError::Conflict(anyhow::anyhow!(
+            "A request with Idempotency-Key '{}' is in progress",
+            key
+        ))
+        .into_response(),
+        Err(err) => {
+            ServerError::DbError(anyhow::anyhow!("Failed to select idempotency key: {}", err))
+                .into_response()
+        }
+    }
+}
+
+// Frees the key of a failed request, so it can be retried with the same key.
+async fn release(state: &AppState, key: &str) {
+    if let Err(err) = state.db.delete_idempotency_record(key).await {
+        tracing::error!("Failed to release idempotency key: {}", err);
+    }
+}
+
+fn replay(record: IdempotencyRecord) -> Response {
+    let status = StatusCode::from_u16(record.status).unwrap_or(StatusCode::OK);
+    if record.body.is_empty() {
+        return status.into_response();
+    }
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        record.body,
+    )
+        .into_response()
+}
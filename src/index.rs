@@ -0,0 +1,534 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::time::Instant;
+
+use crate::{
+    encoder, search, tinyvector,
+    types::{Chunk, Collection, DocumentRef},
+    Db, Metadata, Tiny, Tinyvector,
+};
+
+/// Returns the id under which a chunk is stored in tinyvector.
+pub fn embedding_id(chunk: &Chunk) -> String {
+    format!("{}", chunk.id)
+}
+
+/// Returns tinyvector metadata of a chunk, `documents` are keyed by id.
+pub fn embedding_metadata(chunk: &Chunk, documents: &HashMap<i64, DocumentRef>) -> Metadata {
+    let document = documents
+        .get(&chunk.document_id)
+        .cloned()
+        .unwrap_or_default();
+    Metadata {
+        document_id: chunk.document_id,
+        source_id: chunk.source_id,
+        chunk_index: chunk.chunk_index,
+        path: document.path,
+        nav_path: document.nav_path,
+        nav_index: document.nav_index,
+        url: document.url,
+        tags: chunk.tags.clone(),
+        anchor: chunk.anchor.clone(),
+        language: chunk.language.clone(),
+        model: chunk.model.clone(),
+        model_version: chunk.model_version.clone(),
+        dimension: chunk.dimension,
+    }
+}
+
+/// Same as `embedding_metadata`, for a vector of the chunk created with
+/// another model than the one recorded on it.
+pub fn reembedded_metadata(
+    chunk: &Chunk,
+    documents: &HashMap<i64, DocumentRef>,
+    model: &str,
+    model_version: &str,
+    dimension: usize,
+) -> Metadata {
+    Metadata {
+        model: model.to_string(),
+        model_version: model_version.to_string(),
+        dimension,
+        ..embedding_metadata(chunk, documents)
+    }
+}
+
+/// Words of queries looking for limitations of a feature.
+const CAVEAT_WORDS: &[&str] = &[
+    "caveat",
+    "caution",
+    "danger",
+    "gotcha",
+    "limit",
+    "pitfall",
+    "restriction",
+    "warning",
+];
+
+/// Returns the tag of chunks to boost for the query, chunks with warnings
+/// for queries asking about caveats or limitations.
+pub fn boost_tag(query: &str) -> Option<&'static str> {
+    let query = query.to_lowercase();
+    let asks_for_caveats = query
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| CAVEAT_WORDS.iter().any(|caveat| word.starts_with(caveat)));
+    asks_for_caveats.then_some(encoder::WARNING_TAG)
+}
+
+/// Weight of the link prior, a page linked from `n` others scores `ln(1 + n)` times this higher.
+const LINK_PRIOR_WEIGHT: f32 = 0.02;
+
+/// Returns score priors of the collection's documents keyed by document id,
+/// derived from inbound links, so canonical hub pages rank higher.
+pub async fn link_priors(db: &Db, collection_id: i64) -> Result<HashMap<i64, f32>, sqlx::Error> {
+    let counts = db.query_inbound_link_counts(collection_id).await?;
+    Ok(counts
+        .into_iter()
+        .map(|(document_id, count)| (document_id, LINK_PRIOR_WEIGHT * (count as f32).ln_1p()))
+        .collect())
+}
+
+/// Returns the tinyvector collection name holding vectors of a candidate model.
+pub fn variant_name(collection: &str, model: &str) -> String {
+    format!("{}@{}", collection, model)
+}
+
+/// Names of the collection's builds held by tinyvector: the live one, its
+/// retained snapshots and the variants of its candidate models.
+fn build_names(tiny: &Tiny, name: &str) -> Vec<String> {
+    tiny.collections
+        .keys()
+        .filter(|key| match key.strip_prefix(name) {
+            Some("") => true,
+            Some(rest) => {
+                rest.starts_with('@')
+                    || rest
+                        .strip_prefix('#')
+                        .map_or(false, |version| version.parse::<i64>().is_ok())
+            }
+            None => false,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Drops every build of the collection from tinyvector.
+pub fn delete_builds(tiny: &mut Tiny, name: &str) {
+    for key in build_names(tiny, name) {
+        tiny.collections.remove(&key);
+    }
+}
+
+/// Removes the source's vectors from every build of the collection, so pinned
+/// snapshots and variants stop serving it. Returns the number removed.
+pub fn remove_source_from_builds(tiny: &mut Tiny, name: &str, source_id: i64) -> usize {
+    let mut removed = 0;
+    for key in build_names(tiny, name) {
+        let Some(collection) = tiny.collections.get_mut(&key) else {
+            continue;
+        };
+        let has_source = collection
+            .embeddings
+            .iter()
+            .any(|embedding| embedding.metadata.source_id == source_id);
+        if has_source {
+            let collection = Arc::make_mut(collection);
+            removed += collection.remove_source(source_id);
+            search::index_terms(collection);
+        }
+    }
+    removed
+}
+
+/// Moves every build of the collection to the new name.
+pub fn rename_builds(tiny: &mut Tiny, name: &str, new_name: &str) {
+    for key in build_names(tiny, name) {
+        if let Some(collection) = tiny.collections.remove(&key) {
+            let renamed = format!("{}{}", new_name, &key[name.len()..]);
+            tiny.collections.insert(renamed, collection);
+        }
+    }
+}
+
+/// Returns the collection to search, `model` picks a candidate model's vectors
+/// instead of the collection's primary ones.
+pub async fn resolve_collection(
+    tiny: &Tinyvector,
+    name: &str,
+    model: Option<&str>,
+) -> Option<Arc<tinyvector::Collection>> {
+    let tiny = tiny.read().await;
+    let collection = tiny.get_collection(name)?;
+    match model {
+        Some(model) if model != collection.model => tiny.get_collection(&variant_name(name, model)),
+        _ => Some(collection),
+    }
+}
+
+/// Loads chunks of every collection from the db into tinyvector.
+pub async fn load_tinyvector(db: &Db, tiny: Tinyvector) {
+    let instant = Instant::now();
+    let collections = db
+        .query_collections()
+        .await
+        .expect("Failed to query collections");
+
+    for collection in collections {
+        let chunks = db
+            .query_chunks_by_collection(collection.id)
+            .await
+            .expect("Failed to query chunks");
+        let documents = db
+            .query_document_refs_by_collection(collection.id)
+            .await
+            .expect("Failed to query documents");
+        let priors = link_priors(db, collection.id)
+            .await
+            .expect("Failed to query link priors");
+
+        let candidates = db
+            .query_candidate_models(collection.id)
+            .await
+            .expect("Failed to query candidate models");
+        for model in candidates {
+            let vectors = db
+                .query_chunk_vectors(collection.id, &model)
+                .await
+                .expect("Failed to query chunk vectors");
+            let mut data = build_variant(&collection, &model, &chunks, vectors, &documents);
+            data.set_priors(&priors);
+            search::index_terms(&mut data);
+            tiny.write()
+                .await
+                .swap_collection(variant_name(&collection.name, &model), data);
+        }
+
+        // Builds the collection without holding the lock, so searches aren't
+        // blocked while a large collection is being loaded.
+        let data = build_collection(&collection, chunks, &documents, &priors);
+        tracing::info!(
+            "Loaded {} embeddings into collection '{}'",
+            data.embeddings.len(),
+            collection.name
+        );
+        tiny.write()
+            .await
+            .swap_collection(collection.name.clone(), data);
+    }
+    tracing::info!("Loaded tinyvector, elapsed {:?}", instant.elapsed());
+}
+
+// Builds the tinyvector collection holding the chunks' primary vectors.
+fn build_collection(
+    collection: &Collection,
+    chunks: Vec<Chunk>,
+    documents: &HashMap<i64, DocumentRef>,
+    priors: &HashMap<i64, f32>,
+) -> tinyvector::Collection {
+    let mut data = tinyvector::Collection::new(
+        collection.model.clone(),
+        collection.dimension,
+        collection.distance,
+    );
+    data.index_version = collection.index_version;
+    for chunk in chunks {
+        upsert_chunk(&mut data, chunk, documents);
+    }
+    data.set_priors(priors);
+    search::index_terms(&mut data);
+    data
+}
+
+fn upsert_chunk(
+    data: &mut tinyvector::Collection,
+    chunk: Chunk,
+    documents: &HashMap<i64, DocumentRef>,
+) {
+    let id = embedding_id(&chunk);
+    let metadata = embedding_metadata(&chunk, documents);
+    let chunk_id = chunk.id;
+    if let Err(err) = data.upsert(id, chunk.vector, chunk.data, metadata) {
+        tracing::warn!("Failed to load chunk #{}: {}", chunk_id, err);
+    }
+}
+
+/// Builds of a collection retained after a sync replaced them, so clients
+/// pinning an older `index_version` keep getting the same results.
+pub const RETAINED_SNAPSHOTS: i64 = 3;
+
+/// Returns the tinyvector collection name holding a retained build of the collection.
+pub fn snapshot_name(collection: &str, index_version: i64) -> String {
+    format!("{}#{}", collection, index_version)
+}
+
+/// Returns the build of the collection at the index version, the live one
+/// or a retained snapshot.
+pub async fn resolve_snapshot(
+    tiny: &Tinyvector,
+    name: &str,
+    index_version: i64,
+) -> Option<Arc<tinyvector::Collection>> {
+    let tiny = tiny.read().await;
+    let collection = tiny.get_collection(name)?;
+    match collection.index_version == index_version {
+        true => Some(collection),
+        false => tiny.get_collection(&snapshot_name(name, index_version)),
+    }
+}
+
+/// Increments the collection's index version and swaps in a fresh build of
+/// it, the replaced build is retained as a snapshot. Returns the new version.
+pub async fn publish_collection(
+    db: &Db,
+    tiny: &Tinyvector,
+    collection: &Collection,
+) -> anyhow::Result<i64> {
+    publish(db, tiny, collection, None).await
+}
+
+/// Same as `publish_collection`, but only chunks of the sources are read from
+/// the db, the rest is copied from the live build. Sources without chunks,
+/// e.g. deleted ones, are removed from it.
+pub async fn publish_sources(
+    db: &Db,
+    tiny: &Tinyvector,
+    collection: &Collection,
+    source_ids: &[i64],
+) -> anyhow::Result<i64> {
+    publish(db, tiny, collection, Some(source_ids)).await
+}
+
+async fn publish(
+    db: &Db,
+    tiny: &Tinyvector,
+    collection: &Collection,
+    source_ids: Option<&[i64]>,
+) -> anyhow::Result<i64> {
+    // Publishes of a collection are serialized, so builds are swapped in the
+    // order of their versions and each one starts from the last.
+    let publishing = tiny.write().await.publish_lock(collection.id);
+    let _publishing = publishing.lock().await;
+
+    let index_version = db.bump_index_version(collection.id).await?;
+    let collection = Collection {
+        index_version,
+        ..collection.clone()
+    };
+    let documents = db.query_document_refs_by_collection(collection.id).await?;
+    let priors = link_priors(db, collection.id).await?;
+    let live = tiny.read().await.get_collection(&collection.name);
+    let data = match (source_ids, live) {
+        (Some(source_ids), Some(live)) => {
+            let mut data = (*live).clone();
+            data.index_version = index_version;
+            for &source_id in source_ids {
+                data.remove_source(source_id);
+                for chunk in db.query_chunks_by_source(source_id).await? {
+                    upsert_chunk(&mut data, chunk, &documents);
+                }
+            }
+            data.set_priors(&priors);
+            search::index_terms(&mut data);
+            data
+        }
+        _ => {
+            let chunks = db.query_chunks_by_collection(collection.id).await?;
+            build_collection(&collection, chunks, &documents, &priors)
+        }
+    };
+
+    let mut tiny = tiny.write().await;
+    if let Some(previous) = tiny.swap_collection(collection.name.clone(), data) {
+        let name = snapshot_name(&collection.name, previous.index_version);
+        tiny.collections.insert(name, previous);
+    }
+    let expired = snapshot_name(&collection.name, index_version - RETAINED_SNAPSHOTS - 1);
+    let _ = tiny.delete_collection(&expired);
+    tracing::info!(
+        "Published index version {} of collection '{}'",
+        index_version,
+        collection.name
+    );
+    Ok(index_version)
+}
+
+/// Builds a collection from vectors of a candidate model, `vectors` are keyed by chunk id.
+pub fn build_variant(
+    collection: &Collection,
+    model: &str,
+    chunks: &[Chunk],
+    mut vectors: HashMap<i64, Vec<f32>>,
+    documents: &HashMap<i64, DocumentRef>,
+) -> tinyvector::Collection {
+    let dimension = vectors.values().next().map(|v| v.len()).unwrap_or_default();
+    let mut data = tinyvector::Collection::new(model.to_string(), dimension, collection.distance);
+    for chunk in chunks {
+        let Some(vector) = vectors.remove(&chunk.id) else {
+            continue;
+        };
+        // Candidate vectors don't record the model's version.
+        let metadata = reembedded_metadata(chunk, documents, model, "", vector.len());
+        if let Err(err) = data.upsert(embedding_id(chunk), vector, chunk.data.clone(), metadata) {
+            tracing::warn!(
+                "Failed to load chunk #{} for model '{}': {}",
+                chunk.id,
+                model,
+                err
+            );
+        }
+    }
+    data
+}
+
+/// Difference between chunks stored in the db and embeddings held in tinyvector.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ConsistencyReport {
+    pub collection: String,
+    /// Ids of chunks that are stored in the db but missing in tinyvector.
+    pub missing: Vec<String>,
+    /// Ids of embeddings that are held in tinyvector but have no chunk in the db.
+    pub orphaned: Vec<String>,
+    /// Ids of chunks encoded with another model, model version or dimension
+    /// than the collection's, or whose embedding in tinyvector records another
+    /// model than the chunk. They need a re-embed, fixing leaves them be.
+    #[serde(default)]
+    pub mismatched: Vec<String>,
+    /// Whether missing and orphaned embeddings were fixed.
+    pub fixed: bool,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.orphaned.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Diffs chunk ids in the db against embedding ids in tinyvector for every collection.
+///
+/// Drift usually comes from encode jobs that crashed halfway through.
+/// If `fix` is set, missing chunks are inserted into tinyvector and orphaned
+/// embeddings are removed from it.
+pub async fn check_consistency(
+    db: &Db,
+    tiny: &Tinyvector,
+    fix: bool,
+) -> anyhow::Result<Vec<ConsistencyReport>> {
+    let mut reports = Vec::new();
+    for collection in db.query_collections().await? {
+        let report = check_collection(db, tiny, &collection, fix).await?;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+async fn check_collection(
+    db: &Db,
+    tiny: &Tinyvector,
+    collection: &Collection,
+    fix: bool,
+) -> anyhow::Result<ConsistencyReport> {
+    let chunks = db.query_chunks_by_collection(collection.id).await?;
+
+    // Models recorded by the embeddings, keyed by their id.
+    let (stored, model_version): (HashMap<String, (String, String)>, String) = {
+        let tiny = tiny.read().await;
+        match tiny.get_collection(&collection.name) {
+            Some(c) => (
+                c.embeddings
+                    .iter()
+                    .map(|e| {
+                        let model = (e.metadata.model.clone(), e.metadata.model_version.clone());
+                        (e.id.clone(), model)
+                    })
+                    .collect(),
+                c.model_version.clone(),
+            ),
+            None => (HashMap::new(), String::new()),
+        }
+    };
+    let stored_ids: HashSet<String> = stored.keys().cloned().collect();
+    let expected: HashSet<String> = chunks.iter().map(embedding_id).collect();
+
+    let mut missing: Vec<String> = expected.difference(&stored_ids).cloned().collect();
+    let mut orphaned: Vec<String> = stored_ids.difference(&expected).cloned().collect();
+    let mut mismatched: Vec<String> = chunks
+        .iter()
+        .filter(|chunk| {
+            let recorded = stored
+                .get(&embedding_id(chunk))
+                .map_or(false, |(model, version)| {
+                    (!model.is_empty() && *model != chunk.model)
+                        || (!version.is_empty() && *version != chunk.model_version)
+                });
+            recorded
+                || chunk.vector.len() != collection.dimension
+                || (chunk.dimension != 0 && chunk.dimension != collection.dimension)
+                || (!chunk.model.is_empty() && chunk.model != collection.model)
+                || (!chunk.model_version.is_empty()
+                    && !model_version.is_empty()
+                    && chunk.model_version != model_version)
+        })
+        .map(embedding_id)
+        .collect();
+    missing.sort();
+    orphaned.sort();
+    mismatched.sort();
+
+    let mut report = ConsistencyReport {
+        collection: collection.name.clone(),
+        missing,
+        orphaned,
+        mismatched,
+        fixed: false,
+    };
+    tracing::info!(
+        "Consistency check of '{}': {} missing, {} orphaned, {} mismatched",
+        collection.name,
+        report.missing.len(),
+        report.orphaned.len(),
+        report.mismatched.len()
+    );
+
+    if !fix || (report.missing.is_empty() && report.orphaned.is_empty()) {
+        return Ok(report);
+    }
+
+    // Fixes a copy and swaps it in, searches keep reading the current one
+    // while it's cloned and fixed.
+    let current = tiny.read().await.get_collection(&collection.name);
+    let mut data = match current {
+        Some(current) => (*current).clone(),
+        None => {
+            let mut data = tinyvector::Collection::new(
+                collection.model.clone(),
+                collection.dimension,
+                collection.distance,
+            );
+            data.index_version = collection.index_version;
+            data
+        }
+    };
+    for id in &report.orphaned {
+        data.remove(id);
+    }
+    let documents = db.query_document_refs_by_collection(collection.id).await?;
+    // Mismatched vectors would be refused, they are left for a re-embed.
+    let mismatched: HashSet<&String> = report.mismatched.iter().collect();
+    let missing: HashSet<&String> = report.missing.iter().collect();
+    for chunk in chunks {
+        let id = embedding_id(&chunk);
+        if missing.contains(&id) && !mismatched.contains(&id) {
+            let metadata = embedding_metadata(&chunk, &documents);
+            data.upsert(id, chunk.vector, chunk.data, metadata)?;
+        }
+    }
+    tiny.write()
+        .await
+        .swap_collection(collection.name.clone(), data);
+    report.fixed = true;
+
+    Ok(report)
+}
@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::Serialize;
+use std::future::Future;
+
+use crate::{
+    pipeline::{Cancellation, EncodeReport, ParseReport, SyncReport},
+    types::{Job, JobKind, JobStatus},
+    AppState,
+};
+
+/// Outcome of a job, stored as its result.
+pub trait JobReport: Serialize {
+    /// Documents the job parsed or encoded.
+    fn documents(&self) -> usize;
+}
+
+impl JobReport for ParseReport {
+    fn documents(&self) -> usize {
+        self.documents
+    }
+}
+
+impl JobReport for EncodeReport {
+    fn documents(&self) -> usize {
+        self.documents
+    }
+}
+
+impl JobReport for SyncReport {
+    fn documents(&self) -> usize {
+        self.encode.documents
+    }
+}
+
+/// Registers a queued job of the source and runs it in the background,
+/// recording whether it succeeded and its report, or why it failed. `run` is
+/// given the job's cancellation, see `Cancellations::cancel`. A job that
+/// panics fails. Returns the job's id.
+pub async fn spawn<R, F, T>(state: &AppState, source_id: i64, kind: JobKind, run: R) -> Result<i64>
+where
+    R: FnOnce(Cancellation) -> F,
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: JobReport + Send + 'static,
+{
+    let now = Utc::now();
+    let mut job = Job {
+        id: 0,
+        source_id,
+        kind,
+        status: JobStatus::Queued,
+        documents: 0,
+        error: String::new(),
+        result: None,
+        created_at: now,
+        updated_at: now,
+    };
+    job.id = state
+        .db
+        .insert_job(&job)
+        .await
+        .context("Failed to insert job")?;
+    let id = job.id;
+    let cancel = state.cancellations.register(id);
+    let run = run(cancel.clone());
+
+    let db = state.db.clone();
+    let cancellations = state.cancellations.clone();
+    tokio::spawn(async move {
+        job.status = JobStatus::Running;
+        if let Err(err) = db.update_job(&job).await {
+            tracing::warn!("Failed to mark job #{} as running: {:?}", job.id, err);
+        }
+        // Run as a task of its own, so a panic fails the job instead of leaving
+        // it running.
+        match tokio::spawn(run).await {
+            Ok(Ok(report)) => {
+                job.status = JobStatus::Succeeded;
+                job.documents = report.documents();
+                job.result = serde_json::to_value(report).ok();
+            }
+            Ok(Err(err)) if cancel.is_cancelled() => {
+                tracing::info!("Cancelled job #{}: {:#}", job.id, err);
+                job.status = JobStatus::Cancelled;
+                job.error = format!("{:#}", err);
+            }
+            Ok(Err(err)) => {
+                tracing::error!(
+                    "Failed to {} source #{} in job #{}: {:?}",
+                    kind.as_str(),
+                    source_id,
+                    job.id,
+                    err
+                );
+                job.status = JobStatus::Failed;
+                job.error = format!("{:#}", err);
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Job #{} to {} source #{} panicked: {}",
+                    job.id,
+                    kind.as_str(),
+                    source_id,
+                    err
+                );
+                job.status = JobStatus::Failed;
+                job.error = format!("Job panicked: {}", err);
+            }
+        }
+        if let Err(err) = db.update_job(&job).await {
+            tracing::error!("Failed to update job #{}: {:?}", job.id, err);
+        }
+        cancellations.remove(job.id);
+    });
+    Ok(id)
+}
@@ -1,56 +1,231 @@
-use axum::{routing::IntoMakeService, Router, Server};
+// Without the server most of the crate is only reachable through `rtfm_core`.
+#![cfg_attr(not(feature = "server"), allow(dead_code, unused_imports))]
+
+#[cfg(feature = "server")]
+use axum::{extract::DefaultBodyLimit, routing::IntoMakeService, Router, Server};
+#[cfg(feature = "server")]
 use hyper::server::conn::AddrIncoming;
 use octocrab::Octocrab;
-use std::{sync::Arc, time::Duration};
-use tower_http::{
-    cors::{AllowHeaders, Any, CorsLayer},
-    timeout::TimeoutLayer,
-};
+use std::sync::Arc;
 
+mod access;
+mod aliases;
+#[cfg(feature = "server")]
+mod archive;
+mod ask;
+#[cfg(feature = "server")]
+mod audit;
+mod breaker;
+pub use breaker::Breakers;
 mod cfg;
+mod coverage;
+#[cfg(feature = "server")]
+mod cursor;
 pub use cfg::*;
+#[cfg(feature = "server")]
 mod telemetry;
+#[cfg(feature = "server")]
 pub use telemetry::*;
+#[cfg(feature = "server")]
 mod middleware;
+#[cfg(feature = "server")]
 pub use middleware::*;
 mod db;
 pub use db::*;
+mod discover;
 mod encoder;
+#[cfg(feature = "server")]
 mod errors;
+#[cfg(feature = "server")]
+mod etag;
+mod eval;
+#[cfg(feature = "server")]
+mod export;
+#[cfg(feature = "server")]
+pub use export::export_site;
+#[cfg(feature = "server")]
+mod extract;
+mod format;
+mod github_app;
+pub use format::DocumentFormat;
+pub use github_app::GitHubApp;
+mod i18n;
+#[cfg(feature = "server")]
+mod idempotency;
+mod index;
+#[cfg(feature = "server")]
+mod jobs;
+mod links;
+mod lookup;
+mod mail;
+#[cfg(feature = "server")]
+mod negotiate;
+mod ocr;
+#[cfg(feature = "server")]
+mod oidc;
+pub use index::*;
 mod openai;
 pub use openai::*;
 mod embeddings;
 pub use embeddings::*;
 mod parser;
+mod pdf;
+mod pipeline;
+mod proxy;
+pub use proxy::{github_client, GitHubAuth};
+mod ranking;
+mod reembed;
+mod search;
+mod secrets;
+pub use secrets::Cipher;
+mod seed;
+pub use seed::{seed, Manifest, SeedReport};
+#[cfg(feature = "server")]
 mod routes;
+mod spelling;
+#[cfg(all(feature = "server", any(test, feature = "test-support")))]
+pub mod test_support;
 mod tinyvector;
 pub use tinyvector::*;
+mod tokenizer;
+pub use tokenizer::*;
 mod types;
+#[cfg(feature = "server")]
+mod versioning;
+
+/// Building blocks for parsing, encoding and searching an index in-process,
+/// without the HTTP server. Build with `default-features = false`.
+#[cfg(feature = "embedded")]
+pub mod rtfm_core {
+    pub use crate::{
+        AppState, Breakers, Configuration, Db, Embeddings, Models, OpenAI, Tiny, Tinyvector,
+        Tokenizer,
+    };
+
+    pub mod encoder {
+        pub use crate::encoder::*;
+    }
+
+    pub mod parser {
+        pub use crate::parser::*;
+    }
+
+    pub mod pipeline {
+        pub use crate::pipeline::*;
+    }
+
+    pub mod ranking {
+        pub use crate::ranking::*;
+    }
+
+    pub mod tinyvector {
+        pub use crate::tinyvector::*;
+    }
+
+    pub mod types {
+        pub use crate::types::*;
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Db,
     pub github: Octocrab,
-    pub embeddings: Embeddings,
+    /// Fetches GitHub sources as the app's installations instead, when configured.
+    pub github_app: Option<GitHubApp>,
+    pub models: Models,
     pub tinyvector: Tinyvector,
+    pub tokenizer: Tokenizer,
+    pub openai: OpenAI,
+    pub spelling: spelling::Spelling,
+    pub breakers: Breakers,
+    /// Requests counted per access token.
+    pub rate_limits: access::RateLimits,
+    /// Parse and encode jobs in flight, to cancel them.
+    pub cancellations: pipeline::Cancellations,
+    /// Models found compatible with the vectors of collections.
+    pub model_checks: pipeline::ModelChecks,
+    pub coverage: coverage::CoverageCache,
+    pub rankings: search::Rankings,
+    /// The OIDC provider, discovered on the first login.
+    #[cfg(feature = "server")]
+    pub oidc: oidc::ProviderCache,
+    /// Serves repo files instead of GitHub when set, see `test_support`.
+    pub stub_repos: Option<parser::StubRepos>,
     pub cfg: Arc<Configuration>,
 }
 
+impl AppState {
+    pub fn new(
+        cfg: Config,
+        db: Db,
+        github: Octocrab,
+        models: Models,
+        tinyvector: Tinyvector,
+        tokenizer: Tokenizer,
+    ) -> anyhow::Result<Self> {
+        let breakers = Breakers::default();
+        let github_app = cfg
+            .github_app
+            .as_ref()
+            .map(|app| GitHubApp::new(app, cfg.proxy.as_deref()))
+            .transpose()?;
+        Ok(AppState {
+            db,
+            github,
+            github_app,
+            models,
+            tinyvector,
+            tokenizer,
+            openai: if cfg.offline {
+                OpenAI::offline()
+            } else {
+                OpenAI::with_key(&cfg.open_ai_key, breakers.openai.clone())
+            },
+            spelling: spelling::Spelling::default(),
+            breakers,
+            rate_limits: access::RateLimits::default(),
+            cancellations: pipeline::Cancellations::default(),
+            model_checks: pipeline::ModelChecks::default(),
+            coverage: coverage::CoverageCache::default(),
+            rankings: search::Rankings::default(),
+            #[cfg(feature = "server")]
+            oidc: oidc::ProviderCache::default(),
+            stub_repos: None,
+            cfg,
+        })
+    }
+
+    /// Returns the loaded embeddings model with the given name.
+    pub fn embeddings(&self, model: &str) -> anyhow::Result<Embeddings> {
+        self.models
+            .get(model)
+            .ok_or_else(|| anyhow::anyhow!("Model '{}' is not loaded", model))
+    }
+}
+
+#[cfg(feature = "server")]
 pub fn run(
     cfg: Config,
     db: Db,
     github: Octocrab,
-    embeddings: Embeddings,
+    models: Models,
     tinyvector: Tinyvector,
-) -> Server<AddrIncoming, IntoMakeService<Router>> {
+    tokenizer: Tokenizer,
+) -> anyhow::Result<Server<AddrIncoming, IntoMakeService<Router>>> {
     let addr = cfg.listen_address.clone();
+    let app = app(AppState::new(
+        cfg, db, github, models, tinyvector, tokenizer,
+    )?);
+    Ok(axum::Server::bind(&addr).serve(app.into_make_service()))
+}
 
-    let app_state = AppState {
-        db,
-        github,
-        embeddings,
-        tinyvector,
-        cfg,
+/// Builds the router with all middleware, ready to be served.
+#[cfg(feature = "server")]
+pub fn app(app_state: AppState) -> Router {
+    let limits = BodyLimits {
+        body: app_state.cfg.max_body_bytes,
+        upload: app_state.cfg.max_upload_bytes,
     };
 
     // Adds high level tracing.
@@ -63,28 +238,25 @@ pub fn run(
     let request_id_layer = middleware::request_id_layer();
     let propagate_request_id_layer = middleware::propagate_request_id_layer();
 
-    // Adds headers for CORS.
-    let cors_layer = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(AllowHeaders::mirror_request())
-        .max_age(Duration::from_secs(600));
-
-    // Applies a timeout to requests.
-    // If the request does not complete within the specified timeout
-    // it will be aborted and a 408 Request Timeout response will be sent.
-    let timeout_layer = TimeoutLayer::new(Duration::from_secs(15));
-
-    let app = Router::new()
-        .merge(routes::router())
-        .layer(cors_layer)
-        .layer(timeout_layer)
+    // Limits request bodies, so a malformed client can't exhaust memory.
+    // Declared sizes are checked upfront, streamed bodies while they are read.
+    // The upload route raises its streamed limit itself.
+    let body_limit_layer = DefaultBodyLimit::max(limits.body);
+    let content_length_layer =
+        axum::middleware::from_fn_with_state(limits, middleware::content_length_limit);
+
+    // Actors of the audit log come from sessions, never from clients.
+    let strip_actor_layer = axum::middleware::from_fn(middleware::strip_actor);
+
+    Router::new()
+        .merge(routes::router(app_state.clone()))
+        .layer(strip_actor_layer)
+        .layer(body_limit_layer)
+        .layer(content_length_layer)
         .layer(resp_headers_layer)
         .layer(propagate_request_id_layer)
         .layer(trace_layer)
         .layer(req_headers_layer)
         .layer(request_id_layer)
-        .with_state(app_state);
-
-    axum::Server::bind(&addr).serve(app.into_make_service())
+        .with_state(app_state)
 }
@@ -0,0 +1,282 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::StreamExt;
+use markdown::{mdast::Node, ParseOptions};
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect::Policy,
+    Client, StatusCode, Url,
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::{
+    types::{BrokenLink, LinkCheck},
+    Db,
+};
+
+/// Number of external links checked concurrently.
+const CHECK_CONCURRENCY: usize = 8;
+
+/// External links are rechecked once their cached result is older than this many hours.
+const CHECK_TTL_HOURS: i64 = 24;
+
+/// Time given to a linked page to respond.
+const CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Redirects followed before a link is reported as broken.
+const MAX_REDIRECTS: usize = 10;
+
+/// Returns urls of all links and link definitions in the document, in order.
+pub fn extract_links(value: &str) -> Result<Vec<String>> {
+    let tree = markdown::to_mdast(value, &ParseOptions::default())
+        .map_err(|err| anyhow::anyhow!("Failed to build markdown tree {}", err))?;
+    let mut links = Vec::new();
+    collect_links(&tree, &mut links);
+    Ok(links)
+}
+
+fn collect_links(node: &Node, links: &mut Vec<String>) {
+    match node {
+        Node::Link(link) => links.push(link.url.clone()),
+        Node::Definition(definition) => links.push(definition.url.clone()),
+        _ => {}
+    }
+    for child in node.children().into_iter().flatten() {
+        collect_links(child, links);
+    }
+}
+
+/// Whether the url points outside of the repo.
+pub fn is_external(url: &str) -> bool {
+    url.contains("://") || url.starts_with("mailto:") || url.starts_with("//")
+}
+
+/// Resolves a relative link against the path of the document it's in,
+/// returning the repo path it points at. External links and links to
+/// anchors within the same document resolve to `None`.
+pub fn resolve_link(from: &str, url: &str) -> Option<String> {
+    if is_external(url) {
+        return None;
+    }
+    let url = url.split(['#', '?']).next().unwrap_or_default();
+    if url.is_empty() {
+        return None;
+    }
+
+    let mut parts: Vec<&str> = match url.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => {
+            let mut dir: Vec<&str> = from.split('/').collect();
+            dir.pop();
+            dir
+        }
+    };
+    for part in url.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                // Links escaping the repo root can't point at a document.
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Checks the source's external http links with HEAD requests, skipping urls
+/// checked within the last day. Returns the number of urls checked.
+pub async fn check_external_links(db: &Db, source_id: i64) -> Result<usize> {
+    let urls = db.query_external_urls(source_id).await?;
+    let client = Client::builder()
+        .timeout(CHECK_TIMEOUT)
+        .user_agent("rtfm-link-check")
+        .dns_resolver(Arc::new(PublicResolver))
+        .redirect(Policy::custom(|attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if is_private_literal(attempt.url()) {
+                attempt.error("Redirect to a private address")
+            } else {
+                attempt.follow()
+            }
+        }))
+        .build()?;
+    let stale_before = Utc::now() - Duration::hours(CHECK_TTL_HOURS);
+
+    let results = futures::stream::iter(urls)
+        .filter(|url| futures::future::ready(url.starts_with("http")))
+        .map(|url| check_if_stale(db, &client, url, stale_before))
+        .buffer_unordered(CHECK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut checked = 0;
+    for result in results {
+        if result? {
+            checked += 1;
+        }
+    }
+    Ok(checked)
+}
+
+// Checks the url unless its cached result is newer than `stale_before`,
+// returns whether it was checked.
+async fn check_if_stale(
+    db: &Db,
+    client: &Client,
+    url: String,
+    stale_before: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    if let Some(check) = db.select_link_check(&url).await? {
+        if check.checked_at > stale_before {
+            return Ok(false);
+        }
+    }
+    let check = check_url(client, url).await;
+    db.upsert_link_check(&check).await?;
+    Ok(true)
+}
+
+async fn check_url(client: &Client, url: String) -> LinkCheck {
+    if Url::parse(&url).map_or(false, |parsed| is_private_literal(&parsed)) {
+        return LinkCheck {
+            url,
+            status: None,
+            error: Some("Private address".to_string()),
+            checked_at: Utc::now(),
+        };
+    }
+    let mut resp = client.head(&url).send().await;
+    // Some servers don't implement HEAD, those get a GET instead.
+    if let Ok(r) = &resp {
+        if matches!(
+            r.status(),
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        ) {
+            resp = client.get(&url).send().await;
+        }
+    }
+    let (status, error) = match resp {
+        Ok(resp) => (Some(resp.status().as_u16()), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+    LinkCheck {
+        url,
+        status,
+        error,
+        checked_at: Utc::now(),
+    }
+}
+
+/// Resolves hosts of checked links, refusing the ones with private or local
+/// addresses, so docs can't make the server probe its own network. Redirects
+/// are resolved here as well.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if addrs.iter().any(|addr| !is_public(addr.ip())) {
+                return Err(format!("{} resolves to a private address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+// Hosts given as addresses skip the resolver.
+fn is_private_literal(url: &Url) -> bool {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .and_then(|host| host.parse::<IpAddr>().ok())
+        .map_or(false, |ip| !is_public(ip))
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || a == 0
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (a == 100 && b & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let segment = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local, fc00::/7, and link local, fe80::/10.
+                    || segment & 0xfe00 == 0xfc00
+                    || segment & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Returns the source's relative links pointing at missing documents, followed
+/// by external links that failed their last check.
+pub async fn broken_links(db: &Db, source_id: i64) -> Result<Vec<BrokenLink>, sqlx::Error> {
+    let mut broken = db.query_dangling_links(source_id).await?;
+    broken.extend(db.query_failed_links(source_id).await?);
+    Ok(broken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_link() {
+        let from = "website/docs/r/acm_certificate.html.markdown";
+        assert_eq!(
+            resolve_link(from, "acm_certificate_validation.html.markdown#usage"),
+            Some("website/docs/r/acm_certificate_validation.html.markdown".to_string())
+        );
+        assert_eq!(
+            resolve_link(from, "../d/acm_certificate.html.markdown"),
+            Some("website/docs/d/acm_certificate.html.markdown".to_string())
+        );
+        assert_eq!(
+            resolve_link(from, "/README.md"),
+            Some("README.md".to_string())
+        );
+        assert_eq!(resolve_link(from, "#argument-reference"), None);
+        assert_eq!(resolve_link(from, "https://aws.amazon.com/acm/"), None);
+        assert_eq!(resolve_link("README.md", "../outside.md"), None);
+    }
+
+    #[test]
+    fn test_private_addresses() {
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://10.0.0.7/admin",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://100.64.1.1/",
+            "http://0.0.0.0/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:192.168.1.1]/",
+        ] {
+            assert!(is_private_literal(&Url::parse(url).unwrap()), "{}", url);
+        }
+        for url in [
+            "https://93.184.216.34/",
+            "https://[2606:2800:220:1::]/",
+            "https://docs.example.com/",
+        ] {
+            assert!(!is_private_literal(&Url::parse(url).unwrap()), "{}", url);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Context;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{encoder, index, spelling, types::Document, AppState};
+
+/// How the page was found, exact matches are tried before vector search.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchKind {
+    Title,
+    Heading,
+    Vector,
+}
+
+#[derive(Debug)]
+pub struct Lookup {
+    pub kind: MatchKind,
+    pub score: Option<f32>,
+    pub document: Document,
+}
+
+/// Finds the single best page for a symbol, e.g. `aws_acm_certificate`.
+///
+/// Pages whose front matter title or a heading names the symbol win,
+/// otherwise the page of the most similar chunk is returned.
+pub async fn lookup(
+    state: &AppState,
+    collection: &str,
+    symbol: &str,
+) -> anyhow::Result<Option<Lookup>> {
+    let collection_id = state
+        .db
+        .query_collections()
+        .await
+        .context("Failed to query collections")?
+        .into_iter()
+        .find(|c| c.name == collection)
+        .map(|c| c.id)
+        .context("Collection doesn't exist")?;
+
+    if let Some(found) = find_exact(state, collection_id, symbol).await? {
+        return Ok(Some(found));
+    }
+
+    // Typo'd identifiers have no exact match, retry with the corrected spelling.
+    let tiny = index::resolve_collection(&state.tinyvector, collection, None)
+        .await
+        .context("Failed to get Tinyvector collection")?;
+    if let Some(corrected) = spelling::suggest(state, collection, tiny.clone(), symbol).await? {
+        tracing::info!("Looking up corrected symbol '{}'", corrected);
+        if let Some(found) = find_exact(state, collection_id, &corrected).await? {
+            return Ok(Some(found));
+        }
+    }
+
+    let query = state
+        .embeddings(&tiny.model)?
+        .encode(&[symbol.to_string()])
+        .await
+        .context("Failed to create embedding")?;
+    let Some(result) = tiny.get_similarity(&query[0], 1).into_iter().next() else {
+        return Ok(None);
+    };
+    let metadata = result.embedding.metadata;
+    let document = state
+        .db
+        .select_document(metadata.source_id, &metadata.path)
+        .await
+        .context("Failed to select document")?;
+    Ok(Some(Lookup {
+        kind: MatchKind::Vector,
+        score: Some(result.score),
+        document,
+    }))
+}
+
+async fn find_exact(
+    state: &AppState,
+    collection_id: i64,
+    symbol: &str,
+) -> anyhow::Result<Option<Lookup>> {
+    let docs = state
+        .db
+        .query_documents_containing(collection_id, symbol)
+        .await
+        .context("Failed to query documents")?;
+    let title = docs
+        .into_iter()
+        .filter(|doc| title_names_symbol(doc, symbol))
+        .min_by_key(|doc| doc.path.len());
+    if let Some(document) = title {
+        return Ok(Some(Lookup {
+            kind: MatchKind::Title,
+            score: None,
+            document,
+        }));
+    }
+
+    // Top level headings name the page, deeper ones only a section of it.
+    let heading = state
+        .db
+        .query_headings_containing(collection_id, symbol)
+        .await
+        .context("Failed to query headings")?
+        .into_iter()
+        .filter(|heading| names_symbol(&heading.text, symbol))
+        .min_by_key(|heading| (heading.level, heading.position));
+    let Some(heading) = heading else {
+        return Ok(None);
+    };
+    let document = state
+        .db
+        .select_document_by_id(heading.document_id)
+        .await
+        .context("Failed to select document")?;
+    Ok(Some(Lookup {
+        kind: MatchKind::Heading,
+        score: None,
+        document,
+    }))
+}
+
+fn title_names_symbol(doc: &Document, symbol: &str) -> bool {
+    let head = encoder::extract_head(&doc.data).unwrap_or_default();
+    names_symbol(&encoder::extract_head_values(&head).title, symbol)
+}
+
+// Titles are often prefixed, e.g. "AWS: aws_acm_certificate" or "Resource: aws_acm_certificate".
+fn names_symbol(title: &str, symbol: &str) -> bool {
+    let title = title.trim().trim_matches('`');
+    let name = title
+        .rsplit(':')
+        .next()
+        .unwrap_or(title)
+        .trim()
+        .trim_matches('`');
+    title.eq_ignore_ascii_case(symbol) || name.eq_ignore_ascii_case(symbol)
+}
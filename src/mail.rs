@@ -0,0 +1,249 @@
+use regex::Regex;
+
+/// A message of an mbox archive or an EML file, reduced to what's indexed.
+#[derive(Debug, PartialEq, Default)]
+pub struct Message {
+    pub subject: String,
+    pub from: String,
+    pub date: String,
+    /// Plain text body without quoted replies and signature.
+    pub body: String,
+}
+
+impl Message {
+    /// Subject and author, used as the context of the message's chunk.
+    pub fn context(&self) -> String {
+        match self.from.is_empty() {
+            true => self.subject.clone(),
+            false => format!("{} by {}", self.subject, self.from),
+        }
+    }
+}
+
+/// Parses an mbox archive, a single EML message is parsed as a one message
+/// archive. Messages without a text body are left out.
+pub fn parse_messages(value: &str) -> Vec<Message> {
+    let mut raw: Vec<Vec<&str>> = Vec::new();
+    let mut prev_blank = true;
+    for line in value.lines() {
+        // Envelope lines start messages, but only after a blank line or at the start.
+        if line.starts_with("From ") && prev_blank {
+            raw.push(Vec::new());
+        } else {
+            if raw.is_empty() {
+                raw.push(Vec::new());
+            }
+            if let Some(last) = raw.last_mut() {
+                last.push(line);
+            }
+        }
+        prev_blank = line.trim().is_empty();
+    }
+    raw.into_iter()
+        .filter_map(|lines| parse_message(&lines))
+        .collect()
+}
+
+fn parse_message(lines: &[&str]) -> Option<Message> {
+    let split = lines.iter().position(|line| line.trim().is_empty())?;
+    let headers = unfold(&lines[..split]);
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+    let body = text_body(&headers, &lines[split + 1..])?;
+    let body = strip_replies(&body);
+    if body.is_empty() {
+        return None;
+    }
+    Some(Message {
+        subject: header("Subject"),
+        from: author(&header("From")),
+        date: header("Date"),
+        body,
+    })
+}
+
+// Joins folded header lines, continuations start with whitespace.
+fn unfold(lines: &[&str]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines {
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    headers
+}
+
+// The display name of `Jane Doe <jane@example.com>`, or the address without one.
+fn author(from: &str) -> String {
+    match from.split_once('<') {
+        Some((name, _)) if !name.trim().is_empty() => name.trim().trim_matches('"').to_string(),
+        Some((_, address)) => address.trim_end_matches('>').to_string(),
+        None => from.trim().to_string(),
+    }
+}
+
+// The body as text: the first `text/plain` part of multipart messages,
+// quoted-printable is decoded. Base64 and html only bodies give none.
+fn text_body(headers: &[(String, String)], lines: &[&str]) -> Option<String> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.to_lowercase())
+            .unwrap_or_default()
+    };
+    let content_type = header("Content-Type");
+    if content_type.starts_with("multipart/") {
+        let boundary_re = Regex::new(r#"(?i)boundary="?([^";]+)"?"#).unwrap();
+        let raw_type = headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default();
+        let boundary = format!("--{}", boundary_re.captures(raw_type)?.get(1)?.as_str());
+        let mut parts: Vec<Vec<&str>> = Vec::new();
+        for line in lines {
+            if line.starts_with(&boundary) {
+                parts.push(Vec::new());
+            } else if let Some(part) = parts.last_mut() {
+                part.push(*line);
+            }
+        }
+        return parts.iter().find_map(|part| {
+            let split = part.iter().position(|line| line.trim().is_empty())?;
+            let headers = unfold(&part[..split]);
+            let content_type = headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+                .map_or(String::new(), |(_, value)| value.to_lowercase());
+            if !content_type.is_empty() && !content_type.starts_with("text/plain") {
+                return None;
+            }
+            text_body(&headers, &part[split + 1..])
+        });
+    }
+    if !content_type.is_empty() && !content_type.starts_with("text/plain") {
+        return None;
+    }
+    // mboxrd escapes body lines starting with `From ` as `>From `.
+    let body: Vec<&str> = lines
+        .iter()
+        .map(|line| match line.strip_prefix('>') {
+            Some(rest) if rest.trim_start_matches('>').starts_with("From ") => rest,
+            _ => *line,
+        })
+        .collect();
+    match header("Content-Transfer-Encoding").as_str() {
+        "base64" => None,
+        "quoted-printable" => Some(decode_quoted_printable(&body.join("\n"))),
+        _ => Some(body.join("\n")),
+    }
+}
+
+fn decode_quoted_printable(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.as_bytes();
+    while let Some((&byte, rest)) = input.split_first() {
+        input = rest;
+        if byte != b'=' {
+            bytes.push(byte);
+            continue;
+        }
+        // Soft line break.
+        if let Some(rest) = input.strip_prefix(b"\n") {
+            input = rest;
+            continue;
+        }
+        let hex = input
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(decoded) => {
+                bytes.push(decoded);
+                input = &input[2..];
+            }
+            None => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// Drops quoted replies, their attribution line and the signature, which
+// repeat other messages of the thread.
+fn strip_replies(body: &str) -> String {
+    let attribution_re = Regex::new(r"(?i)^on .+ wrote:\s*$").unwrap();
+    let mut lines = Vec::new();
+    for line in body.lines() {
+        if line == "-- " || line == "--" {
+            break;
+        }
+        if line.starts_with('>') || attribution_re.is_match(line) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n").trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_messages() {
+        let mbox = "From jane@example.com Mon Aug 21 10:00:00 2023\n\
+From: Jane Doe <jane@example.com>\n\
+Subject: [ANN] Widgets 2.0\n\
+\x20released\n\
+Date: Mon, 21 Aug 2023 10:00:00 +0000\n\
+\n\
+Widgets 2.0 spins twice as fast.\n\
+>From now on 1.x is unsupported.\n\
+\n\
+From bob@example.com Mon Aug 21 11:00:00 2023\n\
+From: bob@example.com\n\
+Subject: Re: [ANN] Widgets 2.0 released\n\
+Content-Type: multipart/alternative; boundary=\"xyz\"\n\
+\n\
+--xyz\n\
+Content-Type: text/plain; charset=utf-8\n\
+Content-Transfer-Encoding: quoted-printable\n\
+\n\
+Great news, caf=C3=A9 time!\n\
+On Mon, Jane Doe wrote:\n\
+> Widgets 2.0 spins twice as fast.\n\
+-- \n\
+Bob\n\
+--xyz\n\
+Content-Type: text/html\n\
+\n\
+<p>Great news</p>\n\
+--xyz--\n";
+        let messages = parse_messages(mbox);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].context(),
+            "[ANN] Widgets 2.0 released by Jane Doe"
+        );
+        assert_eq!(
+            messages[0].body,
+            "Widgets 2.0 spins twice as fast.\nFrom now on 1.x is unsupported."
+        );
+        assert_eq!(
+            messages[1].context(),
+            "Re: [ANN] Widgets 2.0 released by bob@example.com"
+        );
+        assert_eq!(messages[1].body, "Great news, café time!");
+    }
+}
@@ -1,6 +1,8 @@
-use octocrab::Octocrab;
-use server::{setup_tracing, Configuration, Db, Embeddings, Tiny, Tinyvector};
-use tokio::time::Instant;
+use server::{
+    export_site, github_client, load_tinyvector, seed, setup_tracing, AppState, Cipher,
+    Configuration, Db, Embeddings, GitHubAuth, Manifest, Models, Tiny, Tokenizer, DEFAULT_MODEL,
+};
+use std::path::Path;
 
 #[tokio::main]
 async fn main() -> Result<(), hyper::Error> {
@@ -14,53 +16,140 @@ async fn main() -> Result<(), hyper::Error> {
     tracing::debug!("Initializing configuration");
     let cfg = Configuration::new();
 
+    // `server check [--fix]` asks the running server to diff the db against tinyvector.
+    if let Some("check") = std::env::args().nth(1).as_deref() {
+        let fix = std::env::args().any(|arg| arg == "--fix");
+        check(cfg.app_port, fix).await;
+        return Ok(());
+    }
+
     tracing::debug!("Initializing db");
-    let db = Db::new(&cfg.db_dsn).await.expect("Failed to setup db");
+    let mut db = Db::new(&cfg.db_dsn).await.expect("Failed to setup db");
+    match &cfg.secrets_key {
+        Some(key) => {
+            let cipher = Cipher::from_hex(key).expect("Invalid secrets key");
+            db = db.with_cipher(cipher);
+        }
+        None => tracing::warn!("No secrets key configured, credentials are stored unencrypted"),
+    }
 
     tracing::debug!("Running migrations");
     let _ = db.migrate().await.expect("Failed to run migrations");
+    let encrypted = db
+        .encrypt_secrets()
+        .await
+        .expect("Failed to encrypt stored credentials");
+    if encrypted > 0 {
+        tracing::info!("Encrypted {} stored credentials", encrypted);
+    }
+    let interrupted = db
+        .fail_unfinished_jobs()
+        .await
+        .expect("Failed to fail unfinished jobs");
+    if interrupted > 0 {
+        tracing::warn!(
+            "Failed {} jobs interrupted by the last shutdown",
+            interrupted
+        );
+    }
+
+    // `server export-site <dir>` renders the indexed documents to static HTML.
+    if let Some("export-site") = std::env::args().nth(1).as_deref() {
+        let dir = std::env::args().nth(2).expect("Missing export directory");
+        let exported = export_site(&db, Path::new(&dir))
+            .await
+            .expect("Failed to export site");
+        println!("Exported {} documents to '{}'", exported, dir);
+        return Ok(());
+    }
 
     tracing::debug!("Initializing GitHub client");
-    let gh = Octocrab::builder()
-        .personal_token(cfg.github_token.clone())
-        .build()
-        .expect("Failed to build GitHub client");
+    let gh = github_client(
+        GitHubAuth::Token(cfg.github_token.clone()),
+        cfg.proxy.as_deref(),
+    )
+    .expect("Failed to build GitHub client");
 
     tracing::debug!("Initializing embeddings model");
-    let embeddings = Embeddings::new().expect("Failed to load embeddings model");
+    let embeddings =
+        Embeddings::from_config(DEFAULT_MODEL, &cfg).expect("Failed to load embeddings model");
+    embeddings
+        .warm_up()
+        .await
+        .expect("Failed to warm up embeddings model");
+    let models = Models::new(embeddings);
+
+    // Collections that were re-embedded with another model or have candidate
+    // models need those loaded as well.
+    let collections = db
+        .query_collections()
+        .await
+        .expect("Failed to query collections");
+    for collection in collections {
+        let mut names = db
+            .query_candidate_models(collection.id)
+            .await
+            .expect("Failed to query candidate models");
+        names.push(collection.model);
+        for name in names {
+            if name != DEFAULT_MODEL && models.get(&name).is_none() {
+                let model = Embeddings::from_config(&name, &cfg)
+                    .expect("Failed to load collection embeddings model");
+                models.insert(model);
+            }
+        }
+    }
+
+    tracing::debug!("Initializing tokenizer");
+    let tokenizer = Tokenizer::new().expect("Failed to load tokenizer");
 
     tracing::debug!("Initializing vector db");
     let tiny = Tiny::new().extension();
     load_tinyvector(&db, tiny.clone()).await;
 
+    // `server seed [manifest.yaml]` creates the manifest's collections and sources
+    // and ingests them, the bundled sample corpus is used without a manifest.
+    if let Some("seed") = std::env::args().nth(1).as_deref() {
+        let manifest = match std::env::args().nth(2) {
+            Some(path) => {
+                let text = std::fs::read_to_string(&path).expect("Failed to read seed manifest");
+                Manifest::parse(&text).expect("Failed to parse seed manifest")
+            }
+            None => Manifest::sample(),
+        };
+        let state =
+            AppState::new(cfg, db, gh, models, tiny, tokenizer).expect("Failed to setup app state");
+        let report = seed(&state, &manifest).await.expect("Failed to seed");
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("Failed to format seed report")
+        );
+        return Ok(());
+    }
+
     tracing::info!("Starting server on {}...", cfg.listen_address);
-    server::run(cfg, db, gh, embeddings, tiny).await
+    server::run(cfg, db, gh, models, tiny, tokenizer)
+        .expect("Failed to setup server")
+        .await
 }
 
-async fn load_tinyvector(db: &Db, tiny: Tinyvector) {
-    let instant = Instant::now();
-    let chunks = db
-        .query_chunks_by_collection(1)
+async fn check(port: u16, fix: bool) {
+    let url = format!("http://localhost:{}/api/admin/check", port);
+    let client = reqwest::Client::new();
+    let req = if fix {
+        client.post(&url)
+    } else {
+        client.get(&url)
+    };
+    let report: serde_json::Value = req
+        .send()
         .await
-        .expect("Failed to query chunks");
-    if chunks.is_empty() {
-        tracing::info!("No chunks to load");
-        return;
-    }
-
-    tiny.clone()
-        .write_owned()
+        .expect("Failed to reach server")
+        .json()
         .await
-        .create_collection("default".to_string())
-        .expect("Failed to create tinyvector collection");
-
-    for chunk in chunks {
-        let _ = tiny.write().await.insert_into_collection(
-            "default",
-            format!("{}", chunk.document_id),
-            chunk.vector,
-            chunk.data,
-        );
-    }
-    tracing::info!("Loaded tinyvector, elapsed {:?}", instant.elapsed());
+        .expect("Failed to parse consistency report");
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).expect("Failed to format report")
+    );
 }
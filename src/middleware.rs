@@ -1,4 +1,11 @@
-use axum::http::HeaderName;
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderName, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body::{LengthLimitError, Limited};
 use hyper::Request;
 use tower_http::request_id::{
     MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
@@ -25,3 +32,60 @@ pub fn propagate_request_id_layer() -> PropagateRequestIdLayer {
     let x_request_id = HeaderName::from_static("x-request-id");
     PropagateRequestIdLayer::new(x_request_id)
 }
+
+/// Drops `X-Actor` headers of clients. The header is only set from the
+/// session of the request, so nobody can write to the audit log as someone else.
+pub async fn strip_actor<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    req.headers_mut().remove("x-actor");
+    next.run(req).await
+}
+
+/// Request body limits in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct BodyLimits {
+    pub body: usize,
+    /// Limit of archive uploads, which are larger than other bodies.
+    pub upload: usize,
+}
+
+impl BodyLimits {
+    fn for_path(&self, path: &str) -> usize {
+        match path.ends_with("/upload") {
+            true => self.upload,
+            false => self.body,
+        }
+    }
+}
+
+/// Rejects requests whose `Content-Length` exceeds the limit before reading the body.
+///
+/// Bodies without `Content-Length` (chunked uploads) are still capped by
+/// `DefaultBodyLimit` while they are being streamed.
+pub async fn content_length_limit<B>(
+    State(limits): State<BodyLimits>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let limit = limits.for_path(req.uri().path());
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if matches!(content_length, Some(len) if len > limit) {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+    next.run(req).await
+}
+
+/// Reads the whole body for middleware inspecting it. `DefaultBodyLimit` only
+/// applies once a handler reads the body, so chunked bodies are capped here.
+pub async fn read_body(body: Body, limit: usize) -> Result<Bytes, StatusCode> {
+    match hyper::body::to_bytes(Limited::new(body, limit)).await {
+        Ok(bytes) => Ok(bytes),
+        Err(err) if err.downcast_ref::<LengthLimitError>().is_some() => {
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        Err(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
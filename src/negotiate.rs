@@ -0,0 +1,131 @@
+use axum::{
+    async_trait,
+    body::{Bytes, StreamBody},
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+    response::{IntoResponse, Response},
+    BoxError, Json,
+};
+use futures::{stream, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// Representation of a listing, negotiated from the `Accept` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    /// Newline delimited JSON, one item per line.
+    Ndjson,
+    Csv,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Format
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        Ok(Self::from_accept(accept))
+    }
+}
+
+impl Format {
+    // Picks the first supported media type, quality values are ignored.
+    fn from_accept(accept: &str) -> Self {
+        for media in accept.split(',') {
+            match media.split(';').next().unwrap_or_default().trim() {
+                "application/x-ndjson" | "application/ndjson" => return Self::Ndjson,
+                "text/csv" => return Self::Csv,
+                "application/json" => return Self::Json,
+                _ => {}
+            }
+        }
+        Self::Json
+    }
+
+    /// Responds with the items as a JSON array, NDJSON and CSV are streamed
+    /// item by item instead of being serialized as one payload.
+    ///
+    /// CSV rows can't contain nested values, so items must be flat structs.
+    pub fn respond<T>(self, items: Vec<T>) -> Response
+    where
+        T: Serialize + Send + 'static,
+    {
+        match self {
+            Self::Json => Json(items).into_response(),
+            _ => self.stream(stream::iter(items.into_iter().map(Ok::<_, Infallible>))),
+        }
+    }
+
+    /// Responds with items as they arrive, e.g. rows fetched from the db one
+    /// by one, so exports are never held in memory as a whole. JSON arrays
+    /// are streamed item by item too.
+    pub fn stream<T, E, S>(self, items: S) -> Response
+    where
+        T: Serialize + Send + 'static,
+        E: Into<BoxError>,
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+    {
+        let items = items.enumerate().map(move |(index, item)| {
+            let item = item.map_err(Into::<BoxError>::into)?;
+            let mut bytes = Vec::new();
+            match self {
+                Self::Json => {
+                    if index > 0 {
+                        bytes.push(b',');
+                    }
+                    serde_json::to_writer(&mut bytes, &item)?;
+                }
+                Self::Ndjson => {
+                    serde_json::to_writer(&mut bytes, &item)?;
+                    bytes.push(b'\n');
+                }
+                Self::Csv => {
+                    let mut writer = csv::WriterBuilder::new()
+                        .has_headers(index == 0)
+                        .from_writer(bytes);
+                    writer.serialize(&item)?;
+                    bytes = writer
+                        .into_inner()
+                        .map_err(|err| csv::Error::from(err.into_error()))?;
+                }
+            }
+            Ok::<_, BoxError>(Bytes::from(bytes))
+        });
+        match self {
+            Self::Json => {
+                let open = stream::once(async { Ok::<_, BoxError>(Bytes::from_static(b"[")) });
+                let close = stream::once(async { Ok::<_, BoxError>(Bytes::from_static(b"]")) });
+                (
+                    [(header::CONTENT_TYPE, "application/json")],
+                    StreamBody::new(open.chain(items).chain(close)),
+                )
+                    .into_response()
+            }
+            Self::Ndjson => (
+                [(header::CONTENT_TYPE, "application/x-ndjson")],
+                StreamBody::new(items),
+            )
+                .into_response(),
+            Self::Csv => (
+                [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+                StreamBody::new(items),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// Marks a response negotiated by `Accept`, so caches keep a copy per format
+/// instead of serving one format's ETag match to requests for another.
+pub fn vary(resp: impl IntoResponse) -> Response {
+    ([(header::VARY, "Accept")], resp).into_response()
+}
@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Context, Result};
+use std::{collections::HashMap, time::Duration};
+use tokio::process::Command;
+
+use crate::{links, types::Source};
+
+/// Time given to the OCR command per image.
+const OCR_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs the OCR command for every image and returns the text found, keyed by
+/// the image url as written in the document. Relative urls are resolved to raw
+/// GitHub urls on the source's branch. Failed images are skipped with a warning.
+pub async fn image_texts(
+    command: &str,
+    source: &Source,
+    path: &str,
+    urls: Vec<String>,
+) -> HashMap<String, String> {
+    let mut texts = HashMap::new();
+    for url in urls {
+        let resolved = match links::resolve_link(path, &url) {
+            Some(repo_path) => format!(
+                "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                source.owner, source.repo, source.branch, repo_path
+            ),
+            None => url.clone(),
+        };
+        match run(command, &resolved).await {
+            Ok(text) => {
+                texts.insert(url, text);
+            }
+            Err(err) => tracing::warn!("Failed to OCR image '{}': {:?}", resolved, err),
+        }
+    }
+    texts
+}
+
+// The command gets the image url as its last argument and prints the text to stdout.
+async fn run(command: &str, url: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", command))
+        .arg("sh")
+        .arg(url)
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(OCR_TIMEOUT, output)
+        .await
+        .context("OCR command timed out")?
+        .context("Failed to run OCR command")?;
+    if !output.status.success() {
+        return Err(anyhow!("OCR command exited with {}", output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
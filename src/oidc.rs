@@ -0,0 +1,274 @@
+use anyhow::{anyhow, bail, Context, Result};
+use axum::http::{header, HeaderMap};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+
+use crate::{types::Role, OidcConfig};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Endpoints of the provider's discovery document used by the login.
+#[derive(Deserialize, Debug)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+/// The signed in user as the provider's userinfo endpoint describes them.
+#[derive(Debug, PartialEq)]
+pub struct UserInfo {
+    pub subject: String,
+    pub email: String,
+    pub groups: Vec<String>,
+}
+
+/// The provider as discovered by the first login, later ones reuse it. Failed
+/// discoveries aren't kept, the next login tries again.
+#[derive(Clone, Default)]
+pub struct ProviderCache(Arc<OnceCell<Provider>>);
+
+impl ProviderCache {
+    pub async fn get(&self, cfg: &OidcConfig) -> Result<&Provider> {
+        self.0.get_or_try_init(|| Provider::discover(cfg)).await
+    }
+}
+
+/// Talks to the OpenID Connect provider for the authorization code flow.
+/// Users are identified through the userinfo endpoint, fetched over TLS from
+/// the provider itself, so ID tokens don't have to be verified.
+pub struct Provider {
+    cfg: OidcConfig,
+    client: Client,
+    pub metadata: ProviderMetadata,
+}
+
+impl Provider {
+    pub async fn discover(cfg: &OidcConfig) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent("rtfm")
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .context("Failed to build OIDC client")?;
+        let url = format!("{}/.well-known/openid-configuration", cfg.issuer);
+        let metadata: ProviderMetadata = client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Invalid discovery document at '{}'", url))?;
+        if metadata.issuer.trim_end_matches('/') != cfg.issuer {
+            bail!(
+                "Discovery document is of issuer '{}', expected '{}'",
+                metadata.issuer,
+                cfg.issuer
+            );
+        }
+        Ok(Self {
+            cfg: cfg.clone(),
+            client,
+            metadata,
+        })
+    }
+
+    /// Url the user is sent to for signing in.
+    pub fn authorization_url(&self, redirect_uri: &str, state: &str) -> Result<Url> {
+        Url::parse_with_params(
+            &self.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.cfg.client_id.as_str()),
+                ("redirect_uri", redirect_uri),
+                ("scope", "openid email profile"),
+                ("state", state),
+            ],
+        )
+        .context("Invalid authorization endpoint")
+    }
+
+    /// Exchanges the code the provider redirected back with for the user.
+    pub async fn user_info(&self, code: &str, redirect_uri: &str) -> Result<UserInfo> {
+        #[derive(Deserialize)]
+        struct TokenResp {
+            access_token: String,
+        }
+        let token: TokenResp = self
+            .client
+            .post(&self.metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("client_id", self.cfg.client_id.as_str()),
+                ("client_secret", self.cfg.client_secret.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()
+            .context("Provider rejected the authorization code")?
+            .json()
+            .await?;
+        let claims: Value = self
+            .client
+            .get(&self.metadata.userinfo_endpoint)
+            .bearer_auth(token.access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        parse_user_info(&claims, &self.cfg.groups_claim)
+    }
+}
+
+fn parse_user_info(claims: &Value, groups_claim: &str) -> Result<UserInfo> {
+    let subject = claims["sub"]
+        .as_str()
+        .ok_or_else(|| anyhow!("Userinfo has no subject"))?;
+    let groups = match &claims[groups_claim] {
+        Value::Array(groups) => groups
+            .iter()
+            .filter_map(|group| group.as_str())
+            .map(String::from)
+            .collect(),
+        Value::String(group) => vec![group.clone()],
+        _ => Vec::new(),
+    };
+    Ok(UserInfo {
+        subject: subject.to_string(),
+        email: claims["email"].as_str().unwrap_or_default().to_string(),
+        groups,
+    })
+}
+
+/// The highest role the user's groups map to, none if they may not sign in.
+pub fn role_for(cfg: &OidcConfig, groups: &[String]) -> Option<Role> {
+    let member = |of: &[String]| groups.iter().any(|group| of.contains(group));
+    if member(&cfg.admin_groups) {
+        Some(Role::Admin)
+    } else if cfg.viewer_groups.is_empty() || member(&cfg.viewer_groups) {
+        Some(Role::Viewer)
+    } else {
+        None
+    }
+}
+
+/// Value of the named cookie of the request.
+pub fn cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Only paths of this server are followed after signing in, anything else
+/// would be an open redirect.
+pub fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if is_local_path(next) => next,
+        _ => "/dashboard/sources",
+    }
+}
+
+// Browsers read `\` like `/`, so `/\evil.com` leaves the server just like
+// `//evil.com`, and so do their encoded forms once anything decodes them.
+fn is_local_path(path: &str) -> bool {
+    let lowercase = path.to_ascii_lowercase();
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !lowercase.starts_with("/%2f")
+        && !path.contains('\\')
+        && !lowercase.contains("%5c")
+        && !path.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cfg() -> OidcConfig {
+        OidcConfig {
+            issuer: "https://idp.example.com".to_string(),
+            client_id: "rtfm".to_string(),
+            client_secret: "secret".to_string(),
+            groups_claim: "groups".to_string(),
+            admin_groups: vec!["docs-admins".to_string()],
+            viewer_groups: vec!["engineering".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_role_for() {
+        let cfg = cfg();
+        let groups = |groups: &[&str]| groups.iter().map(|g| g.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            role_for(&cfg, &groups(&["engineering", "docs-admins"])),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            role_for(&cfg, &groups(&["engineering"])),
+            Some(Role::Viewer)
+        );
+        assert_eq!(role_for(&cfg, &groups(&["sales"])), None);
+        let open = OidcConfig {
+            viewer_groups: Vec::new(),
+            ..cfg
+        };
+        assert_eq!(role_for(&open, &[]), Some(Role::Viewer));
+    }
+
+    #[test]
+    fn test_parse_user_info() {
+        let claims = json!({
+            "sub": "248289761001",
+            "email": "jane@example.com",
+            "groups": ["engineering", 7],
+        });
+        assert_eq!(
+            parse_user_info(&claims, "groups").unwrap(),
+            UserInfo {
+                subject: "248289761001".to_string(),
+                email: "jane@example.com".to_string(),
+                groups: vec!["engineering".to_string()],
+            }
+        );
+        assert!(parse_user_info(&json!({}), "groups").is_err());
+    }
+
+    #[test]
+    fn test_safe_next() {
+        assert_eq!(
+            safe_next(Some("/dashboard/search?q=a")),
+            "/dashboard/search?q=a"
+        );
+        assert_eq!(safe_next(Some("//evil.example.com")), "/dashboard/sources");
+        assert_eq!(
+            safe_next(Some("https://evil.example.com")),
+            "/dashboard/sources"
+        );
+        assert_eq!(safe_next(Some("/\\evil.example.com")), "/dashboard/sources");
+        assert_eq!(
+            safe_next(Some("/%5Cevil.example.com")),
+            "/dashboard/sources"
+        );
+        assert_eq!(
+            safe_next(Some("/%2F%2Fevil.example.com")),
+            "/dashboard/sources"
+        );
+        assert_eq!(
+            safe_next(Some("/dashboard\r\nLocation: https://evil.example.com")),
+            "/dashboard/sources"
+        );
+    }
+}
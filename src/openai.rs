@@ -1,39 +1,151 @@
+use anyhow::{anyhow, Result};
 use async_openai::{
     config::OpenAIConfig,
     error::OpenAIError,
-    types::{CreateEmbeddingRequestArgs, Embedding},
+    types::{
+        ChatCompletionRequestMessageArgs, CreateChatCompletionRequest,
+        CreateChatCompletionRequestArgs, CreateEmbeddingRequestArgs, Embedding, Role,
+    },
     Client,
 };
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use std::sync::Arc;
+
+use crate::breaker::CircuitBreaker;
+
+const CHAT_MODEL: &str = "gpt-3.5-turbo";
+
+/// Answer streamed by the mock client used in offline mode.
+pub const OFFLINE_ANSWER: &str = "Answers aren't generated in offline mode.";
 
+/// OpenAI client, or a deterministic mock of it when the client is unset.
 #[derive(Clone)]
 pub struct OpenAI {
-    client: Client<OpenAIConfig>,
+    client: Option<Client<OpenAIConfig>>,
+    breaker: Arc<CircuitBreaker>,
 }
 
 impl OpenAI {
     pub fn new() -> Self {
         let client = async_openai::Client::new();
-        Self { client }
+        let breaker = Arc::new(CircuitBreaker::new("openai"));
+        Self {
+            client: Some(client),
+            breaker,
+        }
+    }
+
+    /// Creates a client whose calls go through the breaker.
+    pub fn with_key(key: &str, breaker: Arc<CircuitBreaker>) -> Self {
+        let client = async_openai::Client::with_config(OpenAIConfig::new().with_api_key(key));
+        Self {
+            client: Some(client),
+            breaker,
+        }
+    }
+
+    /// Creates a mock that never reaches the network. Chats return an empty
+    /// completion, answers stream `OFFLINE_ANSWER` and embeddings fail.
+    pub fn offline() -> Self {
+        Self {
+            client: None,
+            breaker: Arc::new(CircuitBreaker::new("openai")),
+        }
     }
 
-    pub async fn create_embeddings(
+    /// Returns a chat completion for the system and user prompts.
+    pub async fn create_chat(&self, system: &str, user: &str) -> Result<String> {
+        let Some(client) = &self.client else {
+            return Ok(String::new());
+        };
+        let req = chat_request(system, user)?;
+        let resp = self
+            .breaker
+            .call(async { Ok::<_, anyhow::Error>(client.chat().create(req).await?) })
+            .await?;
+        Ok(resp
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .unwrap_or_default())
+    }
+
+    /// Streams pieces of a chat completion for the system and user prompts.
+    /// Only starting the stream goes through the breaker.
+    pub async fn create_chat_stream(
         &self,
-        chunks: &Vec<String>,
-    ) -> Result<Vec<Embedding>, OpenAIError> {
+        system: &str,
+        user: &str,
+    ) -> Result<BoxStream<'static, Result<String>>> {
+        let Some(client) = &self.client else {
+            return Ok(stream::once(async { Ok(OFFLINE_ANSWER.to_string()) }).boxed());
+        };
+        let req = chat_request(system, user)?;
+        let stream = self
+            .breaker
+            .call(async { Ok::<_, anyhow::Error>(client.chat().create_stream(req).await?) })
+            .await?;
+        Ok(stream
+            .map(|resp| {
+                let resp = resp?;
+                Ok(resp
+                    .choices
+                    .into_iter()
+                    .filter_map(|choice| choice.delta.content)
+                    .collect::<String>())
+            })
+            .boxed())
+    }
+
+    pub async fn create_embeddings(&self, chunks: &Vec<String>) -> Result<Vec<Embedding>> {
+        let client = self.online_client()?;
         let req = CreateEmbeddingRequestArgs::default()
             .model("text-embedding-ada-002")
             .input(chunks)
             .build()?;
-        let emb = self.client.embeddings().create(req).await?;
+        let emb = self
+            .breaker
+            .call(async { Ok::<_, anyhow::Error>(client.embeddings().create(req).await?) })
+            .await?;
         Ok(emb.data)
     }
 
-    pub async fn create_embedding(&self, text: &str) -> Result<Vec<Embedding>, OpenAIError> {
+    pub async fn create_embedding(&self, text: &str) -> Result<Vec<Embedding>> {
+        let client = self.online_client()?;
         let req = CreateEmbeddingRequestArgs::default()
             .model("text-embedding-ada-002")
             .input(text)
             .build()?;
-        let emb = self.client.embeddings().create(req).await?;
+        let emb = self
+            .breaker
+            .call(async { Ok::<_, anyhow::Error>(client.embeddings().create(req).await?) })
+            .await?;
         Ok(emb.data)
     }
+
+    fn online_client(&self) -> Result<&Client<OpenAIConfig>> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| anyhow!("OpenAI is disabled in offline mode"))
+    }
+}
+
+fn chat_request(system: &str, user: &str) -> Result<CreateChatCompletionRequest, OpenAIError> {
+    CreateChatCompletionRequestArgs::default()
+        .model(CHAT_MODEL)
+        .messages([
+            ChatCompletionRequestMessageArgs::default()
+                .role(Role::System)
+                .content(system)
+                .build()?,
+            ChatCompletionRequestMessageArgs::default()
+                .role(Role::User)
+                .content(user)
+                .build()?,
+        ])
+        .build()
 }
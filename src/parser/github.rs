@@ -1,44 +1,234 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use octocrab::Octocrab;
-use reqwest::StatusCode;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH},
+    Client, StatusCode,
+};
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use crate::types::Source;
+use super::ignore_file::{IgnoreFile, IGNORE_FILE};
+use super::nav::{Nav, NavEntry};
+use super::submodules::{self, Submodule, GITMODULES};
+use crate::{
+    breaker::CircuitBreaker,
+    pdf,
+    types::{RepoMetadata, Source},
+};
+
+/// Key the tree's ETag is stored under next to the files' ones, no file has
+/// an empty path.
+pub const TREE_ETAG: &str = "";
+
+/// Timeout of a single file, generous as PDFs may be large.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Clone)]
 pub struct GitHubParser {
     source: Source,
     client: Octocrab,
+    /// Fetches raw file contents, its connections are reused across files.
+    http: Client,
+    breaker: Arc<CircuitBreaker>,
+    /// ETags of the last parse, sent as `If-None-Match`.
+    known: Arc<HashMap<Path, String>>,
+    /// ETags of this parse keyed by path, the tree's by `TREE_ETAG`.
+    etags: Arc<Mutex<HashMap<Path, String>>>,
+    /// Other paths of the same blob keyed by the listed path.
+    alternates: Arc<Mutex<HashMap<Path, Vec<Path>>>>,
+    /// Submodules whose files are listed, with `submodules` on.
+    submodules: Arc<Mutex<Vec<Submodule>>>,
+    /// Navigation of the repo's mkdocs or Docusaurus site, if it has one.
+    nav: Arc<Mutex<Nav>>,
+    /// Whether the tree or a submodule's tree was truncated, the listing then
+    /// misses paths.
+    truncated: Arc<AtomicBool>,
 }
 
 impl GitHubParser {
-    pub fn new(source: Source, client: Octocrab) -> Self {
-        Self { source, client }
+    /// Proxies of the `HTTPS_PROXY` and `HTTP_PROXY` environment variables
+    /// apply to raw contents.
+    pub fn new(source: Source, client: Octocrab, breaker: Arc<CircuitBreaker>) -> Result<Self> {
+        let http = Client::builder()
+            .user_agent("rtfm")
+            .timeout(FETCH_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .context("Failed to build GitHub content client")?;
+        Ok(Self {
+            source,
+            client,
+            http,
+            breaker,
+            known: Arc::default(),
+            etags: Arc::default(),
+            alternates: Arc::default(),
+            submodules: Arc::default(),
+            nav: Arc::default(),
+            truncated: Arc::default(),
+        })
+    }
+
+    /// Makes requests conditional on the ETags of the last parse, unchanged
+    /// trees and files don't count against the rate limit.
+    pub fn with_etags(mut self, known: HashMap<Path, String>) -> Self {
+        self.known = Arc::new(known);
+        self
+    }
+
+    /// ETag of a fetched file or, with `TREE_ETAG`, of the tree.
+    pub fn etag(&self, path: &str) -> Option<String> {
+        self.etags
+            .lock()
+            .expect("ETags lock is poisoned")
+            .get(path)
+            .cloned()
     }
 
-    pub async fn get_paths(&self) -> Result<Vec<Path>> {
+    /// Whether the last listing misses paths, documents of unlisted files may
+    /// still be in the repo.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Other paths of the same content as the listed path.
+    pub fn alternate_paths(&self, path: &str) -> Vec<Path> {
+        self.alternates
+            .lock()
+            .expect("Alternates lock is poisoned")
+            .get(path)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Where the listed path is in the site's navigation.
+    pub fn nav_entry(&self, path: &str) -> Option<NavEntry> {
+        self.nav
+            .lock()
+            .expect("Nav lock is poisoned")
+            .get(path)
+            .cloned()
+    }
+
+    /// Returns blob paths of the repo tree, `filter` applies the source's filters
+    /// and the repo's ignore file. Blobs at several paths are listed once and
+    /// symlinks are left out. Returns none if the tree didn't change since the
+    /// last parse.
+    pub async fn get_paths(&self, filter: bool) -> Result<Vec<Path>> {
         let route = format!(
             "/repos/{}/{}/git/trees/{}?recursive='true'",
             &self.source.owner, &self.source.repo, &self.source.branch
         );
         tracing::info!("Getting git tree {}", route);
-        let resp: TreeResponse = self.client.get(route, None::<&()>).await?;
-        tracing::info!("Tree has {} paths", resp.tree.len());
+        let listing = listing_key(&self.source, filter);
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = self
+            .known
+            .get(TREE_ETAG)
+            .and_then(|known| tree_etag(known, &listing))
+        {
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+        }
+        let resp = self
+            .breaker
+            .call(async {
+                let resp = self
+                    .client
+                    ._get_with_headers(route.as_str(), Some(headers))
+                    .await?;
+                if resp.status().is_server_error() {
+                    return Err(anyhow!("GitHub responded with '{}'", resp.status()));
+                }
+                Ok(resp)
+            })
+            .await?;
+        match resp.status() {
+            StatusCode::NOT_MODIFIED => {
+                tracing::info!("Tree is unchanged since the last parse");
+                return Ok(Vec::new());
+            }
+            status if !status.is_success() => {
+                return Err(anyhow!(
+                    "Failed to get tree '{}', status is '{}'",
+                    route,
+                    status
+                ));
+            }
+            _ => {}
+        }
+        if let Some(etag) = resp.headers().get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            self.etags
+                .lock()
+                .expect("ETags lock is poisoned")
+                .insert(TREE_ETAG.to_string(), format!("{}{}", listing, etag));
+        }
+        let body = self.client.body_to_string(resp).await?;
+        let resp: TreeResponse = serde_json::from_str(&body).context("Invalid tree response")?;
+        if resp.truncated {
+            tracing::warn!("Tree '{}' is truncated, some paths are missing", route);
+        }
+        self.truncated.store(resp.truncated, Ordering::Relaxed);
+        let mut tree = resp.tree;
+        tracing::info!("Tree has {} paths", tree.len());
+        if self.source.github.submodules {
+            let files = self.get_submodule_files(&tree).await?;
+            tree.extend(files);
+        }
         tracing::info!(
             "Filter settings: allowed_ext: {:?}, allowed_dirs: {:?}, ignored_dies: {:?}",
             self.source.allowed_ext,
             self.source.allowed_dirs,
             self.source.ignored_dirs,
         );
-        let paths: Vec<Path> = resp
-            .tree
+        let ignore = match filter && tree.iter().any(|file| file.path == IGNORE_FILE) {
+            true => Some(IgnoreFile::parse(&self.get_text(IGNORE_FILE).await?)),
+            false => None,
+        };
+        if filter {
+            let blobs: Vec<Path> = tree
+                .iter()
+                .filter(|file| matches!(file.tree_type, TreeType::Blob))
+                .map(|file| file.path.clone())
+                .collect();
+            if let Some(config) = Nav::find_config(&blobs) {
+                // Documents are indexed without their nav rather than not at all.
+                match self.get_text(config).await {
+                    Ok(text) => {
+                        *self.nav.lock().expect("Nav lock is poisoned") =
+                            Nav::parse(config, &text, &blobs)
+                    }
+                    Err(err) => tracing::warn!("Failed to get nav '{}': {:?}", config, err),
+                }
+            }
+        }
+        let files: Vec<Tree> = tree
             .into_iter()
-            .filter_map(|file| match file.tree_type {
-                TreeType::Blob if self.is_target_file(&file.path) => Some(file.path),
-                _ => None,
+            .filter(|file| match file.tree_type {
+                TreeType::Blob => !filter || is_target_file(&self.source, &file.path),
+                _ => false,
+            })
+            .filter(|file| {
+                !ignore
+                    .as_ref()
+                    .map_or(false, |ignore| ignore.ignores(&file.path))
             })
             .collect();
-        tracing::info!("Tree has {} target paths", paths.len());
+        let (paths, alternates) = dedup_blobs(files);
+        tracing::info!(
+            "Tree has {} target paths, {} have copies",
+            paths.len(),
+            alternates.len()
+        );
+        *self.alternates.lock().expect("Alternates lock is poisoned") = alternates;
         Ok(paths)
     }
 
@@ -66,7 +256,7 @@ impl GitHubParser {
     //             );
     //             let commit: Commit = self.client.get(route, None::<&()>).await?;
     //             for file in commit.files {
-    //                 if self.is_target_file(&file.filename) {
+    //                 if is_target_file(&self.source, &file.filename) {
     //                     paths.insert(file.filename, file.status);
     //                 }
     //             }
@@ -82,13 +272,32 @@ impl GitHubParser {
     //     Ok(paths)
     // }
 
-    pub async fn get_content(&self, path: &Path) -> Result<String> {
-        let url = format!(
-            "https://raw.githubusercontent.com/{}/{}/{}/{}",
-            &self.source.owner, &self.source.repo, &self.source.branch, path,
-        );
-        let resp = reqwest::get(&url).await?;
-        match resp.status() {
+    /// Returns none if the file didn't change since the last parse.
+    pub async fn get_content(&self, path: &Path) -> Result<Option<String>> {
+        let url = self.raw_url(path);
+        let headers = self.conditional(path)?;
+        // Missing files are the caller's problem, only outages count as failures.
+        let resp = self
+            .breaker
+            .call(async {
+                let resp = self.http.get(&url).headers(headers).send().await?;
+                if resp.status().is_server_error() {
+                    return Err(anyhow!("GitHub responded with '{}'", resp.status()));
+                }
+                Ok(resp)
+            })
+            .await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+        if resp.status() == StatusCode::OK {
+            self.remember(path, resp.headers());
+        }
+        let content = match resp.status() {
+            StatusCode::OK if pdf::is_pdf(path) => {
+                let data = super::read_body(resp, pdf::MAX_PDF_BYTES).await?;
+                tokio::task::spawn_blocking(move || pdf::extract_text(&data)).await?
+            }
             StatusCode::OK => match resp.text().await {
                 Ok(text) => Ok(text),
                 Err(e) => Err(anyhow!("unable to get body text; {}", e)),
@@ -98,38 +307,281 @@ impl GitHubParser {
                 url,
                 resp.status()
             )),
+        };
+        content.map(Some)
+    }
+
+    /// Returns every blob path of the repo tree, regardless of filters and
+    /// ETags of the last parse.
+    pub async fn list_tree(&self) -> Result<Vec<Path>> {
+        let route = format!(
+            "/repos/{}/{}/git/trees/{}?recursive=true",
+            &self.source.owner, &self.source.repo, &self.source.branch
+        );
+        let resp: TreeResponse = self
+            .breaker
+            .call(async {
+                Ok::<_, anyhow::Error>(self.client.get(route.as_str(), None::<&()>).await?)
+            })
+            .await
+            .with_context(|| format!("Failed to get tree '{}'", route))?;
+        if resp.truncated {
+            tracing::warn!("Tree '{}' is truncated, some paths are missing", route);
         }
+        Ok(resp
+            .tree
+            .into_iter()
+            .filter(|file| matches!(file.tree_type, TreeType::Blob))
+            .map(|file| file.path)
+            .collect())
     }
 
-    fn is_target_file(&self, path: &Path) -> bool {
-        for dir in &self.source.allowed_dirs {
-            if !path.starts_with(dir) {
-                return false;
-            }
+    /// Description, topics, language and homepage of the repo.
+    pub async fn get_metadata(&self) -> Result<RepoMetadata> {
+        let route = format!("/repos/{}/{}", &self.source.owner, &self.source.repo);
+        let resp: RepoResponse = self
+            .breaker
+            .call(async {
+                Ok::<_, anyhow::Error>(self.client.get(route.as_str(), None::<&()>).await?)
+            })
+            .await
+            .with_context(|| format!("Failed to get repo '{}'", route))?;
+        Ok(RepoMetadata {
+            description: resp.description.unwrap_or_default(),
+            topics: resp.topics,
+            language: resp.language.unwrap_or_default(),
+            homepage: resp.homepage.unwrap_or_default(),
+        })
+    }
+
+    // Blobs of the submodules at their pinned commits, under the submodules'
+    // paths. Submodules of submodules aren't followed.
+    async fn get_submodule_files(&self, tree: &[Tree]) -> Result<Vec<Tree>> {
+        let commits: Vec<&Tree> = tree
+            .iter()
+            .filter(|file| matches!(file.tree_type, TreeType::Commit))
+            .collect();
+        if commits.is_empty() {
+            return Ok(Vec::new());
+        }
+        let urls: HashMap<String, String> =
+            submodules::parse_gitmodules(&self.get_text(GITMODULES).await?)
+                .into_iter()
+                .collect();
+        let mut files = Vec::new();
+        let mut followed = Vec::new();
+        for commit in commits {
+            let Some((owner, repo)) = urls
+                .get(&commit.path)
+                .and_then(|url| submodules::github_repo(url, &self.source.owner))
+            else {
+                tracing::warn!("Skipping submodule '{}', it isn't on GitHub", commit.path);
+                continue;
+            };
+            let route = format!(
+                "/repos/{}/{}/git/trees/{}?recursive=true",
+                owner, repo, commit.sha
+            );
+            tracing::info!("Getting submodule tree {}", route);
+            let resp: TreeResponse = self
+                .breaker
+                .call(async {
+                    Ok::<_, anyhow::Error>(self.client.get(route.as_str(), None::<&()>).await?)
+                })
+                .await
+                .with_context(|| format!("Failed to get tree of submodule '{}'", commit.path))?;
+            files.extend(self.submodule_blobs(&commit.path, resp));
+            followed.push(Submodule {
+                path: commit.path.clone(),
+                owner,
+                repo,
+                sha: commit.sha.clone(),
+            });
+        }
+        tracing::info!("Submodules have {} paths", files.len());
+        *self.submodules.lock().expect("Submodules lock is poisoned") = followed;
+        Ok(files)
+    }
+
+    // Blobs of a submodule's tree under the submodule's path.
+    fn submodule_blobs(&self, path: &str, resp: TreeResponse) -> Vec<Tree> {
+        if resp.truncated {
+            tracing::warn!(
+                "Tree of submodule '{}' is truncated, some paths are missing",
+                path
+            );
+            self.truncated.store(true, Ordering::Relaxed);
+        }
+        resp.tree
+            .into_iter()
+            .filter(|file| matches!(file.tree_type, TreeType::Blob))
+            .map(|file| Tree {
+                path: format!("{}/{}", path, file.path),
+                ..file
+            })
+            .collect()
+    }
+
+    // Fetched unconditionally, e.g. an ignore file's rules are needed whenever
+    // the tree changed.
+    async fn get_text(&self, path: &str) -> Result<String> {
+        let url = self.raw_url(path);
+        let resp = self
+            .breaker
+            .call(async {
+                let resp = self.http.get(&url).send().await?;
+                if resp.status().is_server_error() {
+                    return Err(anyhow!("GitHub responded with '{}'", resp.status()));
+                }
+                Ok(resp)
+            })
+            .await?;
+        let text = resp
+            .error_for_status()
+            .with_context(|| format!("Failed to get '{}'", path))?
+            .text()
+            .await?;
+        Ok(text)
+    }
+
+    // Files of submodules are fetched from their repos at the pinned commit.
+    fn raw_url(&self, path: &str) -> String {
+        let (owner, repo, rev, path) = self.locate(path);
+        format!(
+            "https://raw.githubusercontent.com/{}/{}/{}/{}",
+            owner, repo, rev, path
+        )
+    }
+
+    /// Link to the file on GitHub, files of submodules link to the
+    /// submodule's repo at the pinned commit.
+    pub fn blob_url(&self, path: &str) -> String {
+        let (owner, repo, rev, path) = self.locate(path);
+        blob_url(&owner, &repo, &rev, &path)
+    }
+
+    // Owner, repo, rev and path of the file within its repo, the submodule's
+    // for files of submodules.
+    fn locate(&self, path: &str) -> (String, String, String, String) {
+        let submodules = self.submodules.lock().expect("Submodules lock is poisoned");
+        let (owner, repo, rev, path) = submodules
+            .iter()
+            .find_map(|module| {
+                let path = module.strip(path)?;
+                Some((&module.owner, &module.repo, &module.sha, path))
+            })
+            .unwrap_or((
+                &self.source.owner,
+                &self.source.repo,
+                &self.source.branch,
+                path,
+            ));
+        (owner.clone(), repo.clone(), rev.clone(), path.to_string())
+    }
+
+    // `If-None-Match` with the path's ETag of the last parse, if any.
+    fn conditional(&self, path: &str) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        if let Some(etag) = self.known.get(path) {
+            headers.insert(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
         }
+        Ok(headers)
+    }
 
-        for dir in &self.source.ignored_dirs {
-            if path.starts_with(dir) {
-                return false;
+    fn remember(&self, path: &str, headers: &HeaderMap) {
+        if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            self.etags
+                .lock()
+                .expect("ETags lock is poisoned")
+                .insert(path.to_string(), etag.to_string());
+        }
+    }
+}
+
+/// Settings the listing of the tree depends on, its ETag is stored behind them.
+/// A 304 only says the tree is unchanged, not that files of changed filters
+/// were listed before, so the tree is fetched again once they change.
+fn listing_key(source: &Source, filter: bool) -> String {
+    fn sorted(set: &HashSet<String>) -> Vec<&String> {
+        let mut items: Vec<&String> = set.iter().collect();
+        items.sort();
+        items
+    }
+    let settings = format!(
+        "{:?}",
+        (
+            filter,
+            &source.branch,
+            sorted(&source.allowed_ext),
+            sorted(&source.allowed_dirs),
+            sorted(&source.ignored_dirs),
+            source.github.submodules,
+        )
+    );
+    format!("{:08x} ", crc32fast::hash(settings.as_bytes()))
+}
+
+/// ETag of the stored tree, none if it was listed with other settings.
+fn tree_etag<'a>(stored: &'a str, listing: &str) -> Option<&'a str> {
+    stored.strip_prefix(listing)
+}
+
+// Symlinks hold the path they point at, their targets are listed on their own.
+const SYMLINK_MODE: &str = "120000";
+
+/// Keeps the first path of each blob, e.g. of a vendored or mirrored directory,
+/// its other paths become its alternates. Symlinks are dropped.
+fn dedup_blobs(files: Vec<Tree>) -> (Vec<Path>, HashMap<Path, Vec<Path>>) {
+    let mut first_by_sha: HashMap<String, Path> = HashMap::new();
+    let mut paths = Vec::new();
+    let mut alternates: HashMap<Path, Vec<Path>> = HashMap::new();
+    for file in files {
+        if file.mode == SYMLINK_MODE {
+            tracing::debug!("Skipping symlink '{}'", file.path);
+            continue;
+        }
+        match first_by_sha.get(&file.sha) {
+            Some(first) => alternates.entry(first.clone()).or_default().push(file.path),
+            None => {
+                first_by_sha.insert(file.sha, file.path.clone());
+                paths.push(file.path);
             }
         }
+    }
+    (paths, alternates)
+}
 
-        if self.source.allowed_ext.len() > 0
-            && !self
-                .source
-                .allowed_ext
-                .iter()
-                .any(|ext| path.ends_with(ext))
-        {
+/// Whether the path passes the source's extension and directory filters.
+pub fn is_target_file(source: &Source, path: &str) -> bool {
+    for dir in &source.allowed_dirs {
+        if !path.starts_with(dir) {
+            return false;
+        }
+    }
+
+    for dir in &source.ignored_dirs {
+        if path.starts_with(dir) {
             return false;
         }
+    }
 
-        true
+    if source.allowed_ext.len() > 0 && !source.allowed_ext.iter().any(|ext| path.ends_with(ext)) {
+        return false;
     }
+
+    true
+}
+
+/// Link to the file of the repo at the branch or commit on GitHub.
+pub fn blob_url(owner: &str, repo: &str, rev: &str, path: &str) -> String {
+    format!(
+        "https://github.com/{}/{}/blob/{}/{}",
+        owner, repo, rev, path
+    )
 }
 
 // website/docs/r/xray_group.html.markdown
-type Path = String;
+pub type Path = String;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Commit {
@@ -160,6 +612,15 @@ pub enum FileStatus {
     Unchanged,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoResponse {
+    pub description: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    pub language: Option<String>,
+    pub homepage: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TreeResponse {
     pub sha: String,
@@ -184,4 +645,128 @@ pub struct Tree {
 pub enum TreeType {
     Blob,
     Tree,
+    /// Submodule, its sha is the pinned commit.
+    Commit,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(path: &str, mode: &str, sha: &str) -> Tree {
+        Tree {
+            path: path.to_string(),
+            mode: mode.to_string(),
+            tree_type: TreeType::Blob,
+            sha: sha.to_string(),
+            size: None,
+            url: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_dedup_blobs() {
+        let files = vec![
+            blob("docs/intro.md", "100644", "a"),
+            blob("docs/setup.md", "100644", "b"),
+            blob("docs/latest", SYMLINK_MODE, "c"),
+            blob("vendor/docs/intro.md", "100644", "a"),
+            blob("mirror/intro.md", "100644", "a"),
+        ];
+        let (paths, alternates) = dedup_blobs(files);
+        assert_eq!(paths, vec!["docs/intro.md", "docs/setup.md"]);
+        assert_eq!(
+            alternates["docs/intro.md"],
+            vec!["vendor/docs/intro.md", "mirror/intro.md"]
+        );
+        assert_eq!(alternates.len(), 1);
+    }
+
+    fn source() -> Source {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "collection_id": 1,
+            "kind": "github",
+            "url": "",
+            "owner": "acme",
+            "repo": "docs",
+            "branch": "main",
+            "allowed_ext": [".md", ".mdx"],
+            "allowed_dirs": [],
+            "ignored_dirs": ["vendor"],
+            "skip_front_matter": [],
+            "labels": [],
+            "crawl": {},
+            "bucket": {},
+            "github": {},
+            "created_at": "2023-09-01T00:00:00Z",
+            "updated_at": "2023-09-01T00:00:00Z",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_tree_etag_of_listing() {
+        let mut source = source();
+        let listing = listing_key(&source, true);
+        let stored = format!("{}{}", listing, "W/\"abc\"");
+        assert_eq!(tree_etag(&stored, &listing), Some("W/\"abc\""));
+        // Without filters, other files are listed.
+        assert_eq!(tree_etag(&stored, &listing_key(&source, false)), None);
+        source.ignored_dirs.insert("docs/api".to_string());
+        assert_eq!(tree_etag(&stored, &listing_key(&source, true)), None);
+        // ETags stored before they were keyed by the listing are dropped.
+        assert_eq!(tree_etag("W/\"abc\"", &listing), None);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_submodule_tree() {
+        let parser = GitHubParser::new(
+            source(),
+            Octocrab::default(),
+            Arc::new(CircuitBreaker::new("github")),
+        )
+        .unwrap();
+        let tree = |truncated| TreeResponse {
+            sha: "c".to_string(),
+            url: String::new(),
+            tree: vec![blob("intro.md", "100644", "a")],
+            truncated,
+        };
+
+        let files = parser.submodule_blobs("vendor/sdk", tree(false));
+        assert_eq!(files[0].path, "vendor/sdk/intro.md");
+        assert!(!parser.is_truncated());
+        // A single truncated submodule makes the whole listing partial.
+        let files = parser.submodule_blobs("vendor/cli", tree(true));
+        assert_eq!(files[0].path, "vendor/cli/intro.md");
+        assert!(parser.is_truncated());
+        parser.submodule_blobs("vendor/sdk", tree(false));
+        assert!(parser.is_truncated());
+    }
+
+    #[tokio::test]
+    async fn test_blob_url_of_submodule_files() {
+        let parser = GitHubParser::new(
+            source(),
+            Octocrab::default(),
+            Arc::new(CircuitBreaker::new("github")),
+        )
+        .unwrap();
+        *parser.submodules.lock().unwrap() = vec![Submodule {
+            path: "vendor/sdk".to_string(),
+            owner: "acme".to_string(),
+            repo: "sdk".to_string(),
+            sha: "c0ffee".to_string(),
+        }];
+        assert_eq!(
+            parser.blob_url("docs/intro.md"),
+            "https://github.com/acme/docs/blob/main/docs/intro.md"
+        );
+        // Files of submodules link to the submodule's repo at the pinned commit.
+        assert_eq!(
+            parser.blob_url("vendor/sdk/docs/intro.md"),
+            "https://github.com/acme/sdk/blob/c0ffee/docs/intro.md"
+        );
+    }
 }
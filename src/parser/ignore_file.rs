@@ -0,0 +1,54 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// File in the root of a repo excluding paths from the index, in gitignore
+/// syntax. It narrows the source's filters, maintainers of the repo can use
+/// it without access to the API.
+pub const IGNORE_FILE: &str = ".rtfmignore";
+
+/// Rules of an ignore file.
+#[derive(Debug, Clone)]
+pub struct IgnoreFile {
+    rules: Gitignore,
+}
+
+impl IgnoreFile {
+    /// Parses the ignore file, invalid patterns are skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for line in text.lines() {
+            if let Err(err) = builder.add_line(None, line) {
+                tracing::warn!("Skipping pattern '{}' of {}: {}", line, IGNORE_FILE, err);
+            }
+        }
+        let rules = builder.build().unwrap_or_else(|err| {
+            tracing::warn!("Ignoring {}: {}", IGNORE_FILE, err);
+            Gitignore::empty()
+        });
+        Self { rules }
+    }
+
+    /// Whether the file or one of its directories is ignored.
+    pub fn ignores(&self, path: &str) -> bool {
+        self.rules
+            .matched_path_or_any_parents(path, false)
+            .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_file() {
+        let text = "# Generated\napi/\n*.draft.md\n!keep.draft.md\n/CHANGELOG.md\n[invalid";
+        let ignore = IgnoreFile::parse(text);
+        assert!(ignore.ignores("api/index.md"));
+        assert!(ignore.ignores("docs/api/index.md"));
+        assert!(ignore.ignores("docs/intro.draft.md"));
+        assert!(!ignore.ignores("docs/keep.draft.md"));
+        assert!(ignore.ignores("CHANGELOG.md"));
+        assert!(!ignore.ignores("docs/CHANGELOG.md"));
+        assert!(!ignore.ignores("docs/intro.md"));
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use regex::Regex;
+use reqwest::Url;
+use std::io::Read;
+
+/// Roles of Python objects turned into documents, labels and terms of other
+/// roles point into the prose docs, which are crawled anyway.
+const PYTHON_ROLES: [&str; 8] = [
+    "module",
+    "class",
+    "function",
+    "method",
+    "classmethod",
+    "staticmethod",
+    "exception",
+    "attribute",
+];
+
+/// Sphinx `objects.inv` inventory, maps documented objects to their urls.
+#[derive(Debug, PartialEq)]
+pub struct Inventory {
+    pub project: String,
+    pub version: String,
+    pub entries: Vec<InventoryEntry>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InventoryEntry {
+    /// Fully qualified name, e.g. `requests.Session.get`.
+    pub name: String,
+    pub domain: String,
+    pub role: String,
+    /// Url relative to the inventory, anchors are expanded.
+    pub uri: String,
+    /// Name shown in the docs, the same as `name` in most inventories.
+    pub display: String,
+}
+
+impl Inventory {
+    /// Parses a version 2 inventory: a plain text header followed by zlib
+    /// compressed lines of `name domain:role priority uri display`.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut project = String::new();
+        let mut version = String::new();
+        let mut rest = data;
+        for index in 0..4 {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .context("Inventory header is truncated")?;
+            let line = String::from_utf8_lossy(&rest[..end]).trim().to_string();
+            rest = &rest[end + 1..];
+            match index {
+                0 if line != "# Sphinx inventory version 2" => {
+                    bail!("Unsupported inventory '{}'", line)
+                }
+                1 => project = line.trim_start_matches("# Project:").trim().to_string(),
+                2 => version = line.trim_start_matches("# Version:").trim().to_string(),
+                _ => {}
+            }
+        }
+
+        let mut text = String::new();
+        ZlibDecoder::new(rest)
+            .read_to_string(&mut text)
+            .context("Failed to decompress inventory")?;
+        let line_re = Regex::new(r"^(.+?)\s+(\S+?):(\S+)\s+(-?\d+)\s+(\S*)\s+(.*)$").unwrap();
+        let entries = text
+            .lines()
+            .filter_map(|line| line_re.captures(line))
+            .map(|caps| {
+                let name = caps[1].to_string();
+                // `$` abbreviates the name and `-` a display name equal to it.
+                let uri = match caps[5].strip_suffix('$') {
+                    Some(prefix) => format!("{}{}", prefix, name),
+                    None => caps[5].to_string(),
+                };
+                let display = match &caps[6] {
+                    "-" => name.clone(),
+                    display => display.to_string(),
+                };
+                InventoryEntry {
+                    domain: caps[2].to_string(),
+                    role: caps[3].to_string(),
+                    uri,
+                    display,
+                    name,
+                }
+            })
+            .collect();
+        Ok(Self {
+            project,
+            version,
+            entries,
+        })
+    }
+
+    /// Entries of Python modules, classes, functions and their members.
+    pub fn python_objects(&self) -> impl Iterator<Item = &InventoryEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.domain == "py" && PYTHON_ROLES.contains(&entry.role.as_str()))
+    }
+}
+
+impl InventoryEntry {
+    /// Markdown document of the entry, titled by its name so that symbol
+    /// lookups match it exactly.
+    pub fn to_markdown(&self, inventory: &Inventory, url: &Url) -> String {
+        let project = match inventory.version.as_str() {
+            "" => inventory.project.clone(),
+            version => format!("{} {}", inventory.project, version),
+        };
+        format!(
+            "---\ntitle: {name}\n---\n\n# {name}\n\nPython {role} `{display}` of {project}, documented at {url}\n",
+            name = self.name,
+            role = self.role,
+            display = self.display,
+            project = project,
+            url = url,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn test_parse_inventory() {
+        let lines = "requests py:module 0 api.html#module-$ -\n\
+            requests.Session py:class 1 api.html#$ -\n\
+            requests.Session.get py:method 1 api.html#$ Session.get\n\
+            install std:label -1 user/install.html#install Installation of Requests\n";
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(lines.as_bytes()).unwrap();
+        let mut data = b"# Sphinx inventory version 2\n# Project: Requests\n# Version: 2.31\n# The remainder of this file is compressed using zlib.\n".to_vec();
+        data.extend(encoder.finish().unwrap());
+
+        let inventory = Inventory::parse(&data).unwrap();
+        assert_eq!(inventory.project, "Requests");
+        assert_eq!(inventory.version, "2.31");
+        assert_eq!(inventory.entries.len(), 4);
+        assert_eq!(inventory.entries[0].uri, "api.html#module-requests");
+        assert_eq!(inventory.entries[3].display, "Installation of Requests");
+
+        let names: Vec<&str> = inventory
+            .python_objects()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["requests", "requests.Session", "requests.Session.get"]
+        );
+        let entry = &inventory.entries[2];
+        assert_eq!(entry.display, "Session.get");
+        let url = Url::parse("https://requests.readthedocs.io/en/latest/")
+            .unwrap()
+            .join(&entry.uri)
+            .unwrap();
+        let markdown = entry.to_markdown(&inventory, &url);
+        assert!(markdown.starts_with("---\ntitle: requests.Session.get\n---"));
+        assert!(markdown
+            .contains("https://requests.readthedocs.io/en/latest/api.html#requests.Session.get"));
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::GzDecoder;
+use reqwest::Client;
+use std::{io::Read, time::Duration};
+
+use super::github::Path;
+use crate::types::Source;
+
+/// Monthly archives of busy lists run into tens of megabytes.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Largest archive read, before and after decompression, so a gzip bomb or a
+/// runaway download doesn't exhaust memory.
+const MAX_ARCHIVE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Fetches the mbox archive or EML message at the source's url, e.g. a
+/// mailing list's monthly archive. Gzipped archives, as served by pipermail,
+/// are decompressed. The archive is a single document, split per message.
+#[derive(Clone)]
+pub struct MboxParser {
+    source: Source,
+    client: Client,
+}
+
+impl MboxParser {
+    pub fn new(source: Source) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent("rtfm")
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .context("Failed to build mbox client")?;
+        Ok(Self { source, client })
+    }
+
+    /// The archive's url is its only path.
+    pub fn get_paths(&self) -> Vec<Path> {
+        vec![self.source.url.clone()]
+    }
+
+    pub async fn get_content(&self, path: &Path) -> Result<String> {
+        if path != &self.source.url {
+            return Err(anyhow!(
+                "unable to get content from '{}', not the archive",
+                path
+            ));
+        }
+        let resp = self.client.get(path).send().await?.error_for_status()?;
+        let data = super::read_body(resp, MAX_ARCHIVE_BYTES).await?;
+        // Gzip magic bytes, the extension isn't reliable behind redirects.
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut text = Vec::new();
+            GzDecoder::new(&data[..])
+                .take(MAX_ARCHIVE_BYTES as u64 + 1)
+                .read_to_end(&mut text)
+                .context("Failed to decompress archive")?;
+            if text.len() > MAX_ARCHIVE_BYTES {
+                bail!(
+                    "Archive is larger than {} bytes decompressed",
+                    MAX_ARCHIVE_BYTES
+                );
+            }
+            return Ok(String::from_utf8_lossy(&text).into_owned());
+        }
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+}
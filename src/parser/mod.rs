@@ -1,2 +1,144 @@
+mod bucket;
 mod github;
-pub(crate) use github::GitHubParser;
+mod ignore_file;
+mod inventory;
+mod mbox;
+mod nav;
+mod robots;
+mod rustdoc;
+mod stub;
+mod submodules;
+mod web;
+pub use bucket::BucketParser;
+pub use github::{is_target_file, GitHubParser, Path, TREE_ETAG};
+pub use mbox::MboxParser;
+pub use nav::{NavEntry, NAV_SEPARATOR};
+pub use rustdoc::RustdocParser;
+pub use stub::{StubParser, StubRepos};
+pub use web::WebParser;
+
+use anyhow::{bail, Result};
+
+use crate::DocumentFormat;
+
+/// Fetches files of a source, from GitHub, a crawled site, rustdoc JSON, a
+/// mail archive, a bucket or from stub repos in tests.
+#[derive(Clone)]
+pub enum Parser {
+    GitHub(GitHubParser),
+    Web(WebParser),
+    Rustdoc(RustdocParser),
+    Mbox(MboxParser),
+    Bucket(BucketParser),
+    Stub(StubParser),
+}
+
+impl Parser {
+    /// Returns blob paths of the repo, `filter` applies the source's filters.
+    /// Crawled sites return page urls, filters don't apply to them.
+    pub async fn get_paths(&self, filter: bool) -> Result<Vec<String>> {
+        match self {
+            Parser::GitHub(parser) => parser.get_paths(filter).await,
+            Parser::Web(parser) => parser.get_paths().await,
+            Parser::Rustdoc(parser) => parser.get_paths().await,
+            Parser::Mbox(parser) => Ok(parser.get_paths()),
+            Parser::Bucket(parser) => parser.get_paths(filter).await,
+            Parser::Stub(parser) => Ok(parser.get_paths(filter)),
+        }
+    }
+
+    /// Returns none if the file didn't change since the last parse, only
+    /// GitHub sources know.
+    pub async fn get_content(&self, path: &String) -> Result<Option<String>> {
+        match self {
+            Parser::GitHub(parser) => parser.get_content(path).await,
+            Parser::Web(parser) => parser.get_content(path).map(Some),
+            Parser::Rustdoc(parser) => parser.get_content(path).map(Some),
+            Parser::Mbox(parser) => parser.get_content(path).await.map(Some),
+            Parser::Bucket(parser) => parser.get_content(path).await.map(Some),
+            Parser::Stub(parser) => parser.get_content(path).map(Some),
+        }
+    }
+
+    /// ETag of the listed or fetched object, only buckets and GitHub know.
+    pub fn etag(&self, path: &String) -> Option<String> {
+        match self {
+            Parser::GitHub(parser) => parser.etag(path),
+            Parser::Bucket(parser) => parser.etag(path),
+            Parser::Web(_) | Parser::Rustdoc(_) | Parser::Mbox(_) | Parser::Stub(_) => None,
+        }
+    }
+
+    /// Where the document is in the navigation of the repo's docs site, only
+    /// repos know.
+    pub fn nav_entry(&self, path: &str) -> Option<NavEntry> {
+        match self {
+            Parser::GitHub(parser) => parser.nav_entry(path),
+            Parser::Stub(parser) => parser.nav_entry(path),
+            Parser::Web(_) | Parser::Rustdoc(_) | Parser::Mbox(_) | Parser::Bucket(_) => None,
+        }
+    }
+
+    /// Link to the file on GitHub, only repos know.
+    pub fn url(&self, path: &str) -> String {
+        match self {
+            Parser::GitHub(parser) => parser.blob_url(path),
+            Parser::Stub(parser) => parser.blob_url(path),
+            Parser::Web(_) | Parser::Rustdoc(_) | Parser::Mbox(_) | Parser::Bucket(_) => {
+                String::new()
+            }
+        }
+    }
+
+    /// Whether the listed paths miss some of the source's, as of a truncated
+    /// tree or a crawl that stopped early.
+    pub fn is_partial(&self) -> bool {
+        match self {
+            Parser::GitHub(parser) => parser.is_truncated(),
+            Parser::Web(parser) => parser.is_partial(),
+            Parser::Stub(parser) => parser.is_truncated(),
+            Parser::Rustdoc(_) | Parser::Mbox(_) | Parser::Bucket(_) => false,
+        }
+    }
+
+    /// Format of a fetched document, repo files are detected by extension
+    /// and content while crawled pages are known upfront.
+    pub fn get_format(&self, path: &String, data: &str) -> DocumentFormat {
+        match self {
+            Parser::Web(parser) => parser.get_format(path),
+            Parser::Rustdoc(_) => DocumentFormat::Markdown,
+            Parser::Mbox(_) => DocumentFormat::Email,
+            Parser::GitHub(_) | Parser::Bucket(_) | Parser::Stub(_) => {
+                DocumentFormat::detect(path, data)
+            }
+        }
+    }
+}
+
+/// Reads the response's body, failing as soon as it's larger than `limit`
+/// bytes. `Content-Length` is only checked upfront, it's missing on chunked
+/// bodies and servers may send more than they declare.
+pub async fn read_body(mut resp: reqwest::Response, limit: usize) -> Result<Vec<u8>> {
+    if resp
+        .content_length()
+        .map_or(false, |len| len > limit as u64)
+    {
+        bail!(
+            "'{}' is larger than the limit of {} bytes",
+            resp.url(),
+            limit
+        );
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        if data.len() + chunk.len() > limit {
+            bail!(
+                "'{}' is larger than the limit of {} bytes",
+                resp.url(),
+                limit
+            );
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
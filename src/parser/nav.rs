@@ -0,0 +1,494 @@
+use serde_yaml::Value;
+use std::collections::HashMap;
+
+/// Config files of documentation sites with a nav, in the order they're
+/// looked for. Docusaurus sidebars refer to doc ids in the `docs` folder next
+/// to them.
+pub const NAV_CONFIGS: &[&str] = &[
+    "mkdocs.yml",
+    "mkdocs.yaml",
+    "sidebars.js",
+    "sidebars.ts",
+    "website/sidebars.js",
+];
+
+const MKDOCS_DOCS_DIR: &str = "docs";
+
+/// Separates the section titles of a nav path, e.g. `Guides > Deployment`.
+pub const NAV_SEPARATOR: &str = " > ";
+
+/// Where a document is in the site's navigation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavEntry {
+    /// Position of the page in the whole nav.
+    pub index: usize,
+    /// Titles of the sections the page is in, outermost first.
+    pub sections: Vec<String>,
+}
+
+impl NavEntry {
+    pub fn nav_path(&self) -> String {
+        self.sections.join(NAV_SEPARATOR)
+    }
+}
+
+/// Navigation of a documentation site, by repo path of its pages.
+#[derive(Debug, Clone, Default)]
+pub struct Nav {
+    entries: HashMap<String, NavEntry>,
+}
+
+impl Nav {
+    /// The first nav config among the repo's paths.
+    pub fn find_config(paths: &[String]) -> Option<&'static str> {
+        NAV_CONFIGS
+            .iter()
+            .find(|config| paths.iter().any(|path| path == *config))
+            .copied()
+    }
+
+    /// Parses the mkdocs config or Docusaurus sidebars at `config`, `paths`
+    /// are the repo's files the nav's pages are resolved against. Navs that
+    /// can't be parsed are empty.
+    pub fn parse(config: &str, text: &str, paths: &[String]) -> Self {
+        let (dir, file) = match config.rsplit_once('/') {
+            Some((dir, file)) => (format!("{}/", dir), file),
+            None => (String::new(), config),
+        };
+        let mut pages = Vec::new();
+        if file.ends_with(".yml") || file.ends_with(".yaml") {
+            let config: Value = match serde_yaml::from_str(text) {
+                Ok(config) => config,
+                Err(err) => {
+                    tracing::warn!("Ignoring nav of invalid '{}': {}", config, err);
+                    return Self::default();
+                }
+            };
+            let docs_dir = config
+                .get("docs_dir")
+                .and_then(Value::as_str)
+                .unwrap_or(MKDOCS_DOCS_DIR)
+                .trim_start_matches("./")
+                .trim_end_matches('/');
+            if let Some(nav) = config.get("nav") {
+                walk_mkdocs(nav, &[], &mut pages);
+            }
+            let prefix = format!("{}{}/", dir, docs_dir);
+            for (path, _) in pages.iter_mut() {
+                *path = format!("{}{}", prefix, path.trim_start_matches("./"));
+            }
+        } else {
+            let docs_dir = format!("{}docs/", dir);
+            let ids = doc_ids(&docs_dir, paths);
+            let tokens = tokenize(text);
+            // The object assigned to a variable or exported, e.g.
+            // `module.exports = {` or `export default {`.
+            let start = tokens.windows(2).position(|pair| {
+                pair[1] == Token::Punct('{')
+                    && (pair[0] == Token::Punct('=') || pair[0] == Token::Ident("default".into()))
+            });
+            let Some(start) = start else {
+                tracing::warn!("Ignoring '{}', no sidebars found", config);
+                return Self::default();
+            };
+            let mut parser = JsParser {
+                tokens: &tokens,
+                at: start + 1,
+            };
+            if let JsValue::Object(sidebars) = parser.value() {
+                for (_, sidebar) in &sidebars {
+                    walk_sidebar(sidebar, &[], &mut pages);
+                }
+            }
+            pages = pages
+                .into_iter()
+                .filter_map(|(id, sections)| Some((ids.get(&id)?.clone(), sections)))
+                .collect();
+        }
+
+        let mut entries = HashMap::new();
+        for (path, sections) in pages {
+            if paths.contains(&path) && !entries.contains_key(&path) {
+                let index = entries.len();
+                entries.insert(path, NavEntry { index, sections });
+            }
+        }
+        Self { entries }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&NavEntry> {
+        self.entries.get(path)
+    }
+}
+
+// Pages of an mkdocs nav, entries are paths, `title: path` pairs or
+// `section: [entries]` pairs.
+fn walk_mkdocs(value: &Value, sections: &[String], pages: &mut Vec<(String, Vec<String>)>) {
+    match value {
+        Value::String(path) if !path.contains("://") => {
+            pages.push((path.clone(), sections.to_vec()));
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                walk_mkdocs(item, sections, pages);
+            }
+        }
+        Value::Mapping(entries) => {
+            for (title, value) in entries {
+                match (title.as_str(), value) {
+                    (_, Value::String(_)) => walk_mkdocs(value, sections, pages),
+                    (Some(title), Value::Sequence(_)) => {
+                        let mut sections = sections.to_vec();
+                        sections.push(title.to_string());
+                        walk_mkdocs(value, &sections, pages);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Doc ids of the markdown files in the docs folder, their path without the
+// extension and the number prefixes Docusaurus uses for ordering.
+fn doc_ids(docs_dir: &str, paths: &[String]) -> HashMap<String, String> {
+    let mut ids = HashMap::new();
+    for path in paths {
+        let Some(relative) = path.strip_prefix(docs_dir) else {
+            continue;
+        };
+        let Some(id) = relative
+            .strip_suffix(".md")
+            .or_else(|| relative.strip_suffix(".mdx"))
+        else {
+            continue;
+        };
+        let stripped: Vec<&str> = id
+            .split('/')
+            .map(|segment| {
+                let digits = segment.len()
+                    - segment
+                        .trim_start_matches(|c: char| c.is_ascii_digit())
+                        .len();
+                match segment[digits..].strip_prefix(['-', '_', '.']) {
+                    Some(rest) if digits > 0 && !rest.is_empty() => rest,
+                    _ => segment,
+                }
+            })
+            .collect();
+        ids.insert(stripped.join("/"), path.clone());
+        ids.insert(id.to_string(), path.clone());
+    }
+    ids
+}
+
+// Pages of a sidebar, items are doc ids, `doc` items, categories with
+// `items` or `{ label: [items] }` shorthands.
+fn walk_sidebar(value: &JsValue, sections: &[String], pages: &mut Vec<(String, Vec<String>)>) {
+    match value {
+        JsValue::Str(id) => pages.push((id.clone(), sections.to_vec())),
+        JsValue::Array(items) => {
+            for item in items {
+                walk_sidebar(item, sections, pages);
+            }
+        }
+        JsValue::Object(entries) => {
+            let get = |key: &str| {
+                entries
+                    .iter()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value)
+            };
+            let string = |key: &str| match get(key) {
+                Some(JsValue::Str(value)) => Some(value.clone()),
+                _ => None,
+            };
+            match (string("type").as_deref(), get("items")) {
+                (Some("doc"), _) => {
+                    if let Some(id) = string("id") {
+                        pages.push((id, sections.to_vec()));
+                    }
+                }
+                (Some("category") | None, Some(items)) => {
+                    let mut sections = sections.to_vec();
+                    sections.extend(string("label"));
+                    // A category's own page comes before its items.
+                    if let Some(link) = get("link") {
+                        walk_sidebar(link, &sections, pages);
+                    }
+                    walk_sidebar(items, &sections, pages);
+                }
+                (None, None) => {
+                    for (label, value) in entries {
+                        let mut sections = sections.to_vec();
+                        sections.push(label.clone());
+                        walk_sidebar(value, &sections, pages);
+                    }
+                }
+                // Links, html and autogenerated items.
+                _ => {}
+            }
+        }
+        JsValue::Other => {}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Ident(String),
+    Punct(char),
+}
+
+// Splits JS or TS source into string literals, identifiers and punctuation,
+// skipping comments. Good enough for the object literal of a sidebars file.
+fn tokenize(text: &str) -> Vec<Token> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut at = 0;
+    while at < chars.len() {
+        let c = chars[at];
+        let next = chars.get(at + 1).copied();
+        if c.is_whitespace() {
+            at += 1;
+        } else if c == '/' && next == Some('/') {
+            while at < chars.len() && chars[at] != '\n' {
+                at += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            at += 2;
+            while at < chars.len() && !(chars[at] == '*' && chars.get(at + 1) == Some(&'/')) {
+                at += 1;
+            }
+            at += 2;
+        } else if matches!(c, '\'' | '"' | '`') {
+            let mut value = String::new();
+            at += 1;
+            while at < chars.len() && chars[at] != c {
+                if chars[at] == '\\' {
+                    at += 1;
+                }
+                if let Some(escaped) = chars.get(at) {
+                    value.push(*escaped);
+                }
+                at += 1;
+            }
+            at += 1;
+            tokens.push(Token::Str(value));
+        } else if c.is_alphanumeric() || c == '_' || c == '$' {
+            let start = at;
+            while at < chars.len()
+                && (chars[at].is_alphanumeric() || matches!(chars[at], '_' | '$'))
+            {
+                at += 1;
+            }
+            tokens.push(Token::Ident(chars[start..at].iter().collect()));
+        } else {
+            tokens.push(Token::Punct(c));
+            at += 1;
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, Clone)]
+enum JsValue {
+    Str(String),
+    Array(Vec<JsValue>),
+    Object(Vec<(String, JsValue)>),
+    /// Any other expression, e.g. a `require` call.
+    Other,
+}
+
+struct JsParser<'a> {
+    tokens: &'a [Token],
+    at: usize,
+}
+
+impl JsParser<'_> {
+    fn value(&mut self) -> JsValue {
+        match self.tokens.get(self.at) {
+            Some(Token::Punct('[')) => {
+                self.at += 1;
+                self.array()
+            }
+            Some(Token::Punct('{')) => {
+                self.at += 1;
+                self.object()
+            }
+            Some(Token::Str(value)) => {
+                self.at += 1;
+                JsValue::Str(value.clone())
+            }
+            _ => {
+                self.skip();
+                JsValue::Other
+            }
+        }
+    }
+
+    fn array(&mut self) -> JsValue {
+        let mut items = Vec::new();
+        loop {
+            match self.tokens.get(self.at) {
+                None => break,
+                Some(Token::Punct(']')) => {
+                    self.at += 1;
+                    break;
+                }
+                Some(Token::Punct(',')) => self.at += 1,
+                Some(_) => items.push(self.value()),
+            }
+        }
+        JsValue::Array(items)
+    }
+
+    fn object(&mut self) -> JsValue {
+        let mut entries = Vec::new();
+        loop {
+            match (self.tokens.get(self.at), self.tokens.get(self.at + 1)) {
+                (None, _) => break,
+                (Some(Token::Punct('}')), _) => {
+                    self.at += 1;
+                    break;
+                }
+                (Some(Token::Punct(',')), _) => self.at += 1,
+                (Some(Token::Str(key) | Token::Ident(key)), Some(Token::Punct(':'))) => {
+                    let key = key.clone();
+                    self.at += 2;
+                    let value = self.value();
+                    entries.push((key, value));
+                }
+                // Spreads, shorthand properties and methods.
+                _ => self.skip(),
+            }
+        }
+        JsValue::Object(entries)
+    }
+
+    // Skips an expression up to the `,` or closing bracket ending it, always
+    // moving past at least one token.
+    fn skip(&mut self) {
+        let start = self.at;
+        let mut depth = 0;
+        while let Some(token) = self.tokens.get(self.at) {
+            match token {
+                Token::Punct('[' | '{' | '(') => depth += 1,
+                Token::Punct(']' | '}' | ')' | ',') if depth == 0 => break,
+                Token::Punct(']' | '}' | ')') => depth -= 1,
+                _ => {}
+            }
+            self.at += 1;
+        }
+        if self.at == start {
+            self.at += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(nav: &Nav, path: &str) -> Option<(usize, String)> {
+        nav.get(path).map(|entry| (entry.index, entry.nav_path()))
+    }
+
+    #[test]
+    fn test_mkdocs_nav() {
+        let paths: Vec<String> = [
+            "mkdocs.yml",
+            "docs/index.md",
+            "docs/guides/install.md",
+            "docs/guides/deploy.md",
+            "docs/reference/api.md",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        let text = r#"
+site_name: Acme
+nav:
+  - Home: index.md
+  - Guides:
+      - guides/install.md
+      - Deploying: guides/deploy.md
+      - Advanced:
+          - Missing: guides/missing.md
+  - GitHub: https://github.com/acme/docs
+  - Reference:
+      - API: reference/api.md
+"#;
+        let nav = Nav::parse(Nav::find_config(&paths).unwrap(), text, &paths);
+        assert_eq!(entry(&nav, "docs/index.md"), Some((0, String::new())));
+        assert_eq!(
+            entry(&nav, "docs/guides/install.md"),
+            Some((1, "Guides".to_string()))
+        );
+        assert_eq!(
+            entry(&nav, "docs/guides/deploy.md"),
+            Some((2, "Guides".to_string()))
+        );
+        assert_eq!(
+            entry(&nav, "docs/reference/api.md"),
+            Some((3, "Reference".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_docusaurus_sidebars() {
+        let paths: Vec<String> = [
+            "website/sidebars.js",
+            "website/docs/intro.md",
+            "website/docs/guides/01-install.mdx",
+            "website/docs/guides/index.md",
+            "website/docs/guides/advanced/tuning.md",
+            "website/docs/api.md",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        let text = r#"
+// @ts-check
+/** @type {import('@docusaurus/plugin-content-docs').SidebarsConfig} */
+const sidebars = {
+  docs: [
+    'intro',
+    {
+      type: 'category',
+      label: "Guides",
+      link: { type: 'doc', id: 'guides/index' },
+      items: [
+        'guides/install',
+        { 'Going further': ['guides/advanced/tuning'] },
+        { type: 'link', label: 'Blog', href: 'https://acme.dev/blog' },
+      ],
+    },
+    { type: 'autogenerated', dirName: 'generated' },
+  ],
+  api: [{ type: 'doc', id: 'api', label: `API` }],
+};
+
+module.exports = sidebars;
+"#;
+        let nav = Nav::parse(Nav::find_config(&paths).unwrap(), text, &paths);
+        assert_eq!(
+            entry(&nav, "website/docs/intro.md"),
+            Some((0, String::new()))
+        );
+        assert_eq!(
+            entry(&nav, "website/docs/guides/index.md"),
+            Some((1, "Guides".to_string()))
+        );
+        assert_eq!(
+            entry(&nav, "website/docs/guides/01-install.mdx"),
+            Some((2, "Guides".to_string()))
+        );
+        assert_eq!(
+            entry(&nav, "website/docs/guides/advanced/tuning.md"),
+            Some((3, "Guides > Going further".to_string()))
+        );
+        assert_eq!(entry(&nav, "website/docs/api.md"), Some((4, String::new())));
+    }
+}
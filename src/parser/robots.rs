@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+/// Rules of a robots.txt group applying to the crawler.
+#[derive(Debug, Default, Clone)]
+pub struct Robots {
+    /// `(allow, pattern)` pairs, the longest matching pattern wins.
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Parses robots.txt, using the group naming `agent` if there is one and
+    /// the `*` group otherwise.
+    pub fn parse(text: &str, agent: &str) -> Self {
+        let agent = agent.to_lowercase();
+        let mut groups: Vec<(Vec<String>, Robots)> = Vec::new();
+        // Consecutive user-agent lines share the group that follows them.
+        let mut in_agents = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            match key.as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((Vec::new(), Robots::default()));
+                    }
+                    in_agents = true;
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_lowercase());
+                    }
+                }
+                "allow" | "disallow" => {
+                    in_agents = false;
+                    // An empty disallow allows everything.
+                    if let (Some((_, robots)), false) = (groups.last_mut(), value.is_empty()) {
+                        robots.rules.push((key == "allow", value.to_string()));
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let (Some((_, robots)), Ok(seconds)) =
+                        (groups.last_mut(), value.parse::<f64>())
+                    {
+                        robots.crawl_delay = Some(Duration::from_secs_f64(seconds.max(0.0)));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let named = groups.iter().find(|(agents, _)| {
+            agents
+                .iter()
+                .any(|name| name != "*" && agent.contains(name))
+        });
+        let any = groups
+            .iter()
+            .find(|(agents, _)| agents.iter().any(|name| name == "*"));
+        named
+            .or(any)
+            .map(|(_, robots)| robots.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns whether the path, with its query, may be crawled.
+    pub fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern, path))
+            // On equal length allow wins, as in Google's implementation.
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map_or(true, |(allow, _)| *allow)
+    }
+}
+
+// Matches a robots.txt pattern: a path prefix where `*` matches any run of
+// characters and a trailing `$` anchors the end.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part of an anchored pattern has to match the end.
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let text = "User-agent: *\nDisallow: /private/\nAllow: /private/docs/\nDisallow: /*.pdf$\nCrawl-delay: 2\n\nUser-agent: other\nDisallow: /";
+        let robots = Robots::parse(text, "rtfm");
+        assert!(robots.allows("/docs/intro"));
+        assert!(!robots.allows("/private/keys"));
+        assert!(robots.allows("/private/docs/intro"));
+        assert!(!robots.allows("/files/guide.pdf"));
+        assert!(robots.allows("/files/guide.pdf?page=2"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+
+        let robots = Robots::parse(text, "other-bot");
+        assert!(!robots.allows("/docs/intro"));
+    }
+}
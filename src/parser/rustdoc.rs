@@ -0,0 +1,382 @@
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::Client;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::github::Path;
+use crate::types::Source;
+
+static NULL: Value = Value::Null;
+
+/// Rustdoc JSON of large crates takes a while to download.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Ingests rustdoc's JSON output, as produced by `cargo rustdoc -- -Z
+/// unstable-options --output-format json`, from the source's url. Every
+/// public item becomes a markdown document with its signature and doc
+/// comment, keyed by its path, e.g. `serde::de::Deserialize`.
+#[derive(Clone)]
+pub struct RustdocParser {
+    source: Source,
+    client: Client,
+    docs: Arc<Mutex<HashMap<Path, String>>>,
+}
+
+impl RustdocParser {
+    pub fn new(source: Source) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent("rtfm")
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .context("Failed to build rustdoc client")?;
+        Ok(Self {
+            source,
+            client,
+            docs: Arc::default(),
+        })
+    }
+
+    pub async fn get_paths(&self) -> Result<Vec<Path>> {
+        let krate: Value = self
+            .client
+            .get(&self.source.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Failed to read rustdoc JSON '{}'", self.source.url))?;
+        let items = documents(&krate)?;
+        tracing::info!("Found {} items in '{}'", items.len(), self.source.url);
+        let paths = items.iter().map(|(path, _)| path.clone()).collect();
+        self.docs
+            .lock()
+            .expect("Docs lock is poisoned")
+            .extend(items);
+        Ok(paths)
+    }
+
+    pub fn get_content(&self, path: &Path) -> Result<String> {
+        self.docs
+            .lock()
+            .expect("Docs lock is poisoned")
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow!("unable to get content from '{}', item wasn't found", path))
+    }
+}
+
+/// Documents of the crate's public items keyed by their paths. Methods of
+/// inherent impls and of traits are documented under their type or trait.
+pub fn documents(krate: &Value) -> Result<Vec<(Path, String)>> {
+    let (Some(index), Some(paths)) = (krate["index"].as_object(), krate["paths"].as_object())
+    else {
+        bail!("Not a rustdoc JSON crate, 'index' or 'paths' is missing");
+    };
+    let mut docs = Vec::new();
+    for (id, summary) in paths {
+        // Paths of other crates are only there to resolve links.
+        if summary["crate_id"].as_u64() != Some(0) {
+            continue;
+        }
+        let Some(item) = index.get(id) else {
+            continue;
+        };
+        let path = summary["path"]
+            .as_array()
+            .map(|parts| {
+                parts
+                    .iter()
+                    .filter_map(|part| part.as_str())
+                    .collect::<Vec<_>>()
+                    .join("::")
+            })
+            .unwrap_or_default();
+        if path.is_empty() {
+            continue;
+        }
+        docs.push((path.clone(), item_markdown(&path, item)));
+
+        let (kind, inner) = kind_and_inner(item);
+        let member_ids: Vec<&Value> = match kind {
+            "trait" => list(&inner["items"]).collect(),
+            "struct" | "enum" | "union" => list(&inner["impls"])
+                .filter_map(|impl_id| index.get(&id_key(impl_id)?))
+                .map(kind_and_inner)
+                .filter(|(kind, inner)| *kind == "impl" && inner["trait"].is_null())
+                .flat_map(|(_, inner)| list(&inner["items"]))
+                .collect(),
+            _ => Vec::new(),
+        };
+        for member in member_ids.iter().filter_map(|id| index.get(&id_key(id)?)) {
+            let Some(name) = member["name"].as_str() else {
+                continue;
+            };
+            let public = kind == "trait" || member["visibility"].as_str() == Some("public");
+            if public {
+                let path = format!("{}::{}", path, name);
+                docs.push((path.clone(), item_markdown(&path, member)));
+            }
+        }
+    }
+    docs.sort_by(|a, b| a.0.cmp(&b.0));
+    docs.dedup_by(|a, b| a.0 == b.0);
+    Ok(docs)
+}
+
+fn item_markdown(path: &str, item: &Value) -> String {
+    let signature = signature(item);
+    let docs = item["docs"].as_str().unwrap_or_default().trim();
+    let mut markdown = format!("---\ntitle: {}\n---\n\n# {}\n\n", path, path);
+    if !signature.is_empty() {
+        markdown.push_str(&format!("```rust\n{}\n```\n\n", signature));
+    }
+    markdown.push_str(docs);
+    markdown.push('\n');
+    markdown
+}
+
+// Older formats tag items with `kind`, newer ones by the only key of `inner`.
+fn kind_and_inner(item: &Value) -> (&str, &Value) {
+    if let Some(kind) = item["kind"].as_str() {
+        return (kind, &item["inner"]);
+    }
+    match item["inner"]
+        .as_object()
+        .and_then(|inner| inner.iter().next())
+    {
+        Some((kind, inner)) => (kind.as_str(), inner),
+        None => ("", &NULL),
+    }
+}
+
+// Ids are strings in older formats and numbers in newer ones.
+fn id_key(id: &Value) -> Option<String> {
+    id.as_str()
+        .map(|id| id.to_string())
+        .or_else(|| id.as_u64().map(|id| id.to_string()))
+}
+
+fn list(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+fn signature(item: &Value) -> String {
+    let name = item["name"].as_str().unwrap_or_default();
+    let vis = match item["visibility"].as_str() {
+        Some("public") => "pub ",
+        _ => "",
+    };
+    let (kind, inner) = kind_and_inner(item);
+    match kind {
+        "function" | "method" => {
+            // Renamed from `decl` to `sig` in later formats.
+            let sig = if inner["sig"].is_null() {
+                &inner["decl"]
+            } else {
+                &inner["sig"]
+            };
+            let inputs: Vec<String> = list(&sig["inputs"])
+                .map(|input| render_input(&input[0], &input[1]))
+                .collect();
+            let output = match &sig["output"] {
+                Value::Null => String::new(),
+                output => format!(" -> {}", render_type(output)),
+            };
+            let header = &inner["header"];
+            let flag = |name: &str| {
+                header[name].as_bool() == Some(true)
+                    || header[format!("is_{}", name)].as_bool() == Some(true)
+            };
+            let mut qualifiers = String::new();
+            for name in ["const", "async", "unsafe"] {
+                if flag(name) {
+                    qualifiers.push_str(name);
+                    qualifiers.push(' ');
+                }
+            }
+            format!(
+                "{}{}fn {}({}){}",
+                vis,
+                qualifiers,
+                name,
+                inputs.join(", "),
+                output
+            )
+        }
+        "struct" | "enum" | "union" | "trait" => format!("{}{} {}", vis, kind, name),
+        "typedef" | "type_alias" => {
+            format!("{}type {} = {}", vis, name, render_type(&inner["type"]))
+        }
+        "constant" | "assoc_const" => {
+            format!("{}const {}: {}", vis, name, render_type(&inner["type"]))
+        }
+        "static" => format!("{}static {}: {}", vis, name, render_type(&inner["type"])),
+        "macro" => inner.as_str().unwrap_or_default().to_string(),
+        "module" => format!("{}mod {}", vis, name),
+        _ => String::new(),
+    }
+}
+
+fn render_input(name: &Value, ty: &Value) -> String {
+    let name = name.as_str().unwrap_or("_");
+    if name == "self" {
+        let borrowed = &ty["borrowed_ref"];
+        if ty["generic"].as_str() == Some("Self") {
+            return "self".to_string();
+        }
+        if borrowed["type"]["generic"].as_str() == Some("Self") {
+            return match is_mutable(borrowed) {
+                true => "&mut self".to_string(),
+                false => "&self".to_string(),
+            };
+        }
+    }
+    format!("{}: {}", name, render_type(ty))
+}
+
+fn is_mutable(value: &Value) -> bool {
+    value["mutable"].as_bool() == Some(true) || value["is_mutable"].as_bool() == Some(true)
+}
+
+/// Renders the common shapes of rustdoc's `Type`, anything else shows as `_`.
+fn render_type(ty: &Value) -> String {
+    let Some((kind, value)) = ty.as_object().and_then(|ty| ty.iter().next()) else {
+        return "_".to_string();
+    };
+    match kind.as_str() {
+        "primitive" | "generic" => value.as_str().unwrap_or("_").to_string(),
+        "resolved_path" => render_path(value),
+        "borrowed_ref" => {
+            let lifetime = value["lifetime"]
+                .as_str()
+                .map(|lifetime| format!("{} ", lifetime))
+                .unwrap_or_default();
+            let mutability = if is_mutable(value) { "mut " } else { "" };
+            format!("&{}{}{}", lifetime, mutability, render_type(&value["type"]))
+        }
+        "raw_pointer" => {
+            let mutability = if is_mutable(value) { "mut" } else { "const" };
+            format!("*{} {}", mutability, render_type(&value["type"]))
+        }
+        "slice" => format!("[{}]", render_type(value)),
+        "array" => format!(
+            "[{}; {}]",
+            render_type(&value["type"]),
+            value["len"].as_str().unwrap_or("_")
+        ),
+        "tuple" => {
+            let types: Vec<String> = list(value).map(render_type).collect();
+            format!("({})", types.join(", "))
+        }
+        "impl_trait" => format!("impl {}", render_bounds(value)),
+        "dyn_trait" => format!("dyn {}", render_bounds(&value["traits"])),
+        "qualified_path" => format!(
+            "<{} as {}>::{}",
+            render_type(&value["self_type"]),
+            render_path(&value["trait"]),
+            value["name"].as_str().unwrap_or("_")
+        ),
+        _ => "_".to_string(),
+    }
+}
+
+fn render_path(path: &Value) -> String {
+    // Newer formats name the field `path`.
+    let name = path["name"]
+        .as_str()
+        .or_else(|| path["path"].as_str())
+        .unwrap_or("_");
+    let args: Vec<String> = list(&path["args"]["angle_bracketed"]["args"])
+        .map(|arg| match (&arg["type"], arg["lifetime"].as_str()) {
+            (_, Some(lifetime)) => lifetime.to_string(),
+            (Value::Null, None) => "_".to_string(),
+            (ty, None) => render_type(ty),
+        })
+        .collect();
+    match args.is_empty() {
+        true => name.to_string(),
+        false => format!("{}<{}>", name, args.join(", ")),
+    }
+}
+
+// Trait bounds of `impl Trait` and `dyn Trait`, lifetime bounds are left out.
+fn render_bounds(bounds: &Value) -> String {
+    let names: Vec<String> = list(bounds)
+        .filter_map(|bound| {
+            let path = if bound["trait_bound"].is_null() {
+                &bound["trait"]
+            } else {
+                &bound["trait_bound"]["trait"]
+            };
+            (!path.is_null()).then(|| render_path(path))
+        })
+        .collect();
+    names.join(" + ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_documents() {
+        let krate = json!({
+            "root": 0,
+            "format_version": 26,
+            "paths": {
+                "1": {"crate_id": 0, "path": ["widgets", "Widget"], "kind": "struct"},
+                "5": {"crate_id": 0, "path": ["widgets", "spin"], "kind": "function"},
+                "9": {"crate_id": 1, "path": ["std", "string", "String"], "kind": "struct"}
+            },
+            "index": {
+                "1": {
+                    "name": "Widget", "visibility": "public", "docs": "A spinning widget.",
+                    "inner": {"struct": {"impls": [2]}}
+                },
+                "2": {"name": null, "inner": {"impl": {"trait": null, "items": [3, 4]}}},
+                "3": {
+                    "name": "rename", "visibility": "public", "docs": "Renames the widget.",
+                    "inner": {"function": {
+                        "decl": {
+                            "inputs": [
+                                ["self", {"borrowed_ref": {"lifetime": null, "mutable": true, "type": {"generic": "Self"}}}],
+                                ["name", {"borrowed_ref": {"lifetime": null, "mutable": false, "type": {"primitive": "str"}}}]
+                            ],
+                            "output": {"resolved_path": {"name": "Option", "args": {"angle_bracketed": {"args": [{"type": {"resolved_path": {"name": "String", "args": null}}}]}}}}
+                        },
+                        "header": {"async": false, "const": false, "unsafe": false}
+                    }}
+                },
+                "4": {"name": "secret", "visibility": "crate", "docs": null, "inner": {"function": {"decl": {"inputs": [], "output": null}}}},
+                "5": {
+                    "name": "spin", "visibility": "public", "docs": "Spins all widgets.",
+                    "inner": {"function": {
+                        "decl": {"inputs": [["widgets", {"slice": {"generic": "W"}}]], "output": null},
+                        "header": {"async": true, "const": false, "unsafe": false}
+                    }}
+                }
+            }
+        });
+        let docs = documents(&krate).unwrap();
+        let paths: Vec<&str> = docs.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "widgets::Widget",
+                "widgets::Widget::rename",
+                "widgets::spin"
+            ]
+        );
+        assert_eq!(
+            docs[1].1,
+            "---\ntitle: widgets::Widget::rename\n---\n\n# widgets::Widget::rename\n\n```rust\npub fn rename(&mut self, name: &str) -> Option<String>\n```\n\nRenames the widget.\n"
+        );
+        assert!(docs[2].1.contains("pub async fn spin(widgets: [W])"));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use super::github::{blob_url, is_target_file, Path};
+use super::ignore_file::{IgnoreFile, IGNORE_FILE};
+use super::nav::{Nav, NavEntry};
+use crate::types::Source;
+
+/// Repo files served instead of GitHub's, keyed by `owner/repo`.
+#[derive(Clone, Default, Debug)]
+pub struct StubRepos {
+    repos: Arc<HashMap<String, BTreeMap<Path, String>>>,
+    truncated: Arc<HashSet<String>>,
+}
+
+impl StubRepos {
+    /// Adds a file to the repo, creating the repo if needed.
+    pub fn with_file(mut self, repo: &str, path: &str, content: &str) -> Self {
+        Arc::make_mut(&mut self.repos)
+            .entry(repo.to_string())
+            .or_default()
+            .insert(path.to_string(), content.to_string());
+        self
+    }
+
+    /// Lists the repo's files as GitHub does for a truncated tree.
+    pub fn with_truncated_tree(mut self, repo: &str) -> Self {
+        Arc::make_mut(&mut self.truncated).insert(repo.to_string());
+        self
+    }
+}
+
+/// Serves a source's files from `StubRepos`, the branch is ignored.
+#[derive(Clone)]
+pub struct StubParser {
+    source: Source,
+    files: BTreeMap<Path, String>,
+    nav: Nav,
+    truncated: bool,
+}
+
+impl StubParser {
+    pub fn new(source: Source, repos: &StubRepos) -> Self {
+        let name = format!("{}/{}", source.owner, source.repo);
+        let files: BTreeMap<Path, String> = repos.repos.get(&name).cloned().unwrap_or_default();
+        let paths: Vec<Path> = files.keys().cloned().collect();
+        let nav = match Nav::find_config(&paths) {
+            Some(config) => Nav::parse(config, &files[config], &paths),
+            None => Nav::default(),
+        };
+        let truncated = repos.truncated.contains(&name);
+        Self {
+            source,
+            files,
+            nav,
+            truncated,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn nav_entry(&self, path: &str) -> Option<NavEntry> {
+        self.nav.get(path).cloned()
+    }
+
+    pub fn blob_url(&self, path: &str) -> String {
+        blob_url(
+            &self.source.owner,
+            &self.source.repo,
+            &self.source.branch,
+            path,
+        )
+    }
+
+    pub fn get_paths(&self, filter: bool) -> Vec<Path> {
+        let ignore = match filter {
+            true => self
+                .files
+                .get(IGNORE_FILE)
+                .map(|text| IgnoreFile::parse(text)),
+            false => None,
+        };
+        self.files
+            .keys()
+            .filter(|path| !filter || is_target_file(&self.source, path))
+            .filter(|path| !ignore.as_ref().map_or(false, |ignore| ignore.ignores(path)))
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_content(&self, path: &Path) -> Result<String> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow!("unable to get content from '{}', no such file", path))
+    }
+}
@@ -0,0 +1,128 @@
+/// File in the root of a repo listing its submodules.
+pub const GITMODULES: &str = ".gitmodules";
+
+/// Repo checked out at a path of the parent repo, pinned to a commit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submodule {
+    pub path: String,
+    pub owner: String,
+    pub repo: String,
+    pub sha: String,
+}
+
+impl Submodule {
+    /// The path within the submodule, if the parent repo's path is in it.
+    pub fn strip<'a>(&self, path: &'a str) -> Option<&'a str> {
+        path.strip_prefix(self.path.as_str())?.strip_prefix('/')
+    }
+}
+
+/// Returns `(path, url)` pairs of the submodules listed in `.gitmodules`.
+pub fn parse_gitmodules(text: &str) -> Vec<(String, String)> {
+    let mut modules = Vec::new();
+    let mut current: Option<(Option<String>, Option<String>)> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') || line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if let Some((Some(path), Some(url))) = current.take() {
+                modules.push((path, url));
+            }
+            if line.starts_with("[submodule") {
+                current = Some((None, None));
+            }
+            continue;
+        }
+        let (Some((path, url)), Some((key, value))) = (current.as_mut(), line.split_once('='))
+        else {
+            continue;
+        };
+        match key.trim() {
+            "path" => *path = Some(value.trim().trim_end_matches('/').to_string()),
+            "url" => *url = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if let Some((Some(path), Some(url))) = current {
+        modules.push((path, url));
+    }
+    modules
+}
+
+/// Owner and repo of a submodule url on GitHub. Relative urls like `../docs.git`
+/// point at repos of the parent's owner.
+pub fn github_repo(url: &str, parent_owner: &str) -> Option<(String, String)> {
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    let (owner, repo) = if let Some(repo) = url.strip_prefix("../") {
+        (parent_owner, repo)
+    } else {
+        let rest = url
+            .strip_prefix("https://github.com/")
+            .or_else(|| url.strip_prefix("http://github.com/"))
+            .or_else(|| url.strip_prefix("git@github.com:"))
+            .or_else(|| url.strip_prefix("ssh://git@github.com/"))?;
+        rest.split_once('/')?
+    };
+    match (owner.is_empty(), repo.is_empty() || repo.contains('/')) {
+        (false, false) => Some((owner.to_string(), repo.to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_gitmodules() {
+        let text = r#"
+[submodule "docs"]
+	path = docs/
+	url = https://github.com/acme/docs.git
+[core]
+	path = ignored
+[submodule "guides"]
+	url = ../guides.git
+	path = vendor/guides
+"#;
+        let modules = parse_gitmodules(text);
+        assert_eq!(
+            modules,
+            vec![
+                (
+                    "docs".to_string(),
+                    "https://github.com/acme/docs.git".to_string()
+                ),
+                ("vendor/guides".to_string(), "../guides.git".to_string()),
+            ]
+        );
+        let owner_repo = |owner: &str, repo: &str| Some((owner.to_string(), repo.to_string()));
+        assert_eq!(
+            github_repo(&modules[0].1, "other"),
+            owner_repo("acme", "docs")
+        );
+        assert_eq!(
+            github_repo(&modules[1].1, "acme"),
+            owner_repo("acme", "guides")
+        );
+        assert_eq!(
+            github_repo("git@github.com:acme/api.git", "other"),
+            owner_repo("acme", "api")
+        );
+        assert_eq!(
+            github_repo("https://gitlab.com/acme/docs.git", "acme"),
+            None
+        );
+
+        let submodule = Submodule {
+            path: "docs".to_string(),
+            owner: "acme".to_string(),
+            repo: "docs".to_string(),
+            sha: "abc".to_string(),
+        };
+        assert_eq!(submodule.strip("docs/intro.md"), Some("intro.md"));
+        assert_eq!(submodule.strip("docs-old/intro.md"), None);
+    }
+}
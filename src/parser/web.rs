@@ -0,0 +1,433 @@
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use reqwest::{header, Client, Url};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::time::Instant;
+
+use super::{github::Path, inventory::Inventory, robots::Robots};
+use crate::{
+    types::{Source, MAX_CRAWL_DEPTH, MAX_CRAWL_PAGES},
+    DocumentFormat,
+};
+
+/// Name the crawler identifies with, also used to pick its robots.txt group.
+const USER_AGENT: &str = "rtfm";
+
+/// Minimum delay between requests to the same host, robots.txt may ask for more.
+const POLITENESS_DELAY: Duration = Duration::from_secs(1);
+
+/// Time given to a page to respond.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Query parameter prefixes that only track visitors, dropped while canonicalizing.
+const TRACKING_PARAMS: [&str; 3] = ["utm_", "fbclid", "gclid"];
+
+/// Crawls a documentation site from the source's url. Pages are followed on
+/// the same host only, robots.txt, `noindex`/`nofollow` robots metas and
+/// canonical urls are honored, and requests to a host are spaced out. The
+/// source's `CrawlOptions` bound the crawl. Python objects of a Sphinx
+/// `objects.inv` inventory become documents of their own.
+#[derive(Clone)]
+pub struct WebParser {
+    source: Source,
+    client: Client,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    /// Crawled pages keyed by their canonical url, html except for the
+    /// markdown documents of inventory entries.
+    pages: Arc<Mutex<HashMap<Path, (DocumentFormat, String)>>>,
+    /// Set if the crawl stopped at the page limit or failed to fetch a page,
+    /// whose urls then miss pages of the site.
+    partial: Arc<AtomicBool>,
+}
+
+// What the crawler needs to know about a fetched page.
+struct Page {
+    links: Vec<Url>,
+    canonical: Option<Url>,
+    noindex: bool,
+    nofollow: bool,
+}
+
+impl WebParser {
+    pub fn new(source: Source) -> Result<Self> {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .context("Failed to build crawler client")?;
+        let include = compile_patterns(&source.crawl.include)?;
+        let exclude = compile_patterns(&source.crawl.exclude)?;
+        Ok(Self {
+            source,
+            client,
+            include,
+            exclude,
+            pages: Arc::default(),
+            partial: Arc::default(),
+        })
+    }
+
+    /// Whether the last crawl missed pages of the site.
+    pub fn is_partial(&self) -> bool {
+        self.partial.load(Ordering::Relaxed)
+    }
+
+    /// Crawls the site and returns canonical urls of the pages to index. Pages
+    /// are kept, so `get_content` doesn't fetch them again.
+    pub async fn get_paths(&self) -> Result<Vec<Path>> {
+        let opts = &self.source.crawl;
+        let start = Url::parse(&self.source.url)
+            .map(|url| canonicalize(url, opts.strip_query))
+            .with_context(|| format!("Invalid source url '{}'", self.source.url))?;
+        let host = start.host_str().unwrap_or_default().to_string();
+        let robots = self.robots(&start).await;
+        let delay = robots
+            .crawl_delay
+            .map_or(POLITENESS_DELAY, |delay| delay.max(POLITENESS_DELAY));
+        let mut last_request: HashMap<String, Instant> = HashMap::new();
+
+        // The start url is crawled even if the patterns don't match it.
+        let mut queue = VecDeque::from([(start.clone(), 0)]);
+        let mut seen = HashSet::from([start.to_string()]);
+        let mut hashes = HashSet::new();
+        let mut paths = Vec::new();
+        let mut fetched = 0;
+        // Sources stored before the limits were enforced may ask for more.
+        let max_pages = opts.max_pages.min(MAX_CRAWL_PAGES);
+        let max_depth = opts.max_depth.min(MAX_CRAWL_DEPTH);
+        while let Some((url, depth)) = queue.pop_front() {
+            if fetched >= max_pages {
+                tracing::warn!("Crawl of '{}' stopped at {} pages", start, max_pages);
+                self.partial.store(true, Ordering::Relaxed);
+                break;
+            }
+            if !robots.allows(&path_with_query(&url)) {
+                tracing::info!("Skipping '{}', disallowed by robots.txt", url);
+                continue;
+            }
+            wait_politely(&mut last_request, &url, delay).await;
+            fetched += 1;
+
+            let (url, html, header_noindex) = match self.fetch(&url).await {
+                Ok(Some(page)) => page,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::warn!("Failed to fetch '{}': {:?}", url, err);
+                    self.partial.store(true, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            let page = parse_page(&url, &html);
+            if !page.nofollow && depth < max_depth {
+                for link in page.links {
+                    let link = canonicalize(link, opts.strip_query);
+                    if link.host_str() == Some(host.as_str())
+                        && self.matches(&link)
+                        && seen.insert(link.to_string())
+                    {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+            if header_noindex || page.noindex {
+                tracing::info!("Skipping '{}', marked noindex", url);
+                continue;
+            }
+
+            // Pages canonical elsewhere are copies, the original is indexed instead.
+            let canonical = canonicalize(page.canonical.unwrap_or(url), opts.strip_query);
+            if canonical.host_str() != Some(host.as_str()) {
+                tracing::info!("Skipping '{}', canonical on another host", canonical);
+                continue;
+            }
+            // Mirrors of a page under other urls, e.g. versioned aliases, are
+            // only indexed once.
+            if opts.dedup && !hashes.insert(crc32fast::hash(normalize_whitespace(&html).as_bytes()))
+            {
+                tracing::info!("Skipping '{}', same content as a crawled page", canonical);
+                continue;
+            }
+            let path = canonical.to_string();
+            let mut pages = self.pages.lock().expect("Pages lock is poisoned");
+            if pages.contains_key(&path) {
+                continue;
+            }
+            pages.insert(path.clone(), (DocumentFormat::Html, html));
+            paths.push(path);
+        }
+        tracing::info!("Crawled {} pages of '{}'", paths.len(), start);
+
+        // Sphinx sites publish an inventory of their API next to the root page.
+        if let Ok(url) = start.join("objects.inv") {
+            if robots.allows(url.path()) {
+                wait_politely(&mut last_request, &url, delay).await;
+                match self.inventory_pages(&url).await {
+                    Ok(inventory) => {
+                        tracing::info!("Found {} objects in '{}'", inventory.len(), url);
+                        let mut pages = self.pages.lock().expect("Pages lock is poisoned");
+                        for (path, markdown) in inventory {
+                            if !pages.contains_key(&path) {
+                                pages.insert(path.clone(), (DocumentFormat::Markdown, markdown));
+                                paths.push(path);
+                            }
+                        }
+                    }
+                    Err(err) => tracing::warn!("Failed to read inventory '{}': {:?}", url, err),
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    pub fn get_content(&self, path: &Path) -> Result<String> {
+        self.pages
+            .lock()
+            .expect("Pages lock is poisoned")
+            .get(path)
+            .map(|(_, content)| content.clone())
+            .ok_or_else(|| anyhow!("unable to get content from '{}', page wasn't crawled", path))
+    }
+
+    /// Crawled pages are html, documents of inventory entries are markdown.
+    pub fn get_format(&self, path: &Path) -> DocumentFormat {
+        self.pages
+            .lock()
+            .expect("Pages lock is poisoned")
+            .get(path)
+            .map_or(DocumentFormat::Html, |(format, _)| *format)
+    }
+
+    // Documents of the Python objects in the inventory keyed by their url,
+    // none if the site has no inventory.
+    async fn inventory_pages(&self, url: &Url) -> Result<Vec<(Path, String)>> {
+        let resp = self.client.get(url.clone()).send().await?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+        let data = resp.error_for_status()?.bytes().await?;
+        let inventory = Inventory::parse(&data)?;
+        let pages = inventory
+            .python_objects()
+            .filter_map(|entry| {
+                let page = url.join(&entry.uri).ok()?;
+                let markdown = entry.to_markdown(&inventory, &page);
+                Some((page.to_string(), markdown))
+            })
+            .collect();
+        Ok(pages)
+    }
+
+    // Excludes win over includes, no includes means everything is included.
+    fn matches(&self, url: &Url) -> bool {
+        let url = url.as_str();
+        if self.exclude.iter().any(|re| re.is_match(url)) {
+            return false;
+        }
+        self.include.is_empty() || self.include.iter().any(|re| re.is_match(url))
+    }
+
+    // Missing or unreadable robots.txt allows everything.
+    async fn robots(&self, start: &Url) -> Robots {
+        let Ok(url) = start.join("/robots.txt") else {
+            return Robots::default();
+        };
+        let resp = match self.client.get(url).send().await {
+            Ok(resp) if resp.status().is_success() => resp,
+            _ => return Robots::default(),
+        };
+        match resp.text().await {
+            Ok(text) => Robots::parse(&text, USER_AGENT),
+            Err(_) => Robots::default(),
+        }
+    }
+
+    // Returns the final url after redirects, the html and whether an
+    // `X-Robots-Tag` header asks not to index it. Non-html responses are skipped.
+    async fn fetch(&self, url: &Url) -> Result<Option<(Url, String, bool)>> {
+        let resp = self
+            .client
+            .get(url.clone())
+            .send()
+            .await?
+            .error_for_status()?;
+        let header_value = |name: header::HeaderName| {
+            resp.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_lowercase()
+        };
+        if !header_value(header::CONTENT_TYPE).contains("text/html") {
+            return Ok(None);
+        }
+        let noindex =
+            header_value(header::HeaderName::from_static("x-robots-tag")).contains("noindex");
+        let url = resp.url().clone();
+        Ok(Some((url, resp.text().await?, noindex)))
+    }
+}
+
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).with_context(|| format!("Invalid crawl pattern '{}'", pattern))
+        })
+        .collect()
+}
+
+/// Canonicalizes a url so that links to the same page compare equal: the
+/// fragment, tracking parameters and a trailing `index.html` are dropped and
+/// the remaining query parameters are sorted.
+pub fn canonicalize(mut url: Url, strip_query: bool) -> Url {
+    url.set_fragment(None);
+    if let Some(path) = url
+        .path()
+        .strip_suffix("/index.html")
+        .or_else(|| url.path().strip_suffix("/index.htm"))
+        .map(|path| format!("{}/", path))
+    {
+        url.set_path(&path);
+    }
+    let mut params: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !TRACKING_PARAMS.iter().any(|param| key.starts_with(param)))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if strip_query || params.is_empty() {
+        url.set_query(None);
+    } else {
+        params.sort();
+        url.query_pairs_mut().clear().extend_pairs(params);
+    }
+    url
+}
+
+fn normalize_whitespace(html: &str) -> String {
+    html.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn path_with_query(url: &Url) -> String {
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+async fn wait_politely(last_request: &mut HashMap<String, Instant>, url: &Url, delay: Duration) {
+    let host = url.host_str().unwrap_or_default().to_string();
+    if let Some(last) = last_request.get(&host) {
+        tokio::time::sleep_until(*last + delay).await;
+    }
+    last_request.insert(host, Instant::now());
+}
+
+fn parse_page(url: &Url, html: &str) -> Page {
+    let tag_re = Regex::new(r"(?is)<(a|link|meta)\s[^>]*>").unwrap();
+    let mut page = Page {
+        links: Vec::new(),
+        canonical: None,
+        noindex: false,
+        nofollow: false,
+    };
+    for caps in tag_re.captures_iter(html) {
+        let (name, tag) = (caps[1].to_lowercase(), &caps[0]);
+        match name.as_str() {
+            "a" => {
+                if let Some(link) = attr(tag, "href").and_then(|href| resolve(url, &href)) {
+                    page.links.push(link);
+                }
+            }
+            "link"
+                if attr(tag, "rel").map_or(false, |rel| rel.eq_ignore_ascii_case("canonical")) =>
+            {
+                page.canonical = attr(tag, "href").and_then(|href| resolve(url, &href));
+            }
+            "meta" => {
+                let name = attr(tag, "name").unwrap_or_default().to_lowercase();
+                if name == "robots" || name == USER_AGENT {
+                    let content = attr(tag, "content").unwrap_or_default().to_lowercase();
+                    page.noindex |= content.contains("noindex") || content.contains("none");
+                    page.nofollow |= content.contains("nofollow") || content.contains("none");
+                }
+            }
+            _ => {}
+        }
+    }
+    page
+}
+
+fn attr(tag: &str, name: &str) -> Option<String> {
+    let re = Regex::new(&format!(r#"(?i)\s{}\s*=\s*["']([^"']*)["']"#, name)).unwrap();
+    re.captures(tag).map(|caps| caps[1].trim().to_string())
+}
+
+// Resolves a link against the page, fragments are dropped and only http(s) is kept.
+fn resolve(base: &Url, href: &str) -> Option<Url> {
+    let mut url = base.join(href).ok()?;
+    url.set_fragment(None);
+    matches!(url.scheme(), "http" | "https").then_some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page() {
+        let url = Url::parse("https://docs.example.com/guide/intro").unwrap();
+        let html = r#"<html><head>
+            <link rel="canonical" href="/guide/">
+            <meta name="robots" content="noindex, follow">
+            </head><body>
+            <a href="setup#install">Setup</a>
+            <a class="ext" href="https://other.com/">Other</a>
+            <a href="mailto:docs@example.com">Mail</a>
+            </body></html>"#;
+        let page = parse_page(&url, html);
+        assert_eq!(
+            page.canonical.map(|url| url.to_string()),
+            Some("https://docs.example.com/guide/".to_string())
+        );
+        assert!(page.noindex);
+        assert!(!page.nofollow);
+        let links: Vec<String> = page.links.iter().map(|url| url.to_string()).collect();
+        assert_eq!(
+            links,
+            vec!["https://docs.example.com/guide/setup", "https://other.com/"]
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let cases = [
+            (
+                "https://Docs.Example.com:443/guide/index.html#intro",
+                false,
+                "https://docs.example.com/guide/",
+            ),
+            (
+                "https://docs.example.com/search?q=rust&utm_source=mail&lang=en",
+                false,
+                "https://docs.example.com/search?lang=en&q=rust",
+            ),
+            (
+                "https://docs.example.com/guide?tab=2",
+                true,
+                "https://docs.example.com/guide",
+            ),
+        ];
+        for (url, strip_query, expected) in cases {
+            let url = canonicalize(Url::parse(url).unwrap(), strip_query);
+            assert_eq!(url.as_str(), expected);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::{bail, Context, Result};
+
+/// Larger PDFs are skipped, they are rarely documentation and slow to parse.
+pub const MAX_PDF_BYTES: usize = 20 * 1024 * 1024;
+
+/// Pages with less text on average are assumed to be scanned images.
+const MIN_CHARS_PER_PAGE: usize = 50;
+
+/// Separates pages in the extracted text, `encoder::split` chunks at it.
+pub const PAGE_BREAK: char = '\x0c';
+
+/// Whether the path is a PDF, which is fetched as bytes and converted to text.
+pub fn is_pdf(path: &str) -> bool {
+    path.to_lowercase().ends_with(".pdf")
+}
+
+/// Extracts the text of every page, pages are separated by `PAGE_BREAK`.
+/// Scanned PDFs have no text layer worth indexing, they give an empty string.
+pub fn extract_text(data: &[u8]) -> Result<String> {
+    if data.len() > MAX_PDF_BYTES {
+        bail!(
+            "PDF is {} bytes, larger than the limit of {}",
+            data.len(),
+            MAX_PDF_BYTES
+        );
+    }
+    let doc = lopdf::Document::load_mem(data).context("Failed to load PDF")?;
+    let pages: Vec<String> = doc
+        .get_pages()
+        .keys()
+        .map(|number| {
+            // A page without text, e.g. a full page figure, shouldn't fail the document.
+            doc.extract_text(&[*number])
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .collect();
+    if looks_scanned(&pages) {
+        tracing::info!("Skipping text of a {} page PDF, looks scanned", pages.len());
+        return Ok(String::new());
+    }
+    Ok(pages.join(&PAGE_BREAK.to_string()))
+}
+
+fn looks_scanned(pages: &[String]) -> bool {
+    let chars: usize = pages.iter().map(|page| page.chars().count()).sum();
+    chars < pages.len().max(1) * MIN_CHARS_PER_PAGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_scanned() {
+        let text = "Widgets spin at up to three thousand rotations per minute.".to_string();
+        assert!(!looks_scanned(&[text.clone(), text.clone()]));
+        assert!(looks_scanned(&[text, String::new(), String::new()]));
+        assert!(looks_scanned(&[]));
+        assert!(is_pdf("docs/Design.PDF"));
+    }
+}
@@ -0,0 +1,1567 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::stream::StreamExt;
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    discover::{self, Package},
+    encoder, github_client, i18n, index, links, mail, ocr,
+    parser::{
+        BucketParser, GitHubParser, MboxParser, NavEntry, Parser, Path, RustdocParser, StubParser,
+        WebParser, TREE_ETAG,
+    },
+    types::{
+        BucketOptions, Chunk, Collection, CrawlOptions, Document, GitHubOptions, Heading, Link,
+        ParseSummary, RepoMetadata, Source, SourceKind, Translations,
+    },
+    AppState, Db, DocumentFormat, Embeddings, GitHubAuth, ParseBudget,
+};
+
+/// Number of files fetched concurrently while parsing.
+const FETCH_CONCURRENCY: usize = 20;
+
+/// Flags controlling the parse stage, on by default unless noted.
+#[derive(Deserialize, Debug, Clone, Copy, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ParseOptions {
+    /// Applies the source's extension and directory filters to repo paths.
+    #[serde(default = "enabled")]
+    pub filter: bool,
+    /// Counts tokens of every document.
+    #[serde(default = "enabled")]
+    pub tokenize: bool,
+    /// Checks external links of the source, so they show up in the broken link
+    /// report. Off by default, every linked site gets a request.
+    #[serde(default)]
+    pub check_links: bool,
+    /// Fetches at most this many files. The configured budget applies if
+    /// unset and caps the limits of requests.
+    pub max_files: Option<usize>,
+    /// Stops once fetched contents add up to this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Stops fetching after this many seconds. The deadline is checked
+    /// between fetches, fetches in flight finish.
+    pub max_duration_secs: Option<u64>,
+    /// Only stores files whose checksum differs from their document's, and
+    /// deletes documents of removed files.
+    #[serde(default)]
+    pub incremental: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            filter: true,
+            tokenize: true,
+            check_links: false,
+            max_files: None,
+            max_bytes: None,
+            max_duration_secs: None,
+            incremental: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    // The request's limits, the configured ones where it has none. Requests
+    // can only lower them.
+    fn budget(&self, cfg: &ParseBudget) -> ParseBudget {
+        ParseBudget {
+            max_files: self
+                .max_files
+                .map_or(cfg.max_files, |max| max.min(cfg.max_files)),
+            max_bytes: self
+                .max_bytes
+                .map_or(cfg.max_bytes, |max| max.min(cfg.max_bytes)),
+            max_duration: self
+                .max_duration_secs
+                .map(Duration::from_secs)
+                .map_or(cfg.max_duration, |max| max.min(cfg.max_duration)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseStatus {
+    Complete,
+    /// A limit of the budget stopped the parse before all files were fetched,
+    /// the fetched ones are stored.
+    Partial,
+}
+
+/// Outcome of parsing a source.
+#[derive(Serialize, Debug, Clone)]
+pub struct ParseReport {
+    pub status: ParseStatus,
+    pub documents: usize,
+    /// Files skipped as their content didn't change, incremental parses only.
+    pub unchanged: usize,
+    /// Documents deleted as their files were removed from a complete listing.
+    pub removed: usize,
+    /// The limit that stopped a partial parse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_by: Option<String>,
+    pub summary: ParseSummary,
+}
+
+/// Outcome of encoding a source.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct EncodeReport {
+    pub documents: usize,
+    pub chunks: usize,
+}
+
+/// Outcome of syncing a source, the reports of both stages.
+#[derive(Serialize, Debug, Clone)]
+pub struct SyncReport {
+    pub parse: ParseReport,
+    pub encode: EncodeReport,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Flags controlling the encode stage.
+#[derive(Deserialize, Debug, Clone, Copy, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EncodeOptions {
+    /// Generates a short LLM summary per document, stored on the document and
+    /// added to the context of its chunks.
+    #[serde(default)]
+    pub summarize: bool,
+    /// Drops mermaid and PlantUML diagrams from chunks, instead of replacing
+    /// them with their node and edge labels.
+    #[serde(default)]
+    pub exclude_diagrams: bool,
+    /// Only encodes documents without chunks, the ones an incremental parse
+    /// stored.
+    #[serde(default)]
+    pub incremental: bool,
+}
+
+/// Stops a parse or encode once set, the loops check it before each file or
+/// document. Documents and chunks stored until then are kept.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> Result<()> {
+        match self.is_cancelled() {
+            true => Err(anyhow::anyhow!("Cancelled")),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Cancellations of the jobs in flight, by job id.
+#[derive(Clone, Default)]
+pub struct Cancellations {
+    jobs: Arc<Mutex<HashMap<i64, Cancellation>>>,
+}
+
+impl Cancellations {
+    /// Returns the job's cancellation, registered until it's removed.
+    pub fn register(&self, job_id: i64) -> Cancellation {
+        let cancel = Cancellation::default();
+        self.jobs
+            .lock()
+            .expect("Cancellations lock is poisoned")
+            .insert(job_id, cancel.clone());
+        cancel
+    }
+
+    /// Cancels the job, false if it isn't in flight.
+    pub fn cancel(&self, job_id: i64) -> bool {
+        let jobs = self.jobs.lock().expect("Cancellations lock is poisoned");
+        match jobs.get(&job_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&self, job_id: i64) {
+        self.jobs
+            .lock()
+            .expect("Cancellations lock is poisoned")
+            .remove(&job_id);
+    }
+}
+
+/// Last chunk of each collection checked against a model, by collection id,
+/// model name and version, so encodes only check the chunks stored since.
+#[derive(Clone, Default)]
+pub struct ModelChecks(Arc<Mutex<HashMap<(i64, String, String), i64>>>);
+
+impl ModelChecks {
+    /// Forgets the collection's checks, once its chunks changed other than by
+    /// being stored, e.g. re-embedded or merged in from another collection.
+    pub fn forget(&self, collection_id: i64) {
+        self.0
+            .lock()
+            .expect("Model checks lock is poisoned")
+            .retain(|(id, _, _), _| *id != collection_id);
+    }
+}
+
+/// Repo of the upload source holding a collection's ad-hoc documents.
+const NOTES_REPO: &str = "notes";
+
+/// Documents are cut to this many characters before being summarized.
+const SUMMARY_INPUT_CHARS: usize = 12_000;
+
+const SUMMARY_PROMPT: &str = "Summarize the documentation page in 2-3 sentences. \
+Mention the key terms, APIs and options it covers.";
+
+/// Fetches the source's files from GitHub and stores them as documents,
+/// within the fetch budget, until it's cancelled.
+pub async fn parse_source(
+    state: &AppState,
+    source: Source,
+    opts: ParseOptions,
+    cancel: &Cancellation,
+) -> Result<ParseReport> {
+    let source_id = source.id;
+    let collection_id = source.collection_id;
+    tracing::info!(
+        ?opts,
+        "Parsing source #{} from collection #{}",
+        source_id,
+        collection_id
+    );
+
+    let skip_front_matter = source.skip_front_matter.clone();
+    let translation_mode = source.translations;
+    let parser = match (&state.stub_repos, source.kind) {
+        (Some(repos), _) => Parser::Stub(StubParser::new(source, repos)),
+        (None, _) if state.cfg.offline => {
+            anyhow::bail!("Parsing needs the network, which is disabled in offline mode")
+        }
+        (None, SourceKind::Web) => Parser::Web(WebParser::new(source)?),
+        (None, SourceKind::Rustdoc) => Parser::Rustdoc(RustdocParser::new(source)?),
+        (None, SourceKind::Mbox) => Parser::Mbox(MboxParser::new(source)?),
+        (None, SourceKind::Bucket) => Parser::Bucket(BucketParser::new(source)?),
+        (None, SourceKind::Upload) => {
+            anyhow::bail!("Upload sources are parsed from their uploaded archives")
+        }
+        (None, SourceKind::GitHub) => {
+            let github = source_github_client(state, &source).await?;
+            let known = state
+                .db
+                .query_object_etags(source_id)
+                .await
+                .context("Failed to query file etags")?;
+            Parser::GitHub(
+                GitHubParser::new(source, github, state.breakers.github.clone())?.with_etags(known),
+            )
+        }
+    };
+    let paths = parser
+        .get_paths(opts.filter)
+        .await
+        .context("Failed to get repo paths")?;
+
+    // Translated copies of docs are skipped, or indexed with their language.
+    let translations = i18n::translations(&paths);
+    let paths: Vec<Path> = match translation_mode {
+        Translations::Skip => {
+            if !translations.is_empty() {
+                tracing::info!(
+                    "Skipping {} translated files of source #{}",
+                    translations.len(),
+                    source_id
+                );
+            }
+            paths
+                .into_iter()
+                .filter(|path| !translations.contains_key(path))
+                .collect()
+        }
+        Translations::Index => paths,
+    };
+
+    // Incremental parses skip files matching their document's checksum, bucket
+    // objects whose ETag didn't change and GitHub files whose requests come
+    // back 304. Documents of files known by either that aren't listed anymore
+    // are deleted. Empty listings, e.g. of an unchanged GitHub tree, and
+    // partial ones, of a truncated tree or a crawl that stopped early, leave
+    // the documents as they are.
+    let checksums = match opts.incremental {
+        true => state
+            .db
+            .query_document_checksums(source_id)
+            .await
+            .context("Failed to query document checksums")?,
+        false => HashMap::new(),
+    };
+    let tree_changed =
+        matches!(&parser, Parser::GitHub(github) if github.etag(TREE_ETAG).is_some());
+    let etags = match matches!(&parser, Parser::Bucket(_)) || tree_changed {
+        true => state
+            .db
+            .query_object_etags(source_id)
+            .await
+            .context("Failed to query object etags")?,
+        false => HashMap::new(),
+    };
+    let listed = match &parser {
+        Parser::Bucket(_) => true,
+        _ => tree_changed || !paths.is_empty(),
+    };
+    let mut removed = 0;
+    if listed && !parser.is_partial() {
+        let known = checksums.keys().chain(etags.keys());
+        for path in removed_paths(known, &paths) {
+            tracing::info!("Deleting '{}', removed from the source", path);
+            state
+                .db
+                .delete_document_by_path(source_id, path)
+                .await
+                .context("Failed to delete document")?;
+            if etags.contains_key(path) {
+                state
+                    .db
+                    .delete_object_etag(source_id, path)
+                    .await
+                    .context("Failed to delete object etag")?;
+            }
+            removed += 1;
+        }
+    }
+
+    let paths = match &parser {
+        Parser::Bucket(bucket) => {
+            let changed: Vec<Path> = paths
+                .iter()
+                .filter(|path| bucket.etag(path).as_ref() != etags.get(*path))
+                .cloned()
+                .collect();
+            tracing::info!("{} of {} objects changed", changed.len(), paths.len());
+            changed
+        }
+        _ => paths,
+    };
+
+    let budget = opts.budget(&state.cfg.parse_budget);
+    let stopped_by: Mutex<Option<String>> = Mutex::new(None);
+    let mut paths = paths;
+    if paths.len() > budget.max_files {
+        tracing::warn!(
+            "Source #{} has {} files, only {} are fetched",
+            source_id,
+            paths.len(),
+            budget.max_files
+        );
+        paths.truncate(budget.max_files);
+        *stopped_by.lock().expect("Budget lock is poisoned") =
+            Some(format!("max_files of {}", budget.max_files));
+    }
+    let deadline = Instant::now() + budget.max_duration;
+    let fetched_bytes = AtomicU64::new(0);
+    let summary = Mutex::new(ParseSummary::default());
+    let unchanged = AtomicUsize::new(0);
+
+    let results = futures::stream::iter(paths)
+        .map(|path| {
+            let parser = &parser;
+            let db = &state.db;
+            let tokenizer = &state.tokenizer;
+            let skip_front_matter = &skip_front_matter;
+            let translations = &translations;
+            let stopped_by = &stopped_by;
+            let fetched_bytes = &fetched_bytes;
+            let checksums = &checksums;
+            let unchanged = &unchanged;
+            let summary = &summary;
+            async move {
+                if cancel.is_cancelled() {
+                    return Ok(false);
+                }
+                // Fetches in flight finish, the ones after a limit is hit are skipped.
+                let exceeded = if Instant::now() >= deadline {
+                    Some(format!(
+                        "max_duration of {}s",
+                        budget.max_duration.as_secs()
+                    ))
+                } else if fetched_bytes.load(Ordering::Relaxed) >= budget.max_bytes {
+                    Some(format!("max_bytes of {}", budget.max_bytes))
+                } else {
+                    None
+                };
+                if let Some(limit) = exceeded {
+                    stopped_by
+                        .lock()
+                        .expect("Budget lock is poisoned")
+                        .get_or_insert(limit);
+                    return Ok(false);
+                }
+                tracing::info!("Gettings path '{}'", &path);
+                let Some(data) = parser
+                    .get_content(&path)
+                    .await
+                    .with_context(|| format!("Failed to get github path content '{}'", path))?
+                else {
+                    tracing::debug!("Skipping '{}', unchanged", path);
+                    refresh_unchanged(db, parser, source_id, &path).await?;
+                    return Ok(false);
+                };
+                fetched_bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+                let checksum = crc32fast::hash(data.as_bytes());
+                let etag = parser.etag(&path);
+                match checksums.get(&path) {
+                    Some(known) if *known == checksum => {
+                        tracing::debug!("Skipping '{}', checksum is unchanged", path);
+                        unchanged.fetch_add(1, Ordering::Relaxed);
+                        refresh_unchanged(db, parser, source_id, &path).await?;
+                        store_etag(db, source_id, &path, etag).await?;
+                        return Ok(false);
+                    }
+                    Some(_) => db
+                        .delete_document_by_path(source_id, &path)
+                        .await
+                        .context("Failed to delete document")?,
+                    None => {}
+                }
+                // A changed object replaces its document, also when it's skipped now.
+                if etag.is_some() || matches!(parser, Parser::Bucket(_)) {
+                    db.delete_document_by_path(source_id, &path)
+                        .await
+                        .context("Failed to delete document")?;
+                }
+                // E.g. scanned PDFs, which have no text layer.
+                if data.trim().is_empty() {
+                    tracing::info!("Skipping '{}', no text", path);
+                    summary
+                        .lock()
+                        .expect("Summary lock is poisoned")
+                        .add_empty(&path, data.len() as u64);
+                    store_etag(db, source_id, &path, etag).await?;
+                    return Ok(false);
+                }
+                if let Some(rule) = encoder::excluded_by(&data, skip_front_matter) {
+                    tracing::info!("Skipping '{}', front matter matches '{}'", path, rule);
+                    store_etag(db, source_id, &path, etag).await?;
+                    return Ok(false);
+                }
+
+                let tokens_len = if opts.tokenize {
+                    tokenizer.count(&data)
+                } else {
+                    0
+                };
+                summary.lock().expect("Summary lock is poisoned").add(
+                    &path,
+                    data.len() as u64,
+                    tokens_len,
+                );
+                let alternate_paths = match parser {
+                    Parser::GitHub(github) => github.alternate_paths(&path),
+                    _ => Vec::new(),
+                };
+                let nav = parser.nav_entry(&path);
+                let url = parser.url(&path);
+                let translation = translations.get(&path);
+                let document = Document {
+                    id: 0,
+                    source_id,
+                    collection_id,
+                    format: parser.get_format(&path, &data),
+                    path,
+                    checksum,
+                    tokens_len,
+                    data,
+                    summary: String::new(),
+                    alternate_paths,
+                    nav_path: nav.as_ref().map(NavEntry::nav_path).unwrap_or_default(),
+                    nav_index: nav.map(|nav| nav.index),
+                    language: translation
+                        .map(|translation| translation.language.clone())
+                        .unwrap_or_default(),
+                    canonical_path: translation
+                        .map(|translation| translation.canonical_path.clone())
+                        .unwrap_or_default(),
+                    url,
+                    created_at: Utc::now(),
+                    updated_at: Utc::now(),
+                };
+
+                store_object(db, &document, etag).await?;
+                Ok(true)
+            }
+        })
+        .buffer_unordered(FETCH_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut inserted = 0;
+    let mut failed = 0;
+    for result in results {
+        match result {
+            Ok(true) => inserted += 1,
+            Ok(false) => {}
+            Err(err) => {
+                failed += 1;
+                tracing::error!("{:?}", err)
+            }
+        }
+    }
+    cancel.check().with_context(|| {
+        format!(
+            "Parse of source #{} stopped after {} documents",
+            source_id, inserted
+        )
+    })?;
+    let stopped_by = stopped_by.into_inner().expect("Budget lock is poisoned");
+    if let Some(limit) = &stopped_by {
+        tracing::warn!("Parse of source #{} stopped early by {}", source_id, limit);
+    }
+    tracing::info!("Parsed source #{}, {} documents", source_id, inserted);
+    let mut summary = summary.into_inner().expect("Summary lock is poisoned");
+    summary.empty_files.sort();
+    state
+        .db
+        .update_source_parsed_at(source_id, Utc::now())
+        .await
+        .context("Failed to store parse time")?;
+
+    // The tree's ETag is only kept once all of its files made it, otherwise
+    // the next parse would skip the failed or unfetched ones as unchanged.
+    if let Parser::GitHub(github) = &parser {
+        if let (0, None, Some(etag)) = (failed, &stopped_by, github.etag(TREE_ETAG)) {
+            state
+                .db
+                .upsert_object_etag(source_id, TREE_ETAG, &etag)
+                .await
+                .context("Failed to store tree etag")?;
+        }
+    }
+
+    if opts.check_links && !state.cfg.offline {
+        match links::check_external_links(&state.db, source_id).await {
+            Ok(checked) => tracing::info!("Checked {} links of source #{}", checked, source_id),
+            Err(err) => tracing::warn!("Failed to check links of source #{}: {:?}", source_id, err),
+        }
+    }
+    Ok(ParseReport {
+        status: match stopped_by {
+            Some(_) => ParseStatus::Partial,
+            None => ParseStatus::Complete,
+        },
+        documents: inserted,
+        unchanged: unchanged.into_inner(),
+        removed,
+        stopped_by,
+        summary,
+    })
+}
+
+// A source's own token wins over the app, which wins over the server's token.
+async fn source_github_client(state: &AppState, source: &Source) -> Result<Octocrab> {
+    match (&state.github_app, source.github.token.is_empty()) {
+        (_, false) => github_client(
+            GitHubAuth::Token(source.github.token.clone()),
+            state.cfg.proxy.as_deref(),
+        )
+        .context("Failed to build GitHub client of the source"),
+        (Some(app), true) => app.client(&source.owner, &source.repo).await,
+        (None, true) => Ok(state.github.clone()),
+    }
+}
+
+/// Metadata of a GitHub source's repo, empty for other sources and when it
+/// can't be fetched, sources are usable without it.
+pub async fn repo_metadata(state: &AppState, source: &Source) -> RepoMetadata {
+    if source.kind != SourceKind::GitHub || state.stub_repos.is_some() || state.cfg.offline {
+        return RepoMetadata::default();
+    }
+    let metadata = async {
+        let github = source_github_client(state, source).await?;
+        GitHubParser::new(source.clone(), github, state.breakers.github.clone())?
+            .get_metadata()
+            .await
+    };
+    metadata.await.unwrap_or_else(|err| {
+        tracing::warn!(
+            "Failed to fetch metadata of {}/{}: {:?}",
+            source.owner,
+            source.repo,
+            err
+        );
+        RepoMetadata::default()
+    })
+}
+
+/// Proposes a source for each package of a monorepo GitHub source with docs
+/// of its own, limited to the package's docs and labeled with its name.
+/// Proposals matching an existing source of the collection carry its id,
+/// with `create` missing ones are created.
+pub async fn discover_sources(
+    state: &AppState,
+    source: &Source,
+    create: bool,
+) -> Result<Vec<DiscoveredSource>> {
+    let paths = match &state.stub_repos {
+        Some(repos) => StubParser::new(source.clone(), repos).get_paths(false),
+        None if source.kind != SourceKind::GitHub => {
+            anyhow::bail!("Only GitHub sources can be discovered")
+        }
+        None if state.cfg.offline => {
+            anyhow::bail!("Discovery needs the network, which is disabled in offline mode")
+        }
+        None => {
+            let github = source_github_client(state, source).await?;
+            GitHubParser::new(source.clone(), github, state.breakers.github.clone())?
+                .list_tree()
+                .await
+                .context("Failed to list repo")?
+        }
+    };
+    let existing = state
+        .db
+        .query_sources()
+        .await
+        .context("Failed to query sources")?;
+    let mut discovered = Vec::new();
+    // Indexes in `discovered` of the packages whose sources are created.
+    let mut created = Vec::new();
+    let mut sources = Vec::new();
+    // Directories of packages with a source of their own are ignored by the
+    // repo's source, they are still found.
+    let ignored_dirs: HashSet<String> = source
+        .ignored_dirs
+        .iter()
+        .filter(|dir| {
+            !existing.iter().any(|other| {
+                other.collection_id == source.collection_id
+                    && other.owner == source.owner
+                    && other.repo == source.repo
+                    && other.branch == source.branch
+                    && other.allowed_dirs.contains(*dir)
+            })
+        })
+        .cloned()
+        .collect();
+    for package in discover::packages(&paths, &ignored_dirs) {
+        let sub = Source {
+            id: 0,
+            allowed_dirs: HashSet::from([package.docs_dir.clone()]),
+            labels: source
+                .labels
+                .iter()
+                .cloned()
+                .chain([package.name.clone()])
+                .collect(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            ..source.clone()
+        };
+        let found = existing.iter().find(|other| {
+            other.collection_id == sub.collection_id
+                && other.owner == sub.owner
+                && other.repo == sub.repo
+                && other.branch == sub.branch
+                && other.allowed_dirs == sub.allowed_dirs
+        });
+        let source_id = found.map(|found| found.id);
+        let mut labels: Vec<String> = sub.labels.iter().cloned().collect();
+        labels.sort();
+        if source_id.is_none() && create {
+            created.push(discovered.len());
+            sources.push(sub);
+        }
+        discovered.push(DiscoveredSource {
+            package,
+            labels,
+            source_id,
+        });
+    }
+    if created.is_empty() {
+        return Ok(discovered);
+    }
+
+    let (ids, deleted) = state
+        .db
+        .insert_package_sources(source, &sources)
+        .await
+        .context("Failed to insert sources")?;
+    for (at, id) in created.into_iter().zip(ids) {
+        tracing::info!(
+            "Created source #{} for package '{}'",
+            id,
+            discovered[at].package.path
+        );
+        discovered[at].source_id = Some(id);
+    }
+    if deleted > 0 {
+        tracing::info!(
+            "Deleted {} documents of source #{} now indexed by its packages",
+            deleted,
+            source.id
+        );
+        let collection = state
+            .db
+            .select_collection(source.collection_id)
+            .await
+            .context("Failed to select collection")?;
+        index::publish_sources(&state.db, &state.tinyvector, &collection, &[source.id])
+            .await
+            .context("Failed to publish collection")?;
+    }
+    Ok(discovered)
+}
+
+/// Source proposed for a package.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct DiscoveredSource {
+    #[serde(flatten)]
+    pub package: Package,
+    pub labels: Vec<String>,
+    /// The existing or created source, unset if it wasn't created.
+    pub source_id: Option<i64>,
+}
+
+// Inserts the document along with its headings and links, returns its id.
+async fn store_document(db: &Db, document: &Document) -> Result<i64> {
+    let document_id = db
+        .insert_document(document)
+        .await
+        .context("Failed to insert document")?;
+    let headings = extract_headings(document_id, &document.data)
+        .with_context(|| format!("Failed to extract headings '{}'", document.path))?;
+    db.replace_headings(document_id, &headings)
+        .await
+        .context("Failed to insert headings")?;
+    let links = extract_links(document_id, document.source_id, document)
+        .with_context(|| format!("Failed to extract links '{}'", document.path))?;
+    db.replace_links(document_id, &links)
+        .await
+        .context("Failed to insert links")?;
+    Ok(document_id)
+}
+
+// The ETag of an object is stored once its document is, or once it's skipped,
+// a failed insert would otherwise be skipped as unchanged by every later parse.
+async fn store_object(db: &Db, document: &Document, etag: Option<String>) -> Result<()> {
+    store_document(db, document).await?;
+    store_etag(db, document.source_id, &document.path, etag).await
+}
+
+// Documents of unchanged files aren't stored again, but the other paths of
+// their content, their place in the nav and their link, e.g. of a bumped
+// submodule, change with the rest of the tree.
+async fn refresh_unchanged(db: &Db, parser: &Parser, source_id: i64, path: &str) -> Result<()> {
+    let alternate_paths = match parser {
+        Parser::GitHub(github) => github.alternate_paths(path),
+        _ => Vec::new(),
+    };
+    let nav = parser.nav_entry(path);
+    db.update_document_listing(
+        source_id,
+        path,
+        &alternate_paths,
+        &nav.as_ref().map(NavEntry::nav_path).unwrap_or_default(),
+        nav.map(|nav| nav.index),
+        &parser.url(path),
+    )
+    .await
+    .context("Failed to update document listing")
+}
+
+async fn store_etag(db: &Db, source_id: i64, path: &str, etag: Option<String>) -> Result<()> {
+    if let Some(etag) = etag {
+        db.upsert_object_etag(source_id, path, &etag)
+            .await
+            .context("Failed to store object etag")?;
+    }
+    Ok(())
+}
+
+// Headings of the document body, front matter is skipped.
+fn extract_headings(document_id: i64, data: &str) -> Result<Vec<Heading>> {
+    let body = encoder::remove_head(data.to_string());
+    let headings = encoder::extract_headings(&body)?;
+    let anchors = encoder::heading_anchors(headings.iter().map(|(_, text)| text.as_str()));
+    Ok(headings
+        .into_iter()
+        .zip(anchors)
+        .enumerate()
+        .map(|(position, ((level, text), anchor))| Heading {
+            document_id,
+            position,
+            level,
+            text,
+            anchor,
+        })
+        .collect())
+}
+
+// Links of the document, relative ones are resolved to repo paths.
+fn extract_links(document_id: i64, source_id: i64, document: &Document) -> Result<Vec<Link>> {
+    let body = encoder::remove_head(document.data.clone());
+    Ok(links::extract_links(&body)?
+        .into_iter()
+        .map(|url| Link {
+            document_id,
+            source_id,
+            target_path: links::resolve_link(&document.path, &url),
+            url,
+        })
+        .collect())
+}
+
+/// Splits the source's documents into chunks, embeds and stores them, until
+/// it's cancelled. Chunks stored before a cancel are published as well.
+/// Returns the number of stored chunks.
+pub async fn encode_source(
+    state: &AppState,
+    source: Source,
+    opts: EncodeOptions,
+    cancel: &Cancellation,
+) -> Result<EncodeReport> {
+    let source_id = source.id;
+    let collection = state
+        .db
+        .select_collection(source.collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let embeddings = state.embeddings(&collection.model)?;
+    check_model(state, &collection, &embeddings).await?;
+
+    let mut documents = state
+        .db
+        .query_documents_by_source(source_id)
+        .await
+        .context("Failed to query documents")?;
+    if opts.incremental {
+        let encoded = state
+            .db
+            .query_encoded_document_ids(source_id)
+            .await
+            .context("Failed to query encoded documents")?;
+        documents.retain(|doc| !encoded.contains(&doc.id));
+    }
+    tracing::info!("Got {} documents", documents.len());
+
+    let mut report = EncodeReport {
+        documents: documents.len(),
+        chunks: 0,
+    };
+    for doc in documents {
+        if let Err(err) = cancel.check() {
+            // Chunks stored until then are searchable like the others.
+            if report.chunks > 0 {
+                index::publish_sources(&state.db, &state.tinyvector, &collection, &[source_id])
+                    .await
+                    .context("Failed to publish collection")?;
+            }
+            return Err(err).with_context(|| {
+                format!(
+                    "Encode of source #{} stopped after {} chunks",
+                    source_id, report.chunks
+                )
+            });
+        }
+        report.chunks += encode_document(state, &source, &embeddings, doc, opts).await?;
+    }
+
+    tracing::info!("Encoded source #{}, {} chunks", source_id, report.chunks);
+    index::publish_sources(&state.db, &state.tinyvector, &collection, &[source_id])
+        .await
+        .context("Failed to publish collection")?;
+    Ok(report)
+}
+
+/// Parses and encodes the source. Its new chunks are searchable once it's
+/// done, the encode publishes them to the live collection.
+pub async fn sync_source(
+    state: &AppState,
+    source: Source,
+    parse_opts: ParseOptions,
+    encode_opts: EncodeOptions,
+    cancel: &Cancellation,
+) -> Result<SyncReport> {
+    let parse = parse_source(state, source.clone(), parse_opts, cancel)
+        .await
+        .with_context(|| format!("Failed to parse source #{}", source.id))?;
+    let encode = encode_source(state, source, encode_opts, cancel)
+        .await
+        .context("Failed to encode source")?;
+    Ok(SyncReport { parse, encode })
+}
+
+/// Returns the name of the collection the source is re-indexed into, which
+/// keeps the source's previous index for a rollback afterwards.
+pub fn shadow_name(collection: &str, source_id: i64) -> String {
+    format!("{}~source-{}", collection, source_id)
+}
+
+/// Shadow collections of the collection's sources, with the staging ones of
+/// re-indexes that didn't finish.
+pub async fn shadow_collections(state: &AppState, collection: &str) -> Result<Vec<Collection>> {
+    let prefix = format!("{}~source-", collection);
+    let collections = state
+        .db
+        .query_collections()
+        .await
+        .context("Failed to query collections")?;
+    Ok(collections
+        .into_iter()
+        .filter(|shadow| shadow.name.starts_with(&prefix))
+        .collect())
+}
+
+/// Re-indexes the source blue/green: a copy of it is parsed and encoded into
+/// a shadow collection while the live index keeps serving, then the contents
+/// of the two are swapped in one transaction and the live collection is
+/// republished. The previous contents stay in the shadow collection for
+/// `rollback_source`. Returns the live collection's new index version.
+///
+/// The copy is built in a staging collection next to the shadow, so the
+/// rollback of the previous re-index is only replaced once the swap succeeded.
+pub async fn reindex_source(state: &AppState, source: Source) -> Result<i64> {
+    let live = state
+        .db
+        .select_collection(source.collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let name = shadow_name(&live.name, source.id);
+    let staging_name = format!("{}~next", name);
+    // Left over by a re-index that didn't finish.
+    if let Some(stale) = find_collection(state, &staging_name).await? {
+        drop_collection(state, &stale).await?;
+    }
+    let mut staging = Collection {
+        id: 0,
+        name: staging_name,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        index_version: 0,
+        ..live.clone()
+    };
+    staging.id = state
+        .db
+        .insert_collection(&staging)
+        .await
+        .context("Failed to insert shadow collection")?;
+
+    let index_version = match build_copy(state, &source, &staging).await {
+        Ok(copy) => swap_indexes(state, &source, &copy, &live, &staging).await?,
+        Err(err) => {
+            if let Err(err) = drop_collection(state, &staging).await {
+                tracing::error!(
+                    "Failed to clean up re-index of source #{}: {:#}",
+                    source.id,
+                    err
+                );
+            }
+            return Err(err);
+        }
+    };
+
+    // The staging collection now holds the previous index and becomes the
+    // shadow, replacing the rollback of the previous re-index.
+    if let Some(previous) = find_collection(state, &name).await? {
+        drop_collection(state, &previous).await?;
+    }
+    state
+        .db
+        .update_collection_name(staging.id, &name)
+        .await
+        .context("Failed to rename shadow collection")?;
+    index::rename_builds(&mut *state.tinyvector.write().await, &staging.name, &name);
+    Ok(index_version)
+}
+
+// Parses and encodes a copy of the source into the staging collection.
+async fn build_copy(state: &AppState, source: &Source, staging: &Collection) -> Result<Source> {
+    let mut copy = Source {
+        id: 0,
+        collection_id: staging.id,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        ..source.clone()
+    };
+    copy.id = state
+        .db
+        .insert_source(&copy)
+        .await
+        .context("Failed to insert shadow source")?;
+    tracing::info!(
+        "Re-indexing source #{} into shadow source #{}",
+        source.id,
+        copy.id
+    );
+    let report = parse_source(
+        state,
+        copy.clone(),
+        ParseOptions::default(),
+        &Cancellation::default(),
+    )
+    .await?;
+    if report.status == ParseStatus::Partial {
+        anyhow::bail!(
+            "Re-index of source #{} stopped early by {}, the live index is kept",
+            source.id,
+            report.stopped_by.unwrap_or_default()
+        );
+    }
+    if report.documents == 0 {
+        anyhow::bail!(
+            "Re-index of source #{} found no documents, the live index is kept",
+            source.id
+        );
+    }
+    encode_source(
+        state,
+        copy.clone(),
+        EncodeOptions::default(),
+        &Cancellation::default(),
+    )
+    .await?;
+    Ok(copy)
+}
+
+/// Swaps the source's index with the one kept by its last re-index.
+/// Returns the live collection's new index version.
+pub async fn rollback_source(state: &AppState, source: Source) -> Result<i64> {
+    let live = state
+        .db
+        .select_collection(source.collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let shadow = find_collection(state, &shadow_name(&live.name, source.id))
+        .await?
+        .context("Source has no previous index")?;
+    let copy = shadow_sources(state, &shadow)
+        .await?
+        .into_iter()
+        .next()
+        .context("Source has no previous index")?;
+    swap_indexes(state, &source, &copy, &live, &shadow).await
+}
+
+/// Deletes the source with its documents, chunks and vectors, along with the
+/// shadow collection holding its rollback. The collection is republished
+/// without the source, which is also removed from its retained snapshots and
+/// variants. Returns the collection's new index version.
+pub async fn delete_source(state: &AppState, source: &Source) -> Result<i64> {
+    let live = state
+        .db
+        .select_collection(source.collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let shadow = find_collection(state, &shadow_name(&live.name, source.id)).await?;
+    state
+        .db
+        .delete_source(source.id, shadow.as_ref().map(|shadow| shadow.id))
+        .await
+        .context("Failed to delete source")?;
+    let index_version = index::publish_sources(&state.db, &state.tinyvector, &live, &[source.id])
+        .await
+        .context("Failed to publish collection")?;
+    let mut tinyvector = state.tinyvector.write().await;
+    let removed = index::remove_source_from_builds(&mut tinyvector, &live.name, source.id);
+    tracing::info!(
+        "Removed {} vectors of source #{} from snapshots and variants",
+        removed,
+        source.id
+    );
+    if let Some(shadow) = shadow {
+        index::delete_builds(&mut tinyvector, &shadow.name);
+    }
+    Ok(index_version)
+}
+
+async fn find_collection(state: &AppState, name: &str) -> Result<Option<Collection>> {
+    let collections = state
+        .db
+        .query_collections()
+        .await
+        .context("Failed to query collections")?;
+    Ok(collections
+        .into_iter()
+        .find(|collection| collection.name == name))
+}
+
+// Deletes the collection along with its builds and snapshots in tinyvector.
+async fn drop_collection(state: &AppState, collection: &Collection) -> Result<()> {
+    state
+        .db
+        .delete_collection(collection.id)
+        .await
+        .with_context(|| format!("Failed to delete collection '{}'", collection.name))?;
+    index::delete_builds(&mut *state.tinyvector.write().await, &collection.name);
+    Ok(())
+}
+
+async fn shadow_sources(state: &AppState, shadow: &Collection) -> Result<Vec<Source>> {
+    let sources = state
+        .db
+        .query_sources()
+        .await
+        .context("Failed to query sources")?;
+    Ok(sources
+        .into_iter()
+        .filter(|source| source.collection_id == shadow.id)
+        .collect())
+}
+
+async fn swap_indexes(
+    state: &AppState,
+    source: &Source,
+    copy: &Source,
+    live: &Collection,
+    shadow: &Collection,
+) -> Result<i64> {
+    state
+        .db
+        .swap_source_contents(source.id, copy.id)
+        .await
+        .context("Failed to swap source contents")?;
+    index::publish_collection(&state.db, &state.tinyvector, shadow)
+        .await
+        .context("Failed to publish shadow collection")?;
+    let index_version = index::publish_collection(&state.db, &state.tinyvector, live)
+        .await
+        .context("Failed to publish collection")?;
+    tracing::info!(
+        "Swapped index of source #{}, collection '{}' is at version {}",
+        source.id,
+        live.name,
+        index_version
+    );
+    Ok(index_version)
+}
+
+/// Stores a single document in the collection's notes source, replacing the
+/// one at the same path, and encodes it right away, so it's searchable
+/// without a parse and encode of a source. Returns the document and the
+/// number of stored chunks.
+pub async fn ingest_document(
+    state: &AppState,
+    collection_id: i64,
+    path: &str,
+    data: String,
+) -> Result<(Document, usize)> {
+    let collection = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let embeddings = state.embeddings(&collection.model)?;
+    check_model(state, &collection, &embeddings).await?;
+    let source = notes_source(state, collection_id).await?;
+
+    // The new document is stored and encoded next to the one it replaces,
+    // which is only deleted once that succeeded.
+    let mut document = Document {
+        id: 0,
+        source_id: source.id,
+        collection_id,
+        path: path.to_string(),
+        checksum: crc32fast::hash(data.as_bytes()),
+        tokens_len: state.tokenizer.count(&data),
+        format: DocumentFormat::detect(path, &data),
+        data,
+        summary: String::new(),
+        alternate_paths: Vec::new(),
+        nav_path: String::new(),
+        nav_index: None,
+        language: String::new(),
+        canonical_path: String::new(),
+        url: String::new(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    document.id = store_document(&state.db, &document).await?;
+    let chunks = match encode_document(
+        state,
+        &source,
+        &embeddings,
+        document.clone(),
+        EncodeOptions::default(),
+    )
+    .await
+    {
+        Ok(chunks) => chunks,
+        Err(err) => {
+            if let Err(err) = state.db.delete_document(document.id).await {
+                tracing::error!("Failed to delete document #{}: {:?}", document.id, err);
+            }
+            return Err(err);
+        }
+    };
+    state
+        .db
+        .delete_document_by_path_except(source.id, path, document.id)
+        .await
+        .context("Failed to delete replaced document")?;
+
+    index::publish_sources(&state.db, &state.tinyvector, &collection, &[source.id])
+        .await
+        .context("Failed to publish collection")?;
+    tracing::info!("Ingested '{}', {} chunks", path, chunks);
+    Ok((document, chunks))
+}
+
+// The collection's notes source, created on first use.
+async fn notes_source(state: &AppState, collection_id: i64) -> Result<Source> {
+    if let Some(source) = find_notes_source(state, collection_id).await? {
+        return Ok(source);
+    }
+    let mut source = Source {
+        id: 0,
+        collection_id,
+        kind: SourceKind::Upload,
+        url: String::new(),
+        owner: String::new(),
+        repo: NOTES_REPO.to_string(),
+        branch: String::new(),
+        allowed_ext: HashSet::new(),
+        allowed_dirs: HashSet::new(),
+        ignored_dirs: HashSet::new(),
+        skip_front_matter: HashSet::new(),
+        labels: HashSet::new(),
+        translations: Translations::default(),
+        crawl: CrawlOptions::default(),
+        bucket: BucketOptions::default(),
+        github: GitHubOptions::default(),
+        metadata: RepoMetadata::default(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    match state.db.insert_source(&source).await {
+        Ok(id) => {
+            source.id = id;
+            Ok(source)
+        }
+        // Created by a concurrent ingestion since it was looked up.
+        Err(sqlx::Error::Database(err)) if err.is_unique_violation() => {
+            find_notes_source(state, collection_id)
+                .await?
+                .context("Notes source is missing")
+        }
+        Err(err) => Err(err).context("Failed to insert notes source"),
+    }
+}
+
+async fn find_notes_source(state: &AppState, collection_id: i64) -> Result<Option<Source>> {
+    let sources = state
+        .db
+        .query_sources()
+        .await
+        .context("Failed to query sources")?;
+    Ok(sources.into_iter().find(|source| {
+        source.collection_id == collection_id
+            && source.kind == SourceKind::Upload
+            && source.repo == NOTES_REPO
+    }))
+}
+
+// Splits the document into chunks, embeds and stores them.
+// Returns the number of stored chunks.
+async fn encode_document(
+    state: &AppState,
+    source: &Source,
+    embeddings: &Embeddings,
+    doc: Document,
+    opts: EncodeOptions,
+) -> Result<usize> {
+    // Only markdown has front matter, other formats may contain `---` lines.
+    let (mut context, data) = if doc.format == DocumentFormat::Markdown {
+        let head = encoder::extract_head(&doc.data).unwrap_or_default();
+        let head = encoder::extract_head_values(&head);
+        (
+            format!("{} {}", head.title, head.desc),
+            encoder::remove_head(doc.data),
+        )
+    } else {
+        (String::new(), doc.data)
+    };
+    if !doc.nav_path.is_empty() {
+        context = format!("{} {}", doc.nav_path, context);
+    }
+    if let Some(repo) = source.metadata.context() {
+        context = format!("{} {}", repo, context);
+    }
+    let data = if matches!(doc.format, DocumentFormat::Markdown | DocumentFormat::Html) {
+        let image_texts = match &state.cfg.ocr_command {
+            Some(command) if !state.cfg.offline => {
+                let urls = encoder::image_urls(&data);
+                ocr::image_texts(command, source, &doc.path, urls).await
+            }
+            _ => HashMap::new(),
+        };
+        encoder::describe_images(&data, &image_texts)
+    } else {
+        data
+    };
+    let data = match doc.format {
+        DocumentFormat::Markdown => encoder::replace_diagrams(&data, opts.exclude_diagrams),
+        _ => data,
+    };
+    if opts.summarize {
+        match summarize(state, &data).await {
+            Ok(summary) => {
+                state
+                    .db
+                    .update_document_summary(doc.id, &summary)
+                    .await
+                    .context("Failed to update document summary")?;
+                context = format!("{} {}", context, summary);
+            }
+            // Summaries only improve recall, the document is still encoded without one.
+            Err(err) => tracing::warn!("Failed to summarize '{}': {:?}", doc.path, err),
+        }
+    }
+
+    // A message per chunk, with its subject and author as the context.
+    let chunks: Vec<(String, String)> = if doc.format == DocumentFormat::Email {
+        mail::parse_messages(&data)
+            .into_iter()
+            .map(|message| (message.context(), message.body))
+            .collect()
+    } else {
+        encoder::split(doc.format, &data)
+            .with_context(|| format!("Failed to split document '{}' to chunks", doc.path))?
+            .into_iter()
+            .map(|chunk| (context.clone(), chunk))
+            .collect()
+    };
+    // Deep links of search results land on the section the chunk starts at.
+    let anchors = match doc.format {
+        DocumentFormat::Markdown | DocumentFormat::Notebook => {
+            encoder::chunk_anchors(chunks.iter().map(|(_, data)| data.as_str()))
+                .with_context(|| format!("Failed to build anchors of '{}'", doc.path))?
+        }
+        _ => vec![String::new(); chunks.len()],
+    };
+    let mut inserted = 0;
+    for (chunk_index, ((context, data), anchor)) in chunks.into_iter().zip(anchors).enumerate() {
+        let payload = format!("{}\n{}", &context, &data);
+        let vector = embeddings
+            .encode(&[payload])
+            .await
+            .context("Failed to create embeddings")?
+            .into_iter()
+            .next()
+            .context("Model returned no embeddings")?;
+
+        let chunk = Chunk {
+            id: 0,
+            document_id: doc.id,
+            source_id: source.id,
+            collection_id: doc.collection_id,
+            chunk_index,
+            context,
+            tags: encoder::chunk_tags(&data)
+                .into_iter()
+                .chain(source.labels.iter().cloned())
+                .collect(),
+            data,
+            dimension: vector.len(),
+            vector,
+            anchor,
+            language: doc.language.clone(),
+            model: embeddings.name().to_string(),
+            model_version: embeddings.version().to_string(),
+        };
+
+        state
+            .db
+            .insert_chunk(&chunk)
+            .await
+            .context("Failed to insert chunk")?;
+        inserted += 1;
+    }
+    Ok(inserted)
+}
+
+// Refuses to encode with a model whose vectors can't be compared with the
+// ones already in the collection, e.g. once another provider serves the
+// model. The collection has to be re-embedded first.
+async fn check_model(
+    state: &AppState,
+    collection: &Collection,
+    embeddings: &Embeddings,
+) -> Result<()> {
+    let key = (
+        collection.id,
+        embeddings.name().to_string(),
+        embeddings.version().to_string(),
+    );
+    let checked = state
+        .model_checks
+        .0
+        .lock()
+        .expect("Model checks lock is poisoned")
+        .get(&key)
+        .copied();
+    if checked.is_none() {
+        let dimension = embeddings
+            .dimension()
+            .await
+            .context("Failed to get model dimension")?;
+        if dimension != collection.dimension {
+            anyhow::bail!(
+                "Model '{}' creates vectors of {} dimensions, collection '{}' holds {}",
+                embeddings.name(),
+                dimension,
+                collection.name,
+                collection.dimension
+            );
+        }
+    }
+    let checked = checked.unwrap_or_default();
+    let models = state
+        .db
+        .query_chunk_models(collection.id, checked)
+        .await
+        .context("Failed to query chunk models")?;
+    // Chunks stored before models were recorded don't say.
+    let mut last_id = checked;
+    for (model, model_version, dimension, last) in models {
+        let compatible = (model.is_empty() || model == embeddings.name())
+            && (model_version.is_empty() || model_version == embeddings.version())
+            && (dimension == 0 || dimension == collection.dimension);
+        if !compatible {
+            anyhow::bail!(
+                "Collection '{}' holds vectors of model '{}' ({}, {} dimensions), re-embed it before encoding with '{}' ({})",
+                collection.name,
+                model,
+                model_version,
+                dimension,
+                embeddings.name(),
+                embeddings.version()
+            );
+        }
+        last_id = last_id.max(last);
+    }
+    state
+        .model_checks
+        .0
+        .lock()
+        .expect("Model checks lock is poisoned")
+        .insert(key, last_id);
+    Ok(())
+}
+
+async fn summarize(state: &AppState, data: &str) -> Result<String> {
+    let input: String = data.chars().take(SUMMARY_INPUT_CHARS).collect();
+    let summary = state
+        .openai
+        .create_chat(SUMMARY_PROMPT, &input)
+        .await
+        .context("Failed to create summary")?;
+    Ok(summary.trim().to_string())
+}
+
+// Known paths, of checksums or ETags, that aren't listed anymore, each once.
+fn removed_paths<'a>(known: impl Iterator<Item = &'a Path>, paths: &[Path]) -> Vec<&'a Path> {
+    let listed: HashSet<&Path> = paths.iter().collect();
+    let mut seen = HashSet::new();
+    known
+        .filter(|path| path.as_str() != TREE_ETAG && !listed.contains(*path))
+        .filter(|path| seen.insert(*path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_budget_is_capped() {
+        let cfg = ParseBudget {
+            max_files: 100,
+            max_bytes: 1 << 20,
+            max_duration: Duration::from_secs(60),
+        };
+        let opts = ParseOptions {
+            max_files: Some(10),
+            max_bytes: Some(1 << 30),
+            max_duration_secs: Some(3600),
+            ..Default::default()
+        };
+        let budget = opts.budget(&cfg);
+        assert_eq!(budget.max_files, 10);
+        assert_eq!(budget.max_bytes, 1 << 20);
+        assert_eq!(budget.max_duration, Duration::from_secs(60));
+        let budget = ParseOptions::default().budget(&cfg);
+        assert_eq!(budget.max_files, 100);
+    }
+
+    #[test]
+    fn test_removed_paths() {
+        let known: HashMap<Path, String> = [TREE_ETAG, "README.md", "docs/intro.md"]
+            .iter()
+            .map(|path| (path.to_string(), "\"etag\"".to_string()))
+            .collect();
+        let paths = vec!["README.md".to_string(), "docs/setup.md".to_string()];
+        assert_eq!(removed_paths(known.keys(), &paths), vec!["docs/intro.md"]);
+    }
+
+    #[cfg(feature = "server")]
+    #[tokio::test]
+    async fn test_store_object_etag() {
+        use crate::test_support::{StubRepos, TestApp};
+
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let mut document = Document {
+            id: 0,
+            source_id: source.id,
+            collection_id: source.collection_id + 1,
+            path: "docs/intro.md".to_string(),
+            checksum: 0,
+            tokens_len: 0,
+            data: "# Intro".to_string(),
+            format: DocumentFormat::Markdown,
+            summary: String::new(),
+            alternate_paths: Vec::new(),
+            nav_path: String::new(),
+            nav_index: None,
+            language: String::new(),
+            canonical_path: String::new(),
+            url: String::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let etag = Some("\"abc\"".to_string());
+
+        // The insert fails on the missing collection, so the next parse
+        // fetches the object again.
+        assert!(store_object(&app.state.db, &document, etag.clone())
+            .await
+            .is_err());
+        let known = app.state.db.query_object_etags(source.id).await.unwrap();
+        assert!(known.is_empty());
+
+        document.collection_id = source.collection_id;
+        store_object(&app.state.db, &document, etag).await.unwrap();
+        let known = app.state.db.query_object_etags(source.id).await.unwrap();
+        assert_eq!(known["docs/intro.md"], "\"abc\"");
+    }
+}
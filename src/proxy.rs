@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use jsonwebtoken::EncodingKey;
+use octocrab::{models::AppId, Octocrab};
+
+/// How the GitHub client authenticates.
+pub enum GitHubAuth {
+    Token(String),
+    App(AppId, EncodingKey),
+}
+
+/// Builds a GitHub client, sending its requests through the proxy if given.
+/// Other clients are reqwest ones, which pick up `HTTPS_PROXY` and `NO_PROXY`
+/// themselves, octocrab's hyper client doesn't.
+pub fn github_client(auth: GitHubAuth, proxy: Option<&str>) -> Result<Octocrab> {
+    #[cfg(feature = "server")]
+    if let Some(proxy) = proxy {
+        return proxied::github_client(auth, proxy);
+    }
+    #[cfg(not(feature = "server"))]
+    if proxy.is_some() {
+        tracing::warn!("GitHub requests only go through proxies with the `server` feature");
+    }
+    let builder = Octocrab::builder();
+    let builder = match auth {
+        GitHubAuth::Token(token) => builder.personal_token(token),
+        GitHubAuth::App(app_id, key) => builder.app(app_id, key),
+    };
+    builder.build().context("Failed to build GitHub client")
+}
+
+#[cfg(feature = "server")]
+mod proxied {
+    use anyhow::{Context, Result};
+    use axum::http::{
+        header::{AUTHORIZATION, USER_AGENT},
+        HeaderValue, Request, Response, Uri,
+    };
+    use octocrab::{
+        auth::AppAuth,
+        service::middleware::{base_uri::BaseUriLayer, extra_headers::ExtraHeadersLayer},
+        AuthState, Octocrab, OctocrabBuilder,
+    };
+    use std::{
+        future::{self, Future, Ready},
+        pin::Pin,
+        sync::Arc,
+        task::{Context as TaskContext, Poll},
+        time::Duration,
+    };
+    use tower::retry::{Policy, RetryLayer};
+
+    use super::GitHubAuth;
+
+    const GITHUB_API: &str = "https://api.github.com";
+
+    /// Time a GitHub API request may take, including reading its response.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Retries of failed requests, as octocrab's own client does.
+    const RETRIES: usize = 3;
+
+    pub fn github_client(auth: GitHubAuth, proxy: &str) -> Result<Octocrab> {
+        let proxy = reqwest::Proxy::all(proxy)
+            .context("Invalid proxy url")?
+            .no_proxy(reqwest::NoProxy::from_env());
+        let client = reqwest::Client::builder()
+            .proxy(proxy)
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .context("Failed to build proxied GitHub client")?;
+
+        let mut headers = vec![(USER_AGENT, HeaderValue::from_static("rtfm"))];
+        let auth_state = match auth {
+            GitHubAuth::Token(token) => {
+                let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                    .context("Invalid GitHub token")?;
+                headers.push((AUTHORIZATION, value));
+                AuthState::None
+            }
+            GitHubAuth::App(app_id, key) => AuthState::App(AppAuth { app_id, key }),
+        };
+        // An empty builder has none of octocrab's middleware, retries are
+        // added back and timeouts are the reqwest client's.
+        let octocrab = OctocrabBuilder::new_empty()
+            .with_service(ReqwestService { client })
+            .with_layer(&RetryLayer::new(Retries(RETRIES)))
+            .with_layer(&ExtraHeadersLayer::new(Arc::new(headers)))
+            .with_layer(&BaseUriLayer::new(Uri::from_static(GITHUB_API)))
+            .with_auth(auth_state)
+            .build()
+            .expect("Building with a service is infallible");
+        Ok(octocrab)
+    }
+
+    /// Retries requests failing with server errors, rate limits or without a
+    /// response, like octocrab's `RetryConfig` does for its hyper client.
+    #[derive(Clone)]
+    struct Retries(usize);
+
+    impl Policy<Request<String>, Response<hyper::Body>, reqwest::Error> for Retries {
+        type Future = Ready<Self>;
+
+        fn retry(
+            &self,
+            _: &Request<String>,
+            result: Result<&Response<hyper::Body>, &reqwest::Error>,
+        ) -> Option<Self::Future> {
+            let failed = match result {
+                Ok(resp) => resp.status().is_server_error() || resp.status().as_u16() == 429,
+                Err(_) => true,
+            };
+            match (failed, self.0) {
+                (true, left) if left > 0 => Some(future::ready(Retries(left - 1))),
+                _ => None,
+            }
+        }
+
+        fn clone_request(&self, req: &Request<String>) -> Option<Request<String>> {
+            let mut clone = Request::builder()
+                .method(req.method())
+                .uri(req.uri())
+                .version(req.version())
+                .body(req.body().clone())
+                .ok()?;
+            *clone.headers_mut() = req.headers().clone();
+            Some(clone)
+        }
+    }
+
+    /// Sends octocrab's requests with reqwest, which supports proxies.
+    #[derive(Clone)]
+    struct ReqwestService {
+        client: reqwest::Client,
+    }
+
+    impl tower::Service<Request<String>> for ReqwestService {
+        type Response = Response<hyper::Body>;
+        type Error = reqwest::Error;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+        fn poll_ready(&mut self, _: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<String>) -> Self::Future {
+            let client = self.client.clone();
+            Box::pin(async move {
+                let resp = client.execute(reqwest::Request::try_from(req)?).await?;
+                let mut builder = Response::builder()
+                    .status(resp.status())
+                    .version(resp.version());
+                if let Some(headers) = builder.headers_mut() {
+                    headers.extend(resp.headers().clone());
+                }
+                // API responses are JSON documents, buffering them is fine.
+                let body = resp.bytes().await?;
+                Ok(builder
+                    .body(hyper::Body::from(body))
+                    .expect("Response parts are copied from a valid response"))
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn resp(status: u16) -> Response<hyper::Body> {
+            Response::builder()
+                .status(status)
+                .body(hyper::Body::empty())
+                .unwrap()
+        }
+
+        #[test]
+        fn test_retries() {
+            let req = Request::builder()
+                .uri("/repos/acme/docs")
+                .header(USER_AGENT, "rtfm")
+                .body(String::new())
+                .unwrap();
+            let retries = Retries(1);
+            assert!(retries.retry(&req, Ok(&resp(200))).is_none());
+            assert!(retries.retry(&req, Ok(&resp(404))).is_none());
+            assert!(retries.retry(&req, Ok(&resp(429))).is_some());
+            let last = retries.retry(&req, Ok(&resp(502))).unwrap().into_inner();
+            assert!(last.retry(&req, Ok(&resp(502))).is_none());
+
+            let clone = retries.clone_request(&req).unwrap();
+            assert_eq!(clone.uri(), req.uri());
+            assert_eq!(clone.headers(), req.headers());
+        }
+
+        #[tokio::test]
+        async fn test_proxied_client() {
+            let token = || GitHubAuth::Token("ghp_token".to_string());
+            assert!(github_client(token(), "http://127.0.0.1:3128").is_ok());
+            assert!(github_client(token(), "not a proxy url").is_err());
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Distance metrics and top-k selection shared by tinyvector and anything
+//! querying a precomputed index without the server, e.g. a browser extension.
+
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, collections::BinaryHeap, str::FromStr};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum Distance {
+    #[serde(rename = "euclidean")]
+    Euclidean,
+    #[default]
+    #[serde(rename = "cosine")]
+    Cosine,
+    #[serde(rename = "dot")]
+    DotProduct,
+}
+
+impl Distance {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Distance::Euclidean => "euclidean",
+            Distance::Cosine => "cosine",
+            Distance::DotProduct => "dot",
+        }
+    }
+}
+
+impl FromStr for Distance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "euclidean" => Ok(Distance::Euclidean),
+            "cosine" => Ok(Distance::Cosine),
+            "dot" => Ok(Distance::DotProduct),
+            _ => Err(format!("Unknown distance metric '{}'", s)),
+        }
+    }
+}
+
+pub fn get_cache_attr(metric: Distance, vec: &[f32]) -> f32 {
+    match metric {
+        // Dot product doesn't allow any caching
+        Distance::DotProduct => 0.0,
+        // Precompute the sum of squares of the query
+        Distance::Euclidean => sum_squares(vec),
+        // Precompute the magnitude of the vector
+        Distance::Cosine => sum_squares(vec).sqrt(),
+    }
+}
+
+pub fn get_distance_fn(metric: Distance) -> impl Fn(&[f32], &[f32], f32) -> f32 {
+    match metric {
+        Distance::Euclidean => euclidian_distance,
+        // We use dot product for cosine because we've normalized the vectors on insertion
+        Distance::Cosine | Distance::DotProduct => dot_product,
+    }
+}
+
+/// Scores the vector against the query, "higher is better" for all metrics.
+/// `cache_attr` comes from `get_cache_attr` of the query, `prior` is added as is.
+pub fn score(metric: Distance, query: &[f32], vector: &[f32], cache_attr: f32, prior: f32) -> f32 {
+    let score = get_distance_fn(metric)(query, vector, cache_attr);
+    // Euclidean is a distance, so we negate it to keep "higher is better" ordering
+    let score = match metric {
+        Distance::Euclidean => -score,
+        Distance::Cosine | Distance::DotProduct => score,
+    };
+    score + prior
+}
+
+fn euclidian_distance(a: &[f32], b: &[f32], a_sum_squares: f32) -> f32 {
+    let mut cross_terms = 0.0;
+    let mut b_sum_squares = 0.0;
+
+    for (i, j) in a.iter().zip(b) {
+        cross_terms += i * j;
+        b_sum_squares += j * j;
+    }
+
+    let squared = a_sum_squares + b_sum_squares - 2.0 * cross_terms;
+    squared.max(0.0).sqrt()
+}
+
+fn dot_product(a: &[f32], b: &[f32], _: f32) -> f32 {
+    a.iter().zip(b).fold(0.0, |acc, (x, y)| acc + x * y)
+}
+
+fn sum_squares(vec: &[f32]) -> f32 {
+    vec.iter().fold(0.0, |acc, &val| acc + val * val)
+}
+
+pub fn normalize(vec: &[f32]) -> Vec<f32> {
+    let magnitude = sum_squares(vec).sqrt();
+
+    if magnitude > f32::EPSILON {
+        vec.iter().map(|&val| val / magnitude).collect()
+    } else {
+        vec.to_vec()
+    }
+}
+
+pub struct ScoreIndex {
+    pub score: f32,
+    pub index: usize,
+}
+
+impl PartialEq for ScoreIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.score.eq(&other.score)
+    }
+}
+
+impl Eq for ScoreIndex {}
+
+impl PartialOrd for ScoreIndex {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        // The comparison is intentionally reversed here to make the heap a min-heap
+        other.score.partial_cmp(&self.score)
+    }
+}
+
+impl Ord for ScoreIndex {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Returns the `k` best scores, best first.
+pub fn top_k(scores: impl IntoIterator<Item = ScoreIndex>, k: usize) -> Vec<ScoreIndex> {
+    let mut heap = BinaryHeap::new();
+    for score_index in scores {
+        if heap.len() < k || score_index < *heap.peek().unwrap() {
+            heap.push(score_index);
+
+            if heap.len() > k {
+                heap.pop();
+            }
+        }
+    }
+    heap.into_sorted_vec()
+}
+
+/// Returns up to `k` scores ranked after the `(score, id)` of the previous page's
+/// last result, best first. `id` maps an index to its id, ties are broken by it,
+/// so pages neither repeat nor skip results with equal scores.
+pub fn page_after<'a>(
+    scores: Vec<ScoreIndex>,
+    k: usize,
+    after: Option<(f32, &str)>,
+    id: impl Fn(usize) -> &'a str,
+) -> Vec<ScoreIndex> {
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let rank = |a: &ScoreIndex, b: &ScoreIndex| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| id(a.index).cmp(id(b.index)))
+    };
+    let mut scores: Vec<ScoreIndex> = scores
+        .into_iter()
+        .filter(|s| match after {
+            Some((score, after_id)) => {
+                s.score < score || (s.score == score && id(s.index) > after_id)
+            }
+            None => true,
+        })
+        .collect();
+    if scores.len() > k {
+        scores.select_nth_unstable_by(k - 1, rank);
+        scores.truncate(k);
+    }
+    scores.sort_by(rank);
+    scores
+}
+
+/// Ranks vectors against the query, returning the `k` best as `(index, score)`
+/// pairs. Meant for precomputed indexes held outside of a `Collection`, whose
+/// cosine vectors have to be normalized already.
+pub fn rank<'a>(
+    metric: Distance,
+    query: &[f32],
+    vectors: impl IntoIterator<Item = &'a [f32]>,
+    k: usize,
+) -> Vec<(usize, f32)> {
+    let cache_attr = get_cache_attr(metric, query);
+    let scores = vectors
+        .into_iter()
+        .enumerate()
+        .map(|(index, vector)| ScoreIndex {
+            score: score(metric, query, vector, cache_attr, 0.0),
+            index,
+        });
+    top_k(scores, k)
+        .into_iter()
+        .map(|s| (s.index, s.score))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_orders_by_metric() {
+        let vectors = vec![vec![0.0, 1.0], vec![1.0, 0.0], vec![3.0, 0.0]];
+        let slices = vectors.iter().map(|v| v.as_slice());
+        let ranked: Vec<usize> = rank(Distance::Euclidean, &[1.0, 0.0], slices, 2)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(ranked, vec![1, 0]);
+
+        let slices = vectors.iter().map(|v| v.as_slice());
+        let ranked: Vec<usize> = rank(Distance::DotProduct, &[1.0, 0.0], slices, 2)
+            .into_iter()
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(ranked, vec![2, 1]);
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use tokio::time::Instant;
+
+use crate::{index, search, tinyvector, types::Chunk, AppState, Embeddings};
+
+/// Number of chunks encoded at once.
+const BATCH_SIZE: usize = 32;
+
+/// Re-embeds every chunk of the collection with another model.
+///
+/// Vectors are built into a shadow collection while the live one keeps serving
+/// searches, then the db and tinyvector are switched over to the new model.
+pub async fn reembed_collection(
+    state: AppState,
+    collection_id: i64,
+    model: Embeddings,
+) -> Result<()> {
+    let instant = Instant::now();
+    let collection = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let chunks = state
+        .db
+        .query_chunks_by_collection(collection_id)
+        .await
+        .context("Failed to query chunks")?;
+    let documents = state
+        .db
+        .query_document_refs_by_collection(collection_id)
+        .await
+        .context("Failed to query documents")?;
+    let dimension = model
+        .dimension()
+        .await
+        .context("Failed to get model dimension")?;
+    tracing::info!(
+        "Re-embedding {} chunks of collection '{}' with model '{}' ({} dimensions)",
+        chunks.len(),
+        collection.name,
+        model.name(),
+        dimension
+    );
+
+    let vectors = encode_chunks(&model, &chunks).await?;
+    let mut shadow =
+        tinyvector::Collection::new(model.name().to_string(), dimension, collection.distance);
+    for (chunk, (_, vector)) in chunks.iter().zip(&vectors) {
+        shadow.upsert(
+            index::embedding_id(chunk),
+            vector.clone(),
+            chunk.data.clone(),
+            index::reembedded_metadata(chunk, &documents, model.name(), model.version(), dimension),
+        )?;
+    }
+    let priors = index::link_priors(&state.db, collection_id)
+        .await
+        .context("Failed to query link priors")?;
+    shadow.set_priors(&priors);
+
+    state
+        .db
+        .update_collection_vectors(
+            collection_id,
+            model.name(),
+            model.version(),
+            dimension,
+            &vectors,
+        )
+        .await
+        .context("Failed to update chunk vectors")?;
+    state.model_checks.forget(collection_id);
+
+    // The model has to be available before searches are routed to the new collection.
+    state.models.insert(model);
+    state
+        .tinyvector
+        .write()
+        .await
+        .swap_collection(collection.name.clone(), shadow);
+
+    tracing::info!(
+        "Re-embedded collection '{}', elapsed {:?}",
+        collection.name,
+        instant.elapsed()
+    );
+    Ok(())
+}
+
+/// Embeds every chunk of the collection with a candidate model, keeping the
+/// primary vectors in place, so both models can be searched and compared.
+pub async fn add_candidate_model(
+    state: AppState,
+    collection_id: i64,
+    model: Embeddings,
+) -> Result<()> {
+    let instant = Instant::now();
+    let collection = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")?;
+    let chunks = state
+        .db
+        .query_chunks_by_collection(collection_id)
+        .await
+        .context("Failed to query chunks")?;
+    let documents = state
+        .db
+        .query_document_refs_by_collection(collection_id)
+        .await
+        .context("Failed to query documents")?;
+    tracing::info!(
+        "Embedding {} chunks of collection '{}' with candidate model '{}'",
+        chunks.len(),
+        collection.name,
+        model.name()
+    );
+
+    let vectors = encode_chunks(&model, &chunks).await?;
+    state
+        .db
+        .insert_chunk_vectors(collection_id, model.name(), &vectors)
+        .await
+        .context("Failed to insert chunk vectors")?;
+
+    let mut variant = index::build_variant(
+        &collection,
+        model.name(),
+        &chunks,
+        vectors.into_iter().collect(),
+        &documents,
+    );
+    let priors = index::link_priors(&state.db, collection_id)
+        .await
+        .context("Failed to query link priors")?;
+    variant.set_priors(&priors);
+    search::index_terms(&mut variant);
+    let name = index::variant_name(&collection.name, model.name());
+    state.models.insert(model);
+    state
+        .tinyvector
+        .write()
+        .await
+        .swap_collection(name, variant);
+
+    tracing::info!(
+        "Added candidate model to collection '{}', elapsed {:?}",
+        collection.name,
+        instant.elapsed()
+    );
+    Ok(())
+}
+
+/// Encodes chunks in batches, returns vectors paired with chunk ids.
+async fn encode_chunks(model: &Embeddings, chunks: &[Chunk]) -> Result<Vec<(i64, Vec<f32>)>> {
+    let mut vectors = Vec::with_capacity(chunks.len());
+    for batch in chunks.chunks(BATCH_SIZE) {
+        let payloads: Vec<String> = batch
+            .iter()
+            .map(|chunk| format!("{}\n{}", chunk.context, chunk.data))
+            .collect();
+        let encoded = model
+            .encode(&payloads)
+            .await
+            .context("Failed to create embeddings")?;
+        for (chunk, vector) in batch.iter().zip(encoded) {
+            vectors.push((chunk.id, vector));
+        }
+    }
+    Ok(vectors)
+}
@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{Path, Query, State},
+    middleware::from_fn_with_state,
+    routing::{get, post},
+    Json, Router,
+};
+use hyper::StatusCode;
+use serde::Deserialize;
+
+use crate::{
+    audit, errors::ServerError, eval, extract::LimitedJson, index, reembed, AppState, Embeddings,
+    Manifest, ModelInfo, SeedReport,
+};
+
+pub fn routes(state: AppState) -> Router<AppState> {
+    Router::new().nest(
+        "/api/admin",
+        Router::new()
+            .route("/check", get(check).post(fix))
+            .route("/model", get(model_info))
+            .route("/seed", post(seed))
+            .route("/collections/:collection_id/reembed", post(reembed))
+            .route(
+                "/collections/:collection_id/candidates",
+                post(add_candidate),
+            )
+            .route("/collections/:collection_id/eval", get(eval_candidate))
+            .route_layer(from_fn_with_state(state, audit::audit)),
+    )
+}
+
+/// Reports drift between chunks in the db and embeddings in tinyvector.
+pub async fn check(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<index::ConsistencyReport>>, ServerError> {
+    let report = index::check_consistency(&state.db, &state.tinyvector, false)
+        .await
+        .context("Failed to check consistency")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(report))
+}
+
+/// Same as `check`, but also fixes the drift.
+pub async fn fix(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<index::ConsistencyReport>>, ServerError> {
+    let report = index::check_consistency(&state.db, &state.tinyvector, true)
+        .await
+        .context("Failed to fix consistency")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(report))
+}
+
+/// Seeds collections and sources from a YAML or JSON manifest in the body,
+/// the bundled sample corpus is seeded when the body is empty.
+pub async fn seed(
+    State(state): State<AppState>,
+    body: String,
+) -> Result<Json<SeedReport>, ServerError> {
+    let manifest = if body.trim().is_empty() {
+        Manifest::sample()
+    } else {
+        Manifest::parse(&body).map_err(|err| ServerError::ValidationError(err))?
+    };
+    let report = crate::seed(&state, &manifest).await.map_err(|err| {
+        // Seeding also loads models and encodes, only db errors are the db's.
+        if err.chain().any(|cause| cause.is::<sqlx::Error>()) {
+            ServerError::DbError(err)
+        } else {
+            ServerError::Embeddings(err)
+        }
+    })?;
+    Ok(Json(report))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReembedReq {
+    /// Directory of the local model to re-embed the collection with.
+    pub model: String,
+}
+
+/// Shows loaded embedding models with their device and average inference latency.
+pub async fn model_info(State(state): State<AppState>) -> Json<Vec<ModelInfo>> {
+    let mut models: Vec<ModelInfo> = state.models.all().iter().map(|m| m.info()).collect();
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Json(models)
+}
+
+/// Starts re-embedding the collection with another model in the background.
+pub async fn reembed(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<ReembedReq>,
+) -> Result<StatusCode, ServerError> {
+    tracing::info!(?payload, "Re-embedding collection #{}", collection_id);
+    check_model(&state, &payload.model)?;
+    let _ = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")
+        .map_err(|err| ServerError::DbError(err))?;
+
+    tokio::spawn(async move {
+        let result = match load_model(&state, payload.model).await {
+            Ok(model) => reembed::reembed_collection(state, collection_id, model).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::error!(
+                "Failed to re-embed collection #{}: {:?}",
+                collection_id,
+                err
+            );
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Starts embedding the collection with a candidate model in the background,
+/// keeping the primary vectors in place.
+pub async fn add_candidate(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<ReembedReq>,
+) -> Result<StatusCode, ServerError> {
+    tracing::info!(
+        ?payload,
+        "Adding candidate model to collection #{}",
+        collection_id
+    );
+    check_model(&state, &payload.model)?;
+    let _ = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")
+        .map_err(|err| ServerError::DbError(err))?;
+
+    tokio::spawn(async move {
+        let result = match load_model(&state, payload.model).await {
+            Ok(model) => reembed::add_candidate_model(state, collection_id, model).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::error!(
+                "Failed to add candidate model to collection #{}: {:?}",
+                collection_id,
+                err
+            );
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Only loaded and configured models may be used, the name of others would be
+// any directory on the server.
+fn check_model(state: &AppState, model: &str) -> Result<(), ServerError> {
+    if state.models.get(model).is_some() || state.cfg.embeddings_models.iter().any(|m| m == model) {
+        return Ok(());
+    }
+    Err(ServerError::ValidationError(anyhow!(
+        "Model '{}' isn't configured in EMBEDDINGS_MODELS",
+        model
+    )))
+}
+
+// Returns an already loaded model or loads it from disk.
+async fn load_model(state: &AppState, model: String) -> anyhow::Result<Embeddings> {
+    if let Some(model) = state.models.get(&model) {
+        return Ok(model);
+    }
+    let cfg = state.cfg.clone();
+    tokio::task::spawn_blocking(move || Embeddings::from_config(&model, &cfg))
+        .await
+        .context("Failed to join model loading task")?
+        .context("Failed to load embeddings model")
+}
+
+#[derive(Deserialize)]
+pub struct EvalQuery {
+    /// Candidate model to compare with the collection's primary model.
+    pub model: String,
+    pub limit: Option<i64>,
+    pub k: Option<usize>,
+}
+
+/// Compares the collection's primary model with a candidate on logged queries.
+pub async fn eval_candidate(
+    Path(collection_id): Path<i64>,
+    params: Query<EvalQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<eval::EvalReport>, ServerError> {
+    let collection = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")
+        .map_err(|err| ServerError::DbError(err))?;
+    let report = eval::compare_models(
+        &state,
+        &collection.name,
+        &params.model,
+        params.limit.unwrap_or(100),
+        params.k.unwrap_or(10),
+    )
+    .await
+    .map_err(|err| ServerError::Embeddings(err))?;
+    Ok(Json(report))
+}
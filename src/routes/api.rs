@@ -1,168 +1,756 @@
 use anyhow::{anyhow, Context};
 use axum::{
-    extract::{Path, Query, State},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::HeaderMap,
+    middleware::{from_fn, from_fn_with_state},
+    response::{IntoResponse, Response},
     routing::{delete, get, post, put},
     Json, Router,
 };
-use chrono::Utc;
-use futures::stream::StreamExt;
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt,
+};
 use hyper::StatusCode;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, time::Instant};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::timeout::TimeoutLayer;
+use utoipa::{IntoParams, ToSchema};
 
+use super::auth;
 use crate::{
+    access, aliases, archive, ask, audit,
+    coverage::{self, CoverageReport},
+    cursor::Cursor,
     encoder,
     errors::ServerError,
-    parser,
-    types::{Chunk, Document, Source},
-    AppState,
+    etag,
+    extract::LimitedJson,
+    idempotency, index, jobs, links, lookup,
+    negotiate::{self, Format},
+    parser::{StubRepos, NAV_SEPARATOR},
+    pipeline::{self, Cancellation, DiscoveredSource, EncodeOptions, ParseOptions},
+    search, spelling,
+    types::{
+        AccessToken, Alias, AuditEntry, BrokenLink, BucketOptions, Chunk, Collection, CrawlOptions,
+        Document, GitHubOptions, Heading, Job, JobKind, JobStatus, RepoMetadata, Source,
+        SourceKind, SourceStats, Translations, MAX_CRAWL_DEPTH, MAX_CRAWL_PAGES,
+    },
+    versioning::{self, ApiVersion},
+    AppState, Db, Delta, Distance, Facets, DEFAULT_MODEL,
 };
 
-pub fn routes() -> Router<AppState> {
-    Router::new().nest(
-        "/api",
-        Router::new()
-            .route("/search", get(search))
-            .route("/sources", put(create_source))
-            .route("/sources/:source_id/parse", post(parse))
-            .route("/sources/:source_id/encode", post(encode_source))
-            .route("/sources/:source_id/chunks", delete(delete_chunks))
-            .route("/sources/:source_id/docs", delete(delete_documents)),
-    )
+pub fn routes(state: AppState) -> Router<AppState> {
+    let cfg = state.cfg.clone();
+
+    // Source creation, collection copies and sync triggers honor the
+    // `Idempotency-Key` header, so retried requests don't create duplicate
+    // sources, collections or jobs.
+    let idempotent = Router::new()
+        .route("/sources", put(create_source))
+        .route("/sources/bulk", put(create_sources))
+        .route("/collections/:collection_id/clone", post(clone_collection))
+        .route("/collections/:collection_id/merge", post(merge_collection))
+        .route("/sources/:source_id/parse", post(parse))
+        .route("/sources/:source_id/encode", post(encode_source))
+        .route("/sources/:source_id/sync", post(sync_source))
+        .route("/sources/:source_id/reindex", post(reindex_source))
+        .route("/sources/:source_id/rollback", post(rollback_source))
+        .route("/sources/:source_id/discover", post(discover_sources))
+        .route_layer(from_fn_with_state(state.clone(), idempotency::idempotency))
+        .layer(TimeoutLayer::new(cfg.long_timeout));
+
+    // Searches give up early, so a slow query doesn't hold a connection for long.
+    // Bursts queue for a slot instead of piling up on the embeddings model,
+    // the timeout covers the wait as well.
+    let searches = Router::new()
+        .route("/search", get(search))
+        .route("/search/hybrid", get(hybrid_search))
+        .route("/lookup", get(lookup))
+        .layer(GlobalConcurrencyLimitLayer::new(cfg.search_concurrency))
+        .layer(TimeoutLayer::new(cfg.search_timeout));
+
+    // Answers wait for the LLM, which may take a while for long ones.
+    let answers = Router::new()
+        .route("/ask", post(ask))
+        .layer(GlobalConcurrencyLimitLayer::new(cfg.ask_concurrency))
+        .layer(TimeoutLayer::new(cfg.long_timeout));
+
+    // Archives are larger than other bodies and take a while to ingest.
+    let uploads = Router::new()
+        .route("/sources/:source_id/upload", post(upload_archive))
+        .layer(DefaultBodyLimit::max(cfg.max_upload_bytes))
+        .layer(TimeoutLayer::new(cfg.long_timeout));
+
+    let api = Router::new()
+        .route("/documents", post(create_document))
+        .route("/documents/:document_id", get(get_document))
+        .route("/documents/:document_id/toc", get(document_toc))
+        .route("/documents/:document_id/links", get(document_links))
+        .route("/sources", get(list_sources))
+        .route(
+            "/sources/:source_id",
+            get(get_source).patch(update_source).delete(delete_source),
+        )
+        .route("/collections", get(list_collections).put(create_collection))
+        .route(
+            "/collections/:collection_id",
+            get(get_collection)
+                .patch(rename_collection)
+                .delete(delete_collection),
+        )
+        .route(
+            "/collections/:collection_id/aliases",
+            get(list_aliases).put(put_alias),
+        )
+        .route(
+            "/collections/:collection_id/aliases/:alias",
+            delete(delete_alias),
+        )
+        .route(
+            "/collections/:collection_id/tokens",
+            get(list_access_tokens).put(create_access_token),
+        )
+        .route("/tokens/:token_id", delete(delete_access_token))
+        .route("/audit", get(list_audit_log))
+        .route("/jobs", get(list_jobs))
+        .route("/jobs/:job_id", get(get_job))
+        .route("/jobs/:job_id/cancel", post(cancel_job))
+        .route("/collections/:collection_id/delta", get(collection_delta))
+        .route(
+            "/collections/:collection_id/ranking",
+            get(get_ranking).put(put_ranking).delete(delete_ranking),
+        )
+        .route(
+            "/sources/:source_id/chunks",
+            get(list_chunks).delete(delete_chunks),
+        )
+        .route("/sources/:source_id/broken-links", get(broken_links))
+        .route("/sources/:source_id/coverage", get(source_coverage))
+        .route(
+            "/sources/:source_id/docs",
+            get(list_documents).delete(delete_documents),
+        )
+        .layer(TimeoutLayer::new(cfg.request_timeout))
+        .merge(searches)
+        .merge(uploads)
+        .merge(idempotent)
+        // Questions aren't administrative, everything else changing state is audited.
+        .route_layer(from_fn_with_state(state.clone(), audit::audit))
+        // Runs first, so the audit log gets the session's user as the actor.
+        .route_layer(from_fn_with_state(state.clone(), auth::require_role))
+        .merge(answers.route_layer(from_fn_with_state(state, auth::require_viewer)));
+
+    // The same routes are served under `/api/v1`, `/api` stays for existing
+    // clients until its sunset. Breaking changes of responses go to the next
+    // version.
+    Router::new()
+        .nest("/api", api.clone())
+        .layer(from_fn_with_state(
+            cfg.api_sunset.clone(),
+            versioning::deprecated,
+        ))
+        .merge(
+            Router::new()
+                .nest("/api/v1", api)
+                .layer(from_fn(versioning::v1)),
+        )
 }
 
+#[derive(Serialize, Debug, ToSchema)]
+pub struct JobResp {
+    pub job_id: i64,
+}
+
+/// Starts parsing the source in the background, the parse report is the
+/// result of the returned job.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/parse",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        ParseOptions,
+    ),
+    responses(
+        (status = 202, description = "Parse job started", body = JobResp),
+        (status = 400, description = "Parsing is disabled in offline mode"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
 pub async fn parse(
     Path(source_id): Path<i64>,
+    opts: Query<ParseOptions>,
     State(state): State<AppState>,
-) -> Result<StatusCode, ServerError> {
+) -> Result<(StatusCode, Json<JobResp>), ServerError> {
     tracing::info!("Got request to parse source #{}", source_id);
-    let source = state
+    if state.cfg.offline && state.stub_repos.is_none() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Parsing is disabled in offline mode"
+        )));
+    }
+    let source = select_source(&state, source_id).await?;
+    let task_state = state.clone();
+    let job_id = jobs::spawn(&state, source_id, JobKind::Parse, |cancel| async move {
+        pipeline::parse_source(&task_state, source, opts.0, &cancel).await
+    })
+    .await
+    .map_err(|err| ServerError::DbError(err))?;
+    Ok((StatusCode::ACCEPTED, Json(JobResp { job_id })))
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct UploadResp {
+    pub files: usize,
+    pub documents: usize,
+}
+
+/// Replaces the source's documents with the files of the uploaded zip or
+/// tar.gz archive, filtered like the files of a repo.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/upload",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        ParseOptions,
+    ),
+    request_body(content = String, content_type = "application/octet-stream", description = "Zip or tar.gz archive"),
+    responses(
+        (status = 200, description = "Documents replaced by the archive's files", body = UploadResp),
+        (status = 400, description = "Archive can't be unpacked"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn upload_archive(
+    Path(source_id): Path<i64>,
+    opts: Query<ParseOptions>,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<UploadResp>, ServerError> {
+    tracing::info!("Got {} byte archive for source #{}", body.len(), source_id);
+    let source = select_source(&state, source_id).await?;
+    let files = tokio::task::spawn_blocking(move || archive::unpack(&body))
+        .await
+        .context("Failed to join unpacking")
+        .map_err(|err| ServerError::DbError(err))?
+        .map_err(|err| ServerError::ValidationError(err))?;
+    if files.is_empty() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Archive has no text files"
+        )));
+    }
+
+    // The files are served through stub repos, like inline seed files.
+    let name = format!("{}/{}", source.owner, source.repo);
+    let repos = files
+        .iter()
+        .fold(StubRepos::default(), |repos, (path, content)| {
+            repos.with_file(&name, path, content)
+        });
+    let state = AppState {
+        stub_repos: Some(repos),
+        ..state
+    };
+    // An incremental parse replaces documents of changed files and deletes
+    // the ones of files left out of the archive, only once it's unpacked.
+    let opts = ParseOptions {
+        incremental: true,
+        ..opts.0
+    };
+    let report = pipeline::parse_source(&state, source, opts, &Cancellation::default())
+        .await
+        .map_err(
+            |err| match err.chain().any(|cause| cause.is::<sqlx::Error>()) {
+                true => ServerError::DbError(err),
+                false => ServerError::ValidationError(err),
+            },
+        )?;
+    Ok(Json(UploadResp {
+        files: files.len(),
+        documents: report.documents + report.unchanged,
+    }))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateDocumentReq {
+    pub collection_id: i64,
+    pub path: String,
+    pub data: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CreateDocumentResp {
+    pub id: i64,
+    pub source_id: i64,
+    pub chunks: usize,
+}
+
+/// Adds a single markdown or text document to the collection and encodes it
+/// immediately, a document at the same path is replaced.
+#[utoipa::path(
+    post,
+    path = "/api/v1/documents",
+    tag = "documents",
+    request_body = CreateDocumentReq,
+    responses(
+        (status = 201, description = "Document stored and encoded", body = CreateDocumentResp),
+        (status = 400, description = "Document has no path or data"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn create_document(
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<CreateDocumentReq>,
+) -> Result<(StatusCode, Json<CreateDocumentResp>), ServerError> {
+    tracing::info!(
+        "Creating document '{}' in collection #{}",
+        payload.path,
+        payload.collection_id
+    );
+    let path = payload.path.trim_matches('/');
+    if path.is_empty() || payload.data.trim().is_empty() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Documents need a path and data"
+        )));
+    }
+    let _ = select_collection(&state, payload.collection_id).await?;
+    let (document, chunks) =
+        pipeline::ingest_document(&state, payload.collection_id, path, payload.data)
+            .await
+            .map_err(
+                |err| match err.chain().any(|cause| cause.is::<sqlx::Error>()) {
+                    true => ServerError::DbError(err),
+                    false => ServerError::Embeddings(err),
+                },
+            )?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateDocumentResp {
+            id: document.id,
+            source_id: document.source_id,
+            chunks,
+        }),
+    ))
+}
+
+/// Starts encoding the source's documents in the background, the encode report
+/// is the result of the returned job.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/encode",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        EncodeOptions,
+    ),
+    responses(
+        (status = 202, description = "Encode job started", body = JobResp),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn encode_source(
+    Path(source_id): Path<i64>,
+    opts: Query<EncodeOptions>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<JobResp>), ServerError> {
+    let source = select_source(&state, source_id).await?;
+    let task_state = state.clone();
+    let job_id = jobs::spawn(&state, source_id, JobKind::Encode, |cancel| async move {
+        pipeline::encode_source(&task_state, source, opts.0, &cancel).await
+    })
+    .await
+    .map_err(|err| ServerError::DbError(err))?;
+    Ok((StatusCode::ACCEPTED, Json(JobResp { job_id })))
+}
+
+/// Parses and encodes the source in the background, its new chunks are
+/// searchable once the returned job succeeded. Takes the options of both.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/sync",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        ParseOptions,
+        EncodeOptions,
+    ),
+    responses(
+        (status = 202, description = "Sync job started", body = JobResp),
+        (status = 400, description = "Syncing is disabled in offline mode"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn sync_source(
+    Path(source_id): Path<i64>,
+    parse_opts: Query<ParseOptions>,
+    encode_opts: Query<EncodeOptions>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<JobResp>), ServerError> {
+    tracing::info!("Got request to sync source #{}", source_id);
+    if state.cfg.offline && state.stub_repos.is_none() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Syncing is disabled in offline mode"
+        )));
+    }
+    let source = select_source(&state, source_id).await?;
+    let task_state = state.clone();
+    let job_id = jobs::spawn(&state, source_id, JobKind::Sync, |cancel| async move {
+        pipeline::sync_source(&task_state, source, parse_opts.0, encode_opts.0, &cancel).await
+    })
+    .await
+    .map_err(|err| ServerError::DbError(err))?;
+    Ok((StatusCode::ACCEPTED, Json(JobResp { job_id })))
+}
+
+/// Re-indexes the source into a shadow collection in the background, the
+/// live index serves until the new one is swapped in.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/reindex",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Re-index started"),
+        (status = 400, description = "Parsing is disabled in offline mode"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn reindex_source(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    if state.cfg.offline && state.stub_repos.is_none() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Parsing is disabled in offline mode"
+        )));
+    }
+    let source = select_source(&state, source_id).await?;
+    let _ = tokio::spawn(async move {
+        if let Err(err) = pipeline::reindex_source(&state, source).await {
+            tracing::error!("Failed to re-index source #{}: {:?}", source_id, err);
+        }
+    });
+    Ok(StatusCode::OK)
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RollbackResp {
+    pub index_version: i64,
+}
+
+/// Swaps the source's index back to the one replaced by its last re-index.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/rollback",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Index swapped back", body = RollbackResp),
+        (status = 400, description = "No index to roll back to"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn rollback_source(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<RollbackResp>, ServerError> {
+    let source = select_source(&state, source_id).await?;
+    let index_version = pipeline::rollback_source(&state, source)
+        .await
+        .map_err(
+            |err| match err.chain().any(|cause| cause.is::<sqlx::Error>()) {
+                true => ServerError::DbError(err),
+                false => ServerError::ValidationError(err),
+            },
+        )?;
+    Ok(Json(RollbackResp { index_version }))
+}
+
+async fn select_source(state: &AppState, source_id: i64) -> Result<Source, ServerError> {
+    state
         .db
         .select_source(source_id)
         .await
         .map_err(|err| match err {
             sqlx::Error::RowNotFound => ServerError::NoContent(anyhow!("Source does not exist")),
             _ => ServerError::DbError(anyhow!("Failed to select source: {}", err)),
-        })?;
-    let collection_id = source.collection_id;
+        })
+}
 
-    tracing::info!(
-        "Parsing source #{} from collection #{}",
-        source_id,
-        collection_id
-    );
+#[derive(Serialize, Debug, ToSchema)]
+pub struct DocumentResp {
+    pub id: i64,
+    pub path: String,
+    pub checksum: u32,
+    pub tokens_len: usize,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
 
-    let parser = parser::GitHubParser::new(source, state.github);
-    let paths = parser
-        .get_paths()
-        .await
-        .context("Failed to get repo paths")
-        .map_err(|err| ServerError::GitHubAPIError(err))?;
+impl From<Document> for DocumentResp {
+    fn from(doc: Document) -> Self {
+        Self {
+            id: doc.id,
+            path: doc.path,
+            checksum: doc.checksum,
+            tokens_len: doc.tokens_len,
+            created_at: doc.created_at,
+            updated_at: doc.updated_at,
+        }
+    }
+}
 
-    let _ = futures::stream::iter(paths)
-        .map(|path| {
-            let parser = &parser;
-            let db = &state.db;
-            async move {
-                tracing::info!("Gettings path '{}'", &path);
-                let data = parser
-                    .get_content(&path)
-                    .await
-                    .context("Failed to get github path content")
-                    .unwrap();
-
-                let document = Document {
-                    id: 0,
-                    source_id,
-                    collection_id,
-                    path,
-                    checksum: crc32fast::hash(data.as_bytes()),
-                    tokens_len: 0, // TODO
-                    data,
-                    created_at: Utc::now(),
-                    updated_at: Utc::now(),
-                };
-
-                let _ = db
-                    .insert_document(&document)
-                    .await
-                    .context("Failed to insert document")
-                    .unwrap();
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+const DEFAULT_PAGE_LIMIT: i64 = 100;
+const MAX_PAGE_LIMIT: i64 = 1000;
+/// Number of rows of an export fetched ahead of the client.
+const EXPORT_BUFFER: usize = 64;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Token from the `X-Next-Cursor` header of the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+    /// Page number starting at 1, for UIs jumping between pages. Cursors stay
+    /// fast on later pages, numbers don't.
+    pub page: Option<i64>,
+    /// Same as `limit`.
+    pub per_page: Option<i64>,
+}
+
+struct Page {
+    /// Id the page continues after.
+    after_id: i64,
+    offset: i64,
+    limit: i64,
+}
+
+impl PageQuery {
+    // NDJSON and CSV requests without any of the parameters export the whole
+    // listing instead of its first page.
+    fn is_export(&self, format: Format) -> bool {
+        format != Format::Json
+            && self.cursor.is_none()
+            && self.limit.is_none()
+            && self.page.is_none()
+            && self.per_page.is_none()
+    }
+
+    fn bounds(&self) -> Result<Page, ServerError> {
+        if self.cursor.is_some() && self.page.is_some() {
+            return Err(ServerError::ValidationError(anyhow!(
+                "Pages are requested either by cursor or by number"
+            )));
+        }
+        let after_id = match &self.cursor {
+            Some(token) => Cursor::decode(token)
+                .and_then(|cursor| cursor.last_id())
+                .ok_or_else(|| ServerError::ValidationError(anyhow!("Invalid cursor")))?,
+            None => 0,
+        };
+        let limit = self
+            .limit
+            .or(self.per_page)
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .clamp(1, MAX_PAGE_LIMIT);
+        let offset = match self.page {
+            Some(page) if page < 1 => {
+                return Err(ServerError::ValidationError(anyhow!(
+                    "Pages are numbered from 1"
+                )))
             }
+            Some(page) => (page - 1).saturating_mul(limit),
+            None => 0,
+        };
+        Ok(Page {
+            after_id,
+            offset,
+            limit,
         })
-        .buffer_unordered(20)
-        .collect::<Vec<_>>()
-        .await;
+    }
+}
 
-    Ok(StatusCode::OK)
+// Streams rows fetched from the db on a task of their own, as the response
+// body has to own its stream while the rows borrow the pool.
+fn export<T, F>(state: AppState, rows: F) -> impl Stream<Item = Result<T, sqlx::Error>>
+where
+    T: Send + 'static,
+    F: for<'a> FnOnce(&'a Db) -> BoxStream<'a, Result<T, sqlx::Error>> + Send + 'static,
+{
+    let (tx, rx) = tokio::sync::mpsc::channel(EXPORT_BUFFER);
+    tokio::spawn(async move {
+        let mut rows = rows(&state.db);
+        while let Some(row) = rows.next().await {
+            // The client went away.
+            if tx.send(row).await.is_err() {
+                break;
+            }
+        }
+    });
+    futures::stream::unfold(
+        rx,
+        |mut rx| async move { rx.recv().await.map(|row| (row, rx)) },
+    )
 }
 
-pub async fn encode_source(
+// Full pages point at their last item, a shorter page is the last one.
+fn with_next_cursor(resp: Response, len: usize, limit: usize, next: Option<Cursor>) -> Response {
+    match next {
+        Some(cursor) if len == limit => {
+            ([(NEXT_CURSOR_HEADER, cursor.encode())], resp).into_response()
+        }
+        _ => resp,
+    }
+}
+
+/// Lists the source's documents without their data as JSON, NDJSON or CSV
+/// depending on `Accept`, paginated with cursors or page numbers. NDJSON and
+/// CSV requests without paging parameters stream every document.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{source_id}/docs",
+    tag = "documents",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        PageQuery,
+    ),
+    responses(
+        (status = 200, description = "Page of documents, or all of them for NDJSON and CSV exports", body = [DocumentResp], content_type = ["application/json", "application/x-ndjson", "text/csv"], headers(("x-next-cursor" = String, description = "Cursor of the next page, set on full pages"))),
+        (status = 400, description = "Invalid cursor or page"),
+    )
+)]
+pub async fn list_documents(
     Path(source_id): Path<i64>,
+    params: Query<PageQuery>,
+    format: Format,
     State(state): State<AppState>,
-) -> Result<StatusCode, ServerError> {
-    let documents = state
+) -> Result<Response, ServerError> {
+    if params.is_export(format) {
+        let docs = export(state, move |db| db.stream_documents(source_id));
+        return Ok(format.stream(docs.map(|doc| doc.map(DocumentResp::from))));
+    }
+    let page = params.bounds()?;
+    let docs = state
         .db
-        .query_documents_by_source(source_id)
+        .query_documents_page(source_id, page.after_id, page.offset, page.limit)
         .await
         .context("Failed to query documents")
         .map_err(|err| ServerError::DbError(err))?;
-    tracing::info!("Got {} documents", documents.len());
-
-    let _ = tokio::spawn(async move {
-        for doc in documents {
-            let head = encoder::extract_head(&doc.data).unwrap_or_default();
-            let head = encoder::extract_head_values(&head);
-            let context = format!("{} {}", head.title, head.desc);
-
-            let data = encoder::remove_head(doc.data);
-
-            let chunks = encoder::split_by_headings(&data)
-                .context("Failed to split document to chunks")
-                .unwrap();
-            if chunks.len() == 0 {
-                continue;
-            }
+    let docs: Vec<DocumentResp> = docs.into_iter().map(DocumentResp::from).collect();
+    let len = docs.len();
+    let next = docs.last().map(|doc| Cursor::new(0.0, doc.id.to_string()));
+    Ok(with_next_cursor(
+        format.respond(docs),
+        len,
+        page.limit as usize,
+        next,
+    ))
+}
 
-            for (chunk_index, data) in chunks.into_iter().enumerate() {
-                let payload = format!("{}\n{}", &context, &data);
-                let sequences = vec![payload.clone()];
-                let vector = state
-                    .embeddings
-                    .encode(&sequences)
-                    .await
-                    .context("Failed to create embeddings")
-                    .unwrap()
-                    .first()
-                    .unwrap()
-                    .to_vec();
-
-                let chunk = Chunk {
-                    id: 0,
-                    document_id: doc.id,
-                    source_id,
-                    collection_id: doc.collection_id,
-                    chunk_index,
-                    context: context.clone(),
-                    data,
-                    vector,
-                };
-
-                let _ = state
-                    .db
-                    .insert_chunk(&chunk)
-                    .await
-                    .context("Failed to inserts chunks")
-                    .unwrap();
-            }
-        }
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ChunkResp {
+    pub id: i64,
+    pub document_id: i64,
+    pub chunk_index: usize,
+    pub context: String,
+    pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector: Option<Vec<f32>>,
+}
 
-        tracing::info!("Inserted all documents");
-    });
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChunksQuery {
+    /// Includes the embedding vectors, which are left out as they make up
+    /// most of the response otherwise.
+    #[serde(default)]
+    pub include_vector: bool,
+}
 
-    Ok(StatusCode::OK)
+/// Lists the source's chunks as JSON, NDJSON or CSV depending on `Accept`,
+/// paginated with cursors or page numbers. NDJSON and CSV requests without
+/// paging parameters stream every chunk.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{source_id}/chunks",
+    tag = "documents",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        PageQuery,
+        ChunksQuery,
+    ),
+    responses(
+        (status = 200, description = "Page of chunks, or all of them for NDJSON and CSV exports", body = [ChunkResp], content_type = ["application/json", "application/x-ndjson", "text/csv"], headers(("x-next-cursor" = String, description = "Cursor of the next page, set on full pages"))),
+        (status = 400, description = "Invalid cursor or page, or vectors requested as CSV"),
+    )
+)]
+pub async fn list_chunks(
+    Path(source_id): Path<i64>,
+    params: Query<PageQuery>,
+    Query(query): Query<ChunksQuery>,
+    format: Format,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    if query.include_vector && format == Format::Csv {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Vectors can't be listed as CSV"
+        )));
+    }
+    let include_vector = query.include_vector;
+    let to_resp = move |chunk: Chunk| ChunkResp {
+        id: chunk.id,
+        document_id: chunk.document_id,
+        chunk_index: chunk.chunk_index,
+        context: chunk.context,
+        data: chunk.data,
+        vector: include_vector.then_some(chunk.vector),
+    };
+    if params.is_export(format) {
+        let chunks = export(state, move |db| db.stream_chunks(source_id));
+        return Ok(format.stream(chunks.map(move |chunk| chunk.map(to_resp))));
+    }
+    let page = params.bounds()?;
+    let chunks = state
+        .db
+        .query_chunks_page(source_id, page.after_id, page.offset, page.limit)
+        .await
+        .context("Failed to query chunks")
+        .map_err(|err| ServerError::DbError(err))?;
+    let chunks: Vec<ChunkResp> = chunks.into_iter().map(to_resp).collect();
+    let len = chunks.len();
+    let next = chunks
+        .last()
+        .map(|chunk| Cursor::new(0.0, chunk.id.to_string()));
+    Ok(with_next_cursor(
+        format.respond(chunks),
+        len,
+        page.limit as usize,
+        next,
+    ))
 }
 
 #[allow(unused)]
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sources/{source_id}/chunks",
+    tag = "documents",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Chunks deleted"),
+    )
+)]
 pub async fn delete_chunks(
     Path(source_id): Path<i64>,
     State(state): State<AppState>,
@@ -177,6 +765,17 @@ pub async fn delete_chunks(
 }
 
 #[allow(unused)]
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sources/{source_id}/docs",
+    tag = "documents",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Documents deleted"),
+    )
+)]
 pub async fn delete_documents(
     Path(source_id): Path<i64>,
     State(state): State<AppState>,
@@ -189,105 +788,2164 @@ pub async fn delete_documents(
         .map_err(|err| ServerError::DbError(err))?;
     Ok(StatusCode::OK)
 }
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CreateSourceReq {
-    pub collection_id: i64,
-    pub owner: String,
-    pub repo: String,
-    pub branch: String,
-    pub allowed_ext: Vec<String>,
-    pub allowed_dirs: Vec<String>,
-    pub ignored_dirs: Vec<String>,
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreateCollectionReq {
+    pub name: String,
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default)]
+    #[schema(value_type = String, example = "cosine")]
+    pub distance: Distance,
+    #[serde(default = "default_dimension")]
+    pub dimension: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct CreateSourceResp {
+fn default_model() -> String {
+    DEFAULT_MODEL.to_string()
+}
+
+fn default_dimension() -> usize {
+    384
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreateCollectionResp {
     pub id: i64,
 }
 
-pub async fn create_source(
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections",
+    tag = "collections",
+    request_body = CreateCollectionReq,
+    responses(
+        (status = 201, description = "Collection created", body = CreateCollectionResp),
+        (status = 400, description = "Invalid dimension, unknown model or name taken"),
+    )
+)]
+pub async fn create_collection(
     State(state): State<AppState>,
-    Json(payload): Json<CreateSourceReq>,
-) -> Result<(StatusCode, Json<CreateSourceResp>), ServerError> {
-    tracing::info!(
-        ?payload,
-        "Creating source {}:{}:{}",
-        payload.owner,
-        payload.repo,
-        payload.branch
-    );
+    LimitedJson(payload): LimitedJson<CreateCollectionReq>,
+) -> Result<(StatusCode, Json<CreateCollectionResp>), ServerError> {
+    tracing::info!(?payload, "Creating collection {}", payload.name);
+    if payload.dimension == 0 {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Dimension must be greater than zero"
+        )));
+    }
+    if state.models.get(&payload.model).is_none() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Model '{}' is not loaded",
+            payload.model
+        )));
+    }
 
-    let source: Source = payload.into();
-    let response = CreateSourceResp { id: source.id };
-    // TODO check collection uniquiness
-    let _ = state
+    if state
+        .tinyvector
+        .read()
+        .await
+        .get_collection(&payload.name)
+        .is_some()
+    {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collection '{}' already exists",
+            payload.name
+        )));
+    }
+
+    let mut collection: Collection = payload.into();
+    collection.id = state
         .db
-        .insert_source(&source)
+        .insert_collection(&collection)
         .await
-        .context("Failed to insert source")
+        .context("Failed to insert collection")
         .map_err(|err| ServerError::DbError(err))?;
 
-    Ok((StatusCode::CREATED, Json(response)))
+    state
+        .tinyvector
+        .write()
+        .await
+        .create_collection(
+            collection.name.clone(),
+            collection.model.clone(),
+            collection.dimension,
+            collection.distance,
+        )
+        .context("Failed to create tinyvector collection")
+        .map_err(|err| ServerError::Embeddings(err))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateCollectionResp { id: collection.id }),
+    ))
 }
 
-impl From<CreateSourceReq> for Source {
-    fn from(value: CreateSourceReq) -> Self {
+impl From<CreateCollectionReq> for Collection {
+    fn from(value: CreateCollectionReq) -> Self {
         Self {
             id: 0,
-            collection_id: value.collection_id,
-            owner: value.owner,
-            repo: value.repo,
-            branch: value.branch,
-            allowed_ext: value.allowed_ext.into_iter().collect(),
-            allowed_dirs: value.allowed_dirs.into_iter().collect(),
-            ignored_dirs: value.ignored_dirs.into_iter().collect(),
+            name: value.name,
+            model: value.model,
+            distance: value.distance,
+            dimension: value.dimension,
+            index_version: 0,
             created_at: Utc::now(),
             updated_at: Utc::now(),
         }
     }
 }
 
-#[derive(Deserialize)]
-pub struct SearchQuery {
-    pub query: String,
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections",
+    tag = "collections",
+    responses(
+        (status = 200, description = "All collections", body = [Collection]),
+    )
+)]
+pub async fn list_collections(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Collection>>, ServerError> {
+    let collections = state
+        .db
+        .query_collections()
+        .await
+        .context("Failed to query collections")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(collections))
 }
 
-#[derive(Serialize)]
-pub struct SearchResp {
-    pub score: f32,
-    pub path: String,
-    pub text: String,
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "The collection", body = Collection),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn get_collection(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Collection>, ServerError> {
+    select_collection(&state, collection_id).await.map(Json)
 }
 
-pub async fn search(
-    params: Query<SearchQuery>,
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct RenameCollectionReq {
+    pub name: String,
+}
+
+/// Renames the collection, searches have to use the new name right away.
+/// Aliases keep pointing at it, its variants, snapshots and shadows are renamed
+/// along.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = RenameCollectionReq,
+    responses(
+        (status = 200, description = "The renamed collection", body = Collection),
+        (status = 400, description = "Name is empty or taken"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn rename_collection(
+    Path(collection_id): Path<i64>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<SearchResp>>, ServerError> {
-    tracing::info!("Searching '{}'", params.query);
-    let query = state
-        .embeddings
-        .encode(&[params.query.clone()])
+    LimitedJson(payload): LimitedJson<RenameCollectionReq>,
+) -> Result<Json<Collection>, ServerError> {
+    let collection = select_collection(&state, collection_id).await?;
+    tracing::info!(
+        "Renaming collection '{}' to '{}'",
+        collection.name,
+        payload.name
+    );
+    if payload.name.is_empty() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collections need a name"
+        )));
+    }
+    if payload.name == collection.name {
+        return Ok(Json(collection));
+    }
+    let shadows = pipeline::shadow_collections(&state, &collection.name)
         .await
-        .context("Failed to create embedding")
-        .map_err(|err| ServerError::Embeddings(err))?;
+        .map_err(|err| ServerError::DbError(err))?;
+    // Shadows keep the suffix `shadow_name` gave them.
+    let renames = shadows
+        .iter()
+        .map(|shadow| {
+            let name = format!("{}{}", payload.name, &shadow.name[collection.name.len()..]);
+            (shadow, name)
+        })
+        .chain([(&collection, payload.name.clone())])
+        .collect::<Vec<_>>();
+    // The write lock is held until the db agrees, so no search sees a name
+    // tinyvector doesn't know.
+    let mut tinyvector = state.tinyvector.write().await;
+    if let Some((_, name)) = renames
+        .iter()
+        .find(|(_, name)| tinyvector.get_collection(name).is_some())
+    {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collection '{}' already exists",
+            name
+        )));
+    }
+    let names = renames
+        .iter()
+        .map(|(renamed, name)| (renamed.id, name.clone()))
+        .collect::<Vec<_>>();
+    state
+        .db
+        .update_collection_names(&names)
+        .await
+        .context("Failed to rename collection")
+        .map_err(|err| ServerError::DbError(err))?;
+    state.rankings.clear();
+    // Collections without any vectors aren't loaded, those have no builds.
+    for (renamed, name) in &renames {
+        index::rename_builds(&mut tinyvector, &renamed.name, name);
+    }
+    drop(tinyvector);
+    select_collection(&state, collection_id).await.map(Json)
+}
+
+/// Deletes the collection with everything indexed in it, its variants,
+/// snapshots and the shadows its sources can be rolled back to.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/collections/{collection_id}",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "Collection deleted"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn delete_collection(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    let collection = select_collection(&state, collection_id).await?;
+    tracing::info!("Deleting collection '{}'", collection.name);
+    let mut deleted = pipeline::shadow_collections(&state, &collection.name)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    // Shadows go first, so a failure doesn't leave them without their collection.
+    deleted.push(collection);
+    for collection in &deleted {
+        state
+            .db
+            .delete_collection(collection.id)
+            .await
+            .context("Failed to delete collection")
+            .map_err(|err| ServerError::DbError(err))?;
+    }
+    state.rankings.clear();
+    let mut tinyvector = state.tinyvector.write().await;
+    for collection in &deleted {
+        state.model_checks.forget(collection.id);
+        index::delete_builds(&mut tinyvector, &collection.name);
+    }
+    Ok(StatusCode::OK)
+}
 
-    let vectors = state
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CloneCollectionReq {
+    pub name: String,
+}
+
+/// Copies the collection with everything indexed in it under a new name,
+/// e.g. to promote a staging index or to keep a snapshot before a re-index.
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{collection_id}/clone",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = CloneCollectionReq,
+    responses(
+        (status = 201, description = "Collection cloned", body = CreateCollectionResp),
+        (status = 400, description = "Name is empty or taken"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn clone_collection(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<CloneCollectionReq>,
+) -> Result<(StatusCode, Json<CreateCollectionResp>), ServerError> {
+    tracing::info!(
+        "Cloning collection #{} as '{}'",
+        collection_id,
+        payload.name
+    );
+    let collection = select_collection(&state, collection_id).await?;
+    if payload.name.is_empty() {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collections need a name"
+        )));
+    }
+    if state
         .tinyvector
         .read()
         .await
-        .get_collection("default")
-        .context("Failed to get Tinyvector collection")
-        .map_err(|err| ServerError::Embeddings(err))?
-        .get_similarity(&query[0], 10);
-
-    let mut result = Vec::with_capacity(vectors.len());
-    for n in vectors {
-        result.push(SearchResp {
-            score: n.score,
-            path: n.embedding.id,
-            text: n.embedding.blob,
-        })
+        .get_collection(&payload.name)
+        .is_some()
+    {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collection '{}' already exists",
+            payload.name
+        )));
+    }
+
+    let id = state
+        .db
+        .clone_collection(collection.id, &payload.name)
+        .await
+        .context("Failed to clone collection")
+        .map_err(|err| ServerError::DbError(err))?;
+    let clone = select_collection(&state, id).await?;
+    index::publish_collection(&state.db, &state.tinyvector, &clone)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok((StatusCode::CREATED, Json(CreateCollectionResp { id })))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct MergeCollectionReq {
+    /// Collection the sources are moved into.
+    pub into: i64,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct MergeCollectionResp {
+    pub sources: u64,
+}
+
+/// Moves the collection's sources with their chunks and vectors into another
+/// collection encoded with the same model, the emptied collection is kept.
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections/{collection_id}/merge",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = MergeCollectionReq,
+    responses(
+        (status = 200, description = "Sources moved", body = MergeCollectionResp),
+        (status = 400, description = "Collections are the same or encoded differently"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn merge_collection(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<MergeCollectionReq>,
+) -> Result<Json<MergeCollectionResp>, ServerError> {
+    tracing::info!(
+        "Merging collection #{} into #{}",
+        collection_id,
+        payload.into
+    );
+    let from = select_collection(&state, collection_id).await?;
+    let into = select_collection(&state, payload.into).await?;
+    if from.id == into.id {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Can't merge a collection into itself"
+        )));
+    }
+    if from.model != into.model
+        || from.dimension != into.dimension
+        || from.distance != into.distance
+    {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Collections '{}' and '{}' are encoded differently, re-embed one first",
+            from.name,
+            into.name
+        )));
+    }
+
+    let sources = state
+        .db
+        .merge_collection(from.id, into.id)
+        .await
+        .context("Failed to merge collections")
+        .map_err(|err| ServerError::DbError(err))?;
+    state.model_checks.forget(into.id);
+    for collection in [&from, &into] {
+        index::publish_collection(&state.db, &state.tinyvector, collection)
+            .await
+            .map_err(|err| ServerError::DbError(err))?;
     }
+    Ok(Json(MergeCollectionResp { sources }))
+}
 
-    Ok(Json(result))
+/// Returns the collection's search pipeline, the default one if it has none.
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{collection_id}/ranking",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "Search pipeline of the collection", body = Object),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn get_ranking(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<search::Pipeline>, ServerError> {
+    let collection = select_collection(&state, collection_id).await?;
+    let ranking = state
+        .db
+        .select_ranking(&collection.name)
+        .await
+        .context("Failed to select search pipeline")
+        .map_err(|err| ServerError::DbError(err))?
+        .unwrap_or_default();
+    let pipeline = match ranking.as_str() {
+        "" => search::Pipeline::default(),
+        ranking => serde_json::from_str(ranking)
+            .context("Failed to parse search pipeline")
+            .map_err(|err| ServerError::DbError(err))?,
+    };
+    Ok(Json(pipeline))
+}
+
+/// Replaces the collection's search pipeline, searches use it right away.
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections/{collection_id}/ranking",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = Object,
+    responses(
+        (status = 200, description = "The stored search pipeline", body = Object),
+        (status = 400, description = "Invalid search pipeline"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn put_ranking(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<search::Pipeline>,
+) -> Result<Json<search::Pipeline>, ServerError> {
+    tracing::info!(
+        ?payload,
+        "Setting search pipeline of collection #{}",
+        collection_id
+    );
+    payload
+        .validate()
+        .map_err(|err| ServerError::ValidationError(anyhow!(err)))?;
+    let collection = select_collection(&state, collection_id).await?;
+    let ranking = serde_json::to_string(&payload)
+        .context("Failed to serialize search pipeline")
+        .map_err(|err| ServerError::DbError(err))?;
+    state
+        .db
+        .update_ranking(collection.id, &ranking)
+        .await
+        .context("Failed to update search pipeline")
+        .map_err(|err| ServerError::DbError(err))?;
+    state.rankings.clear();
+    Ok(Json(payload))
+}
+
+/// Resets the collection to plain vector search.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/collections/{collection_id}/ranking",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "Search pipeline reset"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn delete_ranking(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    let collection = select_collection(&state, collection_id).await?;
+    state
+        .db
+        .update_ranking(collection.id, "")
+        .await
+        .context("Failed to reset search pipeline")
+        .map_err(|err| ServerError::DbError(err))?;
+    state.rankings.clear();
+    Ok(StatusCode::OK)
+}
+
+async fn select_collection(
+    state: &AppState,
+    collection_id: i64,
+) -> Result<Collection, ServerError> {
+    state
+        .db
+        .select_collection(collection_id)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => {
+                ServerError::NoContent(anyhow!("Collection does not exist"))
+            }
+            _ => ServerError::DbError(anyhow!("Failed to select collection: {}", err)),
+        })
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListSourcesQuery {
+    pub collection_id: Option<i64>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SourceEntry {
+    #[serde(flatten)]
+    pub source: Source,
+    #[serde(flatten)]
+    pub stats: SourceStats,
+}
+
+/// Lists sources with their totals, so automation can reconcile them with
+/// its configuration. Credentials are left out.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources",
+    tag = "sources",
+    params(
+        ListSourcesQuery,
+    ),
+    responses(
+        (status = 200, description = "Sources with their totals", body = [SourceEntry]),
+    )
+)]
+pub async fn list_sources(
+    Query(params): Query<ListSourcesQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SourceEntry>>, ServerError> {
+    let mut sources = state
+        .db
+        .query_sources()
+        .await
+        .context("Failed to query sources")
+        .map_err(|err| ServerError::DbError(err))?;
+    if let Some(collection_id) = params.collection_id {
+        sources.retain(|source| source.collection_id == collection_id);
+    }
+    sources.sort_by_key(|source| source.id);
+    let mut stats = state
+        .db
+        .query_source_stats()
+        .await
+        .context("Failed to query source stats")
+        .map_err(|err| ServerError::DbError(err))?;
+    let entries = sources
+        .into_iter()
+        .map(|source| SourceEntry {
+            stats: stats.remove(&source.id).unwrap_or_default(),
+            source,
+        })
+        .collect();
+    Ok(Json(entries))
+}
+
+/// The source with its filters, timestamps and totals.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{source_id}",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "The source with its totals", body = SourceEntry),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn get_source(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<SourceEntry>, ServerError> {
+    let source = select_source(&state, source_id).await?;
+    let stats = state
+        .db
+        .select_source_stats(source.id)
+        .await
+        .context("Failed to query source stats")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(SourceEntry { source, stats }))
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreateSourceReq {
+    pub collection_id: i64,
+    #[serde(default)]
+    pub kind: SourceKind,
+    /// Url the crawl starts at, required for web sources.
+    #[serde(default)]
+    pub url: String,
+    /// Owner, repo and branch are required for GitHub sources.
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub repo: String,
+    #[serde(default)]
+    pub branch: String,
+    #[serde(default)]
+    pub allowed_ext: Vec<String>,
+    #[serde(default)]
+    pub allowed_dirs: Vec<String>,
+    #[serde(default)]
+    pub ignored_dirs: Vec<String>,
+    /// Documents with front matter matching any of these are skipped,
+    /// e.g. `draft=true` or `noindex`. An empty list keeps every document.
+    #[serde(default = "encoder::default_skip_front_matter")]
+    pub skip_front_matter: Vec<String>,
+    /// Tags of all chunks of the source.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Skips translated copies of docs, like `docs/zh/`, by default.
+    #[serde(default)]
+    pub translations: Translations,
+    /// Limits of the crawl of web sources, defaults apply to omitted fields.
+    #[serde(default)]
+    pub crawl: CrawlOptions,
+    /// Prefix and credentials of bucket sources.
+    #[serde(default)]
+    pub bucket: BucketOptions,
+    /// Token of GitHub sources, for repos the server's token can't read.
+    #[serde(default)]
+    pub github: GitHubOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreateSourceResp {
+    pub id: i64,
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/sources",
+    tag = "sources",
+    request_body = CreateSourceReq,
+    responses(
+        (status = 201, description = "Source created", body = CreateSourceResp),
+        (status = 400, description = "Missing repo or url, or invalid crawl options"),
+    )
+)]
+pub async fn create_source(
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<CreateSourceReq>,
+) -> Result<(StatusCode, Json<CreateSourceResp>), ServerError> {
+    tracing::info!(
+        ?payload,
+        "Creating source {}:{}:{}",
+        payload.owner,
+        payload.repo,
+        payload.branch
+    );
+    validate_source(&payload).map_err(ServerError::ValidationError)?;
+
+    let mut source: Source = payload.into();
+    source.metadata = pipeline::repo_metadata(&state, &source).await;
+    // TODO check collection uniquiness
+    let id = state
+        .db
+        .insert_source(&source)
+        .await
+        .context("Failed to insert source")
+        .map_err(|err| ServerError::DbError(err))?;
+
+    Ok((StatusCode::CREATED, Json(CreateSourceResp { id })))
+}
+
+fn validate_source(payload: &CreateSourceReq) -> anyhow::Result<()> {
+    match payload.kind {
+        SourceKind::GitHub => {
+            if payload.owner.is_empty() || payload.repo.is_empty() || payload.branch.is_empty() {
+                return Err(anyhow!("GitHub sources need an owner, repo and branch"));
+            }
+        }
+        SourceKind::Web | SourceKind::Rustdoc | SourceKind::Mbox | SourceKind::Bucket => {
+            let valid = reqwest::Url::parse(&payload.url).map_or(false, |url| {
+                matches!(url.scheme(), "http" | "https") && url.host_str().is_some()
+            });
+            if !valid {
+                return Err(anyhow!(
+                    "{} sources need an http(s) url",
+                    payload.kind.as_str()
+                ));
+            }
+        }
+        SourceKind::Upload => {}
+    }
+    if payload.kind == SourceKind::Web {
+        if payload.crawl.max_pages == 0 || payload.crawl.max_pages > MAX_CRAWL_PAGES {
+            return Err(anyhow!(
+                "crawl.max_pages must be between 1 and {}",
+                MAX_CRAWL_PAGES
+            ));
+        }
+        if payload.crawl.max_depth > MAX_CRAWL_DEPTH {
+            return Err(anyhow!(
+                "crawl.max_depth must be at most {}",
+                MAX_CRAWL_DEPTH
+            ));
+        }
+        let patterns = payload.crawl.include.iter().chain(&payload.crawl.exclude);
+        for pattern in patterns {
+            if let Err(err) = Regex::new(pattern) {
+                return Err(anyhow!("Invalid crawl pattern '{}': {}", pattern, err));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sources created by one bulk request at most.
+const MAX_BULK_SOURCES: usize = 100;
+
+/// Repos whose metadata is fetched at once while creating sources in bulk.
+const METADATA_CONCURRENCY: usize = 8;
+
+/// Outcome of one source of a bulk creation, in the order of the request.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct BulkSourceResult {
+    pub index: usize,
+    /// Id of the created source, unset if nothing was created.
+    pub id: Option<i64>,
+    /// Why the source is invalid.
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct CreateSourcesResp {
+    pub results: Vec<BulkSourceResult>,
+}
+
+/// Creates all sources in one transaction. If any of them is invalid, or the
+/// same repo or site is given twice for a collection, none is created and the
+/// results tell which ones to fix. At most 100 sources are created at once.
+#[utoipa::path(
+    put,
+    path = "/api/v1/sources/bulk",
+    tag = "sources",
+    request_body = [CreateSourceReq],
+    responses(
+        (status = 201, description = "All sources created", body = CreateSourcesResp),
+        (status = 400, description = "Some sources are invalid or duplicates, none was created", body = CreateSourcesResp),
+    )
+)]
+pub async fn create_sources(
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<Vec<CreateSourceReq>>,
+) -> Result<(StatusCode, Json<CreateSourcesResp>), ServerError> {
+    tracing::info!("Creating {} sources", payload.len());
+    if payload.is_empty() {
+        return Err(ServerError::ValidationError(anyhow!("No sources given")));
+    }
+    if payload.len() > MAX_BULK_SOURCES {
+        return Err(ServerError::ValidationError(anyhow!(
+            "At most {} sources can be created at once",
+            MAX_BULK_SOURCES
+        )));
+    }
+
+    // Collections are checked up front to report every invalid source at once,
+    // and again by the transaction inserting the sources in case one is
+    // deleted in between.
+    let mut collections: HashMap<i64, bool> = HashMap::new();
+    for collection_id in payload.iter().map(|req| req.collection_id) {
+        if collections.contains_key(&collection_id) {
+            continue;
+        }
+        let exists = match state.db.select_collection(collection_id).await {
+            Ok(_) => true,
+            Err(sqlx::Error::RowNotFound) => false,
+            Err(err) => {
+                return Err(ServerError::DbError(
+                    anyhow!(err).context("Failed to select collection"),
+                ))
+            }
+        };
+        collections.insert(collection_id, exists);
+    }
+    let mut seen: HashMap<(i64, &str, String), usize> = HashMap::new();
+    let mut results: Vec<BulkSourceResult> = payload
+        .iter()
+        .enumerate()
+        .map(|(index, req)| {
+            let error = match validate_source(req) {
+                _ if !collections[&req.collection_id] => {
+                    Some(format!("Collection #{} does not exist", req.collection_id))
+                }
+                Err(err) => Some(err.to_string()),
+                Ok(()) => match bulk_source_key(req).map(|key| *seen.entry(key).or_insert(index)) {
+                    Some(first) if first != index => {
+                        Some(format!("Duplicate of the source at index {}", first))
+                    }
+                    _ => None,
+                },
+            };
+            BulkSourceResult {
+                index,
+                id: None,
+                error,
+            }
+        })
+        .collect();
+    if results.iter().any(|result| result.error.is_some()) {
+        return Ok((StatusCode::BAD_REQUEST, Json(CreateSourcesResp { results })));
+    }
+
+    let sources: Vec<Source> = stream::iter(payload)
+        .map(|req| {
+            let state = &state;
+            async move {
+                let mut source: Source = req.into();
+                source.metadata = pipeline::repo_metadata(state, &source).await;
+                source
+            }
+        })
+        .buffered(METADATA_CONCURRENCY)
+        .collect()
+        .await;
+    let inserted = state
+        .db
+        .insert_sources(&sources)
+        .await
+        .context("Failed to insert sources")
+        .map_err(|err| ServerError::DbError(err))?;
+    match inserted {
+        Ok(ids) => {
+            for (result, id) in results.iter_mut().zip(ids) {
+                result.id = Some(id);
+            }
+            Ok((StatusCode::CREATED, Json(CreateSourcesResp { results })))
+        }
+        Err(missing) => {
+            for (result, source) in results.iter_mut().zip(&sources) {
+                if missing.contains(&source.collection_id) {
+                    result.error = Some(format!(
+                        "Collection #{} does not exist",
+                        source.collection_id
+                    ));
+                }
+            }
+            Ok((StatusCode::BAD_REQUEST, Json(CreateSourcesResp { results })))
+        }
+    }
+}
+
+// Sources of a batch with the same key would index the same docs twice. Repos
+// are told apart by branch, the others by url, uploads have nothing to compare.
+fn bulk_source_key(req: &CreateSourceReq) -> Option<(i64, &'static str, String)> {
+    let location = match req.kind {
+        SourceKind::GitHub => format!("{}/{}/{}", req.owner, req.repo, req.branch),
+        SourceKind::Web | SourceKind::Rustdoc | SourceKind::Mbox | SourceKind::Bucket => {
+            req.url.clone()
+        }
+        SourceKind::Upload => return None,
+    };
+    Some((req.collection_id, req.kind.as_str(), location))
+}
+
+/// Fields of the source to change, the others are kept.
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct UpdateSourceReq {
+    pub branch: Option<String>,
+    pub allowed_ext: Option<Vec<String>>,
+    pub allowed_dirs: Option<Vec<String>>,
+    pub ignored_dirs: Option<Vec<String>>,
+}
+
+/// Changes the branch and filters of the source, they apply from its next parse.
+#[utoipa::path(
+    patch,
+    path = "/api/v1/sources/{source_id}",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    request_body = UpdateSourceReq,
+    responses(
+        (status = 200, description = "The updated source", body = Source),
+        (status = 400, description = "GitHub source without a branch"),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn update_source(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<UpdateSourceReq>,
+) -> Result<Json<Source>, ServerError> {
+    tracing::info!(?payload, "Updating source #{}", source_id);
+    let mut source = select_source(&state, source_id).await?;
+    if let Some(branch) = payload.branch {
+        if source.kind == SourceKind::GitHub && branch.is_empty() {
+            return Err(ServerError::ValidationError(anyhow!(
+                "GitHub sources need a branch"
+            )));
+        }
+        source.branch = branch;
+    }
+    if let Some(allowed_ext) = payload.allowed_ext {
+        source.allowed_ext = allowed_ext.into_iter().collect();
+    }
+    if let Some(allowed_dirs) = payload.allowed_dirs {
+        source.allowed_dirs = allowed_dirs.into_iter().collect();
+    }
+    if let Some(ignored_dirs) = payload.ignored_dirs {
+        source.ignored_dirs = ignored_dirs.into_iter().collect();
+    }
+    let deleted = state
+        .db
+        .update_source(&source)
+        .await
+        .context("Failed to update source")
+        .map_err(|err| ServerError::DbError(err))?;
+    if deleted > 0 {
+        tracing::info!(
+            "Deleted {} documents of source #{} its filters no longer match",
+            deleted,
+            source.id
+        );
+        let collection = select_collection(&state, source.collection_id).await?;
+        index::publish_collection(&state.db, &state.tinyvector, &collection)
+            .await
+            .map_err(|err| ServerError::DbError(err))?;
+    }
+    select_source(&state, source_id).await.map(Json)
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiscoverQuery {
+    /// Creates the proposed sources, otherwise they are only listed.
+    #[serde(default)]
+    pub create: bool,
+}
+
+/// Scans a monorepo source for packages with docs of their own and proposes,
+/// or creates, a source for each of them. The monorepo source then ignores
+/// the directories of the created ones.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sources/{source_id}/discover",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+        DiscoverQuery,
+    ),
+    responses(
+        (status = 200, description = "Sources proposed or created for packages", body = [DiscoveredSource]),
+        (status = 204, description = "Source does not exist"),
+    )
+)]
+pub async fn discover_sources(
+    Path(source_id): Path<i64>,
+    Query(query): Query<DiscoverQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DiscoveredSource>>, ServerError> {
+    let source = select_source(&state, source_id).await?;
+    let discovered = pipeline::discover_sources(&state, &source, query.create)
+        .await
+        .map_err(
+            |err| match err.chain().any(|cause| cause.is::<sqlx::Error>()) {
+                true => ServerError::DbError(err),
+                false => ServerError::GitHubAPIError(err),
+            },
+        )?;
+    Ok(Json(discovered))
+}
+
+/// Deletes the source with everything indexed from it, searches stop
+/// returning it right away, pinned index versions included.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/sources/{source_id}",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Source deleted"),
+        (status = 204, description = "Source does not exist"),
+        (status = 409, description = "A job of the source is queued or running"),
+    )
+)]
+pub async fn delete_source(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    let source = select_source(&state, source_id).await?;
+    // A parse or encode in flight would store documents of the deleted source.
+    for status in [JobStatus::Queued, JobStatus::Running] {
+        let jobs = state
+            .db
+            .query_jobs(Some(source.id), Some(status), None, 1)
+            .await
+            .context("Failed to query jobs")
+            .map_err(|err| ServerError::DbError(err))?;
+        if let Some(job) = jobs.first() {
+            return Err(ServerError::Conflict(anyhow!(
+                "Job #{} of the source is {}, cancel it first",
+                job.id,
+                status.as_str()
+            )));
+        }
+    }
+    tracing::info!("Deleting source #{}", source.id);
+    let index_version = pipeline::delete_source(&state, &source)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    tracing::info!(
+        "Deleted source #{}, published index version {}",
+        source.id,
+        index_version
+    );
+    Ok(StatusCode::OK)
+}
+
+impl From<CreateSourceReq> for Source {
+    fn from(value: CreateSourceReq) -> Self {
+        Self {
+            id: 0,
+            collection_id: value.collection_id,
+            kind: value.kind,
+            url: value.url,
+            owner: value.owner,
+            repo: value.repo,
+            branch: value.branch,
+            allowed_ext: value.allowed_ext.into_iter().collect(),
+            allowed_dirs: value.allowed_dirs.into_iter().collect(),
+            ignored_dirs: value.ignored_dirs.into_iter().collect(),
+            skip_front_matter: value.skip_front_matter.into_iter().collect(),
+            labels: value.labels.into_iter().collect(),
+            translations: value.translations,
+            crawl: value.crawl,
+            bucket: value.bucket,
+            github: value.github,
+            metadata: RepoMetadata::default(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+pub const SEARCH_PARTIAL_HEADER: &str = "x-search-partial";
+pub const INDEX_VERSION_HEADER: &str = "x-index-version";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    pub query: String,
+    pub collection: Option<String>,
+    /// Searches vectors of a candidate model instead of the primary ones.
+    pub model: Option<String>,
+    /// Token from `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub strategy: ask::Strategy,
+    /// Searches a retained build of the collection instead of the live one.
+    pub index_version: Option<i64>,
+    /// Adds what each stage of the search pipeline contributed to the results.
+    #[serde(default)]
+    pub debug: bool,
+    /// Adds counts of the best matches per source, directory, tag and language.
+    #[serde(default)]
+    pub facets: bool,
+}
+
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+/// Number of best matches facets are counted over.
+const FACET_DEPTH: usize = 100;
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResults {
+    /// Index version of the collection build that was searched.
+    pub index_version: i64,
+    /// Set when the search deadline passed before the whole collection was scanned.
+    pub partial: bool,
+    pub results: Vec<SearchResp>,
+    /// Set when there may be more results, pass it as `cursor` to get the next page.
+    pub next_cursor: Option<String>,
+    /// Spelling corrected query, suggested on the first page only.
+    pub did_you_mean: Option<String>,
+    /// Counted on the first page only, when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Facets>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResp {
+    pub score: f32,
+    pub path: String,
+    pub chunk_index: usize,
+    pub text: String,
+    /// Anchor of the heading the chunk starts at, empty if it doesn't start at one.
+    pub anchor: String,
+    /// Link to the chunk's section of the file on GitHub, empty for other sources.
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stages: Option<Vec<search::Contribution>>,
+    /// Where the chunk comes from, `/api/v1` only and not in CSV rows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<ResultMetadata>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ResultMetadata {
+    pub source_id: i64,
+    pub document_id: i64,
+    pub tags: Vec<String>,
+    /// Language of chunks of translated documents, empty for the others.
+    pub language: String,
+    /// Nav sections of the document in the docs site, see `Document::nav_path`.
+    pub nav_path: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/search",
+    tag = "search",
+    params(
+        SearchQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Page of results, NDJSON and CSV carry the results only",
+            content(
+                ("application/json" = SearchResults),
+                ("application/x-ndjson" = SearchResp),
+                ("text/csv" = SearchResp),
+            ),
+            headers(
+                ("x-next-cursor" = String, description = "Cursor of the next page, NDJSON and CSV only"),
+                ("x-search-partial" = bool, description = "Set when the deadline passed, NDJSON and CSV only"),
+                ("x-index-version" = i64, description = "Index version searched, NDJSON and CSV only"),
+                ("etag" = String),
+            ),
+        ),
+        (status = 304, description = "Results didn't change since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid cursor or index version"),
+    )
+)]
+pub async fn search(
+    Query(params): Query<SearchQuery>,
+    headers: HeaderMap,
+    format: Format,
+    version: ApiVersion,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    run_search(
+        params,
+        headers,
+        format,
+        version,
+        state,
+        search::Mode::Collection,
+    )
+    .await
+}
+
+/// Same as `search`, but fuses vector, keyword and title retrieval by rank
+/// instead of running the collection's pipeline.
+#[utoipa::path(
+    get,
+    path = "/api/v1/search/hybrid",
+    tag = "search",
+    params(
+        SearchQuery,
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Page of results, NDJSON and CSV carry the results only",
+            content(
+                ("application/json" = SearchResults),
+                ("application/x-ndjson" = SearchResp),
+                ("text/csv" = SearchResp),
+            ),
+            headers(
+                ("x-next-cursor" = String, description = "Cursor of the next page, NDJSON and CSV only"),
+                ("x-search-partial" = bool, description = "Set when the deadline passed, NDJSON and CSV only"),
+                ("x-index-version" = i64, description = "Index version searched, NDJSON and CSV only"),
+                ("etag" = String),
+            ),
+        ),
+        (status = 304, description = "Results didn't change since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid cursor or index version"),
+    )
+)]
+pub async fn hybrid_search(
+    Query(params): Query<SearchQuery>,
+    headers: HeaderMap,
+    format: Format,
+    version: ApiVersion,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    run_search(
+        params,
+        headers,
+        format,
+        version,
+        state,
+        search::Mode::Hybrid,
+    )
+    .await
+}
+
+/// Searches the collection, results of unversioned routes keep the fields
+/// they had before `/api/v1`.
+pub async fn run_search(
+    params: SearchQuery,
+    headers: HeaderMap,
+    format: Format,
+    version: ApiVersion,
+    state: AppState,
+    mode: search::Mode,
+) -> Result<Response, ServerError> {
+    let name = params.collection.as_deref().unwrap_or("default");
+    tracing::info!("Searching '{}' in '{}'", params.query, name);
+    let after = match &params.cursor {
+        Some(token) => Some(
+            Cursor::decode(token)
+                .ok_or_else(|| ServerError::ValidationError(anyhow!("Invalid cursor")))?,
+        ),
+        None => None,
+    };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    let collection = match params.index_version {
+        Some(_) if params.model.is_some() => {
+            return Err(ServerError::ValidationError(anyhow!(
+                "Candidate models can't be searched at a pinned index version"
+            )))
+        }
+        Some(index_version) => index::resolve_snapshot(&state.tinyvector, name, index_version)
+            .await
+            .ok_or_else(|| {
+                ServerError::ValidationError(anyhow!(
+                    "Index version {} of '{}' is not retained",
+                    index_version,
+                    name
+                ))
+            })?,
+        None => index::resolve_collection(&state.tinyvector, name, params.model.as_deref())
+            .await
+            .context("Failed to get Tinyvector collection")
+            .map_err(|err| ServerError::Embeddings(err))?,
+    };
+    // Expanded before the ETag, so changing aliases invalidates cached results.
+    let expanded = aliases::expand(&state, name, &params.query)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    let pipeline = match mode {
+        search::Mode::Hybrid => Some(search::Pipeline::hybrid()),
+        search::Mode::Collection => state
+            .rankings
+            .get(&state.db, name)
+            .await
+            .context("Failed to select search pipeline")
+            .map_err(|err| ServerError::DbError(err))?,
+    };
+    let etag = etag::etag(&format!(
+        "search:{}:{}:{}:{:?}:{:?}:{:?}:{}:{}:{}:{}:{:?}:{}",
+        name,
+        collection.model,
+        collection.version,
+        format,
+        version,
+        params.strategy,
+        params.cursor.as_deref().unwrap_or_default(),
+        limit,
+        params.debug,
+        params.facets,
+        pipeline,
+        expanded
+    ));
+    if etag::is_fresh(&headers, &etag) {
+        return Ok(negotiate::vary(etag::not_modified(&etag)));
+    }
+
+    let mut did_you_mean = None;
+    // Following pages repeat the query, only the first one is logged.
+    if after.is_none() {
+        did_you_mean = spelling::suggest(&state, name, collection.clone(), &params.query)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("Failed to suggest spelling: {:?}", err);
+                None
+            });
+        if let Err(err) = state
+            .db
+            .insert_query_log(name, &collection.model, &params.query)
+            .await
+        {
+            tracing::warn!("Failed to log query: {}", err);
+        }
+    }
+
+    let text = ask::query_text(&state, &expanded, params.strategy)
+        .await
+        .map_err(|err| ServerError::Embeddings(err))?;
+    // The deadline covers the scan, not drafting a HyDE document.
+    let deadline = Instant::now() + state.cfg.search_deadline;
+    let query = state
+        .embeddings(&collection.model)
+        .map_err(|err| ServerError::Embeddings(err))?
+        .encode(&[text])
+        .await
+        .context("Failed to create embedding")
+        .map_err(|err| ServerError::Embeddings(err))?;
+    let after = after
+        .as_ref()
+        .map(|cursor| (cursor.score, cursor.id.as_str()));
+    // Collections with a pipeline run through its stages, others are only
+    // scanned for the nearest vectors.
+    // Filters don't change between pages, and only JSON has room for them.
+    let want_facets = params.facets && after.is_none() && format == Format::Json;
+    let mut facets = None;
+    let (mut ranked, partial) = match pipeline {
+        Some(pipeline) => {
+            let query = search::Query {
+                vector: &query[0],
+                text: &expanded,
+                deadline: Some(deadline),
+            };
+            let (ranked, partial) = search::run(&pipeline, &collection, &query);
+            if want_facets {
+                facets = Some(Facets::count(
+                    ranked.iter().take(FACET_DEPTH).map(|r| &r.result.embedding),
+                ));
+            }
+            (search::page(ranked, limit, after), partial)
+        }
+        None => {
+            // Facets are counted over the best results of the same scan.
+            let depth = match want_facets {
+                true => FACET_DEPTH,
+                false => 0,
+            };
+            let (vectors, best, partial) = collection.get_similarity_page_and_best(
+                &query[0],
+                limit,
+                after,
+                depth,
+                Some(deadline),
+                index::boost_tag(&expanded),
+            );
+            if want_facets {
+                facets = Some(Facets::count(best.iter().map(|r| &r.embedding)));
+            }
+            let ranked = vectors
+                .into_iter()
+                .map(|result| search::Ranked {
+                    contributions: vec![search::Contribution {
+                        stage: "vector_retrieve",
+                        score: result.score,
+                    }],
+                    result,
+                })
+                .collect();
+            (ranked, partial)
+        }
+    };
+    let next_cursor = match ranked.last() {
+        Some(last) if ranked.len() == limit => {
+            Some(Cursor::new(last.result.score, last.result.embedding.id.clone()).encode())
+        }
+        _ => None,
+    };
+    if partial {
+        tracing::warn!("Search deadline exceeded, returning partial results");
+    }
+    // Reordered within the page only, the cursor stays at the last result ranked.
+    search::order_guides(&mut ranked, |r| {
+        let metadata = &r.result.embedding.metadata;
+        let guide = metadata
+            .nav_path
+            .split(NAV_SEPARATOR)
+            .next()
+            .filter(|guide| !guide.is_empty())?;
+        Some(((metadata.source_id, guide), metadata.nav_index?))
+    });
+
+    // CSV rows can't hold the list of stages or the metadata.
+    let debug = params.debug && format != Format::Csv;
+    let with_metadata = version != ApiVersion::Unversioned && format != Format::Csv;
+    let mut results = Vec::with_capacity(ranked.len());
+    for n in ranked {
+        let metadata = n.result.embedding.metadata;
+        let result_metadata = with_metadata.then(|| ResultMetadata {
+            source_id: metadata.source_id,
+            document_id: metadata.document_id,
+            tags: metadata.tags.clone(),
+            language: metadata.language.clone(),
+            nav_path: metadata.nav_path.clone(),
+        });
+        let url = match (metadata.url.is_empty(), metadata.anchor.is_empty()) {
+            (false, false) => format!("{}#{}", metadata.url, metadata.anchor),
+            _ => metadata.url,
+        };
+        results.push(SearchResp {
+            score: n.result.score,
+            path: metadata.path,
+            chunk_index: metadata.chunk_index,
+            text: n.result.embedding.blob,
+            anchor: metadata.anchor,
+            url,
+            stages: debug.then_some(n.contributions),
+            metadata: result_metadata,
+        })
+    }
+
+    // NDJSON and CSV carry only the results, partial searches and the next cursor
+    // are passed in headers.
+    let resp = match format {
+        Format::Json => Json(SearchResults {
+            index_version: collection.index_version,
+            partial,
+            results,
+            next_cursor,
+            did_you_mean,
+            facets,
+        })
+        .into_response(),
+        _ => {
+            let resp = (
+                [
+                    (SEARCH_PARTIAL_HEADER, partial.to_string()),
+                    (INDEX_VERSION_HEADER, collection.index_version.to_string()),
+                ],
+                format.respond(results),
+            );
+            match next_cursor {
+                Some(cursor) => ([(NEXT_CURSOR_HEADER, cursor)], resp).into_response(),
+                None => resp.into_response(),
+            }
+        }
+    };
+    // Partial results depend on timing, so they must not be cached.
+    if partial {
+        return Ok(negotiate::vary(resp));
+    }
+    Ok(negotiate::vary(etag::with_etag(&etag, resp)))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LookupQuery {
+    pub symbol: String,
+    pub collection: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LookupResp {
+    #[serde(rename = "match")]
+    pub kind: lookup::MatchKind,
+    /// Similarity of the best chunk, set for vector matches only.
+    pub score: Option<f32>,
+    pub document_id: i64,
+    pub path: String,
+    pub url: String,
+    pub data: String,
+}
+
+/// Returns the single best page for a symbol, for editor hover and goto-docs integrations.
+#[utoipa::path(
+    get,
+    path = "/api/v1/lookup",
+    tag = "search",
+    params(
+        LookupQuery,
+    ),
+    responses(
+        (status = 200, description = "Best page for the symbol", body = LookupResp),
+        (status = 204, description = "No page found for the symbol"),
+    )
+)]
+pub async fn lookup(
+    params: Query<LookupQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<LookupResp>, ServerError> {
+    let name = params.collection.as_deref().unwrap_or("default");
+    tracing::info!("Looking up '{}' in '{}'", params.symbol, name);
+    let found = lookup::lookup(&state, name, &params.symbol)
+        .await
+        .map_err(|err| ServerError::Embeddings(err))?
+        .ok_or_else(|| ServerError::NoContent(anyhow!("No page found for the symbol")))?;
+    let source = select_source(&state, found.document.source_id).await?;
+    Ok(Json(LookupResp {
+        kind: found.kind,
+        score: found.score,
+        document_id: found.document.id,
+        url: format!(
+            "https://github.com/{}/{}/blob/{}/{}",
+            source.owner, source.repo, source.branch, found.document.path
+        ),
+        path: found.document.path,
+        data: found.document.data,
+    }))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct AskReq {
+    pub question: String,
+    pub collection: Option<String>,
+    #[serde(default)]
+    pub strategy: ask::Strategy,
+}
+
+/// Answers the question from the collection's docs, with citations verified
+/// against the chunks they point at.
+#[utoipa::path(
+    post,
+    path = "/api/v1/ask",
+    tag = "search",
+    request_body = AskReq,
+    responses(
+        (status = 200, description = "Answer with verified citations", body = Object),
+    )
+)]
+pub async fn ask(
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<AskReq>,
+) -> Result<Json<ask::Answer>, ServerError> {
+    let name = payload.collection.as_deref().unwrap_or("default");
+    tracing::info!("Answering '{}' from '{}'", payload.question, name);
+    let answer = ask::answer(&state, name, &payload.question, payload.strategy)
+        .await
+        .map_err(|err| ServerError::Embeddings(err))?;
+    Ok(Json(answer))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{collection_id}/aliases",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "Aliases of the collection", body = [Alias]),
+    )
+)]
+pub async fn list_aliases(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Alias>>, ServerError> {
+    let aliases = state
+        .db
+        .query_aliases(collection_id)
+        .await
+        .context("Failed to query aliases")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(aliases))
+}
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct AliasReq {
+    pub alias: String,
+    pub expansion: String,
+}
+
+/// Adds an alias expanded in the collection's queries, replacing an existing one.
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections/{collection_id}/aliases",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = AliasReq,
+    responses(
+        (status = 200, description = "Alias stored"),
+        (status = 400, description = "Alias isn't a single word"),
+        (status = 404, description = "Collection does not exist"),
+    )
+)]
+pub async fn put_alias(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<AliasReq>,
+) -> Result<StatusCode, ServerError> {
+    tracing::info!(?payload, "Setting alias for collection #{}", collection_id);
+    let alias = payload.alias.trim();
+    if alias.is_empty() || alias.contains(char::is_whitespace) {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Alias must be a single word"
+        )));
+    }
+    state
+        .db
+        .select_collection(collection_id)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => ServerError::NotFound(anyhow!("Collection does not exist")),
+            _ => ServerError::DbError(anyhow!("Failed to select collection: {}", err)),
+        })?;
+    state
+        .db
+        .upsert_alias(&Alias {
+            collection_id,
+            alias: alias.to_string(),
+            expansion: payload.expansion.trim().to_string(),
+            created_at: Utc::now(),
+        })
+        .await
+        .context("Failed to upsert alias")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/collections/{collection_id}/aliases/{alias}",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+        ("alias" = String, Path, description = "The aliased word"),
+    ),
+    responses(
+        (status = 200, description = "Alias deleted"),
+    )
+)]
+pub async fn delete_alias(
+    Path((collection_id, alias)): Path<(i64, String)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    state
+        .db
+        .delete_alias(collection_id, &alias)
+        .await
+        .context("Failed to delete alias")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(StatusCode::OK)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{collection_id}/tokens",
+    tag = "tokens",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    responses(
+        (status = 200, description = "Tokens of the collection, without their secrets", body = [AccessToken]),
+    )
+)]
+pub async fn list_access_tokens(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AccessToken>>, ServerError> {
+    let tokens = state
+        .db
+        .query_access_tokens(collection_id)
+        .await
+        .context("Failed to query access tokens")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(tokens))
+}
+
+/// Requests per minute of tokens created without a limit.
+const DEFAULT_TOKEN_RATE: u32 = 60;
+
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct CreateAccessTokenReq {
+    pub name: String,
+    pub requests_per_minute: Option<u32>,
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CreateAccessTokenResp {
+    pub id: i64,
+    /// Shown only once, only its hash is stored.
+    pub token: String,
+}
+
+/// Issues a token searching only this collection through `/public/search`,
+/// from the allowed origins if any are given.
+#[utoipa::path(
+    put,
+    path = "/api/v1/collections/{collection_id}/tokens",
+    tag = "tokens",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+    ),
+    request_body = CreateAccessTokenReq,
+    responses(
+        (status = 201, description = "Token issued", body = CreateAccessTokenResp),
+        (status = 400, description = "Invalid rate or origin"),
+        (status = 204, description = "Collection does not exist"),
+    )
+)]
+pub async fn create_access_token(
+    Path(collection_id): Path<i64>,
+    State(state): State<AppState>,
+    LimitedJson(payload): LimitedJson<CreateAccessTokenReq>,
+) -> Result<(StatusCode, Json<CreateAccessTokenResp>), ServerError> {
+    tracing::info!(
+        ?payload,
+        "Creating access token for collection #{}",
+        collection_id
+    );
+    let requests_per_minute = payload.requests_per_minute.unwrap_or(DEFAULT_TOKEN_RATE);
+    if requests_per_minute == 0 {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Tokens have to allow at least one request per minute"
+        )));
+    }
+    let allowed_origins = payload
+        .allowed_origins
+        .iter()
+        .map(|origin| access::normalize_origin(origin))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ServerError::ValidationError(err))?;
+    let collection = select_collection(&state, collection_id).await?;
+    let token = access::generate_token();
+    let id = state
+        .db
+        .insert_access_token(&AccessToken {
+            id: 0,
+            collection_id: collection.id,
+            name: payload.name.trim().to_string(),
+            token_hash: access::hash_token(&token),
+            requests_per_minute,
+            allowed_origins,
+            created_at: Utc::now(),
+        })
+        .await
+        .context("Failed to insert access token")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateAccessTokenResp { id, token }),
+    ))
+}
+
+/// Revokes the token, searches using it are rejected right away.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/tokens/{token_id}",
+    tag = "tokens",
+    params(
+        ("token_id" = i64, Path, description = "Id of the access token"),
+    ),
+    responses(
+        (status = 200, description = "Token revoked"),
+        (status = 204, description = "Access token does not exist"),
+    )
+)]
+pub async fn delete_access_token(
+    Path(token_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, ServerError> {
+    let deleted = state
+        .db
+        .delete_access_token(token_id)
+        .await
+        .context("Failed to delete access token")
+        .map_err(|err| ServerError::DbError(err))?;
+    match deleted {
+        true => Ok(StatusCode::OK),
+        false => Err(ServerError::NoContent(anyhow!(
+            "Access token does not exist"
+        ))),
+    }
+}
+
+const DEFAULT_AUDIT_LIMIT: i64 = 100;
+const MAX_AUDIT_LIMIT: i64 = 1000;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    /// Method and route, e.g. `PUT /api/sources`.
+    pub action: Option<String>,
+    /// Id of the last entry of the previous page.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Lists audited requests, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/audit",
+    tag = "audit",
+    params(
+        AuditQuery,
+    ),
+    responses(
+        (status = 200, description = "Audited requests, newest first", body = [AuditEntry]),
+    )
+)]
+pub async fn list_audit_log(
+    Query(params): Query<AuditQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AuditEntry>>, ServerError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LIMIT)
+        .clamp(1, MAX_AUDIT_LIMIT);
+    let entries = state
+        .db
+        .query_audit_log(
+            params.actor.as_deref(),
+            params.action.as_deref(),
+            params.before,
+            limit,
+        )
+        .await
+        .context("Failed to query audit log")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(entries))
+}
+
+const DEFAULT_JOBS_LIMIT: i64 = 100;
+const MAX_JOBS_LIMIT: i64 = 1000;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobsQuery {
+    pub source_id: Option<i64>,
+    pub status: Option<JobStatus>,
+    /// Id of the last job of the previous page.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Lists parse and encode jobs, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs",
+    tag = "jobs",
+    params(
+        JobsQuery,
+    ),
+    responses(
+        (status = 200, description = "Jobs, newest first", body = [Job]),
+    )
+)]
+pub async fn list_jobs(
+    Query(params): Query<JobsQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Job>>, ServerError> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_JOBS_LIMIT)
+        .clamp(1, MAX_JOBS_LIMIT);
+    let jobs = state
+        .db
+        .query_jobs(params.source_id, params.status, params.before, limit)
+        .await
+        .context("Failed to query jobs")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(jobs))
+}
+
+/// Returns the job's status, and its report or error once it's finished.
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{job_id}",
+    tag = "jobs",
+    params(
+        ("job_id" = i64, Path, description = "Id of the job"),
+    ),
+    responses(
+        (status = 200, description = "The job", body = Job),
+        (status = 204, description = "Job does not exist"),
+    )
+)]
+pub async fn get_job(
+    Path(job_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Job>, ServerError> {
+    state
+        .db
+        .select_job(job_id)
+        .await
+        .map(Json)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => ServerError::NoContent(anyhow!("Job does not exist")),
+            _ => ServerError::DbError(anyhow!("Failed to select job: {}", err)),
+        })
+}
+
+/// Cancels a queued or running job, it stops before the next file or document
+/// and is marked cancelled.
+#[utoipa::path(
+    post,
+    path = "/api/v1/jobs/{job_id}/cancel",
+    tag = "jobs",
+    params(
+        ("job_id" = i64, Path, description = "Id of the job"),
+    ),
+    responses(
+        (status = 202, description = "Cancellation requested", body = JobResp),
+        (status = 400, description = "Job is already finished"),
+        (status = 204, description = "Job does not exist"),
+    )
+)]
+pub async fn cancel_job(
+    Path(job_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<(StatusCode, Json<JobResp>), ServerError> {
+    let job = state.db.select_job(job_id).await.map_err(|err| match err {
+        sqlx::Error::RowNotFound => ServerError::NoContent(anyhow!("Job does not exist")),
+        _ => ServerError::DbError(anyhow!("Failed to select job: {}", err)),
+    })?;
+    if job.status.is_finished() || !state.cancellations.cancel(job_id) {
+        return Err(ServerError::ValidationError(anyhow!(
+            "Job #{} is already {}",
+            job_id,
+            job.status.as_str()
+        )));
+    }
+    Ok((StatusCode::ACCEPTED, Json(JobResp { job_id })))
+}
+
+/// Returns the document with its data as it was ingested, e.g. to compare it
+/// with the file in the repo.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{document_id}",
+    tag = "documents",
+    params(
+        ("document_id" = i64, Path, description = "Id of the document"),
+    ),
+    responses(
+        (status = 200, description = "The document with its data", body = Document),
+        (status = 204, description = "Document does not exist"),
+    )
+)]
+pub async fn get_document(
+    Path(document_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Document>, ServerError> {
+    state
+        .db
+        .select_document_by_id(document_id)
+        .await
+        .map(Json)
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => ServerError::NoContent(anyhow!("Document does not exist")),
+            _ => ServerError::DbError(anyhow!("Failed to select document: {}", err)),
+        })
+}
+
+/// Returns the document's headings as a table of contents.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{document_id}/toc",
+    tag = "documents",
+    params(
+        ("document_id" = i64, Path, description = "Id of the document"),
+    ),
+    responses(
+        (status = 200, description = "Headings of the document", body = [Heading]),
+    )
+)]
+pub async fn document_toc(
+    Path(document_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Heading>>, ServerError> {
+    let headings = state
+        .db
+        .query_headings_by_document(document_id)
+        .await
+        .context("Failed to query headings")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(headings))
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct LinkResp {
+    pub url: String,
+    /// Repo path the link points at, unset for external links.
+    pub path: Option<String>,
+    /// Id of the linked document, unset if it isn't indexed.
+    pub document_id: Option<i64>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct DocumentLinksResp {
+    pub outbound: Vec<LinkResp>,
+    /// Links from other documents, their count is used as a ranking prior.
+    pub inbound: Vec<LinkResp>,
+}
+
+/// Returns links of the document and links pointing at it.
+#[utoipa::path(
+    get,
+    path = "/api/v1/documents/{document_id}/links",
+    tag = "documents",
+    params(
+        ("document_id" = i64, Path, description = "Id of the document"),
+    ),
+    responses(
+        (status = 200, description = "Links of and to the document", body = DocumentLinksResp),
+    )
+)]
+pub async fn document_links(
+    Path(document_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<DocumentLinksResp>, ServerError> {
+    let outbound = state
+        .db
+        .query_links_from(document_id)
+        .await
+        .context("Failed to query outbound links")
+        .map_err(|err| ServerError::DbError(err))?
+        .into_iter()
+        .map(|(link, target_id)| LinkResp {
+            url: link.url,
+            path: link.target_path,
+            document_id: target_id,
+        })
+        .collect();
+    let inbound = state
+        .db
+        .query_links_to(document_id)
+        .await
+        .context("Failed to query inbound links")
+        .map_err(|err| ServerError::DbError(err))?;
+    let mut linking = Vec::with_capacity(inbound.len());
+    for link in inbound {
+        let path = state
+            .db
+            .select_document_by_id(link.document_id)
+            .await
+            .context("Failed to select linking document")
+            .map_err(|err| ServerError::DbError(err))?
+            .path;
+        linking.push(LinkResp {
+            url: link.url,
+            path: Some(path),
+            document_id: Some(link.document_id),
+        });
+    }
+    Ok(Json(DocumentLinksResp {
+        outbound,
+        inbound: linking,
+    }))
+}
+
+/// Returns relative links of the source pointing at missing documents and
+/// external links that failed their last check.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{source_id}/broken-links",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Broken links of the source", body = [BrokenLink]),
+    )
+)]
+pub async fn broken_links(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BrokenLink>>, ServerError> {
+    let broken = links::broken_links(&state.db, source_id)
+        .await
+        .context("Failed to query broken links")
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(broken))
+}
+
+/// Returns public items of the source's code that no document of the collection mentions.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sources/{source_id}/coverage",
+    tag = "sources",
+    params(
+        ("source_id" = i64, Path, description = "Id of the source"),
+    ),
+    responses(
+        (status = 200, description = "Public items no document mentions", body = Object),
+    )
+)]
+pub async fn source_coverage(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Json<CoverageReport>, ServerError> {
+    let report = coverage::coverage(&state.db, &state.coverage, source_id)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    Ok(Json(report))
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeltaQuery {
+    /// Version of the replica, a full snapshot is returned without it.
+    pub since: Option<u64>,
+}
+
+/// Returns embeddings of the collection changed since the replica's version.
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{collection_id}/delta",
+    tag = "collections",
+    params(
+        ("collection_id" = i64, Path, description = "Id of the collection"),
+        DeltaQuery,
+    ),
+    responses(
+        (status = 200, description = "Embeddings changed since the version", body = Object),
+        (status = 204, description = "Collection isn't loaded"),
+    )
+)]
+pub async fn collection_delta(
+    Path(collection_id): Path<i64>,
+    params: Query<DeltaQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Delta>, ServerError> {
+    let collection = state
+        .db
+        .select_collection(collection_id)
+        .await
+        .context("Failed to select collection")
+        .map_err(|err| ServerError::DbError(err))?;
+    let data = state
+        .tinyvector
+        .read()
+        .await
+        .get_collection(&collection.name)
+        .ok_or_else(|| anyhow!("Collection '{}' isn't loaded", collection.name))
+        .map_err(|err| ServerError::NoContent(err))?;
+    Ok(Json(data.delta(params.since)))
 }
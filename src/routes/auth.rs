@@ -0,0 +1,314 @@
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{AppendHeaders, IntoResponse, Redirect, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{
+    access,
+    errors::ServerError,
+    oidc::{self, Provider},
+    types::{Role, Session},
+    AppState,
+};
+
+const SESSION_COOKIE: &str = "rtfm_session";
+
+/// Holds the login's state until the provider redirects back, with the path
+/// to return to.
+const LOGIN_COOKIE: &str = "rtfm_login";
+
+const SESSION_HOURS: i64 = 12;
+
+/// Login attempts not finished within this many seconds have to start over.
+const LOGIN_SECS: u32 = 600;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().nest(
+        "/auth",
+        Router::new()
+            .route("/login", get(login))
+            .route("/callback", get(callback))
+            .route("/logout", get(logout))
+            .route("/me", get(me)),
+    )
+}
+
+/// Redirects requests without a session to the login, when OIDC is configured.
+/// The user of the session is passed on as `X-Actor`, for the audit log.
+pub async fn require_session<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    authorize(&state, Role::Viewer, true, req, next).await
+}
+
+/// Lets only admins through, when OIDC is configured. Requests without a
+/// session are rejected rather than redirected, they come from scripts.
+pub async fn require_admin<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    authorize(&state, Role::Admin, false, req, next).await
+}
+
+// Passes requests of sessions with at least the role on, with the user as
+// `X-Actor`. Requests without a session are sent to the login if `login` is
+// set, rejected otherwise.
+async fn authorize<B>(
+    state: &AppState,
+    role: Role,
+    login: bool,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if state.cfg.oidc.is_none() {
+        return next.run(req).await;
+    }
+    match session(state, req.headers()).await {
+        Ok(Some(session)) if session.role < role => ServerError::Forbidden(anyhow!(
+            "'{}' is a {}, {} is needed",
+            session.email,
+            session.role.as_str(),
+            role.as_str()
+        ))
+        .into_response(),
+        Ok(Some(session)) => {
+            set_actor(&mut req, &session);
+            next.run(req).await
+        }
+        Ok(None) if !login => ServerError::Unauthorized(anyhow!("Not signed in")).into_response(),
+        Ok(None) => {
+            let path = req
+                .uri()
+                .path_and_query()
+                .map(|pq| pq.as_str())
+                .unwrap_or_default();
+            // Only used to encode the query, the redirect stays relative.
+            let mut login =
+                reqwest::Url::parse("http://localhost/auth/login").expect("Login url is valid");
+            login.query_pairs_mut().append_pair("next", path);
+            let login = format!("{}?{}", login.path(), login.query().unwrap_or_default());
+            Redirect::to(&login).into_response()
+        }
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Lets viewers through, when OIDC is configured. Unlike `require_session`,
+/// requests without a session are rejected, e.g. of scripts and websockets.
+pub async fn require_viewer<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    authorize(&state, Role::Viewer, false, req, next).await
+}
+
+/// Lets viewers read and admins change state, when OIDC is configured.
+/// Requests without a session are rejected.
+pub async fn require_role<B>(
+    State(state): State<AppState>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let role = match *req.method() {
+        Method::GET | Method::HEAD | Method::OPTIONS => Role::Viewer,
+        _ => Role::Admin,
+    };
+    authorize(&state, role, false, req, next).await
+}
+
+fn set_actor<B>(req: &mut Request<B>, session: &Session) {
+    if let Ok(actor) = HeaderValue::from_str(&session.email) {
+        req.headers_mut()
+            .insert(HeaderName::from_static("x-actor"), actor);
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LoginQuery {
+    pub next: Option<String>,
+}
+
+/// Sends the user to the provider to sign in.
+pub async fn login(
+    Query(params): Query<LoginQuery>,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let provider = provider(&state).await?;
+    let login_state = uuid::Uuid::new_v4().simple().to_string();
+    let url = provider
+        .authorization_url(&redirect_uri(&state), &login_state)
+        .map_err(|err| ServerError::BadGateway(err))?;
+    let next = oidc::safe_next(params.next.as_deref());
+    let cookie = format!(
+        "{}={}.{}; Path=/auth; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        LOGIN_COOKIE,
+        login_state,
+        hex::encode(next),
+        LOGIN_SECS,
+        secure(&state)
+    );
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(url.as_str())).into_response())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Signs the user in with the code the provider redirected back with.
+pub async fn callback(
+    Query(params): Query<CallbackQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    if let Some(error) = params.error {
+        return Err(ServerError::Unauthorized(anyhow!(
+            "Provider refused the login: {}",
+            error
+        )));
+    }
+    // The state ties the redirect to the browser that started the login.
+    let (login_state, next) = oidc::cookie(&headers, LOGIN_COOKIE)
+        .and_then(|value| {
+            let (login_state, next) = value.split_once('.')?;
+            let next = String::from_utf8(hex::decode(next).ok()?).ok()?;
+            Some((login_state.to_string(), next))
+        })
+        .ok_or_else(|| ServerError::Unauthorized(anyhow!("Login expired, sign in again")))?;
+    if params.state.as_deref() != Some(login_state.as_str()) {
+        return Err(ServerError::Unauthorized(anyhow!("Login state mismatch")));
+    }
+    let code = params
+        .code
+        .ok_or_else(|| ServerError::ValidationError(anyhow!("Missing authorization code")))?;
+
+    let provider = provider(&state).await?;
+    let user = provider
+        .user_info(&code, &redirect_uri(&state))
+        .await
+        .map_err(|err| ServerError::Unauthorized(err))?;
+    let cfg = state.cfg.oidc.as_ref().expect("OIDC is configured");
+    let role = oidc::role_for(cfg, &user.groups).ok_or_else(|| {
+        ServerError::Forbidden(anyhow!(
+            "'{}' isn't in a group allowed to sign in",
+            user.email
+        ))
+    })?;
+
+    let token = format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    );
+    let now = Utc::now();
+    let session = Session {
+        id_hash: access::hash_token(&token),
+        subject: user.subject,
+        email: user.email,
+        role,
+        created_at: now,
+        expires_at: now + chrono::Duration::hours(SESSION_HOURS),
+    };
+    state
+        .db
+        .insert_session(&session)
+        .await
+        .context("Failed to insert session")
+        .map_err(|err| ServerError::DbError(err))?;
+    tracing::info!("'{}' signed in as {}", session.email, role.as_str());
+
+    let session_cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        SESSION_COOKIE,
+        token,
+        SESSION_HOURS * 3600,
+        secure(&state)
+    );
+    let login_cookie = format!("{}=; Path=/auth; Max-Age=0", LOGIN_COOKIE);
+    Ok((
+        AppendHeaders([
+            (header::SET_COOKIE, session_cookie),
+            (header::SET_COOKIE, login_cookie),
+        ]),
+        Redirect::to(oidc::safe_next(Some(&next))),
+    )
+        .into_response())
+}
+
+pub async fn logout(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    if let Some(token) = oidc::cookie(&headers, SESSION_COOKIE) {
+        state
+            .db
+            .delete_session(&access::hash_token(&token))
+            .await
+            .context("Failed to delete session")
+            .map_err(|err| ServerError::DbError(err))?;
+    }
+    let cookie = format!("{}=; Path=/; Max-Age=0", SESSION_COOKIE);
+    Ok(([(header::SET_COOKIE, cookie)], StatusCode::OK).into_response())
+}
+
+/// Returns the signed in user.
+pub async fn me(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<Session>, ServerError> {
+    session(&state, &headers)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ServerError::Unauthorized(anyhow!("Not signed in")))
+}
+
+async fn session(state: &AppState, headers: &HeaderMap) -> Result<Option<Session>, ServerError> {
+    let Some(token) = oidc::cookie(headers, SESSION_COOKIE) else {
+        return Ok(None);
+    };
+    state
+        .db
+        .select_session(&access::hash_token(&token))
+        .await
+        .context("Failed to select session")
+        .map_err(|err| ServerError::DbError(err))
+}
+
+async fn provider(state: &AppState) -> Result<&Provider, ServerError> {
+    let cfg = state
+        .cfg
+        .oidc
+        .as_ref()
+        .ok_or_else(|| ServerError::NoContent(anyhow!("OIDC login isn't configured")))?;
+    state
+        .oidc
+        .get(cfg)
+        .await
+        .context("Failed to discover OIDC provider")
+        .map_err(|err| ServerError::BadGateway(err))
+}
+
+fn redirect_uri(state: &AppState) -> String {
+    format!("{}/auth/callback", state.cfg.public_url)
+}
+
+// Cookies of servers behind https aren't sent over plain http.
+fn secure(state: &AppState) -> &'static str {
+    match state.cfg.public_url.starts_with("https://") {
+        true => "; Secure",
+        false => "",
+    }
+}
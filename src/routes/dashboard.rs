@@ -1,14 +1,22 @@
 use anyhow::Context;
 use axum::{
     extract::{Path, Query, State},
-    response::Html,
+    http::HeaderMap,
+    response::{Html, IntoResponse, Response},
     routing::get,
     Router,
 };
 use sailfish::TemplateOnce;
 use serde::Deserialize;
+use std::time::Instant;
 
-use crate::{errors::ServerError, AppState};
+use crate::{
+    coverage::{self, Symbol},
+    errors::ServerError,
+    etag, index, links, spelling,
+    types::{BrokenLink, SourceKind},
+    AppState,
+};
 
 pub fn routes() -> Router<AppState> {
     Router::new().nest(
@@ -17,7 +25,9 @@ pub fn routes() -> Router<AppState> {
             .route("/search", get(search))
             .route("/sources", get(get_sources))
             .route("/sources/:source_id/chunks", get(get_chunks))
-            .route("/sources/:source_id/docs", get(get_docs)),
+            .route("/sources/:source_id/docs", get(get_docs))
+            .route("/sources/:source_id/links", get(get_broken_links))
+            .route("/sources/:source_id/coverage", get(get_coverage)),
     )
 }
 
@@ -27,41 +37,97 @@ struct SourcesPage {
     data: Vec<Source>,
 }
 
+#[derive(Debug)]
 struct Source {
     id: i64,
     url: String,
+    /// Language, description and topics of the GitHub repo.
+    about: String,
     allowed_ext: String,
     allowed_dirs: String,
     ignored_dirs: String,
     docs_url: String,
     chunks_url: String,
+    links_url: String,
+    coverage_url: String,
+    documents: i64,
+    tokens: i64,
+    /// Files of the last parse by extension, e.g. `.md: 12, .rst: 3`.
+    files: String,
+    largest_file: String,
+    empty_files: usize,
 }
 
-pub async fn get_sources(State(state): State<AppState>) -> Result<Html<String>, ServerError> {
+pub async fn get_sources(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
     let data = state
         .db
         .query_sources()
         .await
         .context("Failed to query sources")
         .map_err(|err| ServerError::DbError(err))?;
-    let data = data
-        .into_iter()
-        .map(|x| Source {
+    let mut sources = Vec::with_capacity(data.len());
+    for x in data {
+        let stats = state
+            .db
+            .select_source_stats(x.id)
+            .await
+            .context("Failed to query source stats")
+            .map_err(|err| ServerError::DbError(err))?;
+        sources.push(Source {
             id: x.id,
-            url: format!("https://github.com/{}/{}", x.owner, x.repo),
+            url: match x.kind {
+                SourceKind::GitHub => format!("https://github.com/{}/{}", x.owner, x.repo),
+                SourceKind::Web
+                | SourceKind::Rustdoc
+                | SourceKind::Mbox
+                | SourceKind::Bucket
+                | SourceKind::Upload => x.url.clone(),
+            },
+            about: x.metadata.context().unwrap_or_default(),
             allowed_ext: x.allowed_ext.into_iter().collect::<Vec<_>>().join(", "),
             allowed_dirs: x.allowed_dirs.into_iter().collect::<Vec<_>>().join(", "),
             ignored_dirs: x.ignored_dirs.into_iter().collect::<Vec<_>>().join(", "),
             docs_url: format!("/dashboard/sources/{}/docs", &x.id),
             chunks_url: format!("/dashboard/sources/{}/chunk", &x.id),
-        })
-        .collect();
-    let page = SourcesPage { data };
+            links_url: format!("/dashboard/sources/{}/links", &x.id),
+            coverage_url: format!("/dashboard/sources/{}/coverage", &x.id),
+            documents: stats.documents,
+            tokens: stats.tokens,
+            files: stats.summary.as_ref().map_or_else(String::new, |summary| {
+                summary
+                    .files_by_ext
+                    .iter()
+                    .map(|(ext, count)| format!("{}: {}", ext, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }),
+            largest_file: stats
+                .summary
+                .as_ref()
+                .and_then(|summary| summary.largest_files.first())
+                .map(|file| format!("{} ({} bytes)", file.path, file.bytes))
+                .unwrap_or_default(),
+            empty_files: stats
+                .summary
+                .as_ref()
+                .map_or(0, |summary| summary.empty_files.len()),
+        });
+    }
+    // Stats are aggregated per source, so everything shown is the cheapest
+    // fingerprint, taken before the page is rendered.
+    let etag = etag::etag(&format!("sources:{:?}", sources));
+    if etag::is_fresh(&headers, &etag) {
+        return Ok(etag::not_modified(&etag));
+    }
+    let page = SourcesPage { data: sources };
     let html = page
         .render_once()
         .context("Failed to render sources")
         .map_err(|err| ServerError::Embeddings(err))?;
-    Ok(Html(html))
+    Ok(etag::with_etag(&etag, Html(html)))
 }
 
 #[derive(TemplateOnce)]
@@ -77,8 +143,20 @@ struct Chunk {
 
 pub async fn get_chunks(
     Path(source_id): Path<i64>,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Html<String>, ServerError> {
+) -> Result<Response, ServerError> {
+    let version = state
+        .db
+        .select_chunks_version(source_id)
+        .await
+        .context("Failed to select chunks version")
+        .map_err(|err| ServerError::DbError(err))?;
+    let etag = etag::etag(&format!("chunks:{}:{}", source_id, version));
+    if etag::is_fresh(&headers, &etag) {
+        return Ok(etag::not_modified(&etag));
+    }
+
     let data = state
         .db
         .query_chunks_by_source(source_id)
@@ -97,7 +175,7 @@ pub async fn get_chunks(
         .render_once()
         .context("Failed to render chunks")
         .map_err(|err| ServerError::Embeddings(err))?;
-    Ok(Html(html))
+    Ok(etag::with_etag(&etag, Html(html)))
 }
 
 #[derive(TemplateOnce)]
@@ -108,13 +186,26 @@ struct DocsPage {
 
 struct Doc {
     id: String,
+    summary: String,
     html: String,
 }
 
 pub async fn get_docs(
     Path(source_id): Path<i64>,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Html<String>, ServerError> {
+) -> Result<Response, ServerError> {
+    let version = state
+        .db
+        .select_documents_version(source_id)
+        .await
+        .context("Failed to select documents version")
+        .map_err(|err| ServerError::DbError(err))?;
+    let etag = etag::etag(&format!("docs:{}:{}", source_id, version));
+    if etag::is_fresh(&headers, &etag) {
+        return Ok(etag::not_modified(&etag));
+    }
+
     let data = state
         .db
         .query_documents_by_source(source_id)
@@ -125,6 +216,7 @@ pub async fn get_docs(
         .into_iter()
         .map(|x| Doc {
             id: x.path,
+            summary: x.summary,
             html: markdown::to_html(&x.data),
         })
         .collect();
@@ -133,7 +225,56 @@ pub async fn get_docs(
         .render_once()
         .context("Failed to render documents")
         .map_err(|err| ServerError::Embeddings(err))?;
-    Ok(Html(html))
+    Ok(etag::with_etag(&etag, Html(html)))
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "links.html")]
+struct LinksPage {
+    data: Vec<BrokenLink>,
+}
+
+pub async fn get_broken_links(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let data = links::broken_links(&state.db, source_id)
+        .await
+        .context("Failed to query broken links")
+        .map_err(|err| ServerError::DbError(err))?;
+    let page = LinksPage { data };
+    let html = page
+        .render_once()
+        .context("Failed to render broken links")
+        .map_err(|err| ServerError::Embeddings(err))?;
+    Ok(Html(html).into_response())
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "coverage.html")]
+struct CoveragePage {
+    symbols: usize,
+    documented: usize,
+    data: Vec<Symbol>,
+}
+
+pub async fn get_coverage(
+    Path(source_id): Path<i64>,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let report = coverage::coverage(&state.db, &state.coverage, source_id)
+        .await
+        .map_err(|err| ServerError::DbError(err))?;
+    let page = CoveragePage {
+        symbols: report.symbols,
+        documented: report.documented,
+        data: report.undocumented,
+    };
+    let html = page
+        .render_once()
+        .context("Failed to render coverage")
+        .map_err(|err| ServerError::Embeddings(err))?;
+    Ok(Html(html).into_response())
 }
 
 #[derive(Deserialize)]
@@ -145,6 +286,8 @@ pub struct SearchQuery {
 #[template(path = "search.html")]
 struct SearchPage {
     data: Vec<SearchResult>,
+    partial: bool,
+    did_you_mean: Option<String>,
 }
 
 pub struct SearchResult {
@@ -155,47 +298,74 @@ pub struct SearchResult {
 
 pub async fn search(
     params: Query<SearchQuery>,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Html<String>, ServerError> {
+) -> Result<Response, ServerError> {
     if let Some(q) = params.query.clone() {
+        let deadline = Instant::now() + state.cfg.search_deadline;
         tracing::info!("Searching for '{}'", q);
-        let query = state
-            .embeddings
-            .encode(&[q.clone()])
-            .await
-            .context("Failed to create embedding")
-            .map_err(|err| ServerError::Embeddings(err))?;
-
-        let vectors = state
+        let collection = state
             .tinyvector
             .read()
             .await
             .get_collection("default")
             .context("Failed to get Tinyvector collection")
+            .map_err(|err| ServerError::Embeddings(err))?;
+        let etag = etag::etag(&format!("search:default:{}:{}", collection.version, q));
+        if etag::is_fresh(&headers, &etag) {
+            return Ok(etag::not_modified(&etag));
+        }
+
+        let query = state
+            .embeddings(&collection.model)
             .map_err(|err| ServerError::Embeddings(err))?
-            .get_similarity(&query[0], 10);
+            .encode(&[q.clone()])
+            .await
+            .context("Failed to create embedding")
+            .map_err(|err| ServerError::Embeddings(err))?;
+        let boost_tag = index::boost_tag(&q);
+        let (vectors, partial) =
+            collection.get_similarity_within(&query[0], 10, Some(deadline), boost_tag);
 
         let mut data = Vec::with_capacity(vectors.len());
         for n in vectors {
             data.push(SearchResult {
                 score: n.score,
-                path: n.embedding.id,
+                path: n.embedding.metadata.path,
                 html: markdown::to_html(&n.embedding.blob),
             })
         }
 
-        let page = SearchPage { data };
+        let did_you_mean = spelling::suggest(&state, "default", collection, &q)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::warn!("Failed to suggest spelling: {:?}", err);
+                None
+            });
+        let page = SearchPage {
+            data,
+            partial,
+            did_you_mean,
+        };
         let html = page
             .render_once()
             .context("Failed to render search")
             .map_err(|err| ServerError::Embeddings(err))?;
-        Ok(Html(html))
+        // Partial results depend on timing, so they must not be cached.
+        if partial {
+            return Ok(Html(html).into_response());
+        }
+        Ok(etag::with_etag(&etag, Html(html)))
     } else {
-        let page = SearchPage { data: Vec::new() };
+        let page = SearchPage {
+            data: Vec::new(),
+            partial: false,
+            did_you_mean: None,
+        };
         let html = page
             .render_once()
             .context("Failed to render search")
             .map_err(|err| ServerError::Embeddings(err))?;
-        Ok(Html(html))
+        Ok(Html(html).into_response())
     }
 }
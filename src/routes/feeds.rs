@@ -0,0 +1,114 @@
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::Response,
+    routing::get,
+    Router,
+};
+use chrono::SecondsFormat;
+use std::fmt::Write;
+
+use crate::{errors::ServerError, etag, types::DocumentEntry, AppState};
+
+/// Number of most recently updated documents listed in the feed.
+const FEED_ITEMS: usize = 50;
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/sitemap.xml", get(sitemap))
+        .route("/feed.xml", get(feed))
+}
+
+/// Lists every indexed document with its last update time.
+pub async fn sitemap(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let entries = query_entries(&state).await?;
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for entry in &entries {
+        let _ = write!(
+            xml,
+            "  <url>\n    <loc>{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
+            escape(&document_url(entry)),
+            entry.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+    }
+    xml.push_str("</urlset>\n");
+    Ok(xml_response(&headers, "application/xml", xml))
+}
+
+/// RSS feed of the most recently updated documents.
+pub async fn feed(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let entries = query_entries(&state).await?;
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n",
+    );
+    let _ = write!(
+        xml,
+        "  <title>rtfm</title>\n  <link>{}</link>\n  <description>Recently indexed documents</description>\n",
+        escape(&state.cfg.public_url),
+    );
+    if let Some(latest) = entries.first() {
+        let _ = writeln!(
+            xml,
+            "  <lastBuildDate>{}</lastBuildDate>",
+            latest.updated_at.to_rfc2822()
+        );
+    }
+    for entry in entries.iter().take(FEED_ITEMS) {
+        let url = escape(&document_url(entry));
+        let _ = write!(
+            xml,
+            "  <item>\n    <title>{}</title>\n    <link>{}</link>\n    <guid isPermaLink=\"false\">{}:{}</guid>\n    <pubDate>{}</pubDate>\n  </item>\n",
+            escape(&format!("{}/{}: {}", entry.owner, entry.repo, entry.path)),
+            url,
+            entry.id,
+            entry.updated_at.timestamp(),
+            entry.updated_at.to_rfc2822(),
+        );
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    Ok(xml_response(&headers, "application/rss+xml", xml))
+}
+
+async fn query_entries(state: &AppState) -> Result<Vec<DocumentEntry>, ServerError> {
+    state
+        .db
+        .query_document_entries()
+        .await
+        .context("Failed to query documents")
+        .map_err(|err| ServerError::DbError(err))
+}
+
+// Documents are linked to where they live on GitHub.
+fn document_url(entry: &DocumentEntry) -> String {
+    format!(
+        "https://github.com/{}/{}/blob/{}/{}",
+        entry.owner, entry.repo, entry.branch, entry.path
+    )
+}
+
+fn xml_response(headers: &HeaderMap, content_type: &'static str, xml: String) -> Response {
+    let etag = etag::etag(&xml);
+    if etag::is_fresh(headers, &etag) {
+        return etag::not_modified(&etag);
+    }
+    etag::with_etag(&etag, ([(header::CONTENT_TYPE, content_type)], xml))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -1,8 +1,18 @@
-use axum::Json;
+use axum::{extract::State, Json};
 use serde_json::{json, Value};
 
-use crate::errors::ServerError;
+use crate::{breaker::BreakerState, errors::ServerError, AppState};
 
-pub async fn health_check_handler() -> Result<Json<Value>, ServerError> {
-    Ok(Json(json!({ "status": "ok" })))
+/// Reports `degraded` while an external dependency's circuit breaker isn't closed,
+/// already indexed content is still served then.
+pub async fn health_check_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, ServerError> {
+    let breakers = state.breakers.statuses();
+    let status = if breakers.iter().all(|b| b.state == BreakerState::Closed) {
+        "ok"
+    } else {
+        "degraded"
+    };
+    Ok(Json(json!({ "status": status, "breakers": breakers })))
 }
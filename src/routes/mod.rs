@@ -1,14 +1,54 @@
-use axum::{routing::get, Router};
+use axum::{middleware::from_fn_with_state, routing::get, Router};
+use std::time::Duration;
+use tower_http::{
+    cors::{AllowHeaders, Any, CorsLayer},
+    timeout::TimeoutLayer,
+};
 
+mod admin;
 mod api;
+mod auth;
 mod dashboard;
+mod feeds;
 mod health_check;
+mod openapi;
+mod public;
+mod ws;
 
 use crate::AppState;
 
-pub fn router() -> Router<AppState> {
+/// Builds the app's routes, each group with its own timeout. Requests running
+/// past it are aborted with a 408 Request Timeout.
+pub fn router(state: AppState) -> Router<AppState> {
+    let cfg = state.cfg.clone();
     Router::new()
         .route("/health_check", get(health_check::health_check_handler))
-        .merge(api::routes())
-        .merge(dashboard::routes())
+        .merge(auth::routes())
+        .merge(
+            dashboard::routes()
+                .route_layer(from_fn_with_state(state.clone(), auth::require_session)),
+        )
+        .merge(feeds::routes())
+        .merge(openapi::routes())
+        .layer(TimeoutLayer::new(cfg.request_timeout))
+        .merge(
+            admin::routes(state.clone())
+                .layer(TimeoutLayer::new(cfg.long_timeout))
+                .layer(from_fn_with_state(state.clone(), auth::require_admin)),
+        )
+        .merge(api::routes(state.clone()))
+        // Websockets stay open for the whole session, so they get no timeout.
+        .merge(ws::routes().route_layer(from_fn_with_state(state.clone(), auth::require_viewer)))
+        .layer(cors_layer())
+        // Public routes answer preflights of docs sites with their own CORS layer.
+        .merge(public::routes(state))
+}
+
+// Adds headers for CORS.
+fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(AllowHeaders::mirror_request())
+        .max_age(Duration::from_secs(600))
 }
@@ -0,0 +1,236 @@
+use axum::Router;
+use utoipa::{
+    openapi::{
+        path::ParameterIn, Content, ObjectBuilder, OpenApi as Spec, Ref, RefOr, Schema, SchemaType,
+    },
+    Modify, OpenApi,
+};
+use utoipa_swagger_ui::SwaggerUi;
+
+use super::api;
+use crate::{
+    ask::Strategy,
+    discover::Package,
+    lookup::MatchKind,
+    pipeline::DiscoveredSource,
+    search::Contribution,
+    types::{
+        AccessToken, Alias, AuditEntry, BrokenLink, BucketOptions, Collection, CrawlOptions,
+        Document, FileSize, GitHubOptions, Heading, Job, JobKind, JobStatus, ParseSummary,
+        RepoMetadata, Source, SourceKind, SourceStats, Translations,
+    },
+    versioning::{Envelope, EnvelopeError},
+    AppState, DocumentFormat, Facets,
+};
+
+/// Spec of the `/api/v1` routes, generated from the annotations of their handlers.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rtfm",
+        description = "Indexes documentation and searches it. Routes are documented under `/api/v1`, every one is served under `/api` as well with the responses it had before `/api/v1`, those routes are deprecated. JSON and error responses of `/api/v1` are wrapped into an `Envelope` and missing entities are 404s, as documented here. The unversioned routes return the payloads as they are and 204s for missing entities."
+    ),
+    modifiers(&DedupParams, &Envelopes),
+    paths(
+        api::list_sources,
+        api::get_source,
+        api::create_source,
+        api::create_sources,
+        api::update_source,
+        api::delete_source,
+        api::parse,
+        api::encode_source,
+        api::sync_source,
+        api::reindex_source,
+        api::rollback_source,
+        api::discover_sources,
+        api::upload_archive,
+        api::broken_links,
+        api::source_coverage,
+        api::list_documents,
+        api::delete_documents,
+        api::list_chunks,
+        api::delete_chunks,
+        api::create_document,
+        api::get_document,
+        api::document_toc,
+        api::document_links,
+        api::list_collections,
+        api::create_collection,
+        api::get_collection,
+        api::rename_collection,
+        api::delete_collection,
+        api::clone_collection,
+        api::merge_collection,
+        api::get_ranking,
+        api::put_ranking,
+        api::delete_ranking,
+        api::collection_delta,
+        api::list_aliases,
+        api::put_alias,
+        api::delete_alias,
+        api::list_access_tokens,
+        api::create_access_token,
+        api::delete_access_token,
+        api::search,
+        api::hybrid_search,
+        api::lookup,
+        api::ask,
+        api::list_jobs,
+        api::get_job,
+        api::cancel_job,
+        api::list_audit_log,
+    ),
+    components(schemas(
+        api::JobResp,
+        api::UploadResp,
+        api::CreateDocumentReq,
+        api::CreateDocumentResp,
+        api::RollbackResp,
+        api::DocumentResp,
+        api::ChunkResp,
+        api::CreateCollectionReq,
+        api::CreateCollectionResp,
+        api::RenameCollectionReq,
+        api::CloneCollectionReq,
+        api::MergeCollectionReq,
+        api::MergeCollectionResp,
+        api::SourceEntry,
+        api::CreateSourceReq,
+        api::CreateSourceResp,
+        api::BulkSourceResult,
+        api::CreateSourcesResp,
+        api::UpdateSourceReq,
+        api::SearchResults,
+        api::SearchResp,
+        api::ResultMetadata,
+        api::LookupResp,
+        api::AskReq,
+        api::AliasReq,
+        api::CreateAccessTokenReq,
+        api::CreateAccessTokenResp,
+        api::LinkResp,
+        api::DocumentLinksResp,
+        AccessToken,
+        Alias,
+        AuditEntry,
+        BrokenLink,
+        BucketOptions,
+        Collection,
+        Contribution,
+        CrawlOptions,
+        DiscoveredSource,
+        Document,
+        DocumentFormat,
+        Envelope,
+        EnvelopeError,
+        Facets,
+        FileSize,
+        GitHubOptions,
+        Heading,
+        Job,
+        JobKind,
+        JobStatus,
+        MatchKind,
+        Package,
+        ParseSummary,
+        RepoMetadata,
+        Source,
+        SourceKind,
+        SourceStats,
+        Strategy,
+        Translations,
+    )),
+    tags(
+        (name = "sources", description = "Sources and their parse, encode and sync jobs"),
+        (name = "documents", description = "Documents and chunks of sources"),
+        (name = "collections", description = "Collections, their aliases and search pipelines"),
+        (name = "search", description = "Searches, lookups and answers"),
+        (name = "jobs", description = "Background parse, encode and sync jobs"),
+        (name = "tokens", description = "Access tokens of `/public/search`"),
+        (name = "audit", description = "Log of administrative requests"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Lists a parameter of an operation once. Sync takes the query flags of both
+/// the parse and the encode stage, which share `incremental`.
+struct DedupParams;
+
+impl Modify for DedupParams {
+    fn modify(&self, spec: &mut Spec) {
+        let operations = spec
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut());
+        for operation in operations {
+            if let Some(params) = operation.parameters.as_mut() {
+                let mut seen: Vec<(String, ParameterIn)> = Vec::new();
+                params.retain(|param| {
+                    let key = (param.name.clone(), param.parameter_in.clone());
+                    let dup = seen.contains(&key);
+                    if !dup {
+                        seen.push(key);
+                    }
+                    !dup
+                });
+            }
+        }
+    }
+}
+
+/// Describes responses as `/api/v1` serves them: JSON payloads as the `data`
+/// of an `Envelope`, errors as envelopes and missing entities, 204s of the
+/// handlers, as 404s.
+struct Envelopes;
+
+impl Modify for Envelopes {
+    fn modify(&self, spec: &mut Spec) {
+        let operations = spec
+            .paths
+            .paths
+            .values_mut()
+            .flat_map(|item| item.operations.values_mut());
+        for operation in operations {
+            let responses = &mut operation.responses.responses;
+            if let Some(missing) = responses.remove("204") {
+                responses.entry("404".to_string()).or_insert(missing);
+            }
+            for (status, response) in responses.iter_mut() {
+                let RefOr::T(response) = response else {
+                    continue;
+                };
+                if status.starts_with('4') || status.starts_with('5') {
+                    response.content.insert(
+                        "application/json".to_string(),
+                        Content::new(Ref::from_schema_name("Envelope")),
+                    );
+                } else if let Some(content) = response.content.get_mut("application/json") {
+                    content.schema = enveloped(content.schema.clone());
+                }
+            }
+        }
+    }
+}
+
+// Schema of an `Envelope` whose `data` is the payload.
+fn enveloped(data: RefOr<Schema>) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .property("data", data)
+        .required("data")
+        .property("error", Ref::from_schema_name("EnvelopeError"))
+        .property(
+            "request_id",
+            ObjectBuilder::new().schema_type(SchemaType::String),
+        )
+        .required("request_id")
+        .into()
+}
+
+/// The spec as JSON, for generating clients, and Swagger UI browsing it. The
+/// UI's assets are bundled into the binary, so the page loads no scripts from
+/// elsewhere.
+pub fn routes() -> Router<AppState> {
+    Router::new().merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()))
+}
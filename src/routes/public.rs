@@ -0,0 +1,142 @@
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, HeaderName, Method},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::time::Duration;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    timeout::TimeoutLayer,
+};
+
+use super::api::{self, SearchQuery};
+use crate::{
+    access, errors::ServerError, etag, negotiate::Format, search, types::AccessToken,
+    versioning::ApiVersion, AppState,
+};
+
+/// Search box embedded with a script tag, see its header for the attributes.
+const WIDGET_JS: &str = include_str!("../../templates/widget.js");
+
+/// Routes callable with an access token and the widget using them, safe to
+/// expose to docs sites while `/api` stays private.
+pub fn routes(state: AppState) -> Router<AppState> {
+    let cfg = state.cfg.clone();
+    Router::new().route("/widget.js", get(widget)).nest(
+        "/public",
+        Router::new()
+            .route("/search", get(search))
+            .layer(GlobalConcurrencyLimitLayer::new(cfg.search_concurrency))
+            .layer(TimeoutLayer::new(cfg.search_timeout))
+            .layer(cors_layer()),
+    )
+}
+
+// Lets docs sites call the routes from the browser. Any origin passes the
+// preflight, the token's allowed origins are checked by the routes.
+fn cors_layer() -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::mirror_request())
+        .allow_methods([Method::GET])
+        .allow_headers([header::AUTHORIZATION, header::IF_NONE_MATCH])
+        .expose_headers([
+            header::ETAG,
+            header::RETRY_AFTER,
+            HeaderName::from_static(api::NEXT_CURSOR_HEADER),
+            HeaderName::from_static(api::SEARCH_PARTIAL_HEADER),
+            HeaderName::from_static(api::INDEX_VERSION_HEADER),
+        ])
+        .max_age(Duration::from_secs(600))
+}
+
+pub async fn widget(headers: HeaderMap) -> Response {
+    let etag = etag::etag(WIDGET_JS);
+    if etag::is_fresh(&headers, &etag) {
+        return etag::not_modified(&etag);
+    }
+    etag::with_etag(
+        &etag,
+        (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "application/javascript; charset=utf-8",
+                ),
+                (header::CACHE_CONTROL, "public, max-age=3600"),
+            ],
+            WIDGET_JS,
+        )
+            .into_response(),
+    )
+}
+
+/// Searches the live index of the token's collection.
+pub async fn search(
+    Query(mut params): Query<SearchQuery>,
+    headers: HeaderMap,
+    format: Format,
+    State(state): State<AppState>,
+) -> Result<Response, ServerError> {
+    let token = authorize(&state, &headers).await?;
+    if !token.allowed_origins.is_empty() {
+        // Browsers set `Origin` on cross-origin fetches, and scripts can't forge it.
+        let origin = headers
+            .get(header::ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if !token
+            .allowed_origins
+            .iter()
+            .any(|allowed| allowed == origin)
+        {
+            return Err(ServerError::Forbidden(anyhow!(
+                "Origin '{}' is not allowed to use the token",
+                origin
+            )));
+        }
+    }
+    if let Err(retry_after) = state.rate_limits.check(token.id, token.requests_per_minute) {
+        return Err(ServerError::RateLimited(retry_after.as_secs().max(1)));
+    }
+    let collection = state
+        .db
+        .select_collection(token.collection_id)
+        .await
+        .context("Failed to select collection")
+        .map_err(|err| ServerError::DbError(err))?;
+    // Candidate models, retained builds and debug output are for operators.
+    params.collection = Some(collection.name);
+    params.model = None;
+    params.index_version = None;
+    params.debug = false;
+    // Widgets embed the results as they were before `/api/v1`.
+    api::run_search(
+        params,
+        headers,
+        format,
+        ApiVersion::Unversioned,
+        state,
+        search::Mode::Collection,
+    )
+    .await
+}
+
+// Looks up the `Authorization: Bearer` token.
+async fn authorize(state: &AppState, headers: &HeaderMap) -> Result<AccessToken, ServerError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| ServerError::Unauthorized(anyhow!("Missing access token")))?;
+    state
+        .db
+        .select_access_token_by_hash(&access::hash_token(token.trim()))
+        .await
+        .context("Failed to select access token")
+        .map_err(|err| ServerError::DbError(err))?
+        .ok_or_else(|| ServerError::Unauthorized(anyhow!("Invalid access token")))
+}
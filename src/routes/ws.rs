@@ -0,0 +1,255 @@
+use anyhow::Context;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::get,
+    Router,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{aliases, ask, index, AppState};
+
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+const MAX_SEARCH_LIMIT: usize = 100;
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/ws", get(ws))
+}
+
+/// Messages sent by the client, `id` is echoed back in replies.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMsg {
+    Search {
+        id: u64,
+        query: String,
+        collection: Option<String>,
+        limit: Option<usize>,
+        #[serde(default)]
+        strategy: ask::Strategy,
+    },
+    Ask {
+        id: u64,
+        question: String,
+        collection: Option<String>,
+        #[serde(default)]
+        strategy: ask::Strategy,
+    },
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMsg {
+    Results {
+        id: u64,
+        partial: bool,
+        results: Vec<SearchResult>,
+    },
+    AnswerDelta {
+        id: u64,
+        text: String,
+    },
+    AnswerDone {
+        id: u64,
+        sources: Vec<SearchResult>,
+        citations: Vec<ask::Citation>,
+    },
+    Error {
+        id: Option<u64>,
+        error: String,
+    },
+}
+
+#[derive(Serialize, Debug)]
+struct SearchResult {
+    score: f32,
+    path: String,
+    chunk_index: usize,
+    text: String,
+}
+
+/// Interactive sessions for editors and the dashboard: query-as-you-type
+/// search and streamed answers over a single connection.
+pub async fn ws(upgrade: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    upgrade.on_upgrade(move |socket| session(socket, state))
+}
+
+async fn session(socket: WebSocket, state: AppState) {
+    let (mut sink, mut stream) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<ServerMsg>(64);
+    let writer = tokio::spawn(async move {
+        while let Some(msg) = rx.recv().await {
+            let text = match serde_json::to_string(&msg) {
+                Ok(text) => text,
+                Err(err) => {
+                    tracing::error!("Failed to serialize websocket message: {}", err);
+                    continue;
+                }
+            };
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // A new search supersedes the one in flight, so typing doesn't queue up stale queries.
+    let mut search: Option<JoinHandle<()>> = None;
+    let mut asks: Vec<JoinHandle<()>> = Vec::new();
+    while let Some(Ok(msg)) = stream.next().await {
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        match serde_json::from_str::<ClientMsg>(&text) {
+            Ok(ClientMsg::Search {
+                id,
+                query,
+                collection,
+                limit,
+                strategy,
+            }) => {
+                if let Some(handle) = search.take() {
+                    handle.abort();
+                }
+                let (state, tx) = (state.clone(), tx.clone());
+                search = Some(tokio::spawn(async move {
+                    let collection = collection.as_deref().unwrap_or("default");
+                    let limit = limit
+                        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+                        .clamp(1, MAX_SEARCH_LIMIT);
+                    let msg = match run_search(&state, collection, &query, limit, strategy).await {
+                        Ok((results, partial)) => ServerMsg::Results {
+                            id,
+                            partial,
+                            results,
+                        },
+                        Err(err) => ServerMsg::Error {
+                            id: Some(id),
+                            error: format!("{:#}", err),
+                        },
+                    };
+                    let _ = tx.send(msg).await;
+                }));
+            }
+            Ok(ClientMsg::Ask {
+                id,
+                question,
+                collection,
+                strategy,
+            }) => {
+                asks.retain(|handle| !handle.is_finished());
+                let (state, tx) = (state.clone(), tx.clone());
+                asks.push(tokio::spawn(async move {
+                    let collection = collection.as_deref().unwrap_or("default");
+                    if let Err(err) =
+                        run_ask(&state, &tx, id, collection, &question, strategy).await
+                    {
+                        let _ = tx
+                            .send(ServerMsg::Error {
+                                id: Some(id),
+                                error: format!("{:#}", err),
+                            })
+                            .await;
+                    }
+                }));
+            }
+            Err(err) => {
+                let _ = tx
+                    .send(ServerMsg::Error {
+                        id: None,
+                        error: format!("Invalid message: {}", err),
+                    })
+                    .await;
+            }
+        }
+    }
+
+    // The client is gone, nobody is waiting for the results.
+    search
+        .into_iter()
+        .chain(asks)
+        .for_each(|handle| handle.abort());
+    writer.abort();
+}
+
+async fn run_search(
+    state: &AppState,
+    collection: &str,
+    query: &str,
+    limit: usize,
+    strategy: ask::Strategy,
+) -> anyhow::Result<(Vec<SearchResult>, bool)> {
+    let expanded = aliases::expand(state, collection, query).await?;
+    let collection = index::resolve_collection(&state.tinyvector, collection, None)
+        .await
+        .context("Failed to get Tinyvector collection")?;
+    let text = ask::query_text(state, &expanded, strategy).await?;
+    let deadline = Instant::now() + state.cfg.search_deadline;
+    let vector = state
+        .embeddings(&collection.model)?
+        .encode(&[text])
+        .await
+        .context("Failed to create embedding")?;
+    let boost_tag = index::boost_tag(&expanded);
+    let (vectors, partial) =
+        collection.get_similarity_within(&vector[0], limit, Some(deadline), boost_tag);
+    let results = vectors
+        .into_iter()
+        .map(|n| SearchResult {
+            score: n.score,
+            path: n.embedding.metadata.path,
+            chunk_index: n.embedding.metadata.chunk_index,
+            text: n.embedding.blob,
+        })
+        .collect();
+    Ok((results, partial))
+}
+
+async fn run_ask(
+    state: &AppState,
+    tx: &mpsc::Sender<ServerMsg>,
+    id: u64,
+    collection: &str,
+    question: &str,
+    strategy: ask::Strategy,
+) -> anyhow::Result<()> {
+    let context = ask::retrieve(state, collection, question, ask::CONTEXT_CHUNKS, strategy).await?;
+    let mut stream = Box::pin(ask::answer_stream(state, question, &context).await?);
+    let mut answer = String::new();
+    while let Some(text) = stream.next().await {
+        let text = text?;
+        if text.is_empty() {
+            continue;
+        }
+        answer.push_str(&text);
+        tx.send(ServerMsg::AnswerDelta { id, text })
+            .await
+            .context("Websocket is closed")?;
+    }
+
+    let citations = ask::cite(&answer, &context);
+    let sources = context
+        .into_iter()
+        .map(|n| SearchResult {
+            score: n.score,
+            path: n.embedding.metadata.path,
+            chunk_index: n.embedding.metadata.chunk_index,
+            text: n.embedding.blob,
+        })
+        .collect();
+    tx.send(ServerMsg::AnswerDone {
+        id,
+        sources,
+        citations,
+    })
+    .await
+    .context("Websocket is closed")?;
+    Ok(())
+}
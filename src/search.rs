@@ -0,0 +1,840 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use utoipa::ToSchema;
+
+use crate::{
+    index,
+    ranking::{self, ScoreIndex},
+    tinyvector::{Collection, SimilarityResult},
+    Db,
+};
+
+/// Candidates a retriever returns unless configured otherwise.
+const DEFAULT_RETRIEVE_K: usize = 100;
+
+/// Most candidates a retriever can be configured to return.
+const MAX_RETRIEVE_K: usize = 1000;
+
+/// Postings scored between deadline checks of keyword retrieval.
+const KEYWORD_BATCH_SIZE: usize = 1024;
+
+/// BM25 term frequency saturation and length normalization.
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// Dampens the lead of top ranks in reciprocal rank fusion, the constant of
+/// the original paper.
+const RRF_K: f32 = 60.0;
+
+/// A stage of the search pipeline. Retrievers come first, the stages after
+/// them rescore, filter or reorder the merged candidates.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+    /// Nearest chunks by embedding, link priors and the caveat boost included.
+    VectorRetrieve {
+        #[serde(default = "default_k")]
+        k: usize,
+    },
+    /// Chunks containing the query's terms, scored with BM25.
+    KeywordRetrieve {
+        #[serde(default = "default_k")]
+        k: usize,
+    },
+    /// Documents whose title, their file name or first heading, contains the
+    /// query's terms. Returns the first chunk of each.
+    TitleRetrieve {
+        #[serde(default = "default_k")]
+        k: usize,
+    },
+    /// Merges the candidates of the retrievers before it into one ranking.
+    Fuse {
+        #[serde(default)]
+        method: FuseMethod,
+    },
+    /// Adds `weight` times the share of the query's terms the chunk contains.
+    Rerank {
+        #[serde(default = "default_rerank_weight")]
+        weight: f32,
+    },
+    /// Keeps the best `per_document` chunks of a document and drops chunks
+    /// repeating the text of a better one.
+    Dedupe {
+        #[serde(default = "default_per_document")]
+        per_document: usize,
+    },
+    /// Adds weights to chunks carrying the tags or below the path prefixes.
+    Boost {
+        #[serde(default)]
+        tags: BTreeMap<String, f32>,
+        #[serde(default)]
+        paths: BTreeMap<String, f32>,
+    },
+}
+
+fn default_k() -> usize {
+    DEFAULT_RETRIEVE_K
+}
+
+fn default_rerank_weight() -> f32 {
+    0.1
+}
+
+fn default_per_document() -> usize {
+    2
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::VectorRetrieve { .. } => "vector_retrieve",
+            Stage::KeywordRetrieve { .. } => "keyword_retrieve",
+            Stage::TitleRetrieve { .. } => "title_retrieve",
+            Stage::Fuse { .. } => "fuse",
+            Stage::Rerank { .. } => "rerank",
+            Stage::Dedupe { .. } => "dedupe",
+            Stage::Boost { .. } => "boost",
+        }
+    }
+
+    fn is_retriever(&self) -> bool {
+        matches!(
+            self,
+            Stage::VectorRetrieve { .. }
+                | Stage::KeywordRetrieve { .. }
+                | Stage::TitleRetrieve { .. }
+        )
+    }
+}
+
+/// How the scores of a chunk found by several retrievers are combined.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FuseMethod {
+    #[default]
+    Sum,
+    Max,
+    /// Sums `1 / (60 + rank)` of each retriever's ranking. Ranks are comparable
+    /// across retrievers, the scales of their scores aren't.
+    Rrf,
+}
+
+/// Which pipeline a search runs through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The collection's pipeline, a plain vector scan if it has none.
+    Collection,
+    /// `Pipeline::hybrid`, whatever pipeline the collection has.
+    Hybrid,
+}
+
+/// Chain of stages a search runs through, configured per collection.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Default for Pipeline {
+    /// Vector retrieval only, the way collections without a pipeline are searched.
+    fn default() -> Self {
+        Self {
+            stages: vec![Stage::VectorRetrieve {
+                k: DEFAULT_RETRIEVE_K,
+            }],
+        }
+    }
+}
+
+impl Pipeline {
+    /// Vector, keyword and title retrieval fused by rank, used by hybrid searches.
+    pub fn hybrid() -> Self {
+        Self {
+            stages: vec![
+                Stage::VectorRetrieve {
+                    k: DEFAULT_RETRIEVE_K,
+                },
+                Stage::KeywordRetrieve {
+                    k: DEFAULT_RETRIEVE_K,
+                },
+                Stage::TitleRetrieve {
+                    k: DEFAULT_RETRIEVE_K,
+                },
+                Stage::Fuse {
+                    method: FuseMethod::Rrf,
+                },
+            ],
+        }
+    }
+
+    /// Checks the pipeline starts with retrievers and fuses them at most once.
+    pub fn validate(&self) -> Result<(), String> {
+        let retrievers = self.stages.iter().take_while(|s| s.is_retriever()).count();
+        if retrievers == 0 {
+            return Err("The pipeline has to start with a retriever".to_string());
+        }
+        if let Some(stage) = self.stages[retrievers..].iter().find(|s| s.is_retriever()) {
+            return Err(format!(
+                "Retriever '{}' has to come before the other stages",
+                stage.name()
+            ));
+        }
+        let fuses = self
+            .stages
+            .iter()
+            .filter(|s| matches!(s, Stage::Fuse { .. }))
+            .count();
+        if fuses > 1 {
+            return Err("The pipeline can fuse only once".to_string());
+        }
+        for stage in &self.stages {
+            match stage {
+                Stage::VectorRetrieve { k }
+                | Stage::KeywordRetrieve { k }
+                | Stage::TitleRetrieve { k }
+                    if *k == 0 =>
+                {
+                    return Err(format!(
+                        "'{}' has to retrieve at least one chunk",
+                        stage.name()
+                    ))
+                }
+                Stage::VectorRetrieve { k }
+                | Stage::KeywordRetrieve { k }
+                | Stage::TitleRetrieve { k }
+                    if *k > MAX_RETRIEVE_K =>
+                {
+                    return Err(format!(
+                        "'{}' can retrieve at most {} chunks",
+                        stage.name(),
+                        MAX_RETRIEVE_K
+                    ))
+                }
+                Stage::Dedupe { per_document } if *per_document == 0 => {
+                    return Err("'dedupe' has to keep at least one chunk per document".to_string())
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Search pipelines by collection name, so searches don't read them from the
+/// db. Cleared whenever a pipeline is set or a collection renamed or deleted.
+#[derive(Clone, Default)]
+pub struct Rankings(Arc<Mutex<HashMap<String, Option<Pipeline>>>>);
+
+impl Rankings {
+    /// Returns the collection's pipeline, none if it has none or an invalid one.
+    pub async fn get(&self, db: &Db, name: &str) -> Result<Option<Pipeline>, sqlx::Error> {
+        if let Some(pipeline) = self.0.lock().unwrap().get(name) {
+            return Ok(pipeline.clone());
+        }
+        // Names of missing collections aren't cached, they may be created later.
+        let Some(ranking) = db.select_ranking(name).await? else {
+            return Ok(None);
+        };
+        let pipeline = match ranking.as_str() {
+            "" => None,
+            ranking => match serde_json::from_str::<Pipeline>(ranking)
+                .map_err(|err| err.to_string())
+                .and_then(|pipeline| pipeline.validate().map(|_| pipeline))
+            {
+                Ok(pipeline) => Some(pipeline),
+                Err(err) => {
+                    tracing::warn!("Ignoring invalid search pipeline of '{}': {}", name, err);
+                    None
+                }
+            },
+        };
+        self.0
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), pipeline.clone());
+        Ok(pipeline)
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Terms of a build's chunks for keyword retrieval, made once when the build
+/// is published instead of on every search.
+#[derive(Debug, Default)]
+pub struct TermIndex {
+    /// Version of the build the index was made of.
+    version: u64,
+    /// Number of terms of each chunk, by its position in the build.
+    lengths: Vec<u32>,
+    avg_len: f32,
+    /// Positions of the chunks containing a term, with its count in each.
+    postings: HashMap<String, Vec<(u32, u32)>>,
+}
+
+impl TermIndex {
+    pub fn build(collection: &Collection) -> Self {
+        let mut lengths = Vec::with_capacity(collection.embeddings.len());
+        let mut postings: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        for (position, embedding) in collection.embeddings.iter().enumerate() {
+            let terms = terms(&embedding.blob);
+            lengths.push(terms.len() as u32);
+            let mut counts: HashMap<String, u32> = HashMap::new();
+            for term in terms {
+                *counts.entry(term).or_default() += 1;
+            }
+            for (term, count) in counts {
+                postings
+                    .entry(term)
+                    .or_default()
+                    .push((position as u32, count));
+            }
+        }
+        let avg_len = match lengths.len() {
+            0 => 0.0,
+            len => lengths.iter().map(|&len| len as u64).sum::<u64>() as f32 / len as f32,
+        };
+        Self {
+            version: collection.version,
+            lengths,
+            avg_len,
+            postings,
+        }
+    }
+}
+
+/// Indexes the terms of the build's chunks, call it once the build is complete.
+pub fn index_terms(collection: &mut Collection) {
+    collection.terms = Some(Arc::new(TermIndex::build(collection)));
+}
+
+/// Score a stage gave a result, or added to its score after the fuse.
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Contribution {
+    #[schema(value_type = String)]
+    pub stage: &'static str,
+    pub score: f32,
+}
+
+/// A result with what each stage contributed to its score.
+#[derive(Debug, Clone)]
+pub struct Ranked {
+    pub result: SimilarityResult,
+    pub contributions: Vec<Contribution>,
+}
+
+/// The query as embedded and as typed, stages use what they need.
+pub struct Query<'a> {
+    pub vector: &'a [f32],
+    pub text: &'a str,
+    pub deadline: Option<Instant>,
+}
+
+/// Runs the pipeline over the collection. Returns the candidates best first
+/// and whether a retriever was cut short by the deadline.
+pub fn run(pipeline: &Pipeline, collection: &Collection, query: &Query) -> (Vec<Ranked>, bool) {
+    let query_terms = unique_terms(query.text);
+    let mut partial = false;
+    let mut retrieved: Vec<(&'static str, Vec<SimilarityResult>)> = Vec::new();
+    let mut ranked: Option<Vec<Ranked>> = None;
+    for stage in &pipeline.stages {
+        match stage {
+            Stage::VectorRetrieve { k } => {
+                let (results, cut) = collection.get_similarity_within(
+                    query.vector,
+                    *k,
+                    query.deadline,
+                    index::boost_tag(query.text),
+                );
+                partial |= cut;
+                retrieved.push((stage.name(), results));
+            }
+            Stage::KeywordRetrieve { k } => {
+                let (results, cut) = keyword_retrieve(collection, &query_terms, *k, query.deadline);
+                partial |= cut;
+                retrieved.push((stage.name(), results));
+            }
+            Stage::TitleRetrieve { k } => {
+                retrieved.push((stage.name(), title_retrieve(collection, &query_terms, *k)));
+            }
+            Stage::Fuse { method } => ranked = Some(fuse(std::mem::take(&mut retrieved), *method)),
+            _ => {
+                // Without an explicit fuse, the candidates are merged with the default one.
+                let candidates = ranked.get_or_insert_with(|| {
+                    fuse(std::mem::take(&mut retrieved), FuseMethod::default())
+                });
+                apply(stage, candidates, &query_terms);
+            }
+        }
+    }
+    let mut ranked = ranked.unwrap_or_else(|| fuse(retrieved, FuseMethod::default()));
+    sort(&mut ranked);
+    (ranked, partial)
+}
+
+/// Returns up to `k` results ranked after the `(score, id)` of the previous
+/// page's last result, see `ranking::page_after`.
+pub fn page(ranked: Vec<Ranked>, k: usize, after: Option<(f32, &str)>) -> Vec<Ranked> {
+    let scores = ranked
+        .iter()
+        .enumerate()
+        .map(|(index, r)| ScoreIndex {
+            score: r.result.score,
+            index,
+        })
+        .collect();
+    let page = ranking::page_after(scores, k, after, |index| {
+        ranked[index].result.embedding.id.as_str()
+    });
+    page.into_iter()
+        .map(|score| ranked[score.index].clone())
+        .collect()
+}
+
+/// Orders runs of results of the same guide by their position in the docs
+/// site's nav, so a guide's pages read in order. Results never move past one
+/// of another guide or outside of a nav, which keep their rank. `nav`
+/// returns the guide and position of a result, none if it isn't in a nav.
+pub fn order_guides<T: Clone, K: PartialEq>(
+    results: &mut [T],
+    nav: impl Fn(&T) -> Option<(K, usize)>,
+) {
+    let navs: Vec<Option<(K, usize)>> = results.iter().map(nav).collect();
+    let mut start = 0;
+    while start < results.len() {
+        let mut end = start + 1;
+        if let Some((guide, _)) = &navs[start] {
+            while end < results.len() && matches!(&navs[end], Some((other, _)) if other == guide) {
+                end += 1;
+            }
+        }
+        // Results of the same page keep their order.
+        let mut order: Vec<usize> = (start..end).collect();
+        order.sort_by_key(|&at| navs[at].as_ref().map(|(_, index)| *index));
+        let run = results[start..end].to_vec();
+        for (slot, at) in (start..end).zip(order) {
+            results[slot] = run[at - start].clone();
+        }
+        start = end;
+    }
+}
+
+// Merges the retrievers' results by chunk, keeping the order they were first
+// found in. Retrievers return their results best first, which gives the ranks.
+fn fuse(retrieved: Vec<(&'static str, Vec<SimilarityResult>)>, method: FuseMethod) -> Vec<Ranked> {
+    let many = retrieved.len() > 1;
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut ranked: Vec<Ranked> = Vec::new();
+    for (stage, results) in retrieved {
+        for (rank, mut result) in results.into_iter().enumerate() {
+            let contribution = Contribution {
+                stage,
+                score: result.score,
+            };
+            let score = match method {
+                FuseMethod::Rrf => 1.0 / (RRF_K + rank as f32 + 1.0),
+                FuseMethod::Sum | FuseMethod::Max => result.score,
+            };
+            match positions.get(&result.embedding.id) {
+                Some(&position) => {
+                    let candidate = &mut ranked[position];
+                    candidate.result.score = match method {
+                        FuseMethod::Sum | FuseMethod::Rrf => candidate.result.score + score,
+                        FuseMethod::Max => candidate.result.score.max(score),
+                    };
+                    candidate.contributions.push(contribution);
+                }
+                None => {
+                    positions.insert(result.embedding.id.clone(), ranked.len());
+                    result.score = score;
+                    ranked.push(Ranked {
+                        result,
+                        contributions: vec![contribution],
+                    });
+                }
+            }
+        }
+    }
+    // A single retriever's scores are kept as they are, unless replaced by ranks.
+    if many || method == FuseMethod::Rrf {
+        for candidate in &mut ranked {
+            candidate.contributions.push(Contribution {
+                stage: "fuse",
+                score: candidate.result.score,
+            });
+        }
+    }
+    ranked
+}
+
+fn apply(stage: &Stage, candidates: &mut Vec<Ranked>, query_terms: &[String]) {
+    match stage {
+        Stage::Rerank { weight } => {
+            if query_terms.is_empty() {
+                return;
+            }
+            for candidate in candidates.iter_mut() {
+                let chunk_terms: HashSet<String> = terms(&candidate.result.embedding.blob)
+                    .into_iter()
+                    .collect();
+                let found = query_terms
+                    .iter()
+                    .filter(|term| chunk_terms.contains(*term))
+                    .count();
+                add(
+                    candidate,
+                    stage.name(),
+                    weight * found as f32 / query_terms.len() as f32,
+                );
+            }
+        }
+        Stage::Dedupe { per_document } => {
+            sort(candidates);
+            let mut per_document_count: HashMap<i64, usize> = HashMap::new();
+            let mut texts: HashSet<String> = HashSet::new();
+            candidates.retain(|candidate| {
+                let embedding = &candidate.result.embedding;
+                let count = per_document_count
+                    .entry(embedding.metadata.document_id)
+                    .or_default();
+                if *count >= *per_document || !texts.insert(embedding.blob.trim().to_string()) {
+                    return false;
+                }
+                *count += 1;
+                true
+            });
+        }
+        Stage::Boost { tags, paths } => {
+            for candidate in candidates.iter_mut() {
+                let metadata = &candidate.result.embedding.metadata;
+                let by_tags: f32 = tags
+                    .iter()
+                    .filter(|(tag, _)| metadata.tags.contains(tag))
+                    .map(|(_, weight)| weight)
+                    .sum();
+                let by_paths: f32 = paths
+                    .iter()
+                    .filter(|(prefix, _)| metadata.path.starts_with(prefix.as_str()))
+                    .map(|(_, weight)| weight)
+                    .sum();
+                add(candidate, stage.name(), by_tags + by_paths);
+            }
+        }
+        Stage::VectorRetrieve { .. }
+        | Stage::KeywordRetrieve { .. }
+        | Stage::TitleRetrieve { .. }
+        | Stage::Fuse { .. } => {}
+    }
+}
+
+fn add(candidate: &mut Ranked, stage: &'static str, score: f32) {
+    if score == 0.0 {
+        return;
+    }
+    candidate.result.score += score;
+    candidate.contributions.push(Contribution { stage, score });
+}
+
+// Best first, ties are broken by id like pages are.
+fn sort(ranked: &mut [Ranked]) {
+    ranked.sort_by(|a, b| {
+        b.result
+            .score
+            .total_cmp(&a.result.score)
+            .then_with(|| a.result.embedding.id.cmp(&b.result.embedding.id))
+    });
+}
+
+// Scores every chunk containing a query term with BM25, returns the `k` best
+// and whether scoring was cut short by the deadline.
+fn keyword_retrieve(
+    collection: &Collection,
+    query_terms: &[String],
+    k: usize,
+    deadline: Option<Instant>,
+) -> (Vec<SimilarityResult>, bool) {
+    if query_terms.is_empty() || collection.embeddings.is_empty() {
+        return (Vec::new(), false);
+    }
+    // Builds changed since they were published are indexed on the spot.
+    let index = match &collection.terms {
+        Some(index) if index.version == collection.version => index.clone(),
+        _ => Arc::new(TermIndex::build(collection)),
+    };
+    let count = index.lengths.len() as f32;
+    // Kept in the order of the build, so ties are broken like a scan would.
+    let mut scores: BTreeMap<u32, f32> = BTreeMap::new();
+    let mut cut = false;
+    'terms: for term in query_terms {
+        let Some(postings) = index.postings.get(term) else {
+            continue;
+        };
+        let df = postings.len() as f32;
+        let idf = ((count - df + 0.5) / (df + 0.5) + 1.0).ln();
+        for batch in postings.chunks(KEYWORD_BATCH_SIZE) {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                cut = true;
+                break 'terms;
+            }
+            for &(position, tf) in batch {
+                let len = index.lengths[position as usize] as f32;
+                let len_norm = 1.0 - BM25_B + BM25_B * len / index.avg_len.max(1.0);
+                let tf = tf as f32;
+                *scores.entry(position).or_default() +=
+                    idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * len_norm);
+            }
+        }
+    }
+
+    let scores = scores.into_iter().filter_map(|(position, score)| {
+        (score > 0.0).then_some(ScoreIndex {
+            score,
+            index: position as usize,
+        })
+    });
+    let results = ranking::top_k(scores, k)
+        .into_iter()
+        .map(|ScoreIndex { score, index }| SimilarityResult {
+            score,
+            embedding: collection.embeddings[index].clone(),
+        })
+        .collect();
+    (results, cut)
+}
+
+// Scores documents by the share of the query's terms in their title, made of
+// the file name and the first heading, and returns the first chunk of the `k` best.
+fn title_retrieve(
+    collection: &Collection,
+    query_terms: &[String],
+    k: usize,
+) -> Vec<SimilarityResult> {
+    if query_terms.is_empty() {
+        return Vec::new();
+    }
+    // Index of the document's first chunk and its title's terms.
+    let mut documents: HashMap<i64, (usize, HashSet<String>)> = HashMap::new();
+    for (index, embedding) in collection.embeddings.iter().enumerate() {
+        let metadata = &embedding.metadata;
+        let (first, title) = documents.entry(metadata.document_id).or_insert_with(|| {
+            let name = metadata.path.rsplit('/').next().unwrap_or_default();
+            let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+            (index, terms(stem).into_iter().collect())
+        });
+        if metadata.chunk_index < collection.embeddings[*first].metadata.chunk_index {
+            *first = index;
+        }
+        if metadata.chunk_index == 0 {
+            let heading = embedding
+                .blob
+                .lines()
+                .find(|line| line.starts_with('#'))
+                .unwrap_or_default();
+            title.extend(terms(heading));
+        }
+    }
+
+    let scores = documents.into_values().filter_map(|(index, title)| {
+        let found = query_terms
+            .iter()
+            .filter(|term| title.contains(*term))
+            .count();
+        let score = found as f32 / query_terms.len() as f32;
+        (score > 0.0).then_some(ScoreIndex { score, index })
+    });
+    ranking::top_k(scores, k)
+        .into_iter()
+        .map(|ScoreIndex { score, index }| SimilarityResult {
+            score,
+            embedding: collection.embeddings[index].clone(),
+        })
+        .collect()
+}
+
+// Lowercased words, single characters are left out.
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn unique_terms(text: &str) -> Vec<String> {
+    let mut terms = terms(text);
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Distance, Metadata};
+
+    fn collection() -> Collection {
+        let mut collection = Collection::new("test".to_string(), 2, Distance::DotProduct);
+        let chunks = [
+            (
+                "1",
+                1,
+                vec![1.0, 0.0],
+                "Install the widget with the installer.",
+            ),
+            (
+                "2",
+                1,
+                vec![0.9, 0.1],
+                "Install the widget with the installer.",
+            ),
+            (
+                "3",
+                2,
+                vec![0.0, 1.0],
+                "Invoices are sent monthly, see billing.",
+            ),
+        ];
+        for (id, document_id, vector, blob) in chunks {
+            let metadata = Metadata {
+                document_id,
+                path: format!("doc-{}.md", document_id),
+                ..Default::default()
+            };
+            collection
+                .insert(id.to_string(), vector, blob.to_string(), metadata)
+                .unwrap();
+        }
+        collection
+    }
+
+    #[test]
+    fn test_run() {
+        let collection = collection();
+        let pipeline: Pipeline = serde_json::from_str(
+            r#"{"stages": [
+                {"stage": "vector_retrieve", "k": 3},
+                {"stage": "keyword_retrieve"},
+                {"stage": "fuse", "method": "sum"},
+                {"stage": "dedupe", "per_document": 1},
+                {"stage": "boost", "paths": {"doc-2": 10.0}}
+            ]}"#,
+        )
+        .unwrap();
+        pipeline.validate().unwrap();
+        let query = Query {
+            vector: &[1.0, 0.0],
+            text: "billing",
+            deadline: None,
+        };
+        let (ranked, partial) = run(&pipeline, &collection, &query);
+        assert!(!partial);
+        let ids: Vec<&str> = ranked
+            .iter()
+            .map(|r| r.result.embedding.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["3", "1"]);
+        let stages: Vec<&str> = ranked[0].contributions.iter().map(|c| c.stage).collect();
+        assert_eq!(
+            stages,
+            vec!["vector_retrieve", "keyword_retrieve", "fuse", "boost"]
+        );
+    }
+
+    #[test]
+    fn test_order_guides() {
+        let mut results = vec![
+            ("deploy", Some(("guides", 3))),
+            ("install", Some(("guides", 1))),
+            ("install-2", Some(("guides", 1))),
+            ("api", Some(("reference", 9))),
+            ("upgrade", Some(("guides", 4))),
+            ("faq", None),
+            ("setup", Some(("guides", 2))),
+        ];
+        order_guides(&mut results, |(_, nav)| *nav);
+        let order: Vec<&str> = results.iter().map(|(page, _)| *page).collect();
+        // Only adjacent results of a guide are reordered, the rest keeps its rank.
+        assert_eq!(
+            order,
+            vec![
+                "install",
+                "install-2",
+                "deploy",
+                "api",
+                "upgrade",
+                "faq",
+                "setup"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hybrid() {
+        let collection = collection();
+        let query = Query {
+            vector: &[1.0, 0.0],
+            text: "doc billing",
+            deadline: None,
+        };
+        let (ranked, _) = run(&Pipeline::hybrid(), &collection, &query);
+        // Found by all three retrievers, despite the vector ranking it last.
+        assert_eq!(ranked[0].result.embedding.id, "3");
+        let stages: Vec<&str> = ranked[0].contributions.iter().map(|c| c.stage).collect();
+        assert_eq!(
+            stages,
+            vec![
+                "vector_retrieve",
+                "keyword_retrieve",
+                "title_retrieve",
+                "fuse"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let pipeline = Pipeline {
+            stages: vec![
+                Stage::Rerank { weight: 0.1 },
+                Stage::VectorRetrieve { k: 10 },
+            ],
+        };
+        assert!(pipeline.validate().is_err());
+        assert!(Pipeline::default().validate().is_ok());
+        let pipeline = Pipeline {
+            stages: vec![Stage::KeywordRetrieve {
+                k: MAX_RETRIEVE_K + 1,
+            }],
+        };
+        assert!(pipeline.validate().is_err());
+    }
+
+    #[test]
+    fn test_keyword_retrieve() {
+        let mut collection = collection();
+        let terms = unique_terms("installer billing");
+        // Unpublished builds are indexed on the spot, with the same results.
+        let (scanned, _) = keyword_retrieve(&collection, &terms, 10, None);
+        index_terms(&mut collection);
+        let (indexed, cut) = keyword_retrieve(&collection, &terms, 10, None);
+        assert!(!cut);
+        let ids = |results: &[SimilarityResult]| -> Vec<String> {
+            results.iter().map(|r| r.embedding.id.clone()).collect()
+        };
+        assert_eq!(ids(&indexed), ids(&scanned));
+        assert_eq!(indexed.len(), 3);
+        // Billing is rarer than installer, so its chunk ranks first.
+        assert_eq!(indexed[0].embedding.id, "3");
+
+        let (results, cut) = keyword_retrieve(&collection, &terms, 1, None);
+        assert!(!cut);
+        assert_eq!(results.len(), 1);
+
+        let (results, cut) = keyword_retrieve(&collection, &terms, 10, Some(Instant::now()));
+        assert!(cut);
+        assert!(results.is_empty());
+    }
+}
@@ -0,0 +1,90 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, bail, Context, Result};
+
+/// Prefix of encrypted values, with the scheme's version for key rotation.
+const PREFIX: &str = "enc:v1:";
+
+/// Length of AES-GCM nonces in bytes.
+const NONCE_LEN: usize = 12;
+
+/// Encrypts credentials stored in the db with the master key, AES-256-GCM
+/// with a random nonce per value.
+pub struct Cipher {
+    aead: Aes256Gcm,
+}
+
+impl Cipher {
+    /// Takes the key hex encoded, 64 characters for its 32 bytes.
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key = hex::decode(key.trim()).context("Secrets key isn't hex encoded")?;
+        let aead = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("Secrets key has to be 32 bytes, got {}", key.len()))?;
+        Ok(Self { aead })
+    }
+
+    /// Empty values stay empty, so unset credentials are still recognizable.
+    pub fn encrypt(&self, value: &str) -> Result<String> {
+        if value.is_empty() {
+            return Ok(String::new());
+        }
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, value.as_bytes())
+            .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+        Ok(format!(
+            "{}{}{}",
+            PREFIX,
+            hex::encode(nonce),
+            hex::encode(ciphertext)
+        ))
+    }
+
+    /// Values stored before a key was configured are returned as they are.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let data = hex::decode(encoded).context("Encrypted secret isn't hex encoded")?;
+        if data.len() <= NONCE_LEN {
+            bail!("Encrypted secret is truncated");
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .aead
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt secret, was the key changed?"))?;
+        String::from_utf8(plaintext).context("Decrypted secret isn't UTF-8")
+    }
+}
+
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_cipher() {
+        let cipher = Cipher::from_hex(KEY).unwrap();
+        let encrypted = cipher.encrypt("wJalrXUtnFEMI/K7MDENG").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("wJalrXUtnFEMI"));
+        // Nonces are random, the same secret encrypts differently.
+        assert_ne!(encrypted, cipher.encrypt("wJalrXUtnFEMI/K7MDENG").unwrap());
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "wJalrXUtnFEMI/K7MDENG");
+
+        assert_eq!(cipher.encrypt("").unwrap(), "");
+        assert_eq!(cipher.decrypt("plain").unwrap(), "plain");
+        let other = Cipher::from_hex(&KEY.replace("00", "ff")).unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+        assert!(Cipher::from_hex("abcd").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    encoder, index,
+    parser::StubRepos,
+    pipeline::{self, Cancellation, EncodeOptions, ParseOptions},
+    types::{
+        BucketOptions, Collection, CrawlOptions, GitHubOptions, RepoMetadata, Source, SourceKind,
+        Translations,
+    },
+    AppState, Distance, DEFAULT_MODEL,
+};
+
+/// Manifest of the bundled sample corpus, used when no manifest is given.
+pub const SAMPLE_MANIFEST: &str = include_str!("../seed/sample.yaml");
+
+/// Collections and sources to create, see `seed/sample.yaml`.
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    pub collections: Vec<SeedCollection>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SeedCollection {
+    pub name: String,
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default)]
+    pub distance: Distance,
+    /// Defaults to the dimension of the model's vectors.
+    pub dimension: Option<usize>,
+    #[serde(default)]
+    pub sources: Vec<SeedSource>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SeedSource {
+    pub owner: String,
+    pub repo: String,
+    #[serde(default = "default_branch")]
+    pub branch: String,
+    #[serde(default)]
+    pub allowed_ext: Vec<String>,
+    #[serde(default)]
+    pub allowed_dirs: Vec<String>,
+    #[serde(default)]
+    pub ignored_dirs: Vec<String>,
+    #[serde(default = "encoder::default_skip_front_matter")]
+    pub skip_front_matter: Vec<String>,
+    /// Repo files keyed by path, ingested instead of fetching the repo from GitHub.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+fn default_model() -> String {
+    DEFAULT_MODEL.to_string()
+}
+
+fn default_branch() -> String {
+    "main".to_string()
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SeedReport {
+    pub collections_created: usize,
+    pub sources_created: usize,
+    /// Sources that already existed, they are left untouched.
+    pub sources_skipped: usize,
+    pub documents: usize,
+    pub chunks: usize,
+}
+
+impl Manifest {
+    /// Parses a YAML manifest, JSON works as well.
+    pub fn parse(text: &str) -> Result<Self> {
+        serde_yaml::from_str(text).context("Failed to parse seed manifest")
+    }
+
+    pub fn sample() -> Self {
+        Self::parse(SAMPLE_MANIFEST).expect("Sample manifest is valid")
+    }
+}
+
+/// Creates collections and sources of the manifest, then parses and encodes
+/// the new sources. Collections are matched by name and sources by repo and
+/// branch, so seeding the same manifest twice doesn't duplicate documents.
+pub async fn seed(state: &AppState, manifest: &Manifest) -> Result<SeedReport> {
+    let mut report = SeedReport::default();
+    for seed_collection in &manifest.collections {
+        let collections = state
+            .db
+            .query_collections()
+            .await
+            .context("Failed to query collections")?;
+        let collection = match collections
+            .into_iter()
+            .find(|collection| collection.name == seed_collection.name)
+        {
+            Some(collection) => collection,
+            None => {
+                report.collections_created += 1;
+                create_collection(state, seed_collection).await?
+            }
+        };
+
+        let sources = state
+            .db
+            .query_sources()
+            .await
+            .context("Failed to query sources")?;
+        for seed_source in &seed_collection.sources {
+            let exists = sources.iter().any(|source| {
+                source.collection_id == collection.id
+                    && source.owner == seed_source.owner
+                    && source.repo == seed_source.repo
+                    && source.branch == seed_source.branch
+            });
+            if exists {
+                tracing::info!(
+                    "Source {}/{} already exists, skipping",
+                    seed_source.owner,
+                    seed_source.repo
+                );
+                report.sources_skipped += 1;
+                continue;
+            }
+
+            let mut source = Source {
+                id: 0,
+                collection_id: collection.id,
+                kind: SourceKind::GitHub,
+                url: String::new(),
+                owner: seed_source.owner.clone(),
+                repo: seed_source.repo.clone(),
+                branch: seed_source.branch.clone(),
+                allowed_ext: seed_source.allowed_ext.iter().cloned().collect(),
+                allowed_dirs: seed_source.allowed_dirs.iter().cloned().collect(),
+                ignored_dirs: seed_source.ignored_dirs.iter().cloned().collect(),
+                skip_front_matter: seed_source.skip_front_matter.iter().cloned().collect(),
+                labels: HashSet::new(),
+                translations: Translations::default(),
+                crawl: CrawlOptions::default(),
+                bucket: BucketOptions::default(),
+                github: GitHubOptions::default(),
+                metadata: RepoMetadata::default(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            };
+            source.metadata = pipeline::repo_metadata(state, &source).await;
+            source.id = state
+                .db
+                .insert_source(&source)
+                .await
+                .context("Failed to insert source")?;
+            report.sources_created += 1;
+
+            // Inline files are served through stub repos instead of GitHub.
+            let state = if seed_source.files.is_empty() {
+                state.clone()
+            } else {
+                let name = format!("{}/{}", seed_source.owner, seed_source.repo);
+                let repos = seed_source
+                    .files
+                    .iter()
+                    .fold(StubRepos::default(), |repos, (path, content)| {
+                        repos.with_file(&name, path, content)
+                    });
+                AppState {
+                    stub_repos: Some(repos),
+                    ..state.clone()
+                }
+            };
+            let opts = ParseOptions {
+                check_links: false,
+                ..Default::default()
+            };
+            let cancel = Cancellation::default();
+            report.documents += pipeline::parse_source(&state, source.clone(), opts, &cancel)
+                .await
+                .with_context(|| format!("Failed to parse source #{}", source.id))?
+                .documents;
+            report.chunks +=
+                pipeline::encode_source(&state, source, EncodeOptions::default(), &cancel)
+                    .await
+                    .context("Failed to encode source")?
+                    .chunks;
+        }
+    }
+
+    if report.sources_created > 0 {
+        index::load_tinyvector(&state.db, state.tinyvector.clone()).await;
+    }
+    tracing::info!(?report, "Seeded");
+    Ok(report)
+}
+
+async fn create_collection(state: &AppState, seed: &SeedCollection) -> Result<Collection> {
+    let dimension = match seed.dimension {
+        Some(dimension) => dimension,
+        None => state
+            .embeddings(&seed.model)?
+            .dimension()
+            .await
+            .context("Failed to get model dimension")?,
+    };
+    let mut collection = Collection {
+        id: 0,
+        name: seed.name.clone(),
+        model: seed.model.clone(),
+        distance: seed.distance,
+        dimension,
+        index_version: 0,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    collection.id = state
+        .db
+        .insert_collection(&collection)
+        .await
+        .context("Failed to insert collection")?;
+    tracing::info!("Created collection '{}'", collection.name);
+    Ok(collection)
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::test_support::TestApp;
+
+    #[tokio::test]
+    async fn test_seed_sample_twice() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let manifest = Manifest::sample();
+
+        let report = seed(&app.state, &manifest).await.unwrap();
+        assert_eq!(report.collections_created, 1);
+        assert_eq!(report.sources_created, 1);
+        assert_eq!(report.documents, 4);
+        assert!(report.chunks > 0);
+
+        let report = seed(&app.state, &manifest).await.unwrap();
+        assert_eq!(report.collections_created, 0);
+        assert_eq!(report.sources_skipped, 1);
+        assert_eq!(report.documents, 0);
+    }
+}
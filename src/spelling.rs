@@ -0,0 +1,224 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+
+use crate::{tinyvector::Collection, AppState};
+
+/// Maximum edit distance between a word and its correction.
+const MAX_DISTANCE: usize = 2;
+/// Words shorter than this are too ambiguous to correct.
+const MIN_WORD_LEN: usize = 4;
+/// Longer tokens are hashes or URLs, deleting from them only bloats the index.
+const MAX_WORD_LEN: usize = 40;
+
+/// Symmetric delete spelling correction (SymSpell) over the corpus vocabulary.
+///
+/// Every word is indexed by the strings left after deleting up to `MAX_DISTANCE`
+/// characters, so candidates for a misspelled word are found by generating its
+/// own deletes instead of comparing it with the whole vocabulary.
+#[derive(Debug, Default)]
+pub struct SymSpell {
+    words: HashMap<String, u64>,
+    deletes: HashMap<String, Vec<String>>,
+}
+
+impl SymSpell {
+    pub fn from_corpus<'a>(texts: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut words: HashMap<String, u64> = HashMap::new();
+        for text in texts {
+            for word in tokens(text) {
+                *words.entry(word.to_lowercase()).or_default() += 1;
+            }
+        }
+
+        let mut deletes: HashMap<String, Vec<String>> = HashMap::new();
+        for word in words.keys() {
+            for delete in edits(word) {
+                deletes.entry(delete).or_default().push(word.clone());
+            }
+        }
+        Self { words, deletes }
+    }
+
+    /// Returns the most frequent closest word, or `None` if the word is known
+    /// or nothing is close enough.
+    pub fn correct_word(&self, word: &str) -> Option<String> {
+        let word = word.to_lowercase();
+        let len = word.chars().count();
+        if len < MIN_WORD_LEN || len > MAX_WORD_LEN || self.words.contains_key(&word) {
+            return None;
+        }
+
+        let mut candidates = HashSet::new();
+        let mut input_deletes = edits(&word);
+        input_deletes.insert(word.clone());
+        for delete in &input_deletes {
+            if self.words.contains_key(delete) {
+                candidates.insert(delete.clone());
+            }
+            if let Some(words) = self.deletes.get(delete) {
+                candidates.extend(words.iter().cloned());
+            }
+        }
+
+        candidates
+            .into_iter()
+            .map(|candidate| (distance(&word, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= MAX_DISTANCE)
+            .min_by(|(a_dist, a), (b_dist, b)| {
+                a_dist
+                    .cmp(b_dist)
+                    .then(self.words[b].cmp(&self.words[a]))
+                    .then(a.cmp(b))
+            })
+            .map(|(_, candidate)| candidate)
+    }
+
+    /// Corrects every misspelled word of the query, returns `None` if nothing changed.
+    pub fn correct(&self, query: &str) -> Option<String> {
+        let mut changed = false;
+        let corrected: Vec<String> = query
+            .split_whitespace()
+            .map(|word| {
+                let term = word.trim_matches(|c: char| !is_word_char(c));
+                match self.correct_word(term) {
+                    Some(correction) => {
+                        changed = true;
+                        word.replace(term, &correction)
+                    }
+                    None => word.to_string(),
+                }
+            })
+            .collect();
+        changed.then(|| corrected.join(" "))
+    }
+}
+
+// Identifiers like `aws_acm_certificate` are kept as a single word.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn tokens(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !is_word_char(c)).filter(|word| {
+        let len = word.chars().count();
+        (MIN_WORD_LEN..=MAX_WORD_LEN).contains(&len) && !word.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+// All strings left after deleting 1 to `MAX_DISTANCE` characters.
+fn edits(word: &str) -> HashSet<String> {
+    let mut result = HashSet::new();
+    let mut frontier = vec![word.to_string()];
+    for _ in 0..MAX_DISTANCE {
+        let mut next = Vec::new();
+        for current in frontier {
+            let chars: Vec<char> = current.chars().collect();
+            if chars.len() <= 1 {
+                continue;
+            }
+            for i in 0..chars.len() {
+                let delete: String = chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, c)| c)
+                    .collect();
+                if result.insert(delete.clone()) {
+                    next.push(delete);
+                }
+            }
+        }
+        frontier = next;
+    }
+    result
+}
+
+// Optimal string alignment distance, Levenshtein with adjacent transpositions.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Dictionaries built from collections' chunks, rebuilt when a collection changes.
+#[derive(Clone, Default)]
+pub struct Spelling {
+    dictionaries: Arc<RwLock<HashMap<String, (u64, Arc<SymSpell>)>>>,
+}
+
+impl Spelling {
+    fn cached(&self, name: &str, version: u64) -> Option<Arc<SymSpell>> {
+        let dictionaries = self.dictionaries.read().expect("Spelling lock is poisoned");
+        match dictionaries.get(name) {
+            Some((cached, dictionary)) if *cached == version => Some(dictionary.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Suggests a corrected query for "did you mean", `None` if the query looks fine.
+pub async fn suggest(
+    state: &AppState,
+    name: &str,
+    collection: Arc<Collection>,
+    query: &str,
+) -> anyhow::Result<Option<String>> {
+    let dictionary = match state.spelling.cached(name, collection.version) {
+        Some(dictionary) => dictionary,
+        None => {
+            let version = collection.version;
+            let dictionary = tokio::task::spawn_blocking(move || {
+                let texts = collection.embeddings.iter().map(|e| e.blob.as_str());
+                Arc::new(SymSpell::from_corpus(texts))
+            })
+            .await?;
+            state
+                .spelling
+                .dictionaries
+                .write()
+                .expect("Spelling lock is poisoned")
+                .insert(name.to_string(), (version, dictionary.clone()));
+            dictionary
+        }
+    };
+    Ok(dictionary.correct(query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correct_query() {
+        let spell = SymSpell::from_corpus([
+            "Use aws_acm_certificate to request a certificate.",
+            "The certificate is validated by DNS. Certificate renewal is automatic.",
+        ]);
+        assert_eq!(
+            spell.correct("aws_acm_certficate renewl"),
+            Some("aws_acm_certificate renewal".to_string())
+        );
+        assert_eq!(spell.correct("certificate renewal"), None);
+        assert_eq!(spell.correct("xyz"), None);
+    }
+}
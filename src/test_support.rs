@@ -0,0 +1,3143 @@
+//! In-process harness for end-to-end tests of the HTTP API, without model
+//! weights, tokens or network access.
+//!
+//! The app runs on an in-memory db with `Embeddings::hashed` as its default
+//! model, OpenAI is mocked and sources are parsed from `StubRepos`.
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use octocrab::Octocrab;
+use serde_json::Value;
+use std::{
+    net::{Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tower::ServiceExt;
+
+pub use crate::parser::StubRepos;
+use crate::{
+    app, load_tinyvector,
+    pipeline::{self, Cancellation},
+    spelling::Spelling,
+    types::{Job, Source},
+    Breakers, Configuration, Db, Embeddings, EmbeddingsProvider, Models, OpenAI, ParseBudget, Tiny,
+    Tokenizer, DEFAULT_MODEL,
+};
+
+/// Dimension of the vectors of the test model.
+pub const TEST_DIMENSION: usize = 64;
+
+pub struct TestApp {
+    pub router: Router,
+    pub state: crate::AppState,
+}
+
+/// Returns configuration suitable for tests, nothing is read from the environment.
+pub fn test_config() -> Configuration {
+    Configuration {
+        listen_address: SocketAddr::from((Ipv6Addr::LOCALHOST, 0)),
+        app_port: 0,
+        db_dsn: "sqlite::memory:".to_string(),
+        github_token: String::new(),
+        open_ai_key: String::new(),
+        search_deadline: Duration::from_secs(5),
+        embeddings_device: "cpu".to_string(),
+        embeddings_provider: EmbeddingsProvider::Hash,
+        hash_dimension: TEST_DIMENSION,
+        embeddings_models: Vec::new(),
+        max_body_bytes: 2 * 1024 * 1024,
+        max_upload_bytes: 8 * 1024 * 1024,
+        public_url: "http://localhost".to_string(),
+        request_timeout: Duration::from_secs(15),
+        search_timeout: Duration::from_secs(8),
+        long_timeout: Duration::from_secs(60),
+        search_concurrency: 8,
+        ask_concurrency: 4,
+        ocr_command: None,
+        offline: true,
+        oidc: None,
+        secrets_key: None,
+        github_app: None,
+        proxy: None,
+        parse_budget: ParseBudget {
+            max_files: 10_000,
+            max_bytes: 1 << 30,
+            max_duration: Duration::from_secs(3600),
+        },
+        api_sunset: None,
+    }
+}
+
+impl TestApp {
+    /// Spins up the app serving the stub repos.
+    pub async fn spawn(repos: StubRepos) -> anyhow::Result<Self> {
+        Self::spawn_with_config(repos, test_config()).await
+    }
+
+    /// Spins up the app with a configuration changed from `test_config`.
+    pub async fn spawn_with_config(repos: StubRepos, cfg: Configuration) -> anyhow::Result<Self> {
+        let cfg = Arc::new(cfg);
+        let db = Db::new_in_memory().await?;
+        db.migrate().await?;
+
+        let state = crate::AppState {
+            db,
+            github: Octocrab::default(),
+            github_app: None,
+            models: Models::new(Embeddings::from_config(DEFAULT_MODEL, &cfg)?),
+            tinyvector: Tiny::new().extension(),
+            tokenizer: Tokenizer::new()?,
+            openai: OpenAI::offline(),
+            spelling: Spelling::default(),
+            breakers: Breakers::default(),
+            rate_limits: Default::default(),
+            cancellations: Default::default(),
+            model_checks: Default::default(),
+            coverage: Default::default(),
+            rankings: Default::default(),
+            oidc: Default::default(),
+            stub_repos: Some(repos),
+            cfg,
+        };
+        Ok(Self {
+            router: app(state.clone()),
+            state,
+        })
+    }
+
+    /// Sends a request with an optional JSON body, returns the status and the
+    /// JSON response, `Value::Null` if the response isn't JSON.
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> anyhow::Result<(StatusCode, Value)> {
+        let mut req = Request::builder().method(method).uri(uri);
+        let body = match body {
+            Some(body) => {
+                req = req.header(header::CONTENT_TYPE, "application/json");
+                Body::from(serde_json::to_vec(&body)?)
+            }
+            None => Body::empty(),
+        };
+        let resp = self.router.clone().oneshot(req.body(body)?).await?;
+        let status = resp.status();
+        let bytes = hyper::body::to_bytes(resp.into_body()).await?;
+        let json = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+        Ok((status, json))
+    }
+
+    /// Creates the `default` collection, unless it exists, and a source of the
+    /// markdown files of the stub repo `acme/<repo>` in it.
+    pub async fn with_source(&self, repo: &str) -> anyhow::Result<Source> {
+        let collections = self.state.db.query_collections().await?;
+        let collection_id = match collections.iter().find(|c| c.name == "default") {
+            Some(collection) => collection.id,
+            None => {
+                let body = serde_json::json!({ "name": "default", "dimension": TEST_DIMENSION });
+                let (status, body) = self
+                    .request(Method::PUT, "/api/collections", Some(body))
+                    .await?;
+                anyhow::ensure!(status == StatusCode::CREATED, "{}: {}", status, body);
+                body["id"].as_i64().unwrap_or_default()
+            }
+        };
+        let body = serde_json::json!({
+            "collection_id": collection_id,
+            "owner": "acme",
+            "repo": repo,
+            "branch": "main",
+            "allowed_ext": [".md"],
+        });
+        let (status, body) = self
+            .request(Method::PUT, "/api/sources", Some(body))
+            .await?;
+        anyhow::ensure!(status == StatusCode::CREATED, "{}: {}", status, body);
+        self.state
+            .db
+            .query_sources()
+            .await?
+            .into_iter()
+            .find(|source| source.collection_id == collection_id && source.repo == repo)
+            .ok_or_else(|| anyhow::anyhow!("Source acme/{} wasn't created", repo))
+    }
+
+    /// Parses and encodes the source in place and reloads tinyvector, so tests
+    /// don't have to wait for the background encode started by the API.
+    pub async fn index_source(&self, source_id: i64) -> anyhow::Result<()> {
+        let source = self.state.db.select_source(source_id).await?;
+        let cancel = Cancellation::default();
+        pipeline::sync_source(
+            &self.state,
+            source,
+            Default::default(),
+            Default::default(),
+            &cancel,
+        )
+        .await?;
+        load_tinyvector(&self.state.db, self.state.tinyvector.clone()).await;
+        Ok(())
+    }
+
+    /// Polls the job started by the API until it's finished.
+    pub async fn wait_for_job(&self, job_id: i64) -> anyhow::Result<Job> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        loop {
+            let job = self.state.db.select_job(job_id).await?;
+            if job.status.is_finished() {
+                return Ok(job);
+            }
+            anyhow::ensure!(
+                tokio::time::Instant::now() < deadline,
+                "Job #{} didn't finish in time",
+                job_id
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Chunk, JobKind, JobStatus, Role, SourceStats};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_search_stub_repo() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer to set up the widget.",
+            )
+            .with_file(
+                "acme/docs",
+                "billing.md",
+                "# Billing\n\nInvoices are sent at the end of the month.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=invoices%20billing", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["path"], "billing.md");
+    }
+
+    #[tokio::test]
+    async fn test_list_documents() {
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "a.md", "# A\n\nFirst page.")
+            .with_file("acme/docs", "b.md", "# B\n\nSecond page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources/{}/docs?page=2&per_page=1", source.id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert!(body[0]["tokens_len"].as_u64().unwrap() > 0);
+        assert!(body[0].get("data").is_none());
+        let uri = format!("/api/sources/{}/docs?page=3&per_page=1", source.id);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_get_document() {
+        let repos = StubRepos::default().with_file("acme/docs", "a.md", "# A\n\nFirst page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources/{}/docs", source.id);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        let uri = format!("/api/documents/{}", body[0]["id"]);
+        let (status, document) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(document["path"], "a.md");
+        assert!(document["data"].as_str().unwrap().starts_with("# A"));
+        let (status, _) = app
+            .request(Method::GET, "/api/documents/999", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_list_chunks() {
+        let repos = StubRepos::default().with_file("acme/docs", "a.md", "# A\n\nFirst page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources/{}/chunks?per_page=1", source.id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(body[0].get("vector").is_none());
+        let uri = format!(
+            "/api/sources/{}/chunks?per_page=1&include_vector=true",
+            source.id
+        );
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body[0]["vector"].as_array().unwrap().len(), TEST_DIMENSION);
+    }
+
+    #[tokio::test]
+    async fn test_create_document() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+
+        let (status, body) = app
+            .request(
+                Method::POST,
+                "/api/documents",
+                Some(json!({
+                    "collection_id": collection.id,
+                    "path": "notes/oncall.md",
+                    "data": "# On-call\n\nPage the on-call engineer through the pager rotation.",
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert!(body["chunks"].as_u64().unwrap() > 0);
+
+        // Searchable without parsing or encoding a source.
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=pager%20rotation", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["path"], "notes/oncall.md");
+    }
+
+    #[tokio::test]
+    async fn test_reindex_source() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let state = crate::AppState {
+            stub_repos: Some(StubRepos::default().with_file(
+                "acme/docs",
+                "setup.md",
+                "# Setup\n\nThe installer was renamed to setup.",
+            )),
+            ..app.state.clone()
+        };
+        pipeline::reindex_source(&state, source.clone())
+            .await
+            .unwrap();
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].path, "setup.md");
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=setup", None)
+            .await
+            .unwrap();
+        assert_eq!(body["results"][0]["path"], "setup.md");
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/api/sources/{}/rollback", source.id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].path, "install.md");
+    }
+
+    #[tokio::test]
+    async fn test_pinned_index_version() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+
+        for (path, data) in [
+            ("backups.md", "# Backups\n\nSnapshots are taken nightly."),
+            (
+                "restore.md",
+                "# Restore\n\nRestore a snapshot from the console.",
+            ),
+        ] {
+            let (status, _) = app
+                .request(
+                    Method::POST,
+                    "/api/documents",
+                    Some(json!({ "collection_id": collection.id, "path": path, "data": data })),
+                )
+                .await
+                .unwrap();
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=snapshot&limit=10", None)
+            .await
+            .unwrap();
+        let paths = |body: &Value| -> Vec<String> {
+            body["results"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|result| result["path"].as_str().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(body["index_version"], 2);
+        assert!(paths(&body).contains(&"restore.md".to_string()));
+
+        // The build before the second document was added is retained.
+        let (status, body) = app
+            .request(
+                Method::GET,
+                "/api/search?query=snapshot&limit=10&index_version=1",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["index_version"], 1);
+        assert!(!paths(&body).contains(&"restore.md".to_string()));
+        assert!(paths(&body).contains(&"backups.md".to_string()));
+
+        let (status, _) = app
+            .request(
+                Method::GET,
+                "/api/search?query=snapshot&index_version=7",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ranking_pipeline() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        let (status, _) = app
+            .request(
+                Method::POST,
+                "/api/documents",
+                Some(json!({
+                    "collection_id": collection.id,
+                    "path": "billing.md",
+                    "data": "# Billing\n\nInvoices are sent monthly.",
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        let uri = format!("/api/collections/{}/ranking", collection.id);
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                &uri,
+                Some(json!({ "stages": [{ "stage": "dedupe" }] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                &uri,
+                Some(json!({ "stages": [
+                    { "stage": "vector_retrieve" },
+                    { "stage": "keyword_retrieve" },
+                    { "stage": "rerank", "weight": 0.5 }
+                ] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body["stages"].as_array().unwrap().len(), 3);
+
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=invoices&debug=true", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let stages: Vec<&str> = body["results"][0]["stages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stage| stage["stage"].as_str().unwrap())
+            .collect();
+        assert!(stages.contains(&"keyword_retrieve"));
+        assert!(stages.contains(&"rerank"));
+
+        let (status, _) = app.request(Method::DELETE, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(
+            body,
+            json!({ "stages": [{ "stage": "vector_retrieve", "k": 100 }] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "billing.md",
+                "# Billing\n\nInvoices are sent monthly.",
+            )
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let (status, body) = app
+            .request(
+                Method::GET,
+                "/api/search/hybrid?query=billing&debug=true",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["path"], "billing.md");
+        let stages = body["results"][0]["stages"].to_string();
+        assert!(stages.contains("title_retrieve"));
+        assert!(stages.contains("fuse"));
+    }
+
+    #[tokio::test]
+    async fn test_search_facets() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "billing.md",
+                "# Billing\n\nInvoices are sent monthly.",
+            )
+            .with_file(
+                "acme/docs",
+                "guides/invoices.md",
+                "# Invoices\n\nDownload invoices.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let (_, body) = app
+            .request(
+                Method::GET,
+                "/api/search?query=invoices&facets=true&limit=10",
+                None,
+            )
+            .await
+            .unwrap();
+        let facets = &body["facets"];
+        assert_eq!(facets["directories"][""], 1);
+        assert_eq!(facets["directories"]["guides"], 1);
+        assert_eq!(facets["sources"][source.id.to_string()], 2);
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=invoices", None)
+            .await
+            .unwrap();
+        assert!(body.get("facets").map_or(true, Value::is_null));
+    }
+
+    #[tokio::test]
+    async fn test_access_token() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                &format!("/api/collections/{}/tokens", collection.id),
+                Some(json!({ "name": "docs site", "requests_per_minute": 1 })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let search = |token: Option<&str>| {
+            let mut req = Request::builder().uri("/public/search?query=install");
+            if let Some(token) = token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {}", token));
+            }
+            let req = req.body(Body::empty()).unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(search(None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            search(Some("rtfm_pub_guess")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(search(Some(&token)).await, StatusCode::OK);
+        assert_eq!(search(Some(&token)).await, StatusCode::TOO_MANY_REQUESTS);
+
+        let (_, body) = app
+            .request(
+                Method::GET,
+                &format!("/api/collections/{}/tokens", collection.id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body[0]["name"], "docs site");
+        assert!(body[0].get("token_hash").is_none());
+        let (status, _) = app
+            .request(
+                Method::DELETE,
+                &format!("/api/tokens/{}", body[0]["id"]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(search(Some(&token)).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                &format!("/api/collections/{}/tokens", body["id"]),
+                Some(json!({ "name": "docs site", "requests_per_minute": 1 })),
+            )
+            .await
+            .unwrap();
+        let token_id = body["id"].clone();
+        app.request(Method::GET, "/api/collections", None)
+            .await
+            .unwrap();
+
+        // Clients can't name the actor themselves.
+        let req = Request::builder()
+            .method(Method::DELETE)
+            .uri(format!("/api/tokens/{}", token_id))
+            .header("x-actor", "mallory")
+            .body(Body::empty())
+            .unwrap();
+        app.router.clone().oneshot(req).await.unwrap();
+
+        let (status, body) = app
+            .request(Method::GET, "/api/audit?limit=10", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let actions: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["action"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                "DELETE /api/tokens/:token_id",
+                "PUT /api/collections/:collection_id/tokens",
+                "PUT /api/collections",
+            ]
+        );
+        assert_eq!(body[0]["actor"], "anonymous");
+        assert!(body[1]["summary"]
+            .as_str()
+            .unwrap()
+            .contains("\"requests_per_minute\":1"));
+    }
+
+    #[tokio::test]
+    async fn test_widget_origins() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let uri = format!(
+            "/api/collections/{}/tokens",
+            app.state.db.query_collections().await.unwrap()[0].id
+        );
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                &uri,
+                Some(json!({ "name": "docs", "allowed_origins": ["docs.example.com"] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                &uri,
+                Some(json!({ "name": "docs", "allowed_origins": ["https://docs.example.com/"] })),
+            )
+            .await
+            .unwrap();
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let search = |origin: &str| {
+            let req = Request::builder()
+                .uri("/public/search?query=install")
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .header(header::ORIGIN, origin)
+                .body(Body::empty())
+                .unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+        assert_eq!(search("https://docs.example.com").await, StatusCode::OK);
+        assert_eq!(
+            search("https://evil.example.com").await,
+            StatusCode::FORBIDDEN
+        );
+
+        let req = Request::builder()
+            .uri("/widget.js")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.router.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("application/javascript"));
+    }
+
+    #[tokio::test]
+    async fn test_session_roles() {
+        let cfg = Configuration {
+            oidc: Some(crate::OidcConfig {
+                issuer: "https://idp.example.com".to_string(),
+                client_id: "rtfm".to_string(),
+                client_secret: "secret".to_string(),
+                groups_claim: "groups".to_string(),
+                admin_groups: vec!["docs-admins".to_string()],
+                viewer_groups: Vec::new(),
+            }),
+            ..test_config()
+        };
+        let app = TestApp::spawn_with_config(StubRepos::default(), cfg)
+            .await
+            .unwrap();
+        for (token, role) in [("viewer-token", Role::Viewer), ("admin-token", Role::Admin)] {
+            let now = chrono::Utc::now();
+            let session = crate::types::Session {
+                id_hash: crate::access::hash_token(token),
+                subject: token.to_string(),
+                email: format!("{}@example.com", role.as_str()),
+                role,
+                created_at: now,
+                expires_at: now + chrono::Duration::hours(1),
+            };
+            app.state.db.insert_session(&session).await.unwrap();
+        }
+        let get = |uri: &str, token: Option<&str>| {
+            let mut req = Request::builder().uri(uri);
+            if let Some(token) = token {
+                req = req.header(header::COOKIE, format!("rtfm_session={}", token));
+            }
+            let req = req.body(Body::empty()).unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+
+        assert_eq!(
+            get("/dashboard/sources", Some("viewer-token")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get("/api/admin/model", Some("viewer-token")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            get("/api/admin/model", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            get("/api/admin/model", Some("admin-token")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collections_crud() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "staging", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["id"].as_i64().unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/sources",
+                Some(json!({
+                    "collection_id": id,
+                    "owner": "acme",
+                    "repo": "docs",
+                    "branch": "main",
+                })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let source = &app.state.db.query_sources().await.unwrap()[0];
+        app.index_source(source.id).await.unwrap();
+
+        let (status, body) = app
+            .request(
+                Method::PATCH,
+                &format!("/api/collections/{}", id),
+                Some(json!({ "name": "default" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["name"], "default");
+        let (_, body) = app
+            .request(Method::GET, "/api/collections", None)
+            .await
+            .unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["name"], "default");
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=installer", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["path"], "install.md");
+
+        let (status, _) = app
+            .request(Method::DELETE, &format!("/api/collections/{}", id), None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = app
+            .request(Method::GET, &format!("/api/collections/{}", id), None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(app.state.db.query_sources().await.unwrap().is_empty());
+        assert!(app
+            .state
+            .tinyvector
+            .read()
+            .await
+            .get_collection("default")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_sources() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources?collection_id={}", source.collection_id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["repo"], "docs");
+        assert_eq!(body[0]["documents"], 1);
+        assert!(body[0]["chunks"].as_i64().unwrap() > 0);
+        assert!(body[0]["parsed_at"].is_string());
+        let (_, body) = app
+            .request(Method::GET, "/api/sources?collection_id=0", None)
+            .await
+            .unwrap();
+        assert_eq!(body, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_get_source() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources/{}", source.id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["allowed_ext"], json!([".md"]));
+        assert_eq!(body["documents"], 1);
+        assert!(body["tokens"].as_i64().unwrap() > 0);
+        let (status, _) = app
+            .request(Method::GET, "/api/sources/999", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_source_summary() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer.",
+            )
+            .with_file("acme/docs", "faq.md", "# FAQ");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let parse = |query: &str| {
+            let uri = format!("/api/sources/{}/parse?{}", source.id, query);
+            let app = &app;
+            async move {
+                let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+                app.wait_for_job(body["job_id"].as_i64().unwrap())
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let full = parse("").await;
+        let uri = format!("/api/sources/{}", source.id);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body["summary"]["files_by_ext"][".md"], 2);
+        assert_eq!(body["summary"]["largest_files"][0]["path"], "install.md");
+
+        // Each parse keeps its summary with its job, the source shows the last one.
+        parse("max_files=1").await;
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body["summary"]["files_by_ext"][".md"], 1);
+        let full = app.state.db.select_job(full.id).await.unwrap();
+        assert_eq!(full.result.unwrap()["summary"]["files_by_ext"][".md"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_source() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        let uri = format!("/api/sources/{}", source.id);
+        let (status, body) = app
+            .request(
+                Method::PATCH,
+                &uri,
+                Some(json!({ "branch": "next", "ignored_dirs": ["drafts"] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["branch"], "next");
+        assert_eq!(body["ignored_dirs"], json!(["drafts"]));
+        assert_eq!(body["allowed_ext"], json!([".md"]));
+    }
+
+    #[tokio::test]
+    async fn test_delete_source() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!("/api/sources/{}", source.id);
+        let (status, _) = app.request(Method::DELETE, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert!(app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap()
+            .is_empty());
+        let tinyvector = app.state.tinyvector.read().await;
+        assert!(tinyvector
+            .get_collection("default")
+            .unwrap()
+            .embeddings
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_parse_budget() {
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "a.md", "# A\n\nFirst page.")
+            .with_file("acme/docs", "b.md", "# B\n\nSecond page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        let uri = format!("/api/sources/{}/parse?max_files=1", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        let report = job.result.unwrap();
+        assert_eq!(report["status"], "partial");
+        assert_eq!(report["documents"], 1);
+        assert_eq!(report["stopped_by"], "max_files of 1");
+    }
+
+    #[tokio::test]
+    async fn test_jobs() {
+        let repos = StubRepos::default().with_file("acme/docs", "a.md", "# A\n\nFirst page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        let uri = format!("/api/sources/{}/parse", source.id);
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        let uri = format!("/api/jobs/{}", job.id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["kind"], "parse");
+        assert_eq!(body["status"], "succeeded");
+        assert_eq!(body["documents"], 1);
+        assert_eq!(body["error"], "");
+
+        let uri = format!("/api/sources/{}/encode", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Succeeded);
+        assert_eq!(job.documents, 1);
+        let uri = format!("/api/jobs?source_id={}&status=succeeded", source.id);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let kinds: Vec<&str> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|job| job["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["encode", "parse"]);
+        let (status, _) = app
+            .request(Method::GET, "/api/jobs/999", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_job() {
+        let repos = StubRepos::default().with_file("acme/docs", "a.md", "# A\n\nFirst page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        let uri = format!("/api/sources/{}/parse", source.id);
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        let uri = format!("/api/jobs/{}/cancel", job.id);
+        let (status, _) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let cancel = Cancellation::default();
+        cancel.cancel();
+        let err = pipeline::encode_source(&app.state, source, Default::default(), &cancel)
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).ends_with("Cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_sync_source() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.\n\n# Usage\n\nCall it.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        let uri = format!("/api/sources/{}/sync?check_links=false", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(job.kind, JobKind::Sync);
+        assert_eq!(job.status, JobStatus::Succeeded);
+        let report = job.result.unwrap();
+        assert_eq!(report["parse"]["documents"], 1);
+        assert_eq!(report["encode"]["chunks"], 1);
+
+        // Searchable without reloading tinyvector.
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=installer", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["results"][0]["path"], "install.md");
+    }
+
+    #[tokio::test]
+    async fn test_incremental_sync() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.\n\n# Usage\n\nCall it.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let uri = format!(
+            "/api/sources/{}/sync?check_links=false&incremental=true",
+            source.id
+        );
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        let report = job.result.unwrap();
+        assert_eq!(report["parse"]["documents"], 0);
+        assert_eq!(report["parse"]["unchanged"], 1);
+        assert_eq!(report["encode"]["chunks"], 0);
+
+        let state = crate::AppState {
+            stub_repos: Some(StubRepos::default().with_file(
+                "acme/docs",
+                "usage.md",
+                "# Usage\n\nCall the widget.\n\n# Limits\n\nTen per day.",
+            )),
+            ..app.state.clone()
+        };
+        let incremental = pipeline::ParseOptions {
+            incremental: true,
+            check_links: false,
+            ..Default::default()
+        };
+        let encode = pipeline::EncodeOptions {
+            incremental: true,
+            ..Default::default()
+        };
+        let report = pipeline::sync_source(
+            &state,
+            source.clone(),
+            incremental,
+            encode,
+            &Cancellation::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.parse.documents, 1);
+        assert_eq!(report.parse.removed, 1);
+        assert_eq!(report.encode.chunks, 1);
+        let chunks = app
+            .state
+            .db
+            .query_chunks_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert!(chunks[0].data.starts_with("# Usage"));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_models() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer.\n\n# Usage\n\nCall it.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        app.index_source(source.id).await.unwrap();
+
+        let mut chunks = app
+            .state
+            .db
+            .query_chunks_by_source(source.id)
+            .await
+            .unwrap();
+        let chunk = chunks.remove(0);
+        assert_eq!(chunk.model, collection.model);
+        let embeddings = app.state.embeddings(&collection.model).unwrap();
+        assert_eq!(chunk.model_version, embeddings.version());
+        assert_eq!(chunk.dimension, TEST_DIMENSION);
+        let (status, body) = app
+            .request(Method::GET, "/api/admin/check", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["mismatched"], json!([]));
+
+        // A chunk encoded by another provider's build of the model.
+        app.state
+            .db
+            .insert_chunk(&Chunk {
+                chunk_index: 1,
+                model_version: "bert".to_string(),
+                ..chunk
+            })
+            .await
+            .unwrap();
+        let (_, body) = app
+            .request(Method::GET, "/api/admin/check", None)
+            .await
+            .unwrap();
+        assert_eq!(body[0]["mismatched"].as_array().unwrap().len(), 1);
+
+        let uri = format!("/api/sources/{}/sync?check_links=false", source.id);
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        let job = app
+            .wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.contains("re-embed"), "{}", job.error);
+    }
+
+    #[tokio::test]
+    async fn test_discover_sources() {
+        let repos = StubRepos::default()
+            .with_file("acme/mono", "README.md", "# Mono")
+            .with_file("acme/mono", "packages/cli/docs/usage.md", "# Usage")
+            .with_file("acme/mono", "packages/cli/src/main.rs", "fn main() {}");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("mono").await.unwrap();
+        let uri = format!("/api/sources/{}/discover", source.id);
+
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["path"], "packages/cli");
+        assert_eq!(body[0]["docs_dir"], "packages/cli/docs/");
+        assert_eq!(body[0]["labels"], json!(["cli"]));
+        assert!(body[0]["source_id"].is_null());
+
+        let uri = format!("{}?create=true", uri);
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        let created = body[0]["source_id"].as_i64().unwrap();
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(body[0]["source_id"], created);
+        let source = app.state.db.select_source(created).await.unwrap();
+        assert!(source.allowed_dirs.contains("packages/cli/docs/"));
+    }
+
+    #[tokio::test]
+    async fn test_translations() {
+        let repos = ["acme/docs", "acme/site"]
+            .iter()
+            .fold(StubRepos::default(), |repos, name| {
+                repos
+                    .with_file(
+                        name,
+                        "docs/intro.md",
+                        "# Intro\n\nThe widget makes widgets.",
+                    )
+                    .with_file(name, "docs/zh/intro.md", "# 简介\n\n这个小部件制作小部件。")
+            });
+        let app = TestApp::spawn(repos).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        for (repo, translations) in [("docs", "skip"), ("site", "index")] {
+            let (status, _) = app
+                .request(
+                    Method::PUT,
+                    "/api/sources",
+                    Some(json!({
+                        "collection_id": collection.id,
+                        "owner": "acme",
+                        "repo": repo,
+                        "branch": "main",
+                        "allowed_ext": [".md"],
+                        "translations": translations,
+                    })),
+                )
+                .await
+                .unwrap();
+            assert_eq!(status, StatusCode::CREATED);
+        }
+
+        let sources = app.state.db.query_sources().await.unwrap();
+        let mut paths = Vec::new();
+        for source in &sources {
+            app.index_source(source.id).await.unwrap();
+            let mut docs = app
+                .state
+                .db
+                .query_documents_by_source(source.id)
+                .await
+                .unwrap();
+            docs.sort_by(|a, b| a.path.cmp(&b.path));
+            paths.push(
+                docs.into_iter()
+                    .map(|doc| (doc.path, doc.language, doc.canonical_path))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        let doc = |path: &str, language: &str, canonical_path: &str| {
+            (
+                path.to_string(),
+                language.to_string(),
+                canonical_path.to_string(),
+            )
+        };
+        assert_eq!(paths[0], vec![doc("docs/intro.md", "", "")]);
+        assert_eq!(
+            paths[1],
+            vec![
+                doc("docs/intro.md", "", ""),
+                doc("docs/zh/intro.md", "zh", "docs/intro.md")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openapi() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, spec) = app
+            .request(Method::GET, "/api/openapi.json", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let paths = &spec["paths"];
+        assert!(paths["/api/v1/sources/{source_id}/sync"]["post"].is_object());
+        assert!(paths["/api/v1/search"]["get"].is_object());
+        assert!(paths["/api/v1/jobs/{job_id}/cancel"]["post"].is_object());
+        assert!(spec["components"]["schemas"]["SearchResults"].is_object());
+
+        let (status, _) = app.request(Method::GET, "/api/docs/", None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_covers_routes() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, spec) = app
+            .request(Method::GET, "/api/openapi.json", None)
+            .await
+            .unwrap();
+
+        // Routes of `api::routes` as registered, `.route("/path", get(a).put(b))`.
+        let route =
+            regex::Regex::new(r#"\.route\(\s*"([^"]+)",\s*((?:\.?\s*[a-z]+\(\w+\)\s*)+),?\s*\)"#)
+                .unwrap();
+        let verb = regex::Regex::new(r"([a-z]+)\(\w+\)").unwrap();
+        let param = regex::Regex::new(r":(\w+)").unwrap();
+        let source = include_str!("routes/api.rs");
+        let source = &source[..source.find("\n#[derive").unwrap()];
+        let mut routes = 0;
+        for captures in route.captures_iter(source) {
+            let path = format!("/api/v1{}", param.replace_all(&captures[1], "{${1}}"));
+            for method in verb.captures_iter(&captures[2]) {
+                let operation = &spec["paths"][&path][&method[1]];
+                assert!(
+                    operation.is_object(),
+                    "{} {} is not documented",
+                    &method[1],
+                    path
+                );
+                routes += 1;
+            }
+        }
+        assert!(routes >= 48, "only {} routes found", routes);
+
+        // Sync takes the flags of both stages, `incremental` is listed once.
+        let params = spec["paths"]["/api/v1/sources/{source_id}/sync"]["post"]["parameters"]
+            .as_array()
+            .unwrap();
+        let names: Vec<&str> = params.iter().filter_map(|p| p["name"].as_str()).collect();
+        assert!(names.contains(&"summarize"));
+        assert!(names.contains(&"exclude_diagrams"));
+        assert_eq!(
+            names.iter().filter(|name| **name == "incremental").count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_api_versions() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let get = |uri: &str| {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+
+        let resp = get("/api/sources?limit=5").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["deprecation"], "true");
+        assert_eq!(
+            resp.headers()[header::LINK],
+            "</api/v1/sources?limit=5>; rel=\"successor-version\""
+        );
+        assert!(resp.headers().get("api-version").is_none());
+
+        let resp = get("/api/v1/sources?limit=5").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["api-version"], "1");
+        assert!(resp.headers().get("deprecation").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_sources_in_bulk() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "docs", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let id = body["id"].as_i64().unwrap();
+        let source = |repo: &str| json!({ "collection_id": id, "owner": "acme", "repo": repo, "branch": "main" });
+
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/api/sources/bulk",
+                Some(json!([source("docs"), source("guides")])),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let results = body["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| result["id"].is_i64()));
+        let uri = format!("/api/sources/{}", results[1]["id"]);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["repo"], "guides");
+
+        // One invalid source keeps the others from being created as well.
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/api/sources/bulk",
+                Some(json!([
+                    source("blog"),
+                    { "collection_id": id, "owner": "acme" },
+                    { "collection_id": id + 1, "owner": "acme", "repo": "api", "branch": "main" },
+                ])),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["results"][0]["error"].is_null());
+        assert!(body["results"][0]["id"].is_null());
+        assert!(body["results"][1]["error"].is_string());
+        assert!(body["results"][2]["error"]
+            .as_str()
+            .unwrap()
+            .contains("does not exist"));
+        let (_, body) = app
+            .request(
+                Method::GET,
+                &format!("/api/sources?collection_id={}", id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_api_envelope() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, legacy) = app
+            .request(Method::GET, "/api/sources", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = app
+            .request(Method::GET, "/api/v1/sources", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"], legacy);
+        assert!(body["error"].is_null());
+        assert!(!body["request_id"].as_str().unwrap().is_empty());
+
+        // Rejections of extractors are plain text, they are wrapped as well.
+        let (status, body) = app
+            .request(Method::GET, "/api/v1/search", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["data"].is_null());
+        assert_eq!(body["error"]["status"], 400);
+        assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/api/v1/collections",
+                Some(json!({ "name": "staging", "dimension": 0 })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body["error"]["message"],
+            "Dimension must be greater than zero"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "docs", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let payload = serde_json::to_vec(&json!({
+            "collection_id": body["id"], "owner": "acme", "repo": "docs", "branch": "main"
+        }))
+        .unwrap();
+        let put = |key: &str, body: Body| {
+            let req = Request::builder()
+                .method(Method::PUT)
+                .uri("/api/sources")
+                .header(header::CONTENT_TYPE, "application/json")
+                .header("idempotency-key", key)
+                .body(body)
+                .unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+
+        assert_eq!(
+            put("a", Body::from(payload.clone())).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            put("a", Body::from(payload.clone())).await,
+            StatusCode::CREATED
+        );
+        let (_, sources) = app
+            .request(Method::GET, "/api/sources", None)
+            .await
+            .unwrap();
+        assert_eq!(sources.as_array().unwrap().len(), 1);
+
+        // A retry while the first request still holds the key doesn't run it again.
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(b"PUT/api/sources");
+        hasher.update(&payload);
+        let claim = crate::types::IdempotencyRecord {
+            key: "b".to_string(),
+            request_hash: hasher.finalize(),
+            status: 0,
+            body: String::new(),
+            created_at: chrono::Utc::now(),
+        };
+        app.state
+            .db
+            .insert_idempotency_record(&claim)
+            .await
+            .unwrap();
+        assert_eq!(put("b", Body::from(payload)).await, StatusCode::CONFLICT);
+
+        // Chunked bodies have no Content-Length to reject upfront.
+        let chunks =
+            futures::stream::iter((0..3).map(|_| Ok::<_, std::io::Error>(vec![b' '; 1024 * 1024])));
+        assert_eq!(
+            put("c", Body::wrap_stream(chunks)).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reembed_takes_configured_models() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "docs", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let uri = format!("/api/admin/collections/{}/candidates", body["id"]);
+        let (status, _) = app
+            .request(Method::POST, &uri, Some(json!({ "model": "/etc" })))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = app
+            .request(Method::POST, &uri, Some(json!({ "model": DEFAULT_MODEL })))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_export_documents() {
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "a.md", "# A\n\nFirst page.")
+            .with_file("acme/docs", "b.md", "# B\n\nSecond page.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let export = |uri: String| {
+            let req = Request::builder()
+                .uri(uri)
+                .header(header::ACCEPT, "application/x-ndjson")
+                .body(Body::empty())
+                .unwrap();
+            let router = app.router.clone();
+            async move {
+                let resp = router.oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+                String::from_utf8(bytes.to_vec())
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str::<Value>(line).unwrap())
+                    .collect::<Vec<_>>()
+            }
+        };
+        let docs = export(format!("/api/sources/{}/docs", source.id)).await;
+        let mut paths: Vec<&str> = docs
+            .iter()
+            .map(|doc| doc["path"].as_str().unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["a.md", "b.md"]);
+        assert!(docs[0].get("data").is_none());
+        let docs = export(format!("/api/sources/{}/docs?per_page=1", source.id)).await;
+        assert_eq!(docs.len(), 1);
+
+        let chunks = export(format!("/api/sources/{}/chunks", source.id)).await;
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].get("vector").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_get() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let get = |uri: &str, etag: Option<&str>| {
+            let mut req = Request::builder().uri(uri);
+            if let Some(etag) = etag {
+                req = req.header(header::IF_NONE_MATCH, etag);
+            }
+            let req = req.body(Body::empty()).unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap() }
+        };
+        for uri in ["/api/search?query=installer", "/dashboard/sources"] {
+            let resp = get(uri, None).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let etag = resp.headers()[header::ETAG].to_str().unwrap().to_string();
+            let resp = get(uri, Some(&etag)).await;
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED, "{}", uri);
+        }
+        let resp = get("/api/search?query=installer", Some("\"stale\"")).await;
+        assert_eq!(resp.headers()[header::VARY], "Accept");
+    }
+
+    #[tokio::test]
+    async fn test_aliases() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let alias = json!({ "alias": "k8s", "expansion": "kubernetes" });
+        let uri = format!("/api/collections/{}/aliases", body["id"]);
+        let (status, _) = app
+            .request(Method::PUT, &uri, Some(alias.clone()))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(body[0]["expansion"], "kubernetes");
+
+        let (status, body) = app
+            .request(Method::PUT, "/api/collections/999/aliases", Some(alias))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "Collection does not exist");
+    }
+
+    #[tokio::test]
+    async fn test_crawl_limits() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let source = |crawl: Value| {
+            json!({
+                "collection_id": body["id"],
+                "kind": "web",
+                "url": "https://docs.example.com/",
+                "crawl": crawl,
+            })
+        };
+        for crawl in [
+            json!({ "max_pages": 1_000_000 }),
+            json!({ "max_depth": 1000 }),
+        ] {
+            let (status, _) = app
+                .request(Method::PUT, "/api/sources", Some(source(crawl)))
+                .await
+                .unwrap();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                "/api/sources",
+                Some(source(json!({ "max_pages": 50, "max_depth": 3 }))),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn test_upload_archive() {
+        use flate2::{write::GzEncoder, Compression};
+
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let upload = |files: &[(&str, &str)]| {
+            let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+            for (path, data) in files {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                tar.append_data(&mut header, path, data.as_bytes()).unwrap();
+            }
+            tar.into_inner().unwrap().finish().unwrap()
+        };
+        let post = |body: Vec<u8>| {
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/sources/{}/upload", source.id))
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .body(Body::from(body))
+                .unwrap();
+            let router = app.router.clone();
+            async move {
+                let resp = router.oneshot(req).await.unwrap();
+                let status = resp.status();
+                let bytes = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+                (status, serde_json::from_slice::<Value>(&bytes).unwrap())
+            }
+        };
+        let paths = || async {
+            let mut paths: Vec<String> = app
+                .state
+                .db
+                .query_document_checksums(source.id)
+                .await
+                .unwrap()
+                .into_keys()
+                .collect();
+            paths.sort();
+            paths
+        };
+
+        let (status, body) = post(upload(&[
+            ("a.md", "# A\n\nFirst page."),
+            ("b.md", "# B\n\nSecond page."),
+        ]))
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["documents"], 2);
+
+        // A bad archive leaves the documents as they are.
+        let (status, _) = post(b"# Not an archive".to_vec()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(paths().await, vec!["a.md", "b.md"]);
+
+        let (status, body) = post(upload(&[("a.md", "# A\n\nFirst page, revised.")])).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["documents"], 1);
+        assert_eq!(paths().await, vec!["a.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_replace_document() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "default", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let collection_id = body["id"].as_i64().unwrap();
+        let post = |path: &'static str, data: &'static str| {
+            app.request(
+                Method::POST,
+                "/api/documents",
+                Some(json!({ "collection_id": collection_id, "path": path, "data": data })),
+            )
+        };
+
+        // Concurrent first documents share a single notes source.
+        let (first, second) = tokio::join!(
+            post("notes/oncall.md", "# On-call\n\nPage the pager rotation."),
+            post("notes/deploys.md", "# Deploys\n\nDeploys run on Tuesdays."),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first.0, StatusCode::CREATED);
+        assert_eq!(second.0, StatusCode::CREATED);
+        assert_eq!(first.1["source_id"], second.1["source_id"]);
+
+        let (status, body) = post(
+            "notes/oncall.md",
+            "# On-call\n\nEscalate to the incident lead.",
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let source_id = body["source_id"].as_i64().unwrap();
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source_id)
+            .await
+            .unwrap();
+        let oncall: Vec<_> = docs
+            .iter()
+            .filter(|doc| doc.path == "notes/oncall.md")
+            .collect();
+        assert_eq!(oncall.len(), 1);
+        assert!(oncall[0].data.contains("incident lead"));
+    }
+
+    #[tokio::test]
+    async fn test_clone_and_merge_collections() {
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "install.md", "# Install\n\nRun the installer.")
+            .with_file(
+                "acme/docs",
+                "billing.md",
+                "# Billing\n\nInvoices are monthly.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        let db = &app.state.db;
+        let chunks = |collection_id: i64| async move {
+            db.query_chunks_by_collection(collection_id).await.unwrap()
+        };
+        let original = chunks(source.collection_id).await;
+
+        let (status, body) = app
+            .request(
+                Method::POST,
+                &format!("/api/collections/{}/clone", source.collection_id),
+                Some(json!({ "name": "staging" })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED, "{}", body);
+        let staging = body["id"].as_i64().unwrap();
+        let copies = chunks(staging).await;
+        assert_eq!(copies.len(), original.len());
+        let mut data: Vec<&str> = copies.iter().map(|chunk| chunk.data.as_str()).collect();
+        data.sort();
+        let mut expected: Vec<&str> = original.iter().map(|chunk| chunk.data.as_str()).collect();
+        expected.sort();
+        assert_eq!(data, expected);
+        // Copies belong to the clone's documents and sources, not the originals'.
+        assert!(copies
+            .iter()
+            .all(|copy| original.iter().all(|chunk| chunk.id != copy.id
+                && chunk.document_id != copy.document_id
+                && chunk.source_id != copy.source_id)));
+        let embeddings = app
+            .state
+            .tinyvector
+            .read()
+            .await
+            .get_collection("staging")
+            .map(|collection| collection.embeddings.len());
+        assert_eq!(embeddings, Some(original.len()));
+
+        let (status, body) = app
+            .request(
+                Method::POST,
+                &format!("/api/collections/{}/merge", staging),
+                Some(json!({ "into": source.collection_id })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["sources"], 1);
+        assert!(chunks(staging).await.is_empty());
+        assert_eq!(chunks(source.collection_id).await.len(), 2 * original.len());
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/api/collections/{}/merge", staging),
+                Some(json!({ "into": staging })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_publish_sources() {
+        use std::collections::HashSet;
+
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "install.md", "# Install\n\nRun the installer.")
+            .with_file(
+                "acme/guides",
+                "billing.md",
+                "# Billing\n\nInvoices are monthly.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let docs = app.with_source("docs").await.unwrap();
+        let guides = app.with_source("guides").await.unwrap();
+        app.index_source(docs.id).await.unwrap();
+        app.index_source(guides.id).await.unwrap();
+        let (db, tiny) = (&app.state.db, &app.state.tinyvector);
+        let collection = db.select_collection(docs.collection_id).await.unwrap();
+        let live = || async { tiny.read().await.get_collection(&collection.name).unwrap() };
+
+        // Only the guides are read again, their chunks are gone.
+        db.delete_chunks_by_source(guides.id).await.unwrap();
+        let version = crate::index::publish_sources(db, tiny, &collection, &[guides.id])
+            .await
+            .unwrap();
+        let sources: HashSet<i64> = live()
+            .await
+            .embeddings
+            .iter()
+            .map(|embedding| embedding.metadata.source_id)
+            .collect();
+        assert_eq!(sources, HashSet::from([docs.id]));
+        assert_eq!(live().await.index_version, version);
+
+        // Concurrent publishes leave the latest build live.
+        let (first, second) = tokio::join!(
+            crate::index::publish_collection(db, tiny, &collection),
+            crate::index::publish_sources(db, tiny, &collection, &[docs.id]),
+        );
+        let latest = first.unwrap().max(second.unwrap());
+        assert_eq!(latest, version + 2);
+        assert_eq!(live().await.index_version, latest);
+    }
+
+    #[tokio::test]
+    async fn test_failed_reindex_keeps_rollback() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        let state = crate::AppState {
+            stub_repos: Some(StubRepos::default().with_file(
+                "acme/docs",
+                "setup.md",
+                "# Setup\n\nThe installer was renamed to setup.",
+            )),
+            ..app.state.clone()
+        };
+        pipeline::reindex_source(&state, source.clone())
+            .await
+            .unwrap();
+
+        // A re-index finding no documents fails and leaves the rollback of
+        // the previous one in place.
+        let state = crate::AppState {
+            stub_repos: Some(StubRepos::default()),
+            ..app.state.clone()
+        };
+        assert!(pipeline::reindex_source(&state, source.clone())
+            .await
+            .is_err());
+        let collections = app.state.db.query_collections().await.unwrap();
+        assert_eq!(collections.len(), 2);
+        assert!(!collections
+            .iter()
+            .any(|collection| collection.name.ends_with("~next")));
+
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/api/sources/{}/rollback", source.id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].path, "install.md");
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_ignores_pipeline() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "billing.md",
+                "# Billing\n\nInvoices are sent monthly.",
+            )
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        let (status, _) = app
+            .request(
+                Method::PUT,
+                &format!("/api/collections/{}/ranking", source.collection_id),
+                Some(json!({ "stages": [{ "stage": "keyword_retrieve" }] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=billing&debug=true", None)
+            .await
+            .unwrap();
+        let stages = body["results"][0]["stages"].to_string();
+        assert!(stages.contains("keyword_retrieve"));
+        assert!(!stages.contains("title_retrieve"));
+
+        let (_, body) = app
+            .request(
+                Method::GET,
+                "/api/search/hybrid?query=billing&debug=true",
+                None,
+            )
+            .await
+            .unwrap();
+        let stages = body["results"][0]["stages"].to_string();
+        assert!(stages.contains("title_retrieve"));
+        assert!(stages.contains("fuse"));
+    }
+
+    #[tokio::test]
+    async fn test_public_search_cors() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let preflight = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/public/search?query=install")
+            .header(header::ORIGIN, "https://docs.example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.router.clone().oneshot(preflight).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://docs.example.com"
+        );
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("authorization"));
+
+        // Rejections carry the headers too, so scripts can read them.
+        let search = Request::builder()
+            .uri("/public/search?query=install")
+            .header(header::ORIGIN, "https://docs.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.router.clone().oneshot(search).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            resp.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://docs.example.com"
+        );
+        assert!(resp.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("x-next-cursor"));
+    }
+
+    #[tokio::test]
+    async fn test_api_requires_session() {
+        let cfg = Configuration {
+            oidc: Some(crate::OidcConfig {
+                issuer: "https://idp.example.com".to_string(),
+                client_id: "rtfm".to_string(),
+                client_secret: "secret".to_string(),
+                groups_claim: "groups".to_string(),
+                admin_groups: vec!["docs-admins".to_string()],
+                viewer_groups: Vec::new(),
+            }),
+            ..test_config()
+        };
+        let app = TestApp::spawn_with_config(StubRepos::default(), cfg)
+            .await
+            .unwrap();
+        let now = chrono::Utc::now();
+        let session = crate::types::Session {
+            id_hash: crate::access::hash_token("viewer-token"),
+            subject: "viewer".to_string(),
+            email: "viewer@example.com".to_string(),
+            role: Role::Viewer,
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(1),
+        };
+        app.state.db.insert_session(&session).await.unwrap();
+        let send = |method: Method, uri: &str, token: Option<&str>| {
+            let mut req = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(token) = token {
+                req = req.header(header::COOKIE, format!("rtfm_session={}", token));
+            }
+            let body = json!({ "owner": "acme", "repo": "docs", "collection_id": 1 });
+            let req = req.body(Body::from(body.to_string())).unwrap();
+            let router = app.router.clone();
+            async move { router.oneshot(req).await.unwrap().status() }
+        };
+
+        assert_eq!(
+            send(Method::PUT, "/api/sources", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(Method::PUT, "/api/v1/sources", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(Method::PUT, "/api/sources", Some("viewer-token")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send(Method::GET, "/api/sources", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(Method::GET, "/api/sources", Some("viewer-token")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(Method::GET, "/ws", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert!(app.state.db.query_sources().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_encrypted_at_rest() {
+        const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        const OTHER_KEY: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let mut source = app.with_source("docs").await.unwrap();
+
+        // Stored before a key was configured, in plaintext.
+        let plain = app.state.db.clone();
+        source.repo = "private".to_string();
+        source.github.token = "ghp_secret".to_string();
+        source.bucket.secret_access_key = "wJalrXUtnFEMI".to_string();
+        source.id = plain.insert_source(&source).await.unwrap();
+
+        // The migration encrypts both credentials once.
+        let db = plain
+            .clone()
+            .with_cipher(crate::Cipher::from_hex(KEY).unwrap());
+        assert_eq!(db.encrypt_secrets().await.unwrap(), 2);
+        assert_eq!(db.encrypt_secrets().await.unwrap(), 0);
+        let stored = db.select_source(source.id).await.unwrap();
+        assert_eq!(stored.github.token, "ghp_secret");
+        assert_eq!(stored.bucket.secret_access_key, "wJalrXUtnFEMI");
+        let sources = db.query_sources().await.unwrap();
+        assert!(sources.iter().any(|s| s.github.token == "ghp_secret"));
+
+        // Credentials written with a key are encrypted too.
+        source.repo = "vault".to_string();
+        source.github.token = "ghp_vault".to_string();
+        let id = db.insert_source(&source).await.unwrap();
+        assert_eq!(
+            db.select_source(id).await.unwrap().github.token,
+            "ghp_vault"
+        );
+        assert!(plain.select_source(id).await.is_err());
+
+        // Reads without the key or with another one fail, rather than
+        // yielding the ciphertext or an empty credential.
+        assert!(plain.select_source(source.id).await.is_err());
+        assert!(plain.query_sources().await.is_err());
+        assert!(plain.encrypt_secrets().await.is_err());
+        let other = plain.with_cipher(crate::Cipher::from_hex(OTHER_KEY).unwrap());
+        assert!(other.select_source(source.id).await.is_err());
+        assert!(other.encrypt_secrets().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_source_github_token() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let docs = app.with_source("docs").await.unwrap();
+
+        let body = json!({
+            "collection_id": docs.collection_id,
+            "owner": "other-org",
+            "repo": "private-docs",
+            "branch": "main",
+            "allowed_ext": [".md"],
+            "github": { "token": "ghp_other_org" },
+        });
+        let (status, body) = app
+            .request(Method::PUT, "/api/sources", Some(body))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let id = body["id"].as_i64().unwrap();
+        assert!(!body.to_string().contains("ghp_other_org"));
+
+        // The token is stored with the source, but never returned or logged.
+        let source = app.state.db.select_source(id).await.unwrap();
+        assert_eq!(source.github.token, "ghp_other_org");
+        assert!(!format!("{:?}", source).contains("ghp_other_org"));
+        for uri in [format!("/api/sources/{}", id), "/api/sources".to_string()] {
+            let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+            assert_eq!(status, StatusCode::OK);
+            assert!(!body.to_string().contains("ghp_other_org"));
+        }
+
+        // Sources without one fall back to the server's token.
+        let docs = app.state.db.select_source(docs.id).await.unwrap();
+        assert!(docs.github.token.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_app_state_rejects_github_app_key() {
+        let cfg = Configuration {
+            github_app: Some(crate::GitHubAppConfig {
+                app_id: 1,
+                private_key: "not a key".to_string(),
+                installation_id: None,
+            }),
+            ..test_config()
+        };
+        let state = crate::AppState::new(
+            Arc::new(cfg),
+            Db::new_in_memory().await.unwrap(),
+            Octocrab::default(),
+            Models::new(Embeddings::from_config(DEFAULT_MODEL, &test_config()).unwrap()),
+            Tiny::new().extension(),
+            Tokenizer::new().unwrap(),
+        );
+        assert!(state.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_collection_shadows_follow_renames_and_deletes() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        pipeline::reindex_source(&app.state, source.clone())
+            .await
+            .unwrap();
+        let shadow = pipeline::shadow_name("default", source.id);
+        assert!(app
+            .state
+            .tinyvector
+            .read()
+            .await
+            .get_collection(&shadow)
+            .is_some());
+
+        let uri = format!("/api/collections/{}", source.collection_id);
+        let (status, _) = app
+            .request(Method::PATCH, &uri, Some(json!({ "name": "handbook" })))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let renamed = pipeline::shadow_name("handbook", source.id);
+        let names = app
+            .state
+            .db
+            .query_collections()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|collection| collection.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&renamed));
+        {
+            let tiny = app.state.tinyvector.read().await;
+            assert!(tiny.get_collection(&shadow).is_none());
+            assert!(tiny.get_collection(&renamed).is_some());
+        }
+        // The source still rolls back to its renamed shadow.
+        let (status, _) = app
+            .request(
+                Method::POST,
+                &format!("/api/sources/{}/rollback", source.id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = app.request(Method::DELETE, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert!(app.state.db.query_collections().await.unwrap().is_empty());
+        assert!(app.state.tinyvector.read().await.collections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_source_stats() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer to set up the widget.",
+            )
+            .with_file("acme/docs", "faq.md", "# FAQ\n\nAsk the widget team.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let indexed = app.with_source("docs").await.unwrap();
+        app.index_source(indexed.id).await.unwrap();
+        let unparsed = app.with_source("handbook").await.unwrap();
+
+        // The aggregate agrees with the totals of each source on its own.
+        let stats = app.state.db.query_source_stats().await.unwrap();
+        assert_eq!(stats.len(), 2);
+        for id in [indexed.id, unparsed.id] {
+            let expected = app.state.db.select_source_stats(id).await.unwrap();
+            assert_eq!(stats[&id], expected);
+        }
+        assert_eq!(stats[&indexed.id].documents, 2);
+        assert_eq!(stats[&unparsed.id], SourceStats::default());
+    }
+
+    #[tokio::test]
+    async fn test_parse_budget_bytes_and_deadline() {
+        async fn parse(app: &TestApp, source_id: i64, query: &str) -> Value {
+            let uri = format!("/api/sources/{}/parse?{}", source_id, query);
+            let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+            assert_eq!(status, StatusCode::ACCEPTED);
+            let job = app
+                .wait_for_job(body["job_id"].as_i64().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(job.status, JobStatus::Succeeded);
+            job.result.unwrap()
+        }
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "a.md", "# A\n\nFirst page.")
+            .with_file("acme/docs", "b.md", "# B\n\nSecond page.")
+            .with_file("acme/docs", "c.md", "# C\n\nThird page.");
+        let app = TestApp::spawn(repos.clone()).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        // Fetches already started finish, later ones are skipped.
+        let report = parse(&app, source.id, "max_bytes=1").await;
+        assert_eq!(report["status"], "partial");
+        assert_eq!(report["stopped_by"], "max_bytes of 1");
+        assert_eq!(report["documents"], 1);
+
+        let report = parse(&app, source.id, "max_duration_secs=0").await;
+        assert_eq!(report["status"], "partial");
+        assert_eq!(report["stopped_by"], "max_duration of 0s");
+        assert_eq!(report["documents"], 0);
+
+        // Requests can't lift the configured limits.
+        let cfg = Configuration {
+            parse_budget: ParseBudget {
+                max_files: 2,
+                ..test_config().parse_budget
+            },
+            ..test_config()
+        };
+        let app = TestApp::spawn_with_config(repos, cfg).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let report = parse(&app, source.id, "max_files=100").await;
+        assert_eq!(report["stopped_by"], "max_files of 2");
+        assert_eq!(report["documents"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_source_filters() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer to set up the widget.",
+            )
+            .with_file(
+                "acme/docs",
+                "drafts/pricing.md",
+                "# Pricing\n\nUnannounced invoices and billing plans.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        let paths = |app: &TestApp| {
+            let db = app.state.db.clone();
+            async move {
+                let mut paths = db
+                    .query_documents_by_source(source.id)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|document| document.path)
+                    .collect::<Vec<_>>();
+                paths.sort();
+                paths
+            }
+        };
+        assert_eq!(paths(&app).await, vec!["drafts/pricing.md", "install.md"]);
+
+        // Documents the filters no longer match are gone from searches right away.
+        let uri = format!("/api/sources/{}", source.id);
+        let (status, _) = app
+            .request(
+                Method::PATCH,
+                &uri,
+                Some(json!({ "ignored_dirs": ["drafts"] })),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(paths(&app).await, vec!["install.md"]);
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=invoices%20billing", None)
+            .await
+            .unwrap();
+        assert!(body["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|result| result["path"] != "drafts/pricing.md"));
+
+        // And don't come back with the next parse, until the filters match them again.
+        app.index_source(source.id).await.unwrap();
+        assert_eq!(paths(&app).await, vec!["install.md"]);
+        let (status, _) = app
+            .request(Method::PATCH, &uri, Some(json!({ "ignored_dirs": [] })))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        app.index_source(source.id).await.unwrap();
+        assert_eq!(paths(&app).await, vec!["drafts/pricing.md", "install.md"]);
+    }
+
+    #[tokio::test]
+    async fn test_delete_source_from_every_build() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        // Leaves a shadow holding the rollback and a snapshot of the previous build.
+        pipeline::reindex_source(&app.state, source.clone())
+            .await
+            .unwrap();
+        {
+            let mut tiny = app.state.tinyvector.write().await;
+            let live = tiny.get_collection("default").unwrap();
+            tiny.collections
+                .insert(crate::variant_name("default", "other"), live);
+        }
+        let before = app
+            .state
+            .db
+            .select_collection(source.collection_id)
+            .await
+            .unwrap()
+            .index_version;
+
+        // Sources with a job in flight aren't deleted under it.
+        let job = Job {
+            id: 0,
+            source_id: source.id,
+            kind: JobKind::Parse,
+            status: JobStatus::Queued,
+            documents: 0,
+            error: String::new(),
+            result: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let mut job = Job {
+            id: app.state.db.insert_job(&job).await.unwrap(),
+            ..job
+        };
+        let uri = format!("/api/sources/{}", source.id);
+        let (status, _) = app.request(Method::DELETE, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::CONFLICT);
+        job.status = JobStatus::Cancelled;
+        app.state.db.update_job(&job).await.unwrap();
+
+        let (status, _) = app.request(Method::DELETE, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let collections = app.state.db.query_collections().await.unwrap();
+        assert_eq!(collections.len(), 1);
+        assert!(collections[0].index_version > before);
+        let tiny = app.state.tinyvector.read().await;
+        assert!(tiny.collections.len() > 2);
+        for (name, build) in &tiny.collections {
+            assert!(!name.contains('~'), "shadow '{}' is left", name);
+            assert!(
+                build
+                    .embeddings
+                    .iter()
+                    .all(|embedding| embedding.metadata.source_id != source.id),
+                "'{}' still serves the source",
+                name
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_applies_ignore_file() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                ".rtfmignore",
+                "# Not ours\napi/\n*.draft.md\n!keep.draft.md",
+            )
+            .with_file(
+                "acme/docs",
+                "install.md",
+                "# Installation\n\nRun the installer.",
+            )
+            .with_file("acme/docs", "api/index.md", "# API\n\nGenerated reference.")
+            .with_file(
+                "acme/docs",
+                "docs/intro.draft.md",
+                "# Intro\n\nNot ready yet.",
+            )
+            .with_file(
+                "acme/docs",
+                "docs/keep.draft.md",
+                "# Keep\n\nPublished draft.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let paths = || async {
+            let mut paths = app
+                .state
+                .db
+                .query_documents_by_source(source.id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|document| document.path)
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+
+        app.index_source(source.id).await.unwrap();
+        assert_eq!(paths().await, vec!["docs/keep.draft.md", "install.md"]);
+
+        // Unfiltered parses ignore it along with the source's filters.
+        let uri = format!("/api/sources/{}/parse?filter=false", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        app.wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+        assert!(paths().await.contains(&"api/index.md".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_update_document_listing() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "intro.md",
+            "# Intro\n\nThe widget in a nutshell.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        // Files skipped as unchanged only get their other paths and nav replaced.
+        let db = &app.state.db;
+        let alternates = vec!["guide/intro.md".to_string(), "v2/intro.md".to_string()];
+        let url = "https://github.com/acme/sdk/blob/c0ffee/intro.md";
+        db.update_document_listing(source.id, "intro.md", &alternates, "Guides", Some(2), url)
+            .await
+            .unwrap();
+        let docs = db.query_documents_by_source(source.id).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].alternate_paths, alternates);
+        assert_eq!(docs[0].nav_path, "Guides");
+        assert_eq!(docs[0].nav_index, Some(2));
+        assert_eq!(docs[0].url, url);
+        assert_eq!(docs[0].path, "intro.md");
+
+        db.update_document_listing(source.id, "intro.md", &[], "", None, "")
+            .await
+            .unwrap();
+        let docs = db.query_documents_by_source(source.id).await.unwrap();
+        assert!(docs[0].alternate_paths.is_empty());
+        assert_eq!(docs[0].nav_index, None);
+    }
+
+    #[tokio::test]
+    async fn test_discovered_packages_leave_the_repo_source() {
+        let repos = StubRepos::default()
+            .with_file("acme/mono", "README.md", "# Mono\n\nThe monorepo.")
+            .with_file(
+                "acme/mono",
+                "packages/cli/docs/usage.md",
+                "# Usage\n\nRun the cli.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("mono").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+
+        let uri = format!("/api/sources/{}/discover?create=true", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let created = body[0]["source_id"].as_i64().unwrap();
+
+        // The package's docs are only indexed by its own source.
+        let source = app.state.db.select_source(source.id).await.unwrap();
+        assert!(source.ignored_dirs.contains("packages/cli/docs/"));
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].path, "README.md");
+        app.index_source(source.id).await.unwrap();
+        app.index_source(created).await.unwrap();
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(created)
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].path, "packages/cli/docs/usage.md");
+        assert_eq!(
+            app.state
+                .db
+                .query_documents_by_source(source.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unfinished_jobs_fail() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+
+        // A panic fails the job instead of leaving it running.
+        let id = crate::jobs::spawn(&app.state, source.id, JobKind::Parse, |_| async {
+            let report: Option<pipeline::ParseReport> = None;
+            Ok(report.expect("Parser is gone"))
+        })
+        .await
+        .unwrap();
+        let job = app.wait_for_job(id).await.unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert!(job.error.contains("panicked"), "{}", job.error);
+
+        // Jobs a restart interrupted fail once the server is back.
+        let now = chrono::Utc::now();
+        let mut job = Job {
+            id: 0,
+            source_id: source.id,
+            kind: JobKind::Sync,
+            status: JobStatus::Running,
+            documents: 0,
+            error: String::new(),
+            result: None,
+            created_at: now,
+            updated_at: now,
+        };
+        job.id = app.state.db.insert_job(&job).await.unwrap();
+        assert_eq!(app.state.db.fail_unfinished_jobs().await.unwrap(), 1);
+        let job = app.state.db.select_job(job.id).await.unwrap();
+        assert_eq!(job.status, JobStatus::Failed);
+        assert_eq!(job.error, "Interrupted by a restart of the server");
+    }
+
+    #[tokio::test]
+    async fn test_nav_is_published_with_chunks() {
+        let repos = StubRepos::default()
+            .with_file(
+                "acme/docs",
+                "mkdocs.yml",
+                "nav:\n  - Guides:\n      - guides/install.md\n      - guides/deploy.md\n",
+            )
+            .with_file(
+                "acme/docs",
+                "docs/guides/install.md",
+                "# Install\n\nRun the installer to set up the widget.",
+            )
+            .with_file(
+                "acme/docs",
+                "docs/guides/deploy.md",
+                "# Deploy\n\nShip the widget to production.",
+            );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        // Searches order a guide's results by the nav their chunks carry.
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        let tiny = app.state.tinyvector.read().await;
+        let data = tiny.get_collection(&collection.name).unwrap();
+        let mut navs: Vec<(String, String, Option<usize>)> = data
+            .embeddings
+            .iter()
+            .map(|embedding| {
+                let metadata = &embedding.metadata;
+                (
+                    metadata.path.clone(),
+                    metadata.nav_path.clone(),
+                    metadata.nav_index,
+                )
+            })
+            .collect();
+        navs.sort();
+        navs.dedup();
+        assert_eq!(
+            navs,
+            vec![
+                (
+                    "docs/guides/deploy.md".to_string(),
+                    "Guides".to_string(),
+                    Some(1)
+                ),
+                (
+                    "docs/guides/install.md".to_string(),
+                    "Guides".to_string(),
+                    Some(0)
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_result_urls() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "install.md",
+            "# Installation\n\nRun the installer to set up the widget.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        // Links come with the chunks, they are resolved when files are parsed.
+        let docs = app
+            .state
+            .db
+            .query_documents_by_source(source.id)
+            .await
+            .unwrap();
+        let url = "https://github.com/acme/docs/blob/main/install.md";
+        assert_eq!(docs[0].url, url);
+        let (status, body) = app
+            .request(Method::GET, "/api/search?query=installer", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let result = &body["results"][0];
+        let expected = match result["anchor"].as_str().unwrap() {
+            "" => url.to_string(),
+            anchor => format!("{}#{}", url, anchor),
+        };
+        assert_eq!(result["url"], expected);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_encode_publishes_stored_chunks() {
+        let repos = (0..30).fold(StubRepos::default(), |repos, n| {
+            repos.with_file(
+                "acme/docs",
+                &format!("page-{:02}.md", n),
+                &format!("# Page {}\n\nThe widget, part {}.", n, n),
+            )
+        });
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        let uri = format!("/api/sources/{}/parse", source.id);
+        let (_, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        app.wait_for_job(body["job_id"].as_i64().unwrap())
+            .await
+            .unwrap();
+
+        // Cancelled once the first document's chunks are stored.
+        let uri = format!("/api/sources/{}/encode", source.id);
+        let (status, body) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job_id = body["job_id"].as_i64().unwrap();
+        while app
+            .state
+            .db
+            .query_chunks_by_source(source.id)
+            .await
+            .unwrap()
+            .is_empty()
+        {
+            tokio::task::yield_now().await;
+        }
+        let uri = format!("/api/jobs/{}/cancel", job_id);
+        let (status, _) = app.request(Method::POST, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let job = app.wait_for_job(job_id).await.unwrap();
+        assert_eq!(job.status, JobStatus::Cancelled);
+
+        let stored = app
+            .state
+            .db
+            .query_chunks_by_source(source.id)
+            .await
+            .unwrap()
+            .len();
+        assert!(stored > 0 && stored < 30, "{} chunks stored", stored);
+        let collection = &app.state.db.query_collections().await.unwrap()[0];
+        let tiny = app.state.tinyvector.read().await;
+        let data = tiny.get_collection(&collection.name).unwrap();
+        assert_eq!(data.embeddings.len(), stored);
+    }
+
+    #[tokio::test]
+    async fn test_partial_listing_keeps_documents() {
+        let repos = StubRepos::default()
+            .with_file("acme/docs", "install.md", "# Installation\n\nRun it.")
+            .with_file("acme/docs", "usage.md", "# Usage\n\nCall it.");
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let opts = pipeline::ParseOptions {
+            incremental: true,
+            check_links: false,
+            ..Default::default()
+        };
+        let listing =
+            StubRepos::default().with_file("acme/docs", "install.md", "# Installation\n\nRun it.");
+        let state = crate::AppState {
+            stub_repos: Some(listing.clone().with_truncated_tree("acme/docs")),
+            ..app.state.clone()
+        };
+        let report = pipeline::parse_source(&state, source.clone(), opts, &Cancellation::default())
+            .await
+            .unwrap();
+        assert_eq!(report.removed, 0);
+        let checksums = app
+            .state
+            .db
+            .query_document_checksums(source.id)
+            .await
+            .unwrap();
+        assert_eq!(checksums.len(), 2);
+
+        let state = crate::AppState {
+            stub_repos: Some(listing),
+            ..app.state.clone()
+        };
+        let report = pipeline::parse_source(&state, source.clone(), opts, &Cancellation::default())
+            .await
+            .unwrap();
+        assert_eq!(report.removed, 1);
+        let checksums = app
+            .state
+            .db
+            .query_document_checksums(source.id)
+            .await
+            .unwrap();
+        assert!(checksums.contains_key("install.md"));
+        assert!(!checksums.contains_key("usage.md"));
+    }
+
+    #[tokio::test]
+    async fn test_search_metadata_is_versioned() {
+        let repos = StubRepos::default().with_file(
+            "acme/docs",
+            "setup.md",
+            "# Setup\n\nInstall the widget and configure it.",
+        );
+        let app = TestApp::spawn(repos).await.unwrap();
+        let source = app.with_source("docs").await.unwrap();
+        app.index_source(source.id).await.unwrap();
+
+        let (_, body) = app
+            .request(Method::GET, "/api/search?query=setup", None)
+            .await
+            .unwrap();
+        assert_eq!(body["results"][0]["path"], "setup.md");
+        assert!(body["results"][0].get("metadata").is_none());
+
+        let (status, body) = app
+            .request(Method::GET, "/api/v1/search?query=setup", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        let result = &body["data"]["results"][0];
+        assert_eq!(result["path"], "setup.md");
+        assert_eq!(result["metadata"]["source_id"], source.id);
+        assert!(result["metadata"]["document_id"].is_i64());
+    }
+
+    #[tokio::test]
+    async fn test_api_envelope_missing_entities() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (status, _) = app
+            .request(Method::GET, "/api/sources/999", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, body) = app
+            .request(Method::GET, "/api/v1/sources/999", None)
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert!(body["data"].is_null());
+        assert_eq!(body["error"]["status"], 404);
+        assert!(!body["error"]["message"].as_str().unwrap().is_empty());
+        assert!(!body["request_id"].as_str().unwrap().is_empty());
+
+        // The spec describes the responses of `/api/v1`.
+        let (_, spec) = app
+            .request(Method::GET, "/api/openapi.json", None)
+            .await
+            .unwrap();
+        let responses = &spec["paths"]["/api/v1/sources/{source_id}"]["get"]["responses"];
+        assert!(responses.get("204").is_none());
+        let missing = &responses["404"]["content"]["application/json"]["schema"];
+        assert_eq!(missing["$ref"], "#/components/schemas/Envelope");
+        let found = &responses["200"]["content"]["application/json"]["schema"];
+        assert_eq!(
+            found["properties"]["data"]["$ref"],
+            "#/components/schemas/SourceEntry"
+        );
+        assert!(found["properties"]["request_id"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_create_sources_ids_and_duplicates() {
+        let app = TestApp::spawn(StubRepos::default()).await.unwrap();
+        let (_, body) = app
+            .request(
+                Method::PUT,
+                "/api/collections",
+                Some(json!({ "name": "docs", "dimension": TEST_DIMENSION })),
+            )
+            .await
+            .unwrap();
+        let id = body["id"].as_i64().unwrap();
+        let source = |repo: &str| json!({ "collection_id": id, "owner": "acme", "repo": repo, "branch": "main" });
+
+        let (status, body) = app
+            .request(Method::PUT, "/api/sources", Some(source("docs")))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        let uri = format!("/api/sources/{}", body["id"]);
+        let (status, body) = app.request(Method::GET, &uri, None).await.unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["repo"], "docs");
+
+        let (status, body) = app
+            .request(
+                Method::PUT,
+                "/api/sources/bulk",
+                Some(json!([source("guides"), source("blog"), source("guides")])),
+            )
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["results"][0]["error"].is_null());
+        assert!(body["results"][1]["error"].is_null());
+        assert!(body["results"][2]["error"]
+            .as_str()
+            .unwrap()
+            .contains("index 0"));
+
+        let batch: Vec<Value> = (0..101).map(|i| source(&format!("repo-{}", i))).collect();
+        let (status, _) = app
+            .request(Method::PUT, "/api/sources/bulk", Some(json!(batch)))
+            .await
+            .unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (_, body) = app
+            .request(
+                Method::GET,
+                &format!("/api/sources?collection_id={}", id),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 1);
+    }
+}
@@ -1,14 +1,33 @@
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
 use std::{
-    collections::{BinaryHeap, HashMap},
-    sync::Arc,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering},
+        Arc,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{Mutex, RwLock};
+use utoipa::ToSchema;
+
+pub use crate::ranking::{get_cache_attr, get_distance_fn, normalize, Distance};
+use crate::{
+    ranking::{self, ScoreIndex},
+    search::TermIndex,
 };
-use tokio::sync::RwLock;
 
 pub type Tinyvector = Arc<RwLock<Tiny>>;
 
+/// Number of embeddings scanned between deadline checks.
+const SCAN_BATCH_SIZE: usize = 1024;
+
+/// Added to the score of embeddings carrying the tag boosted by a query.
+const TAG_BOOST: f32 = 0.05;
+
+/// Number of removals remembered for deltas, older ones require a full snapshot.
+const MAX_TOMBSTONES: usize = 10_000;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Collection already exists")]
@@ -19,6 +38,9 @@ pub enum Error {
 
     #[error("The dimension of the vector doesn't match the dimension of the collection")]
     DimensionMismatch,
+
+    #[error("The vector was created with another model than the vectors of the collection")]
+    ModelMismatch,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +51,13 @@ pub struct SimilarityResult {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Collection {
+    /// Name of the model the vectors were created with
+    #[serde(default)]
+    pub model: String,
+    /// Version of the model, taken from the first vector recording one since
+    /// the collection was empty.
+    #[serde(default)]
+    pub model_version: String,
     /// Dimension of the vectors in the collection
     pub dimension: usize,
     /// Distance metric used for querying
@@ -36,41 +65,404 @@ pub struct Collection {
     /// Embeddings in the collection
     #[serde(default)]
     pub embeddings: Vec<Embedding>,
+    /// Index version of the db collection this was built from.
+    #[serde(default)]
+    pub index_version: i64,
+    /// Changes on every modification, used to build ETags for search results
+    /// and as the sequence number of deltas. Increases monotonically across
+    /// collections and restarts.
+    #[serde(skip, default = "next_version")]
+    pub version: u64,
+    /// Deltas since versions older than this can't list every removal.
+    #[serde(skip, default = "next_version")]
+    base_version: u64,
+    /// Ids of removed embeddings with the version they were removed at, oldest first.
+    #[serde(skip)]
+    tombstones: Vec<(u64, String)>,
+    /// Index of each embedding in `embeddings` by id, so bulk loads don't
+    /// scan the collection for every upsert.
+    #[serde(skip)]
+    positions: HashMap<String, usize>,
+    /// Terms of the embeddings for keyword retrieval, indexed when the build
+    /// is published.
+    #[serde(skip)]
+    pub terms: Option<Arc<TermIndex>>,
+}
+
+/// Changes to a collection since a version, for replicas to catch up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delta {
+    /// Version the replica is at once the delta is applied.
+    pub version: u64,
+    /// Whether the delta is a full snapshot, replicas drop their embeddings first.
+    pub full: bool,
+    pub upserted: Vec<Embedding>,
+    /// Applied before `upserted`, an id may be in both if it was re-added.
+    pub removed: Vec<String>,
+}
+
+// Versions start from the current time, so they don't repeat across restarts.
+fn next_version() -> u64 {
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default();
+    let last = LAST
+        .fetch_update(AtomicOrdering::SeqCst, AtomicOrdering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    now.max(last + 1)
 }
 
 impl Collection {
-    pub fn get_similarity(&self, query: &[f32], k: usize) -> Vec<SimilarityResult> {
-        let memo_attr = get_cache_attr(self.distance, query);
-        let distance_fn = get_distance_fn(self.distance);
+    pub fn new(model: String, dimension: usize, distance: Distance) -> Self {
+        let version = next_version();
+        Self {
+            model,
+            model_version: String::new(),
+            dimension,
+            distance,
+            embeddings: Vec::new(),
+            index_version: 0,
+            version,
+            base_version: version,
+            tombstones: Vec::new(),
+            positions: HashMap::new(),
+            terms: None,
+        }
+    }
 
-        let scores = self
+    pub fn insert(
+        &mut self,
+        id: String,
+        vector: Vec<f32>,
+        blob: String,
+        metadata: Metadata,
+    ) -> Result<(), Error> {
+        if self.positions.contains_key(&id) {
+            return Err(Error::UniqueViolation);
+        }
+        let vector = self.prepare(vector)?;
+        self.check_model(&metadata)?;
+        self.version = next_version();
+        self.positions.insert(id.clone(), self.embeddings.len());
+        self.embeddings.push(Embedding {
+            id,
+            vector,
+            blob,
+            metadata,
+            prior: 0.0,
+            version: self.version,
+        });
+        Ok(())
+    }
+
+    /// Inserts the embedding, or replaces the vector and blob if the id already exists.
+    pub fn upsert(
+        &mut self,
+        id: String,
+        vector: Vec<f32>,
+        blob: String,
+        metadata: Metadata,
+    ) -> Result<(), Error> {
+        let vector = self.prepare(vector)?;
+        self.check_model(&metadata)?;
+        self.version = next_version();
+        match self.positions.get(&id) {
+            Some(&index) => {
+                let embedding = &mut self.embeddings[index];
+                embedding.vector = vector;
+                embedding.blob = blob;
+                embedding.metadata = metadata;
+                embedding.version = self.version;
+            }
+            None => {
+                self.positions.insert(id.clone(), self.embeddings.len());
+                self.embeddings.push(Embedding {
+                    id,
+                    vector,
+                    blob,
+                    metadata,
+                    prior: 0.0,
+                    version: self.version,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets score priors of embeddings by their document id, others get none.
+    pub fn set_priors(&mut self, priors: &HashMap<i64, f32>) {
+        self.version = next_version();
+        for embedding in self.embeddings.iter_mut() {
+            let prior = priors
+                .get(&embedding.metadata.document_id)
+                .copied()
+                .unwrap_or_default();
+            if embedding.prior != prior {
+                embedding.prior = prior;
+                embedding.version = self.version;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        self.version = next_version();
+        let Some(index) = self.positions.remove(id) else {
+            return;
+        };
+        self.embeddings.remove(index);
+        for embedding in &self.embeddings[index..] {
+            if let Some(position) = self.positions.get_mut(&embedding.id) {
+                *position -= 1;
+            }
+        }
+        self.tombstones.push((self.version, id.to_string()));
+        self.trim_tombstones();
+        self.forget_model_version();
+    }
+
+    /// Removes the embeddings of the source, returns how many there were.
+    pub fn remove_source(&mut self, source_id: i64) -> usize {
+        self.version = next_version();
+        let mut removed = Vec::new();
+        self.embeddings.retain(|e| {
+            if e.metadata.source_id == source_id {
+                removed.push(e.id.clone());
+                false
+            } else {
+                true
+            }
+        });
+        let count = removed.len();
+        if count > 0 {
+            self.index_positions();
+        }
+        let version = self.version;
+        self.tombstones
+            .extend(removed.into_iter().map(|id| (version, id)));
+        self.trim_tombstones();
+        self.forget_model_version();
+        count
+    }
+
+    // An emptied collection takes the version of the next vector, e.g. once
+    // its sources were re-indexed with other weights of the model.
+    fn forget_model_version(&mut self) {
+        if self.embeddings.is_empty() {
+            self.model_version.clear();
+        }
+    }
+
+    fn index_positions(&mut self) {
+        self.positions = self
             .embeddings
-            .par_iter()
+            .iter()
             .enumerate()
-            .map(|(index, embedding)| {
-                let score = distance_fn(&embedding.vector, query, memo_attr);
-                ScoreIndex { score, index }
-            })
-            .collect::<Vec<_>>();
+            .map(|(index, e)| (e.id.clone(), index))
+            .collect();
+    }
 
-        let mut heap = BinaryHeap::new();
-        for score_index in scores {
-            if heap.len() < k || score_index < *heap.peek().unwrap() {
-                heap.push(score_index);
+    fn trim_tombstones(&mut self) {
+        if self.tombstones.len() > MAX_TOMBSTONES {
+            let dropped = self.tombstones.len() - MAX_TOMBSTONES;
+            self.base_version = self.tombstones[dropped - 1].0;
+            self.tombstones.drain(..dropped);
+        }
+    }
 
-                if heap.len() > k {
-                    heap.pop();
-                }
-            }
+    /// Returns changes made after `since`, or a full snapshot if they are no
+    /// longer known, e.g. because the collection was reloaded in between.
+    pub fn delta(&self, since: Option<u64>) -> Delta {
+        let since = since.filter(|since| *since >= self.base_version);
+        let Some(since) = since else {
+            return Delta {
+                version: self.version,
+                full: true,
+                upserted: self.embeddings.clone(),
+                removed: Vec::new(),
+            };
+        };
+        Delta {
+            version: self.version,
+            full: false,
+            upserted: self
+                .embeddings
+                .iter()
+                .filter(|e| e.version > since)
+                .cloned()
+                .collect(),
+            removed: self
+                .tombstones
+                .iter()
+                .filter(|(version, _)| *version > since)
+                .map(|(_, id)| id.clone())
+                .collect(),
+        }
+    }
+
+    // Vectors of another model, or of another version of it, can't be compared
+    // with the collection's. Vectors without a recorded model are let in.
+    fn check_model(&mut self, metadata: &Metadata) -> Result<(), Error> {
+        if !metadata.model.is_empty() && !self.model.is_empty() && metadata.model != self.model {
+            return Err(Error::ModelMismatch);
+        }
+        if metadata.model_version.is_empty() {
+            return Ok(());
         }
+        if self.model_version.is_empty() {
+            self.model_version = metadata.model_version.clone();
+        }
+        match self.model_version == metadata.model_version {
+            true => Ok(()),
+            false => Err(Error::ModelMismatch),
+        }
+    }
+
+    fn prepare(&self, vector: Vec<f32>) -> Result<Vec<f32>, Error> {
+        if vector.len() != self.dimension {
+            return Err(Error::DimensionMismatch);
+        }
+        // Normalize the vector if the distance metric is cosine, so we can use dot product later
+        if self.distance == Distance::Cosine {
+            return Ok(normalize(&vector));
+        }
+        Ok(vector)
+    }
+
+    pub fn get_similarity(&self, query: &[f32], k: usize) -> Vec<SimilarityResult> {
+        self.get_similarity_within(query, k, None, None).0
+    }
 
-        heap.into_sorted_vec()
+    /// Same as `get_similarity`, but stops scanning once the deadline passes.
+    /// Returns the best results found so far and whether the scan was cut short.
+    /// Embeddings tagged with `boost_tag` score a bit higher.
+    pub fn get_similarity_within(
+        &self,
+        query: &[f32],
+        k: usize,
+        deadline: Option<Instant>,
+        boost_tag: Option<&str>,
+    ) -> (Vec<SimilarityResult>, bool) {
+        let (scores, partial) = self.score_all(query, deadline, boost_tag);
+
+        let result = ranking::top_k(scores, k)
             .into_iter()
             .map(|ScoreIndex { score, index }| SimilarityResult {
                 score,
                 embedding: self.embeddings[index].clone(),
             })
-            .collect()
+            .collect();
+        (result, partial)
+    }
+
+    /// Returns up to `k` results ranked after the `(score, id)` of the previous page's
+    /// last result. Ties are broken by id, so pages neither repeat nor skip results
+    /// with equal scores.
+    pub fn get_similarity_page(
+        &self,
+        query: &[f32],
+        k: usize,
+        after: Option<(f32, &str)>,
+        deadline: Option<Instant>,
+        boost_tag: Option<&str>,
+    ) -> (Vec<SimilarityResult>, bool) {
+        let (scores, partial) = self.score_all(query, deadline, boost_tag);
+        let scores =
+            ranking::page_after(scores, k, after, |index| self.embeddings[index].id.as_str());
+
+        let result = scores
+            .into_iter()
+            .map(|ScoreIndex { score, index }| SimilarityResult {
+                score,
+                embedding: self.embeddings[index].clone(),
+            })
+            .collect();
+        (result, partial)
+    }
+
+    /// Same as `get_similarity_page`, but also returns the `depth` best results
+    /// of the same scan, e.g. to count facets over more than a page.
+    pub fn get_similarity_page_and_best(
+        &self,
+        query: &[f32],
+        k: usize,
+        after: Option<(f32, &str)>,
+        depth: usize,
+        deadline: Option<Instant>,
+        boost_tag: Option<&str>,
+    ) -> (Vec<SimilarityResult>, Vec<SimilarityResult>, bool) {
+        let (scores, partial) = self.score_all(query, deadline, boost_tag);
+        let best = match depth {
+            0 => Vec::new(),
+            depth => ranking::top_k(
+                scores
+                    .iter()
+                    .map(|&ScoreIndex { score, index }| ScoreIndex { score, index }),
+                depth,
+            ),
+        };
+        let page =
+            ranking::page_after(scores, k, after, |index| self.embeddings[index].id.as_str());
+        let results = |scores: Vec<ScoreIndex>| -> Vec<SimilarityResult> {
+            scores
+                .into_iter()
+                .map(|ScoreIndex { score, index }| SimilarityResult {
+                    score,
+                    embedding: self.embeddings[index].clone(),
+                })
+                .collect()
+        };
+        (results(page), results(best), partial)
+    }
+
+    // Scores every embedding against the query, "higher is better" for all metrics.
+    fn score_all(
+        &self,
+        query: &[f32],
+        deadline: Option<Instant>,
+        boost_tag: Option<&str>,
+    ) -> (Vec<ScoreIndex>, bool) {
+        let memo_attr = get_cache_attr(self.distance, query);
+        let partial = AtomicBool::new(false);
+
+        let scores = self
+            .embeddings
+            .par_chunks(SCAN_BATCH_SIZE)
+            .enumerate()
+            .map(|(batch, embeddings)| {
+                if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                    partial.store(true, AtomicOrdering::Relaxed);
+                    return Vec::new();
+                }
+                embeddings
+                    .iter()
+                    .enumerate()
+                    .map(|(i, embedding)| {
+                        let boosted = boost_tag.map_or(false, |tag| {
+                            embedding.metadata.tags.iter().any(|t| t == tag)
+                        });
+                        let prior = match boosted {
+                            true => embedding.prior + TAG_BOOST,
+                            false => embedding.prior,
+                        };
+                        ScoreIndex {
+                            score: ranking::score(
+                                self.distance,
+                                query,
+                                &embedding.vector,
+                                memo_attr,
+                                prior,
+                            ),
+                            index: batch * SCAN_BATCH_SIZE + i,
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        (scores.into_iter().flatten().collect(), partial.into_inner())
     }
 }
 
@@ -79,42 +471,137 @@ pub struct Embedding {
     pub id: String,
     vector: Vec<f32>,
     pub blob: String,
+    #[serde(default)]
+    pub metadata: Metadata,
+    /// Added to the similarity score, e.g. to favor pages many others link to.
+    #[serde(default)]
+    pub prior: f32,
+    /// Version of the collection the embedding was last changed at.
+    #[serde(default)]
+    pub version: u64,
 }
 
 impl Embedding {
-    pub fn new(id: String, vector: Vec<f32>, blob: String) -> Self {
-        Self { id, vector, blob }
+    pub fn new(id: String, vector: Vec<f32>, blob: String, metadata: Metadata) -> Self {
+        Self {
+            id,
+            vector,
+            blob,
+            metadata,
+            prior: 0.0,
+            version: 0,
+        }
+    }
+}
+
+/// Describes where the embedded text comes from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Metadata {
+    pub document_id: i64,
+    pub source_id: i64,
+    pub chunk_index: usize,
+    pub path: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Anchor of the heading the chunk starts at, for deep links.
+    #[serde(default)]
+    pub anchor: String,
+    /// Language of chunks of translated documents, empty for the others.
+    #[serde(default)]
+    pub language: String,
+    /// Nav sections and position of the document in the docs site's nav,
+    /// see `Document::nav_path`.
+    #[serde(default)]
+    pub nav_path: String,
+    #[serde(default)]
+    pub nav_index: Option<usize>,
+    /// Link to the document, see `Document::url`.
+    #[serde(default)]
+    pub url: String,
+    /// Model, its version and the dimension the vector was created with,
+    /// empty for vectors stored before they were recorded.
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub model_version: String,
+    #[serde(default)]
+    pub dimension: usize,
+}
+
+/// Number of matching chunks per source, top level directory and tag, for
+/// filtering search results.
+#[derive(Debug, Default, Serialize, PartialEq, ToSchema)]
+pub struct Facets {
+    pub sources: BTreeMap<i64, usize>,
+    /// Keyed by the first segment of the path, empty for files at the root.
+    pub directories: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+    /// Keyed by the language of translated documents, empty for the others.
+    pub languages: BTreeMap<String, usize>,
+}
+
+impl Facets {
+    pub fn count<'a>(embeddings: impl IntoIterator<Item = &'a Embedding>) -> Self {
+        let mut facets = Self::default();
+        for embedding in embeddings {
+            let metadata = &embedding.metadata;
+            *facets.sources.entry(metadata.source_id).or_default() += 1;
+            let directory = match metadata.path.split_once('/') {
+                Some((directory, _)) => directory,
+                None => "",
+            };
+            *facets.directories.entry(directory.to_string()).or_default() += 1;
+            for tag in &metadata.tags {
+                *facets.tags.entry(tag.clone()).or_default() += 1;
+            }
+            *facets
+                .languages
+                .entry(metadata.language.clone())
+                .or_default() += 1;
+        }
+        facets
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Collections are shared behind `Arc`, so searches can clone a collection
+/// and release the lock before scanning it. Bulk loads build a new collection
+/// off to the side and swap it in, holding the writer only for the swap.
+#[derive(Debug)]
 pub struct Tiny {
-    pub collections: HashMap<String, Collection>,
+    pub collections: HashMap<String, Arc<Collection>>,
+    /// Held while a build of the collection with the id is published.
+    publishing: HashMap<i64, Arc<Mutex<()>>>,
 }
 
 impl Tiny {
     pub fn new() -> Self {
         Self {
             collections: HashMap::new(),
+            publishing: HashMap::new(),
         }
     }
 
+    /// Lock serializing publishes of the collection with the id.
+    pub fn publish_lock(&mut self, collection_id: i64) -> Arc<Mutex<()>> {
+        self.publishing.entry(collection_id).or_default().clone()
+    }
+
     pub fn extension(self) -> Tinyvector {
         Arc::new(RwLock::new(self))
     }
 
-    pub fn create_collection(&mut self, name: String) -> Result<Collection, Error> {
+    pub fn create_collection(
+        &mut self,
+        name: String,
+        model: String,
+        dimension: usize,
+        distance: Distance,
+    ) -> Result<Collection, Error> {
         if self.collections.contains_key(&name) {
             return Err(Error::UniqueViolation);
         }
-        let dimension = 384;
-        let distance = Distance::Cosine;
-        let collection = Collection {
-            dimension,
-            distance,
-            embeddings: Vec::new(),
-        };
-        self.collections.insert(name, collection.clone());
+        let collection = Collection::new(model, dimension, distance);
+        self.collections.insert(name, Arc::new(collection.clone()));
         Ok(collection)
     }
 
@@ -126,119 +613,379 @@ impl Tiny {
         Ok(())
     }
 
+    pub fn rename_collection(&mut self, name: &str, new_name: String) -> Result<(), Error> {
+        if self.collections.contains_key(&new_name) {
+            return Err(Error::UniqueViolation);
+        }
+        let collection = self.collections.remove(name).ok_or(Error::NotFound)?;
+        self.collections.insert(new_name, collection);
+        Ok(())
+    }
+
+    /// Replaces the collection with the given one, returning the previous collection if any.
+    pub fn swap_collection(
+        &mut self,
+        name: String,
+        collection: Collection,
+    ) -> Option<Arc<Collection>> {
+        self.collections.insert(name, Arc::new(collection))
+    }
+
     pub fn insert_into_collection(
         &mut self,
         collection_name: &str,
         id: String,
-        mut vector: Vec<f32>,
+        vector: Vec<f32>,
         blob: String,
+        metadata: Metadata,
     ) -> Result<(), Error> {
-        let collection = self
-            .collections
-            .get_mut(collection_name)
-            .ok_or(Error::NotFound)?;
-
-        if collection.embeddings.iter().any(|e| e.id == id) {
-            return Err(Error::UniqueViolation);
-        }
-
-        if vector.len() != collection.dimension {
-            return Err(Error::DimensionMismatch);
-        }
-
-        // Normalize the vector if the distance metric is cosine, so we can use dot product later
-        if collection.distance == Distance::Cosine {
-            vector = normalize(&vector);
-        }
+        self.get_collection_mut(collection_name)?
+            .insert(id, vector, blob, metadata)
+    }
 
-        collection.embeddings.push(Embedding { id, vector, blob });
+    /// Inserts the embedding, or replaces the vector and blob if the id already exists.
+    pub fn upsert_into_collection(
+        &mut self,
+        collection_name: &str,
+        id: String,
+        vector: Vec<f32>,
+        blob: String,
+        metadata: Metadata,
+    ) -> Result<(), Error> {
+        self.get_collection_mut(collection_name)?
+            .upsert(id, vector, blob, metadata)
+    }
 
+    pub fn remove_from_collection(&mut self, collection_name: &str, id: &str) -> Result<(), Error> {
+        self.get_collection_mut(collection_name)?.remove(id);
         Ok(())
     }
 
-    pub fn get_collection(&self, name: &str) -> Option<&Collection> {
-        self.collections.get(name)
+    pub fn remove_source_from_collection(
+        &mut self,
+        collection_name: &str,
+        source_id: i64,
+    ) -> Result<usize, Error> {
+        Ok(self
+            .get_collection_mut(collection_name)?
+            .remove_source(source_id))
     }
-}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
-pub enum Distance {
-    #[serde(rename = "euclidean")]
-    Euclidean,
-    #[serde(rename = "cosine")]
-    Cosine,
-    #[serde(rename = "dot")]
-    DotProduct,
-}
 
-pub fn get_cache_attr(metric: Distance, vec: &[f32]) -> f32 {
-    match metric {
-        // Dot product doesn't allow any caching
-        Distance::DotProduct | Distance::Euclidean => 0.0,
-        // Precompute the magnitude of the vector
-        Distance::Cosine => vec.iter().map(|&x| x.powi(2)).sum::<f32>().sqrt(),
+    pub fn get_collection(&self, name: &str) -> Option<Arc<Collection>> {
+        self.collections.get(name).cloned()
     }
-}
 
-pub fn get_distance_fn(metric: Distance) -> impl Fn(&[f32], &[f32], f32) -> f32 {
-    match metric {
-        Distance::Euclidean => euclidian_distance,
-        // We use dot product for cosine because we've normalized the vectors on insertion
-        Distance::Cosine | Distance::DotProduct => dot_product,
+    // Clones the collection if it's being read by a search at the moment, for
+    // single changes. Bulk changes clone it off the lock and swap it in.
+    fn get_collection_mut(&mut self, name: &str) -> Result<&mut Collection, Error> {
+        self.collections
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(Error::NotFound)
     }
 }
 
-fn euclidian_distance(a: &[f32], b: &[f32], a_sum_squares: f32) -> f32 {
-    let mut cross_terms = 0.0;
-    let mut b_sum_squares = 0.0;
-
-    for (i, j) in a.iter().zip(b) {
-        cross_terms += i * j;
-        b_sum_squares += j.powi(2);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_facets() {
+        let embeddings = [
+            ("docs/install.md", 1, vec!["linux"], ""),
+            ("docs/billing.md", 1, vec![], ""),
+            ("README.zh-CN.md", 2, vec!["linux"], "zh-cn"),
+        ]
+        .map(|(path, source_id, tags, language)| {
+            let metadata = Metadata {
+                source_id,
+                path: path.to_string(),
+                tags: tags.into_iter().map(String::from).collect(),
+                language: language.to_string(),
+                ..Default::default()
+            };
+            Embedding::new(path.to_string(), vec![], String::new(), metadata)
+        });
+        let facets = Facets::count(&embeddings);
+        assert_eq!(facets.sources, BTreeMap::from([(1, 2), (2, 1)]));
+        assert_eq!(
+            facets.directories,
+            BTreeMap::from([("".to_string(), 1), ("docs".to_string(), 2)])
+        );
+        assert_eq!(facets.tags, BTreeMap::from([("linux".to_string(), 2)]));
+        assert_eq!(
+            facets.languages,
+            BTreeMap::from([("".to_string(), 2), ("zh-cn".to_string(), 1)])
+        );
     }
 
-    2.0f32
-        .mul_add(-cross_terms, a_sum_squares + b_sum_squares)
-        .max(0.0)
-        .sqrt()
-}
-
-fn dot_product(a: &[f32], b: &[f32], _: f32) -> f32 {
-    a.iter().zip(b).fold(0.0, |acc, (x, y)| acc + x * y)
-}
+    #[test]
+    fn test_upsert_replaces_existing_embedding() {
+        let mut tiny = Tiny::new();
+        tiny.create_collection(
+            "test".to_string(),
+            "test".to_string(),
+            2,
+            Distance::DotProduct,
+        )
+        .unwrap();
+        tiny.upsert_into_collection(
+            "test",
+            "1".to_string(),
+            vec![1.0, 0.0],
+            "a".to_string(),
+            Metadata::default(),
+        )
+        .unwrap();
+        tiny.upsert_into_collection(
+            "test",
+            "1".to_string(),
+            vec![0.0, 1.0],
+            "b".to_string(),
+            Metadata::default(),
+        )
+        .unwrap();
+
+        let collection = tiny.get_collection("test").unwrap();
+        assert_eq!(collection.embeddings.len(), 1);
+        assert_eq!(collection.embeddings[0].blob, "b");
+        assert_eq!(collection.embeddings[0].vector, vec![0.0, 1.0]);
+    }
 
-pub fn normalize(vec: &[f32]) -> Vec<f32> {
-    let magnitude = (vec.iter().fold(0.0, |acc, &val| val.mul_add(val, acc))).sqrt();
+    #[test]
+    fn test_upsert_after_remove() {
+        let mut collection = Collection::new("test".to_string(), 2, Distance::DotProduct);
+        for id in ["1", "2", "3"] {
+            collection
+                .insert(
+                    id.to_string(),
+                    vec![1.0, 0.0],
+                    id.to_string(),
+                    Metadata::default(),
+                )
+                .unwrap();
+        }
+        collection.remove("1");
+        collection
+            .upsert(
+                "3".to_string(),
+                vec![0.0, 1.0],
+                "c".to_string(),
+                Metadata::default(),
+            )
+            .unwrap();
+        collection
+            .upsert(
+                "4".to_string(),
+                vec![0.0, 1.0],
+                "d".to_string(),
+                Metadata::default(),
+            )
+            .unwrap();
+
+        let blobs: Vec<&str> = collection
+            .embeddings
+            .iter()
+            .map(|e| e.blob.as_str())
+            .collect();
+        assert_eq!(blobs, vec!["2", "c", "d"]);
+        assert!(collection
+            .insert(
+                "2".to_string(),
+                vec![1.0, 0.0],
+                String::new(),
+                Metadata::default()
+            )
+            .is_err());
+    }
 
-    if magnitude > std::f32::EPSILON {
-        vec.iter().map(|&val| val / magnitude).collect()
-    } else {
-        vec.to_vec()
+    #[test]
+    fn test_insert_rejects_existing_id() {
+        let mut tiny = Tiny::new();
+        tiny.create_collection(
+            "test".to_string(),
+            "test".to_string(),
+            2,
+            Distance::DotProduct,
+        )
+        .unwrap();
+        tiny.insert_into_collection(
+            "test",
+            "1".to_string(),
+            vec![1.0, 0.0],
+            "a".to_string(),
+            Metadata::default(),
+        )
+        .unwrap();
+        let res = tiny.insert_into_collection(
+            "test",
+            "1".to_string(),
+            vec![0.0, 1.0],
+            "b".to_string(),
+            Metadata::default(),
+        );
+        assert!(matches!(res, Err(Error::UniqueViolation)));
     }
-}
 
-pub struct ScoreIndex {
-    pub score: f32,
-    pub index: usize,
-}
+    #[test]
+    fn test_upsert_rejects_other_models() {
+        let mut collection = Collection::new("minilm".to_string(), 2, Distance::DotProduct);
+        let metadata = |model: &str, model_version: &str| Metadata {
+            model: model.to_string(),
+            model_version: model_version.to_string(),
+            dimension: 2,
+            ..Default::default()
+        };
+        collection
+            .upsert(
+                "1".to_string(),
+                vec![1.0, 0.0],
+                String::new(),
+                metadata("minilm", "bert"),
+            )
+            .unwrap();
+        // Vectors stored before models were recorded are let in.
+        collection
+            .upsert(
+                "2".to_string(),
+                vec![0.0, 1.0],
+                String::new(),
+                Metadata::default(),
+            )
+            .unwrap();
+        let res = collection.upsert(
+            "3".to_string(),
+            vec![1.0, 1.0],
+            String::new(),
+            metadata("mpnet", "bert"),
+        );
+        assert!(matches!(res, Err(Error::ModelMismatch)));
+        let res = collection.upsert(
+            "3".to_string(),
+            vec![1.0, 1.0],
+            String::new(),
+            metadata("minilm", "hash"),
+        );
+        assert!(matches!(res, Err(Error::ModelMismatch)));
+        assert_eq!(collection.embeddings.len(), 2);
+        assert_eq!(collection.model_version, "bert");
+    }
 
-impl PartialEq for ScoreIndex {
-    fn eq(&self, other: &Self) -> bool {
-        self.score.eq(&other.score)
+    #[test]
+    fn test_emptied_collection_takes_new_model_version() {
+        let mut collection = Collection::new("minilm".to_string(), 2, Distance::DotProduct);
+        let metadata = |model_version: &str| Metadata {
+            source_id: 1,
+            model: "minilm".to_string(),
+            model_version: model_version.to_string(),
+            dimension: 2,
+            ..Default::default()
+        };
+        collection
+            .upsert(
+                "1".to_string(),
+                vec![1.0, 0.0],
+                String::new(),
+                metadata("v1"),
+            )
+            .unwrap();
+        assert_eq!(collection.remove_source(1), 1);
+        collection
+            .upsert(
+                "2".to_string(),
+                vec![0.0, 1.0],
+                String::new(),
+                metadata("v2"),
+            )
+            .unwrap();
+        assert_eq!(collection.model_version, "v2");
+
+        collection.remove("2");
+        collection
+            .upsert(
+                "3".to_string(),
+                vec![1.0, 1.0],
+                String::new(),
+                metadata("v3"),
+            )
+            .unwrap();
+        assert_eq!(collection.model_version, "v3");
     }
-}
 
-impl Eq for ScoreIndex {}
+    #[test]
+    fn test_similarity_pages_dont_skip_ties() {
+        let mut collection = Collection::new("test".to_string(), 2, Distance::DotProduct);
+        for (id, vector) in [
+            ("1", vec![1.0, 0.0]),
+            ("2", vec![0.5, 0.0]),
+            ("3", vec![0.5, 0.0]),
+            ("4", vec![0.0, 1.0]),
+        ] {
+            collection
+                .insert(id.to_string(), vector, String::new(), Metadata::default())
+                .unwrap();
+        }
 
-impl PartialOrd for ScoreIndex {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        // The comparison is intentionally reversed here to make the heap a min-heap
-        other.score.partial_cmp(&self.score)
+        let mut ids = Vec::new();
+        let mut after: Option<(f32, String)> = None;
+        loop {
+            let (page, _) = collection.get_similarity_page(
+                &[1.0, 0.0],
+                2,
+                after.as_ref().map(|(score, id)| (*score, id.as_str())),
+                None,
+                None,
+            );
+            let Some(last) = page.last() else { break };
+            after = Some((last.score, last.embedding.id.clone()));
+            ids.extend(page.into_iter().map(|r| r.embedding.id));
+        }
+        assert_eq!(ids, vec!["1", "2", "3", "4"]);
+
+        let (page, best, _) =
+            collection.get_similarity_page_and_best(&[1.0, 0.0], 2, None, 3, None, None);
+        let ids = |results: Vec<SimilarityResult>| -> Vec<String> {
+            results.into_iter().map(|r| r.embedding.id).collect()
+        };
+        assert_eq!(ids(page), vec!["1", "2"]);
+        assert_eq!(ids(best).len(), 3);
     }
-}
 
-impl Ord for ScoreIndex {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    #[test]
+    fn test_delta_lists_changes_since_version() {
+        let mut collection = Collection::new("test".to_string(), 2, Distance::DotProduct);
+        for id in ["1", "2"] {
+            collection
+                .insert(
+                    id.to_string(),
+                    vec![1.0, 0.0],
+                    String::new(),
+                    Metadata::default(),
+                )
+                .unwrap();
+        }
+        let since = collection.version;
+        collection
+            .upsert(
+                "2".to_string(),
+                vec![0.0, 1.0],
+                String::new(),
+                Metadata::default(),
+            )
+            .unwrap();
+        collection.remove("1");
+
+        let delta = collection.delta(Some(since));
+        assert!(!delta.full);
+        assert_eq!(delta.version, collection.version);
+        let upserted: Vec<&str> = delta.upserted.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(upserted, vec!["2"]);
+        assert_eq!(delta.removed, vec!["1".to_string()]);
+
+        assert!(collection
+            .delta(Some(collection.version))
+            .upserted
+            .is_empty());
+        assert!(collection.delta(None).full);
     }
 }
@@ -0,0 +1,23 @@
+use std::sync::Arc;
+use tiktoken_rs::CoreBPE;
+
+/// Shared cl100k tokenizer.
+///
+/// Building the BPE ranks is expensive, so it's constructed once at startup
+/// and cloned cheaply wherever tokens need to be counted.
+#[derive(Clone)]
+pub struct Tokenizer {
+    bpe: Arc<CoreBPE>,
+}
+
+impl Tokenizer {
+    pub fn new() -> anyhow::Result<Self> {
+        let bpe = tiktoken_rs::cl100k_base()?;
+        Ok(Self { bpe: Arc::new(bpe) })
+    }
+
+    /// Returns the number of tokens in the text.
+    pub fn count(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
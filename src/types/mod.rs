@@ -1,30 +1,289 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::{Distance, DocumentFormat};
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct Collection {
     pub id: i64,
     pub name: String,
+    pub model: String,
+    #[schema(value_type = String, example = "cosine")]
+    pub distance: Distance,
+    pub dimension: usize,
+    /// Incremented whenever a sync changes what the collection serves,
+    /// clients pin it to compare results of the same index.
+    pub index_version: i64,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+/// Where a source's documents come from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// Files of a GitHub repo branch.
+    #[default]
+    GitHub,
+    /// Pages crawled from `Source::url`.
+    Web,
+    /// Items of the rustdoc JSON at `Source::url`.
+    Rustdoc,
+    /// Messages of the mbox archive or EML file at `Source::url`.
+    Mbox,
+    /// Objects of the S3 compatible bucket at `Source::url`.
+    Bucket,
+    /// Files of archives uploaded to the source, there's nothing to fetch.
+    Upload,
+}
+
+impl SourceKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceKind::GitHub => "github",
+            SourceKind::Web => "web",
+            SourceKind::Rustdoc => "rustdoc",
+            SourceKind::Mbox => "mbox",
+            SourceKind::Bucket => "bucket",
+            SourceKind::Upload => "upload",
+        }
+    }
+}
+
+impl FromStr for SourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "github" => Ok(SourceKind::GitHub),
+            "web" => Ok(SourceKind::Web),
+            "rustdoc" => Ok(SourceKind::Rustdoc),
+            "mbox" => Ok(SourceKind::Mbox),
+            "bucket" => Ok(SourceKind::Bucket),
+            "upload" => Ok(SourceKind::Upload),
+            _ => Err(format!("Unknown source kind '{}'", s)),
+        }
+    }
+}
+
+/// What happens to translated copies of docs, e.g. `docs/zh/`, so a page
+/// translated to N languages doesn't take N results of every search.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Translations {
+    /// Only the canonical documents are indexed.
+    #[default]
+    Skip,
+    /// Translations are indexed with their language, linked to the canonical
+    /// document.
+    Index,
+}
+
+impl Translations {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Translations::Skip => "skip",
+            Translations::Index => "index",
+        }
+    }
+}
+
+impl FromStr for Translations {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Translations::Skip),
+            "index" => Ok(Translations::Index),
+            _ => Err(format!("Unknown translations mode '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
 pub struct Source {
     pub id: i64,
     pub collection_id: i64,
+    pub kind: SourceKind,
+    /// Url the crawl starts at, of the rustdoc JSON, the mail archive or the
+    /// bucket, empty for GitHub sources.
+    pub url: String,
     pub owner: String,
     pub repo: String,
     pub branch: String,
+    #[schema(value_type = Vec<String>)]
     pub allowed_ext: HashSet<String>,
+    #[schema(value_type = Vec<String>)]
     pub allowed_dirs: HashSet<String>,
+    #[schema(value_type = Vec<String>)]
     pub ignored_dirs: HashSet<String>,
+    /// Front matter rules of documents skipped while parsing, e.g. `draft=true`
+    /// or `noindex`, see `encoder::excluded_by`.
+    #[schema(value_type = Vec<String>)]
+    pub skip_front_matter: HashSet<String>,
+    /// Tags of all chunks of the source, e.g. the package of a monorepo its
+    /// docs belong to, searches can filter and boost by them.
+    #[schema(value_type = Vec<String>)]
+    pub labels: HashSet<String>,
+    /// Whether translated copies of docs are skipped or indexed.
+    #[serde(default)]
+    pub translations: Translations,
+    /// Limits of the crawl, only used by web sources.
+    pub crawl: CrawlOptions,
+    /// Prefix and credentials, only used by bucket sources.
+    pub bucket: BucketOptions,
+    /// Credentials, only used by GitHub sources.
+    pub github: GitHubOptions,
+    /// About the GitHub repo, fetched when the source is created.
+    #[serde(default)]
+    pub metadata: RepoMetadata,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// Deepest crawl a source can ask for.
+pub const MAX_CRAWL_DEPTH: usize = 50;
+
+/// Most pages a crawl of a source can fetch.
+pub const MAX_CRAWL_PAGES: usize = 20_000;
+
+/// Keeps crawls of large documentation portals bounded.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct CrawlOptions {
+    /// Links followed from the start url, the start page is at depth 0, at
+    /// most `MAX_CRAWL_DEPTH`.
+    pub max_depth: usize,
+    /// Pages fetched at most, including the ones that aren't indexed, at most
+    /// `MAX_CRAWL_PAGES`.
+    pub max_pages: usize,
+    /// Regexes, when any are given a url has to match one of them to be crawled.
+    pub include: Vec<String>,
+    /// Regexes of urls never crawled, they win over `include`.
+    pub exclude: Vec<String>,
+    /// Drops query strings while canonicalizing urls, for sites that use
+    /// them for navigation state only.
+    pub strip_query: bool,
+    /// Skips pages with the same content as an already crawled page.
+    pub dedup: bool,
+}
+
+/// Where a bucket source's objects are and how to access them.
+#[derive(Serialize, Deserialize, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct BucketOptions {
+    /// Signing region, `auto` for GCS.
+    pub region: String,
+    /// Only objects below it are listed, paths are relative to it.
+    pub prefix: String,
+    /// Requests are unsigned without one, for public buckets.
+    pub access_key_id: String,
+    #[serde(skip_serializing)]
+    pub secret_access_key: String,
+}
+
+// Sources and requests creating them are logged, the secret must not be.
+impl fmt::Debug for BucketOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BucketOptions")
+            .field("region", &self.region)
+            .field("prefix", &self.prefix)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Credentials of a GitHub source, for repos of orgs or accounts the server's
+/// `GITHUB_TOKEN` can't read, and how its repo is traversed.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Default, ToSchema)]
+#[serde(default)]
+pub struct GitHubOptions {
+    /// Personal access token, the server's token is used without one.
+    #[serde(skip_serializing)]
+    pub token: String,
+    /// Indexes files of the repo's submodules at their pinned commits, for
+    /// projects keeping their docs in a separate repo.
+    pub submodules: bool,
+}
+
+impl fmt::Debug for GitHubOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let token = match self.token.is_empty() {
+            true => "",
+            false => "<redacted>",
+        };
+        f.debug_struct("GitHubOptions")
+            .field("token", &token)
+            .field("submodules", &self.submodules)
+            .finish()
+    }
+}
+
+/// What a GitHub repo says about itself, it tells the embeddings which
+/// project and stack the docs are about, e.g. for chunks only naming "the
+/// client".
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct RepoMetadata {
+    pub description: String,
+    pub topics: Vec<String>,
+    /// Primary language as detected by GitHub.
+    pub language: String,
+    pub homepage: String,
+}
+
+impl RepoMetadata {
+    /// Context of the source's chunks, None if the repo has no metadata.
+    pub fn context(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if !self.language.is_empty() {
+            parts.push(format!("{} project.", self.language));
+        }
+        if !self.description.is_empty() {
+            parts.push(self.description.clone());
+        }
+        if !self.topics.is_empty() {
+            parts.push(format!("Topics: {}.", self.topics.join(", ")));
+        }
+        match parts.is_empty() {
+            true => None,
+            false => Some(parts.join(" ")),
+        }
+    }
+}
+
+impl Default for BucketOptions {
+    fn default() -> Self {
+        Self {
+            region: "us-east-1".to_string(),
+            prefix: String::new(),
+            access_key_id: String::new(),
+            secret_access_key: String::new(),
+        }
+    }
+}
+
+impl Default for CrawlOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 10,
+            max_pages: 500,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            strip_query: false,
+            dedup: true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
 pub struct Document {
     pub id: i64,
     pub source_id: i64,
@@ -33,10 +292,39 @@ pub struct Document {
     pub checksum: u32,
     pub tokens_len: usize,
     pub data: String,
+    /// Detected when the document is parsed, picks the splitter used to chunk it.
+    pub format: DocumentFormat,
+    /// Short LLM summary, empty unless the document was encoded with summaries.
+    pub summary: String,
+    /// Other paths of the same content, e.g. of vendored or mirrored directories,
+    /// which are indexed once.
+    pub alternate_paths: Vec<String>,
+    /// Sections of the docs site's nav the document is in, e.g.
+    /// `Guides > Deployment`, from the repo's mkdocs or Docusaurus config.
+    pub nav_path: String,
+    /// Position of the document in the nav, none if it isn't in it.
+    pub nav_index: Option<usize>,
+    /// Language of a translated copy, e.g. `zh`, empty for canonical documents.
+    pub language: String,
+    /// Path of the document this one is a translation of, empty if it isn't one.
+    pub canonical_path: String,
+    /// Link to the document, e.g. the file on GitHub, empty if it has none.
+    pub url: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// What embeddings of a document's chunks record of it, so searches don't
+/// look the document or its source up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentRef {
+    pub path: String,
+    /// See `Document::nav_path`.
+    pub nav_path: String,
+    pub nav_index: Option<usize>,
+    pub url: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Chunk {
     pub id: i64,
@@ -47,4 +335,320 @@ pub struct Chunk {
     pub context: String,
     pub data: String,
     pub vector: Vec<f32>,
+    /// E.g. `encoder::WARNING_TAG`, so searches for caveats can boost the chunk.
+    pub tags: Vec<String>,
+    /// Anchor of the heading the chunk starts at, empty if it doesn't start at one.
+    pub anchor: String,
+    /// Language of the chunk's document if it's a translation, see `Document::language`.
+    pub language: String,
+    /// Model the vector was created with, vectors of different models aren't
+    /// comparable and never share a collection.
+    pub model: String,
+    /// See `Embeddings::version`, empty if it wasn't recorded.
+    pub model_version: String,
+    pub dimension: usize,
+}
+
+/// Totals derived from a source's documents and chunks.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, ToSchema)]
+pub struct SourceStats {
+    pub documents: i64,
+    pub chunks: i64,
+    pub tokens: i64,
+    /// None if the source was never parsed.
+    pub parsed_at: Option<DateTime<Utc>>,
+    /// Files fetched by the last parse job, None without one.
+    pub summary: Option<ParseSummary>,
+}
+
+/// Files fetched by a parse, to see which filters are worth adding, e.g. to
+/// ignore a directory of large generated files.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, ToSchema)]
+pub struct ParseSummary {
+    /// Number of files by extension, files without one are counted under "".
+    pub files_by_ext: BTreeMap<String, usize>,
+    pub tokens: usize,
+    /// The largest files, biggest first.
+    pub largest_files: Vec<FileSize>,
+    /// Files without text, e.g. scanned PDFs.
+    pub empty_files: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct FileSize {
+    pub path: String,
+    pub bytes: u64,
+}
+
+impl ParseSummary {
+    const LARGEST_FILES: usize = 10;
+
+    pub fn add(&mut self, path: &str, bytes: u64, tokens: usize) {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_default();
+        *self.files_by_ext.entry(ext).or_default() += 1;
+        self.tokens += tokens;
+        let at = self
+            .largest_files
+            .partition_point(|file| file.bytes >= bytes);
+        if at < Self::LARGEST_FILES {
+            self.largest_files.insert(
+                at,
+                FileSize {
+                    path: path.to_string(),
+                    bytes,
+                },
+            );
+            self.largest_files.truncate(Self::LARGEST_FILES);
+        }
+    }
+
+    pub fn add_empty(&mut self, path: &str, bytes: u64) {
+        self.add(path, bytes, 0);
+        self.empty_files.push(path.to_string());
+    }
+}
+
+/// Response stored for a request made with an `Idempotency-Key` header.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct IdempotencyRecord {
+    pub key: String,
+    pub request_hash: u32,
+    /// Zero while the request holding the key is running.
+    pub status: u16,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Parse or encode of a source running in the background, polled by clients
+/// through its id.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct Job {
+    pub id: i64,
+    pub source_id: i64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    /// Documents parsed or encoded, set once the job is finished.
+    pub documents: usize,
+    /// Why the job failed, empty unless it did.
+    pub error: String,
+    /// Report of the job once it succeeded, e.g. the documents of a parse.
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Parse,
+    Encode,
+    /// Parse followed by an encode.
+    Sync,
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::Parse => "parse",
+            JobKind::Encode => "encode",
+            JobKind::Sync => "sync",
+        }
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parse" => Ok(JobKind::Parse),
+            "encode" => Ok(JobKind::Encode),
+            "sync" => Ok(JobKind::Sync),
+            _ => Err(format!("Unknown job kind '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the job is done, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Succeeded | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+impl FromStr for JobStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(JobStatus::Queued),
+            "running" => Ok(JobStatus::Running),
+            "succeeded" => Ok(JobStatus::Succeeded),
+            "failed" => Ok(JobStatus::Failed),
+            "cancelled" => Ok(JobStatus::Cancelled),
+            _ => Err(format!("Unknown job status '{}'", s)),
+        }
+    }
+}
+
+/// Administrative request, recorded whether or not it succeeded.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// User of the request's session, `anonymous` without one.
+    pub actor: String,
+    pub request_id: String,
+    /// Method and route, e.g. `DELETE /api/tokens/:token_id`.
+    pub action: String,
+    /// Path and query the request was made to.
+    pub path: String,
+    /// Truncated payload with secrets redacted.
+    pub summary: String,
+    pub status: u16,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What a signed in user may do, mapped from their groups at the IdP.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("Unknown role '{}'", s)),
+        }
+    }
+}
+
+/// Dashboard login, the cookie holds the token whose hash is `id_hash`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Session {
+    #[serde(skip_serializing)]
+    pub id_hash: String,
+    /// Subject of the user at the IdP.
+    pub subject: String,
+    pub email: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Query term expanded before embedding, e.g. "k8s" to "kubernetes".
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct Alias {
+    pub collection_id: i64,
+    pub alias: String,
+    pub expansion: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Read-only token searching a single collection, for docs sites embedding
+/// the search box. Only the hash of the token is stored.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct AccessToken {
+    pub id: i64,
+    pub collection_id: i64,
+    /// Tells tokens apart, e.g. the site using it.
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub requests_per_minute: u32,
+    /// Origins of the sites allowed to search with the token, e.g.
+    /// `https://docs.example.com`. Any origin is allowed if empty.
+    pub allowed_origins: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Heading of a document, in the order they appear.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct Heading {
+    pub document_id: i64,
+    pub position: usize,
+    pub level: u8,
+    pub text: String,
+    pub anchor: String,
+}
+
+/// Link found in a document, `target_path` is set for links within the repo.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Link {
+    pub document_id: i64,
+    pub source_id: i64,
+    pub url: String,
+    pub target_path: Option<String>,
+}
+
+/// Cached result of checking an external link.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LinkCheck {
+    pub url: String,
+    /// HTTP status of the response, unset if the request failed.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Link pointing at a missing document or an unreachable page.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct BrokenLink {
+    pub document_id: i64,
+    /// Path of the document the link is in.
+    pub path: String,
+    pub url: String,
+    pub reason: String,
+}
+
+/// Document listed in the sitemap and feed, without its content.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct DocumentEntry {
+    pub id: i64,
+    pub path: String,
+    pub owner: String,
+    pub repo: String,
+    pub branch: String,
+    pub updated_at: DateTime<Utc>,
 }
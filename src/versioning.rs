@@ -0,0 +1,232 @@
+use axum::{
+    async_trait,
+    body::{self, Bytes, Full, StreamBody},
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, HeaderName, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use futures::stream::{self, StreamExt};
+use http_body::{Body as _, Limited};
+use serde::Serialize;
+use serde_json::Value;
+use std::convert::Infallible;
+use utoipa::ToSchema;
+
+use crate::errors::Missing;
+
+static API_VERSION: HeaderName = HeaderName::from_static("api-version");
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Error bodies are read whole to be wrapped, larger ones are replaced by the
+/// reason of their status.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Version of the API a request was routed to, by the prefix of its path.
+/// Responses of a version only change in backwards compatible ways, anything
+/// else lands in the next one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// Routes under `/api`, kept for existing clients and deprecated.
+    #[default]
+    Unversioned,
+    V1,
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V1;
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::Unversioned => "/api",
+            ApiVersion::V1 => "/api/v1",
+        }
+    }
+}
+
+/// Body of every JSON and error response of `/api/v1`, so clients find the
+/// payload, the error and the id to report issues with at the same place.
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Envelope {
+    /// Payload of the route, null for errors.
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<Value>,
+    pub error: Option<EnvelopeError>,
+    /// Id of the request, as in the `X-Request-Id` header.
+    pub request_id: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct EnvelopeError {
+    /// HTTP status code of the response.
+    pub status: u16,
+    pub message: String,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ApiVersion
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<ApiVersion>()
+            .copied()
+            .unwrap_or_default())
+    }
+}
+
+/// Marks responses of the unversioned `/api` routes as deprecated, with a
+/// `Link` to their successor under the latest version and, once the date is
+/// announced, a `Sunset` header of when they are removed.
+pub async fn deprecated<B>(
+    State(sunset): State<Option<String>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    req.extensions_mut().insert(ApiVersion::Unversioned);
+    let successor = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or_default()
+        .replacen(
+            ApiVersion::Unversioned.prefix(),
+            ApiVersion::LATEST.prefix(),
+            1,
+        );
+
+    let mut resp = next.run(req).await;
+    let headers = resp.headers_mut();
+    headers.insert(&DEPRECATION, HeaderValue::from_static("true"));
+    if let Some(value) = sunset.and_then(|sunset| HeaderValue::from_str(&sunset).ok()) {
+        headers.insert(&SUNSET, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.insert(header::LINK, value);
+    }
+    resp
+}
+
+/// Tags requests to the `/api/v1` routes and their responses with the
+/// version, and wraps responses into an `Envelope`.
+pub async fn v1<B>(mut req: Request<B>, next: Next<B>) -> Response {
+    req.extensions_mut().insert(ApiVersion::V1);
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let resp = next.run(req).await;
+    let mut resp = envelope(resp, request_id).await;
+    resp.headers_mut()
+        .insert(&API_VERSION, HeaderValue::from_static("1"));
+    resp
+}
+
+// Wraps JSON bodies and errors into an `Envelope`. Streams, like NDJSON and
+// CSV listings, and responses without a body pass through as they are.
+// Missing entities, 204s of the unversioned routes, become 404 errors.
+async fn envelope(resp: Response, request_id: String) -> Response {
+    let missing = resp.extensions().get::<Missing>().is_some();
+    let status = match missing {
+        true => StatusCode::NOT_FOUND,
+        false => resp.status(),
+    };
+    if matches!(status, StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED) {
+        return resp;
+    }
+    let is_json = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("application/json"));
+    let is_error = status.is_client_error() || status.is_server_error();
+    if !is_json && !is_error {
+        return resp;
+    }
+
+    let (mut parts, resp_body) = resp.into_parts();
+    parts.status = status;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    // Payloads are streamed into the envelope instead of being read whole,
+    // the fields are serialized in the order of `Envelope`.
+    if !is_error {
+        let close = format!(
+            ",\"error\":null,\"request_id\":{}}}",
+            Value::from(request_id)
+        );
+        let open = stream::once(async { Ok::<_, BoxError>(Bytes::from_static(b"{\"data\":")) });
+        let data = stream::unfold(resp_body, |mut body| async move {
+            body.data()
+                .await
+                .map(|chunk| (chunk.map_err(BoxError::from), body))
+        });
+        let close = stream::once(async move { Ok::<_, BoxError>(Bytes::from(close)) });
+        return Response::from_parts(
+            parts,
+            body::boxed(StreamBody::new(open.chain(data).chain(close))),
+        );
+    }
+
+    // Error bodies are small, they are read to find their message.
+    let bytes = match hyper::body::to_bytes(Limited::new(resp_body, MAX_ERROR_BODY)).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::warn!("Failed to read error response body: {}", err);
+            Bytes::new()
+        }
+    };
+    let payload = serde_json::from_slice::<Value>(&bytes).ok();
+    // Handlers' errors carry a message, rejections of extractors, timeouts
+    // and body limits plain text or nothing.
+    let envelope = match payload {
+        Some(Value::Object(object)) if object.get("error").map_or(false, Value::is_string) => {
+            Envelope {
+                data: None,
+                error: Some(EnvelopeError {
+                    status: status.as_u16(),
+                    message: object["error"].as_str().unwrap_or_default().to_string(),
+                }),
+                request_id,
+            }
+        }
+        payload => {
+            let message = match String::from_utf8_lossy(&bytes).trim() {
+                message if message.is_empty() || payload.is_some() => {
+                    status.canonical_reason().unwrap_or_default().to_string()
+                }
+                message => message.to_string(),
+            };
+            Envelope {
+                data: payload,
+                error: Some(EnvelopeError {
+                    status: status.as_u16(),
+                    message,
+                }),
+                request_id,
+            }
+        }
+    };
+    let bytes = match serde_json::to_vec(&envelope) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            tracing::error!("Failed to serialize response envelope: {}", err);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    Response::from_parts(parts, body::boxed(Full::from(bytes)))
+}
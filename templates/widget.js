@@ -0,0 +1,93 @@
+// Search box for docs sites, searching a collection through an access token:
+//
+//   <div id="rtfm-search"></div>
+//   <script src="https://rtfm.example.com/widget.js"
+//           data-token="rtfm_pub_..." data-target="#rtfm-search" async></script>
+//
+// `data-limit` sets the number of results, `data-placeholder` the input's placeholder.
+(function () {
+  "use strict";
+
+  var script = document.currentScript;
+  if (!script) {
+    return;
+  }
+  var base = new URL(script.src).origin;
+  var token = script.dataset.token;
+  var limit = script.dataset.limit || "5";
+  var target = document.querySelector(script.dataset.target || "#rtfm-search");
+  if (!token || !target) {
+    console.warn("rtfm widget: data-token and an existing data-target are required");
+    return;
+  }
+
+  var root = document.createElement("div");
+  root.className = "rtfm-widget";
+  var input = document.createElement("input");
+  input.type = "search";
+  input.placeholder = script.dataset.placeholder || "Search the docs";
+  input.setAttribute("aria-label", input.placeholder);
+  var list = document.createElement("ol");
+  list.className = "rtfm-results";
+  root.appendChild(input);
+  root.appendChild(list);
+  target.appendChild(root);
+
+  var pending = null;
+  var timer = null;
+
+  function render(results) {
+    list.textContent = "";
+    results.forEach(function (result) {
+      var item = document.createElement("li");
+      var path = document.createElement("strong");
+      path.textContent = result.path;
+      var text = document.createElement("p");
+      text.textContent =
+        result.text.length > 200 ? result.text.slice(0, 200) + "…" : result.text;
+      item.appendChild(path);
+      item.appendChild(text);
+      list.appendChild(item);
+    });
+  }
+
+  function search(query) {
+    if (pending) {
+      pending.abort();
+    }
+    if (!query.trim()) {
+      render([]);
+      return;
+    }
+    pending = new AbortController();
+    var url =
+      base + "/public/search?limit=" + encodeURIComponent(limit) +
+      "&query=" + encodeURIComponent(query);
+    fetch(url, {
+      headers: { Authorization: "Bearer " + token, Accept: "application/json" },
+      signal: pending.signal,
+    })
+      .then(function (resp) {
+        if (!resp.ok) {
+          throw new Error("search failed with " + resp.status);
+        }
+        return resp.json();
+      })
+      .then(function (body) {
+        render(body.results);
+      })
+      .catch(function (err) {
+        if (err.name !== "AbortError") {
+          console.warn("rtfm widget:", err);
+        }
+      });
+  }
+
+  // Waits for a pause in typing, so every keystroke isn't a search.
+  input.addEventListener("input", function () {
+    clearTimeout(timer);
+    timer = setTimeout(function () {
+      search(input.value);
+    }, 250);
+  });
+})();